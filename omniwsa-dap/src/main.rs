@@ -0,0 +1,740 @@
+use std::{
+    error::Error,
+    fs,
+    io::{self, BufRead, Cursor, ErrorKind, Write},
+    mem,
+    path::{Path, PathBuf},
+    process::exit,
+};
+
+use bstr::ByteSlice;
+use omniwsa::{
+    codegen::CodegenConfig,
+    dialects::{Burghard, ConfigDialect, Dialect, DialectConfig, Palaiologos},
+    exec::{DebugProgram, Debugger, ExecError, Stop},
+    syntax::Cst,
+};
+use serde_json::{Value, json};
+
+// TODO:
+// - Run the program on a separate thread, so that `pause` can interrupt
+//   infinite loops.
+// - Support `evaluate` for inspecting heap addresses and stack indices.
+// - Report errors with the source map of a prebuilt `.ws` file, once
+//   sidecar source maps exist.
+
+/// The only thread in a Whitespace program.
+const THREAD_ID: u64 = 1;
+/// The variables reference for the stack scope.
+const STACK_REF: u64 = 1;
+/// The variables reference for the heap scope.
+const HEAP_REF: u64 = 2;
+
+type DapResult<T> = Result<T, Box<dyn Error + Send + Sync>>;
+
+fn main() {
+    if let Err(err) = do_main() {
+        eprintln!("Error: {err}");
+        exit(1);
+    }
+}
+
+fn do_main() -> DapResult<()> {
+    let mut conn = Connection::stdio();
+
+    let launch = loop {
+        let Some(req) = conn.recv()? else {
+            return Ok(());
+        };
+        match command(&req) {
+            "initialize" => conn.respond(&req, capabilities())?,
+            "launch" => break req,
+            "disconnect" | "terminate" => {
+                conn.respond(&req, json!({}))?;
+                return Ok(());
+            }
+            _ => conn.respond_error(&req, "program not launched")?,
+        }
+    };
+
+    let args = match LaunchArgs::parse(&launch["arguments"]) {
+        Ok(args) => args,
+        Err(err) => {
+            conn.respond_error(&launch, &err)?;
+            conn.event("terminated", json!({}))?;
+            return Ok(());
+        }
+    };
+    let src = match fs::read(&args.program) {
+        Ok(src) => src,
+        Err(err) => {
+            conn.respond_error(&launch, &format!("reading {:?}: {err}", args.program))?;
+            conn.event("terminated", json!({}))?;
+            return Ok(());
+        }
+    };
+    let cst = args.dialect.parse(&src);
+    let options = (args.options.iter()).map(|o| o.as_bytes()).collect();
+    let errors = (cst.check_syntax().iter())
+        .map(|err| err.to_string())
+        .chain(cst.check_reps().iter().map(|err| err.to_string()))
+        .chain(cst.check_labels(&options).iter().map(|err| err.to_string()))
+        .collect::<Vec<_>>();
+    if !errors.is_empty() {
+        let message = format!("parsing {:?}: {}", args.program, errors.join("; "));
+        conn.respond_error(&launch, &message)?;
+        conn.event("terminated", json!({}))?;
+        return Ok(());
+    }
+    let program = DebugProgram::new(&cst, &options, &CodegenConfig::default());
+
+    conn.respond(&launch, json!({}))?;
+    conn.event("initialized", json!({}))?;
+    let mut session = Session {
+        conn,
+        debugger: Debugger::new(&program, Cursor::new(args.stdin), Vec::new()),
+        path: args.program,
+        lines: Vec::new(),
+        labels: Vec::new(),
+        stop_on_entry: args.stop_on_entry,
+        finished: false,
+    };
+    session.run()
+}
+
+/// A connection to a debug client.
+struct Connection {
+    input: Box<dyn BufRead>,
+    output: Box<dyn Write>,
+    seq: u64,
+}
+
+impl Connection {
+    fn new(input: impl BufRead + 'static, output: impl Write + 'static) -> Self {
+        Connection {
+            input: Box::new(input),
+            output: Box::new(output),
+            seq: 0,
+        }
+    }
+
+    fn stdio() -> Self {
+        Connection::new(io::stdin().lock(), io::stdout().lock())
+    }
+
+    /// Receives a message. Returns `None` when the client closes the
+    /// connection.
+    fn recv(&mut self) -> DapResult<Option<Value>> {
+        let mut len = None;
+        let mut line = String::new();
+        loop {
+            line.clear();
+            if self.input.read_line(&mut line)? == 0 {
+                return Ok(None);
+            }
+            let header = line.trim_end_matches(['\r', '\n']);
+            if header.is_empty() {
+                break;
+            }
+            if let Some((name, value)) = header.split_once(':')
+                && name.eq_ignore_ascii_case("Content-Length")
+            {
+                len = Some(value.trim().parse::<usize>()?);
+            }
+        }
+        let Some(len) = len else {
+            return Err(Box::new(io::Error::new(
+                ErrorKind::InvalidData,
+                "missing Content-Length header",
+            )));
+        };
+        let mut body = vec![0; len];
+        self.input.read_exact(&mut body)?;
+        let msg: Value = serde_json::from_slice(&body)?;
+        eprintln!("Receive {msg}");
+        Ok(Some(msg))
+    }
+
+    fn send(&mut self, mut msg: Value) -> DapResult<()> {
+        self.seq += 1;
+        msg["seq"] = json!(self.seq);
+        eprintln!("Send {msg}");
+        let body = serde_json::to_vec(&msg)?;
+        write!(self.output, "Content-Length: {}\r\n\r\n", body.len())?;
+        self.output.write_all(&body)?;
+        self.output.flush()?;
+        Ok(())
+    }
+
+    fn respond(&mut self, req: &Value, body: Value) -> DapResult<()> {
+        self.send(json!({
+            "type": "response",
+            "request_seq": req["seq"],
+            "command": req["command"],
+            "success": true,
+            "body": body,
+        }))
+    }
+
+    fn respond_error(&mut self, req: &Value, message: &str) -> DapResult<()> {
+        self.send(json!({
+            "type": "response",
+            "request_seq": req["seq"],
+            "command": req["command"],
+            "success": false,
+            "message": message,
+        }))
+    }
+
+    fn event(&mut self, event: &str, body: Value) -> DapResult<()> {
+        self.send(json!({
+            "type": "event",
+            "event": event,
+            "body": body,
+        }))
+    }
+}
+
+fn command(req: &Value) -> &str {
+    req["command"].as_str().unwrap_or_default()
+}
+
+fn capabilities() -> Value {
+    json!({
+        "supportsConfigurationDoneRequest": true,
+        "supportsFunctionBreakpoints": true,
+        "supportsSteppingGranularity": true,
+        "supportsTerminateRequest": true,
+    })
+}
+
+/// Arguments of the `launch` request.
+struct LaunchArgs {
+    program: PathBuf,
+    dialect: LaunchDialect,
+    options: Vec<String>,
+    stdin: Vec<u8>,
+    stop_on_entry: bool,
+}
+
+enum LaunchDialect {
+    Burghard,
+    Palaiologos,
+    Config(DialectConfig),
+}
+
+impl LaunchArgs {
+    fn parse(args: &Value) -> Result<Self, String> {
+        let program = (args["program"].as_str())
+            .ok_or("missing `program` argument")?
+            .into();
+        let dialect = match (args["dialect"].as_str(), args["dialectConfig"].as_str()) {
+            (Some("burghard"), None) => LaunchDialect::Burghard,
+            (Some("palaiologos"), None) => LaunchDialect::Palaiologos,
+            (Some(dialect), None) => return Err(format!("unsupported dialect {dialect:?}")),
+            (None, Some(path)) => {
+                let config = fs::read_to_string(path)
+                    .map_err(|err| format!("reading dialect config {path:?}: {err}"))?;
+                let config = DialectConfig::parse(&config)
+                    .map_err(|err| format!("parsing dialect config {path:?}: {err}"))?;
+                LaunchDialect::Config(config)
+            }
+            (None, None) => return Err("missing `dialect` or `dialectConfig` argument".into()),
+            (Some(_), Some(_)) => {
+                return Err("`dialect` and `dialectConfig` are exclusive".into());
+            }
+        };
+        let options = match &args["options"] {
+            Value::Null => Vec::new(),
+            Value::Array(options) => (options.iter())
+                .map(|option| option.as_str().map(str::to_owned))
+                .collect::<Option<_>>()
+                .ok_or("`options` must be strings")?,
+            _ => return Err("`options` must be an array".into()),
+        };
+        let stdin = match (args["stdin"].as_str(), args["stdinFile"].as_str()) {
+            (Some(stdin), None) => stdin.as_bytes().to_vec(),
+            (None, Some(path)) => {
+                fs::read(path).map_err(|err| format!("reading stdin file {path:?}: {err}"))?
+            }
+            (None, None) => Vec::new(),
+            (Some(_), Some(_)) => return Err("`stdin` and `stdinFile` are exclusive".into()),
+        };
+        let stop_on_entry = args["stopOnEntry"].as_bool().unwrap_or(false);
+        Ok(LaunchArgs {
+            program,
+            dialect,
+            options,
+            stdin,
+            stop_on_entry,
+        })
+    }
+}
+
+impl LaunchDialect {
+    fn parse<'s>(&self, src: &'s [u8]) -> Cst<'s> {
+        match self {
+            LaunchDialect::Burghard => Burghard::new().parse(src),
+            LaunchDialect::Palaiologos => Palaiologos::new().parse(src),
+            LaunchDialect::Config(config) => ConfigDialect::new(config).unwrap().parse(src),
+        }
+    }
+}
+
+/// A debugging session for a launched program.
+struct Session<'p> {
+    conn: Connection,
+    debugger: Debugger<'p, Cursor<Vec<u8>>, Vec<u8>>,
+    path: PathBuf,
+    /// Lines with breakpoints, as requested by the client.
+    lines: Vec<usize>,
+    /// Labels with breakpoints, as requested by the client.
+    labels: Vec<Vec<u8>>,
+    stop_on_entry: bool,
+    /// Whether the program has ended or encountered an error.
+    finished: bool,
+}
+
+impl<'p> Session<'p> {
+    fn run(&mut self) -> DapResult<()> {
+        while let Some(req) = self.conn.recv()? {
+            let args = &req["arguments"];
+            match command(&req) {
+                "setBreakpoints" => {
+                    let body = self.set_breakpoints(args);
+                    self.conn.respond(&req, body)?;
+                }
+                "setFunctionBreakpoints" => {
+                    let body = self.set_function_breakpoints(args);
+                    self.conn.respond(&req, body)?;
+                }
+                "configurationDone" => {
+                    self.conn.respond(&req, json!({}))?;
+                    if self.stop_on_entry {
+                        self.stopped("entry")?;
+                    } else {
+                        self.resume(Debugger::cont)?;
+                    }
+                }
+                "threads" => {
+                    let threads = json!({ "threads": [{ "id": THREAD_ID, "name": "main" }] });
+                    self.conn.respond(&req, threads)?;
+                }
+                "stackTrace" => {
+                    let body = self.stack_trace();
+                    self.conn.respond(&req, body)?;
+                }
+                "scopes" => {
+                    let scopes = json!({ "scopes": [
+                        { "name": "Stack", "variablesReference": STACK_REF, "expensive": false },
+                        { "name": "Heap", "variablesReference": HEAP_REF, "expensive": false },
+                    ] });
+                    self.conn.respond(&req, scopes)?;
+                }
+                "variables" => {
+                    let body = self.variables(args["variablesReference"].as_u64());
+                    self.conn.respond(&req, body)?;
+                }
+                "continue" => {
+                    self.conn
+                        .respond(&req, json!({ "allThreadsContinued": true }))?;
+                    self.resume(Debugger::cont)?;
+                }
+                "next" => {
+                    self.conn.respond(&req, json!({}))?;
+                    if is_instruction_granularity(args) {
+                        self.resume(Debugger::step_inst)?;
+                    } else {
+                        self.resume(Debugger::step_over)?;
+                    }
+                }
+                "stepIn" => {
+                    self.conn.respond(&req, json!({}))?;
+                    if is_instruction_granularity(args) {
+                        self.resume(Debugger::step_inst)?;
+                    } else {
+                        self.resume(Debugger::step_in)?;
+                    }
+                }
+                "stepOut" => {
+                    self.conn.respond(&req, json!({}))?;
+                    self.resume(Debugger::step_out)?;
+                }
+                "disconnect" | "terminate" => {
+                    self.conn.respond(&req, json!({}))?;
+                    return Ok(());
+                }
+                command => {
+                    let message = format!("unsupported request {command:?}");
+                    self.conn.respond_error(&req, &message)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Resumes execution with the debugger method and reports why it stopped.
+    fn resume(
+        &mut self,
+        f: impl FnOnce(&mut Debugger<'p, Cursor<Vec<u8>>, Vec<u8>>) -> Result<Stop, ExecError>,
+    ) -> DapResult<()> {
+        if self.finished {
+            return self.conn.event("terminated", json!({}));
+        }
+        let res = f(&mut self.debugger);
+        let output = mem::take(self.debugger.output_mut());
+        if !output.is_empty() {
+            let output = output.to_str_lossy();
+            self.conn
+                .event("output", json!({ "category": "stdout", "output": output }))?;
+        }
+        match res {
+            Ok(Stop::End) => {
+                self.finished = true;
+                self.conn.event("exited", json!({ "exitCode": 0 }))?;
+                self.conn.event("terminated", json!({}))
+            }
+            Ok(Stop::Breakpoint) => self.stopped("breakpoint"),
+            Ok(Stop::Step) => self.stopped("step"),
+            Err(err) => {
+                self.finished = true;
+                let text = err.to_string();
+                self.conn.event(
+                    "output",
+                    json!({ "category": "stderr", "output": format!("Error: {text}\n") }),
+                )?;
+                self.conn.event(
+                    "stopped",
+                    json!({
+                        "reason": "exception",
+                        "description": "Execution error",
+                        "text": text,
+                        "threadId": THREAD_ID,
+                        "allThreadsStopped": true,
+                    }),
+                )
+            }
+        }
+    }
+
+    fn stopped(&mut self, reason: &str) -> DapResult<()> {
+        self.conn.event(
+            "stopped",
+            json!({ "reason": reason, "threadId": THREAD_ID, "allThreadsStopped": true }),
+        )
+    }
+
+    fn set_breakpoints(&mut self, args: &Value) -> Value {
+        let same_source = args["source"]["path"]
+            .as_str()
+            .is_some_and(|path| same_file(Path::new(path), &self.path));
+        let requested = (args["breakpoints"].as_array().into_iter().flatten())
+            .filter_map(|bp| bp["line"].as_u64())
+            .map(|line| line as usize);
+        self.lines.clear();
+        let mut breakpoints = Vec::new();
+        for line in requested {
+            let bound = if same_source {
+                self.debugger.program().line_pc(line)
+            } else {
+                None
+            };
+            match bound {
+                Some((line, _)) => {
+                    self.lines.push(line);
+                    breakpoints.push(json!({ "verified": true, "line": line }));
+                }
+                None => breakpoints.push(json!({
+                    "verified": false,
+                    "line": line,
+                    "message": "no instructions at or after this line",
+                })),
+            }
+        }
+        self.update_breakpoints();
+        json!({ "breakpoints": breakpoints })
+    }
+
+    fn set_function_breakpoints(&mut self, args: &Value) -> Value {
+        self.labels = (args["breakpoints"].as_array().into_iter().flatten())
+            .filter_map(|bp| bp["name"].as_str())
+            .map(|name| name.as_bytes().to_vec())
+            .collect();
+        let breakpoints = (self.labels.iter())
+            .map(|label| {
+                let verified = self.debugger.program().label_pc(label).is_some();
+                json!({ "verified": verified })
+            })
+            .collect::<Vec<_>>();
+        self.update_breakpoints();
+        json!({ "breakpoints": breakpoints })
+    }
+
+    fn update_breakpoints(&mut self) {
+        self.debugger.clear_breakpoints();
+        for &line in &self.lines {
+            self.debugger.set_line_breakpoint(line);
+        }
+        for label in &self.labels {
+            self.debugger.set_label_breakpoint(label);
+        }
+    }
+
+    fn stack_trace(&self) -> Value {
+        let program = self.debugger.program();
+        let name = self.path.file_name().unwrap_or_default().to_string_lossy();
+        let source = json!({ "name": name, "path": self.path });
+        let frames = (self.debugger.frames().iter().enumerate())
+            .map(|(id, frame)| {
+                let name = match &frame.label {
+                    Some(label) => match program.label_name(label) {
+                        Some(name) => name.to_str_lossy().into_owned(),
+                        None => label.to_string(),
+                    },
+                    None => "main".to_owned(),
+                };
+                let line = program.source(frame.pc).map_or(0, |src| src.line);
+                json!({
+                    "id": id,
+                    "name": name,
+                    "source": source,
+                    "line": line,
+                    "column": 1,
+                })
+            })
+            .collect::<Vec<_>>();
+        json!({ "stackFrames": frames, "totalFrames": frames.len() })
+    }
+
+    fn variables(&self, reference: Option<u64>) -> Value {
+        let variables = match reference {
+            Some(STACK_REF) => (self.debugger.stack().iter().rev().enumerate())
+                .map(|(i, n)| variable(&i.to_string(), &n.to_string()))
+                .collect(),
+            Some(HEAP_REF) => (self.debugger.heap().iter())
+                .map(|(addr, n)| variable(&addr.to_string(), &n.to_string()))
+                .collect(),
+            _ => Vec::new(),
+        };
+        json!({ "variables": variables })
+    }
+}
+
+fn variable(name: &str, value: &str) -> Value {
+    json!({ "name": name, "value": value, "variablesReference": 0 })
+}
+
+fn is_instruction_granularity(args: &Value) -> bool {
+    args["granularity"].as_str() == Some("instruction")
+}
+
+fn same_file(a: &Path, b: &Path) -> bool {
+    match (fs::canonicalize(a), fs::canonicalize(b)) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => a == b,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        cell::RefCell,
+        collections::HashSet,
+        io::{self, Cursor, Write},
+        path::PathBuf,
+        rc::Rc,
+    };
+
+    use omniwsa::{
+        codegen::CodegenConfig,
+        dialects::{Burghard, Dialect as _},
+        exec::{DebugProgram, Debugger},
+    };
+    use serde_json::{Value, json};
+
+    use crate::{Connection, LaunchArgs, LaunchDialect, Session};
+
+    /// An output shared between a connection and the test.
+    #[derive(Clone, Default)]
+    struct SharedBuf(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for SharedBuf {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.borrow_mut().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl SharedBuf {
+        /// Takes the messages sent so far.
+        fn messages(&self) -> Vec<Value> {
+            let sent = self.0.take();
+            let mut conn = Connection::new(Cursor::new(sent), io::sink());
+            let mut messages = Vec::new();
+            while let Some(msg) = conn.recv().unwrap() {
+                messages.push(msg);
+            }
+            messages
+        }
+
+        /// Takes the events sent so far, as their names and bodies.
+        fn events(&self) -> Vec<(String, Value)> {
+            (self.messages().into_iter())
+                .filter(|msg| msg["type"] == "event")
+                .map(|msg| {
+                    (
+                        msg["event"].as_str().unwrap().to_owned(),
+                        msg["body"].clone(),
+                    )
+                })
+                .collect()
+        }
+    }
+
+    const SRC: &[u8] = b"push 1
+; count
+label loop
+push 2
+add
+outN
+exit
+";
+
+    fn program() -> DebugProgram {
+        let cst = Burghard::new().parse(SRC);
+        assert!(cst.check_syntax().is_empty());
+        DebugProgram::new(&cst, &HashSet::new(), &CodegenConfig::default())
+    }
+
+    fn session(program: &DebugProgram) -> (Session<'_>, SharedBuf) {
+        let output = SharedBuf::default();
+        let session = Session {
+            conn: Connection::new(Cursor::new(Vec::new()), output.clone()),
+            debugger: Debugger::new(program, Cursor::new(Vec::new()), Vec::new()),
+            path: PathBuf::from("prog.wsa"),
+            lines: Vec::new(),
+            labels: Vec::new(),
+            stop_on_entry: false,
+            finished: false,
+        };
+        (session, output)
+    }
+
+    #[test]
+    fn launch_args() {
+        let args = LaunchArgs::parse(&json!({
+            "program": "prog.wsa",
+            "dialect": "palaiologos",
+            "options": ["a", "b"],
+            "stdin": "1\n",
+            "stopOnEntry": true,
+        }))
+        .unwrap();
+        assert_eq!(args.program, PathBuf::from("prog.wsa"));
+        assert!(matches!(args.dialect, LaunchDialect::Palaiologos));
+        assert_eq!(args.options, ["a", "b"]);
+        assert_eq!(args.stdin, b"1\n");
+        assert!(args.stop_on_entry);
+
+        let args = LaunchArgs::parse(&json!({ "program": "p", "dialect": "burghard" })).unwrap();
+        assert!(matches!(args.dialect, LaunchDialect::Burghard));
+        assert!(args.options.is_empty() && args.stdin.is_empty() && !args.stop_on_entry);
+
+        let err = |args: Value| LaunchArgs::parse(&args).err().unwrap();
+        assert_eq!(
+            err(json!({ "dialect": "burghard" })),
+            "missing `program` argument",
+        );
+        assert_eq!(
+            err(json!({ "program": "p" })),
+            "missing `dialect` or `dialectConfig` argument",
+        );
+        assert_eq!(
+            err(json!({ "program": "p", "dialect": "wsf" })),
+            "unsupported dialect \"wsf\"",
+        );
+        assert_eq!(
+            err(json!({ "program": "p", "dialect": "burghard", "dialectConfig": "c.toml" })),
+            "`dialect` and `dialectConfig` are exclusive",
+        );
+        assert_eq!(
+            err(json!({ "program": "p", "dialect": "burghard", "options": "a" })),
+            "`options` must be an array",
+        );
+        assert_eq!(
+            err(json!({ "program": "p", "dialect": "burghard", "options": [1] })),
+            "`options` must be strings",
+        );
+        assert_eq!(
+            err(json!({ "program": "p", "dialect": "burghard", "stdin": "", "stdinFile": "f" })),
+            "`stdin` and `stdinFile` are exclusive",
+        );
+    }
+
+    #[test]
+    fn set_breakpoints() {
+        let program = program();
+        let (mut session, _) = session(&program);
+        // Breakpoints on comments and labels move to the next instruction.
+        let body = session.set_breakpoints(&json!({
+            "source": { "path": "prog.wsa" },
+            "breakpoints": [{ "line": 2 }, { "line": 5 }, { "line": 9 }],
+        }));
+        assert_eq!(
+            body["breakpoints"],
+            json!([
+                { "verified": true, "line": 4 },
+                { "verified": true, "line": 5 },
+                {
+                    "verified": false,
+                    "line": 9,
+                    "message": "no instructions at or after this line",
+                },
+            ]),
+        );
+        assert_eq!(session.lines, [4, 5]);
+
+        // Breakpoints in other sources are not bound and replace the previous.
+        let body = session.set_breakpoints(&json!({
+            "source": { "path": "other.wsa" },
+            "breakpoints": [{ "line": 1 }],
+        }));
+        assert_eq!(body["breakpoints"][0]["verified"], false);
+        assert!(session.lines.is_empty());
+    }
+
+    #[test]
+    fn stop_at_breakpoint() {
+        let program = program();
+        let (mut session, output) = session(&program);
+        session.set_breakpoints(&json!({
+            "source": { "path": "prog.wsa" },
+            "breakpoints": [{ "line": 5 }],
+        }));
+
+        session.resume(Debugger::cont).unwrap();
+        assert_eq!(session.debugger.source().unwrap().line, 5);
+        assert_eq!(session.debugger.stack(), [1, 2]);
+        assert_eq!(
+            output.events(),
+            [(
+                "stopped".to_owned(),
+                json!({ "reason": "breakpoint", "threadId": 1, "allThreadsStopped": true }),
+            )],
+        );
+
+        session.resume(Debugger::step_over).unwrap();
+        assert_eq!(session.debugger.source().unwrap().line, 6);
+        assert_eq!(output.events()[0].1["reason"], "step");
+
+        session.resume(Debugger::cont).unwrap();
+        assert!(session.finished);
+        let events = output.events();
+        let names = events.iter().map(|(name, _)| &**name).collect::<Vec<_>>();
+        assert_eq!(names, ["output", "exited", "terminated"]);
+        assert_eq!(events[0].1["output"], "3");
+    }
+}
@@ -0,0 +1,267 @@
+//! Completion of mnemonics, labels, variables, and options.
+
+use std::collections::BTreeSet;
+
+use bstr::ByteSlice;
+use lsp_types::{CompletionItem, CompletionItemKind, CompletionTextEdit, TextEdit};
+use omniwsa::{
+    syntax::{ArgType, Opcode, Overload, Pretty},
+    tokens::{
+        Token,
+        comment::BlockCommentError,
+        label::{LabelStyle, LabelToken},
+    },
+};
+
+use crate::{
+    diagnostics::arg_type_name,
+    dialect::{DialectKind, Dialects},
+    symbols::{Program, SymbolKind},
+};
+
+// TODO:
+// - Offer mnemonics of macros and includes, once they are resolved.
+// - Complete from the CST instead of relexing the prefix of the line, once
+//   tokens record spans.
+
+/// The part of an instruction before the cursor.
+struct Context<'s> {
+    /// The words before the word at the cursor.
+    words: Vec<Token<'s>>,
+    /// The byte offset of the start of the word at the cursor, which the
+    /// completion replaces.
+    start: usize,
+}
+
+/// Computes the completions at a byte offset in the document of the program.
+pub fn completions(
+    program: &Program<'_>,
+    dialects: &Dialects,
+    offset: usize,
+) -> Vec<CompletionItem> {
+    let doc = &program.files[0].doc;
+    let dialect = doc.dialect();
+    let Some(cx) = context(dialects, dialect, &doc.text()[..offset]) else {
+        return Vec::new();
+    };
+    let range = doc.range(cx.start..offset);
+    let item = |label: String, kind: CompletionItemKind, detail: Option<String>| CompletionItem {
+        label: label.clone(),
+        kind: Some(kind),
+        detail,
+        text_edit: Some(CompletionTextEdit::Edit(TextEdit::new(range, label))),
+        ..Default::default()
+    };
+
+    let Some((mnemonic, args)) = cx.words.split_first() else {
+        let overloads = dialect.overloads();
+        return (dialect.mnemonics().iter())
+            .map(|(mnemonic, opcodes)| {
+                let mnemonic = mnemonic.bytes.to_str_lossy();
+                let signatures = signatures(opcodes, &overloads)
+                    .map(|arg_types| signature(&mnemonic, arg_types))
+                    .collect::<Vec<_>>();
+                let detail = signatures.join(" | ");
+                item(
+                    mnemonic.into_owned(),
+                    CompletionItemKind::KEYWORD,
+                    Some(detail),
+                )
+            })
+            .collect();
+    };
+
+    let mnemonic = match mnemonic.peel_groups() {
+        Token::Mnemonic(m) => &m.mnemonic,
+        Token::Word(w) => &w.word,
+        _ => return Vec::new(),
+    };
+    let opcodes = dialects.opcodes(dialect, mnemonic);
+    let overloads = dialect.overloads();
+    let arg_types = signatures(opcodes, &overloads)
+        .filter_map(|arg_types| arg_types.get(args.len()).copied())
+        .collect::<Vec<_>>();
+    let expects = |ty: ArgType| opcodes.is_empty() || arg_types.contains(&ty);
+
+    let mut items = Vec::new();
+    let files = &program.files;
+    // Exclude the symbol being typed.
+    let symbols = || {
+        (files.iter().enumerate()).flat_map(move |(i, file)| {
+            (file.symbols.symbols.iter()).filter(move |sym| i != 0 || sym.range.end != offset)
+        })
+    };
+    if opcodes
+        .iter()
+        .any(|&op| matches!(op, Opcode::IfOption | Opcode::ElseIfOption))
+    {
+        let options = (files.iter())
+            .flat_map(|file| &file.symbols.options)
+            .collect::<BTreeSet<_>>();
+        for option in options {
+            items.push(item(
+                option.to_str_lossy().into_owned(),
+                CompletionItemKind::CONSTANT,
+                None,
+            ));
+        }
+        return items;
+    }
+    if expects(ArgType::Label) {
+        // At a definition, offer the labels which are used, but not defined.
+        let defining = opcodes.contains(&Opcode::Label);
+        let defined = symbols()
+            .filter(|sym| sym.kind == SymbolKind::Label && sym.is_def)
+            .map(|sym| &sym.name)
+            .collect::<BTreeSet<_>>();
+        let labels = symbols()
+            .filter(|sym| sym.kind == SymbolKind::Label)
+            .map(|sym| &sym.name)
+            .filter(|name| defining != defined.contains(name))
+            .collect::<BTreeSet<_>>();
+        let sigil = match dialect {
+            DialectKind::Burghard => LabelStyle::NoSigil,
+            DialectKind::Palaiologos => LabelStyle::PercentSigil,
+        }
+        .sigil();
+        for label in labels {
+            let label = format!("{sigil}{}", label.as_bstr());
+            items.push(item(label, CompletionItemKind::FUNCTION, None));
+        }
+    }
+    if dialect == DialectKind::Burghard
+        && (expects(ArgType::Integer) || expects(ArgType::String) || expects(ArgType::Variable))
+    {
+        let variables = symbols()
+            .filter(|sym| sym.kind == SymbolKind::Variable)
+            .map(|sym| &sym.name)
+            .collect::<BTreeSet<_>>();
+        for variable in variables {
+            let variable = format!("_{}", variable.as_bstr());
+            items.push(item(variable, CompletionItemKind::VARIABLE, None));
+        }
+    }
+    items
+}
+
+/// Finds the instruction at the end of the text, by lexing it. Returns `None`,
+/// when it ends in a comment.
+fn context<'s>(dialects: &Dialects, dialect: DialectKind, text: &'s str) -> Option<Context<'s>> {
+    let toks = dialects.lex(dialect, text.as_bytes());
+    let mut words = Vec::new();
+    let mut start = text.len();
+    let mut end = text.len();
+    let mut at_cursor = true;
+    let mut buf = Vec::new();
+    for tok in toks.into_iter().rev() {
+        buf.clear();
+        tok.pretty(&mut buf);
+        let tok_start = end - buf.len();
+        match tok.peel_groups() {
+            Token::LineTerm(_) | Token::Eof(_) | Token::InstSep(_) => break,
+            Token::LineComment(_) => return None,
+            Token::BlockComment(comment) => {
+                if comment.errors.contains(BlockCommentError::Unterminated) {
+                    return None;
+                }
+                at_cursor = false;
+            }
+            Token::Space(_) | Token::ArgSep(_) => at_cursor = false,
+            // A label definition is a separate instruction (Palaiologos).
+            Token::Label(LabelToken {
+                style: LabelStyle::AtSigil,
+                ..
+            }) => {
+                if at_cursor {
+                    return None;
+                }
+                break;
+            }
+            _ => {
+                if at_cursor {
+                    start = tok_start;
+                } else {
+                    words.push(tok);
+                }
+                at_cursor = false;
+            }
+        }
+        end = tok_start;
+    }
+    words.reverse();
+    Some(Context { words, start })
+}
+
+/// Iterates the argument types of each signature of the opcodes, including
+/// their overloads in the dialect.
+fn signatures<'a>(
+    opcodes: &'a [Opcode],
+    overloads: &'a [(Opcode, Overload)],
+) -> impl Iterator<Item = &'static [ArgType]> + 'a {
+    opcodes.iter().flat_map(|&opcode| {
+        let overloads = (overloads.iter())
+            .filter(move |&&(op, _)| op == opcode)
+            .map(|(_, overload)| overload.arg_types());
+        [opcode.arg_types()].into_iter().chain(overloads)
+    })
+}
+
+/// Formats the signature of a mnemonic.
+fn signature(mnemonic: &str, arg_types: &[ArgType]) -> String {
+    let mut s = mnemonic.to_owned();
+    for &ty in arg_types {
+        s.push_str(&format!(" <{}>", arg_type_name(ty)));
+    }
+    s
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use lsp_types::{DidOpenTextDocumentParams, TextDocumentItem, Uri};
+
+    use crate::{
+        completion::completions,
+        dialect::Dialects,
+        documents::{Documents, PositionEncoding},
+        symbols::Program,
+    };
+
+    /// Computes the labels of the completions at the end of the text.
+    fn complete(text: &str) -> Vec<String> {
+        let dialects = Dialects::new();
+        let mut documents = Documents::new(PositionEncoding::Utf16);
+        let uri = Uri::from_str("file:///main.wsa").unwrap();
+        let text_document = TextDocumentItem::new(uri.clone(), "wsa".into(), 1, text.into());
+        documents.open(DidOpenTextDocumentParams { text_document }, &dialects);
+        let program = Program::load(&documents, &dialects, &uri).unwrap();
+        (completions(&program, &dialects, text.len()).into_iter())
+            .map(|item| item.label)
+            .collect()
+    }
+
+    #[test]
+    fn completions_by_context() {
+        let mnemonics = complete("push 1\npu");
+        assert!(mnemonics.iter().any(|m| m == "push"));
+        assert!(mnemonics.iter().any(|m| m == "jump"));
+
+        // References offer the defined labels and definitions the undefined.
+        let text = "label used\njump used\njump undefined\njump ";
+        assert_eq!(complete(text), ["used"]);
+        let text = "label used\njump used\njump undefined\nlabel ";
+        assert_eq!(complete(text), ["undefined"]);
+        // The symbol being typed is excluded.
+        let text = "label used\njump u";
+        assert_eq!(complete(text), ["used"]);
+        let text = "valueinteger _x 1\npush ";
+        assert_eq!(complete(text), ["_x"]);
+        let text = "option a\nifoption ";
+        assert_eq!(complete(text), ["a"]);
+        assert!(complete("push 1 ; ").is_empty());
+
+        let text = "@loop\njmp %loop\njmp %";
+        assert_eq!(complete(text), ["%loop"]);
+    }
+}
@@ -0,0 +1,393 @@
+//! Diagnostics from the errors recorded in a parsed document.
+
+use bstr::ByteSlice;
+use lsp_types::{Diagnostic, DiagnosticSeverity};
+use omniwsa::{
+    syntax::{ArgLayout, ArgType, Cst, Inst, InstError, Opcode, Pretty},
+    tokens::{
+        GroupError, Token, WordError,
+        comment::{BlockCommentError, LineCommentError},
+        integer::{IntegerError, IntegerSyntax},
+        label::LabelError,
+        spaces::{ArgSepError, InstSepError, Spaces},
+        string::{CharError, StringError},
+    },
+};
+
+use crate::{dialect::DialectKind, documents::Document};
+
+/// Collects a diagnostic for every error in the CST of a document.
+pub fn diagnostics(
+    cst: &Cst<'_>,
+    dialect: DialectKind,
+    integers: &IntegerSyntax,
+    doc: &Document,
+) -> Vec<Diagnostic> {
+    let mut c = Collector {
+        doc,
+        dialect: dialect.name(),
+        integers,
+        offset: 0,
+        buf: Vec::new(),
+        diagnostics: Vec::new(),
+    };
+    c.cst(cst);
+    c.diagnostics
+}
+
+/// Walks a CST in source order, tracking the offset of each node by the text
+/// it pretty-prints as, which is the same as its source.
+struct Collector<'a> {
+    doc: &'a Document,
+    dialect: &'static str,
+    integers: &'a IntegerSyntax,
+    offset: usize,
+    buf: Vec<u8>,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl Collector<'_> {
+    fn cst(&mut self, cst: &Cst<'_>) {
+        match cst {
+            Cst::Inst(inst) => {
+                self.inst(inst);
+            }
+            Cst::Block { nodes } => nodes.iter().for_each(|node| self.cst(node)),
+            Cst::OptionBlock(block) => {
+                let mut first = None;
+                for (option, nodes) in &block.options {
+                    let span = self.inst(option);
+                    first.get_or_insert((span, option.opcode));
+                    nodes.iter().for_each(|node| self.cst(node));
+                }
+                if let Some(end) = &block.end {
+                    self.inst(end);
+                }
+                if let Some(((start, end), opcode)) = first {
+                    if opcode != Opcode::IfOption {
+                        let message = "option block does not start with `ifoption`".to_owned();
+                        self.error(start, end, message);
+                    }
+                    if block.end.is_none() {
+                        let message = "option block is not closed by `endoption`".to_owned();
+                        self.error(start, end, message);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Reports the errors in an instruction and returns its span.
+    fn inst(&mut self, inst: &Inst<'_>) -> (usize, usize) {
+        self.spaces(&inst.words.space_before);
+        let start = self.offset;
+        let mut end = start;
+        for (word, space) in &inst.words.words {
+            self.token(word);
+            end = self.offset;
+            self.spaces(space);
+        }
+
+        let mnemonic = match (inst.arg_layout, inst.words.words.first()) {
+            (ArgLayout::Mnemonic, Some((word, _))) => {
+                self.buf.clear();
+                word.pretty(&mut self.buf);
+                Some(self.buf.to_str_lossy().into_owned())
+            }
+            _ => None,
+        };
+        let name = mnemonic.as_deref().unwrap_or(inst.opcode.name());
+        if inst.opcode == Opcode::Invalid {
+            let message = format!("unknown instruction `{name}` in {}", self.dialect);
+            self.error(start, end, message);
+        }
+        let arg_types = match inst.overload {
+            Some(overload) => overload.arg_types(),
+            None => inst.opcode.arg_types(),
+        };
+        if inst.errors.contains(InstError::InvalidArity) {
+            let message = format!(
+                "`{name}` expects {} argument{} in {}, but has {}",
+                arg_types.len(),
+                if arg_types.len() == 1 { "" } else { "s" },
+                self.dialect,
+                inst.len_args(),
+            );
+            self.error(start, end, message);
+        }
+        if inst.errors.contains(InstError::InvalidTypes) {
+            let types = (arg_types.iter())
+                .map(|&ty| arg_type_name(ty))
+                .collect::<Vec<_>>();
+            let message = format!(
+                "`{name}` expects arguments of type ({}) in {}",
+                types.join(", "),
+                self.dialect,
+            );
+            self.error(start, end, message);
+        }
+        (start, end)
+    }
+
+    fn spaces(&mut self, spaces: &Spaces<'_>) {
+        spaces.tokens().iter().for_each(|tok| self.token(tok));
+    }
+
+    fn token(&mut self, tok: &Token<'_>) {
+        let start = self.offset;
+        match tok {
+            Token::Group(group) => {
+                self.offset += group.delim.open().len();
+                self.token(&group.inner);
+                if group.errors.contains(GroupError::Unterminated) {
+                    let message = format!("unclosed `{}`", group.delim.open());
+                    self.error(start, self.offset, message);
+                } else {
+                    self.offset += group.delim.close().len();
+                }
+                return;
+            }
+            Token::Splice(splice) => {
+                splice.tokens.iter().for_each(|tok| self.token(tok));
+                return;
+            }
+            _ => {}
+        }
+        self.buf.clear();
+        tok.pretty(&mut self.buf);
+        self.offset += self.buf.len();
+        let end = self.offset;
+
+        let dialect = self.dialect;
+        match tok {
+            Token::Integer(int) => {
+                for err in int.errors {
+                    let message = integer_error_message(err, dialect, self.integers);
+                    self.error(start, end, message);
+                }
+            }
+            Token::String(s) => {
+                for err in s.errors {
+                    let message = match err {
+                        StringError::Unterminated => "unterminated string",
+                        StringError::InvalidEscape => "invalid escape sequence in string",
+                        StringError::InvalidUtf8 => "string contains invalid UTF-8",
+                    };
+                    self.error(start, end, message.to_owned());
+                }
+            }
+            Token::Char(c) => {
+                for err in c.errors {
+                    let message = match err {
+                        CharError::Unterminated => "unterminated character".to_owned(),
+                        CharError::Empty => "empty character".to_owned(),
+                        CharError::MultipleChars => "character has more than one char".to_owned(),
+                        CharError::InvalidEscape => {
+                            "invalid escape sequence in character".to_owned()
+                        }
+                        CharError::InvalidUtf8 => "character contains invalid UTF-8".to_owned(),
+                        CharError::UnexpectedUnicode => {
+                            format!("{dialect} characters must be a single byte")
+                        }
+                    };
+                    self.error(start, end, message);
+                }
+            }
+            Token::Label(label) => {
+                for err in label.errors {
+                    let message = match err {
+                        LabelError::Redefined => format!(
+                            "label `{}{}` is already defined",
+                            label.style.sigil(),
+                            label.label.as_bstr(),
+                        ),
+                        LabelError::Empty => "label is empty".to_owned(),
+                        LabelError::StartsWithDigit => {
+                            format!("{dialect} labels cannot start with a digit")
+                        }
+                    };
+                    self.error(start, end, message);
+                }
+            }
+            Token::InstSep(sep) => {
+                for err in sep.errors {
+                    let message = match err {
+                        InstSepError::Multiple => "multiple adjacent instruction separators",
+                        InstSepError::StartOfLine => "instruction separator at the start of a line",
+                        InstSepError::EndOfLine => "instruction separator at the end of a line",
+                    };
+                    self.error(start, end, message.to_owned());
+                }
+            }
+            Token::ArgSep(sep) => {
+                for err in sep.errors {
+                    let message = match err {
+                        ArgSepError::NotBetweenArguments => {
+                            "argument separator is not between arguments"
+                        }
+                        ArgSepError::Multiple => "multiple adjacent argument separators",
+                    };
+                    self.error(start, end, message.to_owned());
+                }
+            }
+            Token::LineComment(comment) => {
+                if comment.errors.contains(LineCommentError::InvalidUtf8) {
+                    let message = "comment contains invalid UTF-8".to_owned();
+                    self.warning(start, end, message);
+                }
+            }
+            Token::BlockComment(comment) => {
+                for err in comment.errors {
+                    match err {
+                        BlockCommentError::Unterminated => {
+                            let message = format!("unclosed `{}`", comment.style.open());
+                            self.error(start, end, message);
+                        }
+                        BlockCommentError::Unopened => {
+                            let message = format!(
+                                "`{}` without an opening `{}`",
+                                comment.style.close(),
+                                comment.style.open(),
+                            );
+                            self.error(start, end, message);
+                        }
+                        BlockCommentError::InvalidUtf8 => {
+                            let message = "comment contains invalid UTF-8".to_owned();
+                            self.warning(start, end, message);
+                        }
+                    }
+                }
+            }
+            Token::Word(word) => {
+                if word.errors.contains(WordError::InvalidUtf8) {
+                    self.error(start, end, "word contains invalid UTF-8".to_owned());
+                }
+            }
+            Token::Error(err) => {
+                let message = format!("unrecognized `{}` in {dialect}", err.text.as_bstr());
+                self.error(start, end, message);
+            }
+            Token::Mnemonic(_)
+            | Token::Variable(_)
+            | Token::LabelColon(_)
+            | Token::Space(_)
+            | Token::LineTerm(_)
+            | Token::Eof(_)
+            | Token::Group(_)
+            | Token::Splice(_)
+            | Token::Placeholder => {}
+        }
+    }
+
+    fn error(&mut self, start: usize, end: usize, message: String) {
+        self.push(start, end, DiagnosticSeverity::ERROR, message);
+    }
+
+    fn warning(&mut self, start: usize, end: usize, message: String) {
+        self.push(start, end, DiagnosticSeverity::WARNING, message);
+    }
+
+    fn push(&mut self, start: usize, end: usize, severity: DiagnosticSeverity, message: String) {
+        let range = self.doc.range(start..end);
+        self.diagnostics.push(Diagnostic {
+            range,
+            severity: Some(severity),
+            source: Some("omniwsa".to_owned()),
+            message,
+            ..Default::default()
+        });
+    }
+}
+
+/// Describes an error in an integer literal.
+pub fn integer_error_message(err: IntegerError, dialect: &str, integers: &IntegerSyntax) -> String {
+    match err {
+        IntegerError::InvalidDigit => "invalid digit in integer".to_owned(),
+        IntegerError::NoDigits => "integer has no digits".to_owned(),
+        IntegerError::Range => match (&integers.min_value, &integers.max_value) {
+            (Some(min), Some(max)) => {
+                format!("integer out of range for {dialect}, which allows {min} to {max}")
+            }
+            _ => format!("integer out of range for {dialect}"),
+        },
+        IntegerError::InvalidSign => format!("{dialect} does not support this integer sign"),
+        IntegerError::InvalidBase => format!("{dialect} does not support this integer base"),
+        IntegerError::InvalidDigitSep => format!("{dialect} does not support digit separators"),
+        IntegerError::StartsWithHex => format!("{dialect} integers cannot start with a hex letter"),
+        IntegerError::UnpairedParen => "unpaired parenthesis in integer".to_owned(),
+    }
+}
+
+/// A name for the type of an argument, for messages.
+pub fn arg_type_name(ty: ArgType) -> &'static str {
+    match ty {
+        ArgType::Integer => "integer",
+        ArgType::String => "string",
+        ArgType::Label => "label",
+        ArgType::Variable => "variable",
+        ArgType::Include => "include path",
+        ArgType::Option => "option",
+        ArgType::Mnemonic => "mnemonic",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use lsp_types::{DiagnosticSeverity, Position, Range};
+
+    use crate::{
+        diagnostics::diagnostics,
+        dialect::{DialectKind, Dialects},
+        documents::{Document, PositionEncoding},
+    };
+
+    fn ranges(text: &str, encoding: PositionEncoding) -> Vec<(Range, String)> {
+        let dialects = Dialects::new();
+        let dialect = DialectKind::Burghard;
+        let doc = Document::new(text.to_owned(), 0, encoding, dialect);
+        let cst = dialects.parse(dialect, text.as_bytes());
+        diagnostics(&cst, dialect, dialects.integers(dialect), &doc)
+            .into_iter()
+            .inspect(|diag| assert_eq!(diag.severity, Some(DiagnosticSeverity::ERROR)))
+            .map(|diag| (diag.range, diag.message))
+            .collect()
+    }
+
+    fn range(l0: u32, c0: u32, l1: u32, c1: u32) -> Range {
+        Range::new(Position::new(l0, c0), Position::new(l1, c1))
+    }
+
+    #[test]
+    fn ranges_in_encoding() {
+        // Burghard quoted words are integers here, so `草` is an invalid digit.
+        let text = "push \"草\" ; 😀\nfoo 1\nadd 1 2";
+        let digit = "invalid digit in integer".to_owned();
+        let unknown = "unknown instruction `foo` in Burghard".to_owned();
+        let arity = "`add` expects 0 arguments in Burghard, but has 2".to_owned();
+        assert_eq!(
+            ranges(text, PositionEncoding::Utf8),
+            [
+                (range(0, 6, 0, 9), digit.clone()),
+                (range(1, 0, 1, 5), unknown.clone()),
+                (range(2, 0, 2, 7), arity.clone()),
+            ],
+        );
+        assert_eq!(
+            ranges(text, PositionEncoding::Utf16),
+            [
+                (range(0, 6, 0, 7), digit),
+                (range(1, 0, 1, 5), unknown.clone()),
+                (range(2, 0, 2, 7), arity),
+            ],
+        );
+        let text = "push 1 ; 😀\nfoo \"草\"";
+        assert_eq!(
+            ranges(text, PositionEncoding::Utf8),
+            [(range(1, 0, 1, 9), unknown.clone())],
+        );
+        assert_eq!(
+            ranges(text, PositionEncoding::Utf16),
+            [(range(1, 0, 1, 7), unknown)],
+        );
+    }
+}
@@ -0,0 +1,282 @@
+//! Selection of the Whitespace assembly dialect of each document.
+
+use glob::{MatchOptions, Pattern};
+use lsp_types::Uri;
+use omniwsa::{
+    dialects::{Burghard, Dialect, DialectState, Palaiologos},
+    syntax::{Cst, HasError, Opcode, Overload},
+    tokens::{Token, integer::IntegerSyntax, mnemonics::FoldedStr},
+};
+use serde_json::Value;
+
+// TODO:
+// - Support dialect configs from files, like `omniwsa --dialect-config`.
+// - Detect the dialect from more than error counts, e.g., distinctive
+//   mnemonics and comment styles.
+
+/// A Whitespace assembly dialect, which the server can analyze.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DialectKind {
+    /// Burghard Whitespace assembly.
+    Burghard,
+    /// Palaiologos Whitespace assembly.
+    Palaiologos,
+}
+
+/// The state for each dialect and the settings for choosing between them.
+#[derive(Debug)]
+pub struct Dialects {
+    burghard: DialectState<Burghard>,
+    palaiologos: DialectState<Palaiologos>,
+    /// Glob patterns from the `omniwsa.dialects` setting, in order of
+    /// decreasing specificity.
+    patterns: Vec<(Pattern, DialectKind)>,
+}
+
+impl DialectKind {
+    const ALL: [DialectKind; 2] = [DialectKind::Burghard, DialectKind::Palaiologos];
+
+    /// The display name of this dialect.
+    pub fn name(self) -> &'static str {
+        match self {
+            DialectKind::Burghard => "Burghard",
+            DialectKind::Palaiologos => "Palaiologos",
+        }
+    }
+
+    /// The mnemonics of this dialect, in their conventional case, and the
+    /// opcodes they can parse as.
+    pub fn mnemonics(self) -> &'static [(FoldedStr<'static>, &'static [Opcode])] {
+        match self {
+            DialectKind::Burghard => Burghard::MNEMONICS,
+            DialectKind::Palaiologos => Palaiologos::MNEMONICS,
+        }
+    }
+
+    /// The overloads of opcodes, which this dialect supports.
+    pub fn overloads(self) -> Vec<(Opcode, Overload)> {
+        match self {
+            DialectKind::Burghard => Burghard::make_config().overloads,
+            DialectKind::Palaiologos => Palaiologos::make_config().overloads,
+        }
+    }
+
+    /// Parses the name of a dialect, as in settings and modelines.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "burghard" => Some(DialectKind::Burghard),
+            "palaiologos" => Some(DialectKind::Palaiologos),
+            _ => None,
+        }
+    }
+}
+
+impl Dialects {
+    /// Constructs the dialects with no settings.
+    pub fn new() -> Self {
+        Dialects {
+            burghard: Burghard::new(),
+            palaiologos: Palaiologos::new(),
+            patterns: Vec::new(),
+        }
+    }
+
+    /// Applies the `omniwsa` settings object. Returns an error message for each
+    /// invalid entry, which is skipped.
+    pub fn configure(&mut self, settings: &Value) -> Vec<String> {
+        let mut errors = Vec::new();
+        self.patterns.clear();
+        let Some(dialects) = settings.get("dialects") else {
+            return errors;
+        };
+        let Some(dialects) = dialects.as_object() else {
+            errors.push("omniwsa.dialects: expected an object".to_owned());
+            return errors;
+        };
+        for (glob, dialect) in dialects {
+            let Some(dialect) = dialect.as_str().and_then(DialectKind::from_name) else {
+                errors.push(format!(
+                    "omniwsa.dialects: unknown dialect {dialect} for {glob:?}"
+                ));
+                continue;
+            };
+            match Pattern::new(glob) {
+                Ok(pattern) => self.patterns.push((pattern, dialect)),
+                Err(err) => errors.push(format!("omniwsa.dialects: {glob:?}: {err}")),
+            }
+        }
+        // Settings objects are unordered, so prefer the longest pattern.
+        (self.patterns).sort_by_key(|(pattern, _)| std::cmp::Reverse(pattern.as_str().len()));
+        errors
+    }
+
+    /// Chooses the dialect of a document. A modeline on the first line takes
+    /// precedence over the `omniwsa.dialects` setting, then the dialect is
+    /// detected from the text.
+    pub fn select(&self, uri: &Uri, text: &str) -> DialectKind {
+        modeline(text)
+            .or_else(|| self.configured(uri))
+            .unwrap_or_else(|| self.detect(text))
+    }
+
+    /// Gets the dialect of a document from the `omniwsa.dialects` setting.
+    pub fn configured(&self, uri: &Uri) -> Option<DialectKind> {
+        let path = uri.path().as_estr().decode().into_string().ok()?;
+        self.match_path(&path)
+    }
+
+    /// Lexes a document in the dialect.
+    pub fn lex<'s>(&self, dialect: DialectKind, src: &'s [u8]) -> Vec<Token<'s>> {
+        match dialect {
+            DialectKind::Burghard => self.burghard.lex(src),
+            DialectKind::Palaiologos => self.palaiologos.lex(src),
+        }
+    }
+
+    /// Parses a document in the dialect.
+    pub fn parse<'s>(&self, dialect: DialectKind, src: &'s [u8]) -> Cst<'s> {
+        match dialect {
+            DialectKind::Burghard => self.burghard.parse(src),
+            DialectKind::Palaiologos => self.palaiologos.parse(src),
+        }
+    }
+
+    /// Gets the opcodes a mnemonic can parse as in the dialect.
+    pub fn opcodes(&self, dialect: DialectKind, mnemonic: &[u8]) -> &'static [Opcode] {
+        let mnemonics = match dialect {
+            DialectKind::Burghard => self.burghard.mnemonics(),
+            DialectKind::Palaiologos => self.palaiologos.mnemonics(),
+        };
+        mnemonics.get_opcodes(mnemonic).unwrap_or_default()
+    }
+
+    /// Gets the integer syntax of the dialect.
+    pub fn integers(&self, dialect: DialectKind) -> &IntegerSyntax {
+        match dialect {
+            DialectKind::Burghard => self.burghard.integers(),
+            DialectKind::Palaiologos => self.palaiologos.integers(),
+        }
+    }
+
+    /// Matches a path against the `omniwsa.dialects` setting. Patterns without
+    /// a slash match the file name, like `files.associations` in VS Code.
+    fn match_path(&self, path: &str) -> Option<DialectKind> {
+        let file_name = path.rsplit('/').next().unwrap_or(path);
+        let options = MatchOptions {
+            require_literal_separator: true,
+            ..MatchOptions::new()
+        };
+        (self.patterns.iter())
+            .find(|(pattern, _)| {
+                if pattern.as_str().contains('/') {
+                    pattern.matches_with(path, options)
+                } else {
+                    pattern.matches_with(file_name, options)
+                }
+            })
+            .map(|&(_, dialect)| dialect)
+    }
+
+    /// Detects the dialect, which parses the document with the fewest
+    /// erroneous instructions. Ties prefer Burghard.
+    fn detect(&self, text: &str) -> DialectKind {
+        (DialectKind::ALL.into_iter())
+            .min_by_key(|&dialect| count_errors(&self.parse(dialect, text.as_bytes())))
+            .unwrap()
+    }
+}
+
+/// Parses a modeline like `; omniwsa: dialect=burghard` in the first line.
+pub fn modeline(text: &str) -> Option<DialectKind> {
+    let line = text.lines().next()?;
+    let (_, rest) = line.split_once("omniwsa:")?;
+    rest.split_whitespace()
+        .find_map(|setting| setting.strip_prefix("dialect="))
+        .and_then(DialectKind::from_name)
+}
+
+/// Counts the instructions with syntax errors.
+fn count_errors(cst: &Cst<'_>) -> usize {
+    match cst {
+        Cst::Inst(inst) => inst.has_error() as usize,
+        Cst::Block { nodes } => nodes.iter().map(count_errors).sum(),
+        Cst::OptionBlock(block) => {
+            let options = (block.options.iter())
+                .map(|(inst, nodes)| {
+                    inst.has_error() as usize + nodes.iter().map(count_errors).sum::<usize>()
+                })
+                .sum::<usize>();
+            options + block.end.as_ref().is_none_or(|end| end.has_error()) as usize
+        }
+    }
+}
+
+impl Default for Dialects {
+    fn default() -> Self {
+        Dialects::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use lsp_types::Uri;
+    use serde_json::json;
+
+    use crate::dialect::{DialectKind, Dialects};
+
+    #[test]
+    fn select() {
+        let mut dialects = Dialects::new();
+        let errors = dialects.configure(&json!({
+            "dialects": {
+                "*.pal": "palaiologos",
+                "src/*.pal": "burghard",
+                "*.x": "unknown",
+                "mé.wsa": "palaiologos",
+            },
+        }));
+        assert_eq!(errors.len(), 1);
+        let uri = |s: &str| Uri::from_str(s).unwrap();
+
+        let pal = "@loop\njmp %loop\n";
+        let burghard = "label loop\njmp loop\n";
+        assert_eq!(
+            dialects.select(&uri("file:///a.wsa"), pal),
+            DialectKind::Palaiologos,
+        );
+        assert_eq!(
+            dialects.select(&uri("file:///a.wsa"), burghard),
+            DialectKind::Burghard,
+        );
+        assert_eq!(
+            dialects.select(&uri("file:///a.pal"), burghard),
+            DialectKind::Palaiologos,
+        );
+        // The longest pattern takes precedence and patterns with a slash match
+        // the whole path.
+        assert_eq!(
+            dialects.select(&uri("file:///src/a.pal"), pal),
+            DialectKind::Palaiologos,
+        );
+        assert_eq!(
+            dialects.select(&uri("file:///x/src/a.pal"), pal),
+            DialectKind::Palaiologos,
+        );
+        assert_eq!(
+            dialects.select(&uri("src/a.pal"), pal),
+            DialectKind::Burghard,
+        );
+        // Paths are percent-decoded before matching.
+        assert_eq!(
+            dialects.select(&uri("file:///dir/m%C3%A9.wsa"), burghard),
+            DialectKind::Palaiologos,
+        );
+        // A modeline takes precedence over the settings.
+        assert_eq!(
+            dialects.select(&uri("file:///a.pal"), "; omniwsa: dialect=Burghard\n"),
+            DialectKind::Burghard,
+        );
+    }
+}
@@ -0,0 +1,388 @@
+//! In-memory store of the text documents open in the client.
+
+use std::{cell::OnceCell, collections::HashMap, fs, ops::Range as OffsetRange};
+
+use lsp_types::{
+    DidChangeTextDocumentParams, DidCloseTextDocumentParams, DidOpenTextDocumentParams,
+    InitializeParams, Position, PositionEncodingKind, Range, TextDocumentContentChangeEvent, Uri,
+};
+
+use crate::{
+    dialect::{self, DialectKind, Dialects},
+    symbols::Symbols,
+};
+
+/// The text documents open in the client, which are synchronized by
+/// notifications, so that requests see unsaved edits.
+#[derive(Debug)]
+pub struct Documents {
+    docs: HashMap<Uri, Document>,
+    encoding: PositionEncoding,
+}
+
+/// An open text document.
+#[derive(Clone, Debug)]
+pub struct Document {
+    text: String,
+    version: i32,
+    encoding: PositionEncoding,
+    dialect: DialectKind,
+    /// The byte offset of the start of each line.
+    line_starts: Vec<usize>,
+    /// The symbols in this version of the document, which are collected on
+    /// first use.
+    symbols: OnceCell<Symbols>,
+}
+
+/// The units of the character offsets in positions.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PositionEncoding {
+    /// UTF-8 code units, i.e., bytes.
+    Utf8,
+    /// UTF-16 code units, which the protocol requires clients to support.
+    Utf16,
+}
+
+impl Documents {
+    /// Constructs an empty store.
+    pub fn new(encoding: PositionEncoding) -> Self {
+        Documents {
+            docs: HashMap::new(),
+            encoding,
+        }
+    }
+
+    /// Handles `textDocument/didOpen`.
+    pub fn open(&mut self, params: DidOpenTextDocumentParams, dialects: &Dialects) {
+        let doc = params.text_document;
+        let dialect = dialects.select(&doc.uri, &doc.text);
+        let document = Document::new(doc.text, doc.version, self.encoding, dialect);
+        self.docs.insert(doc.uri, document);
+    }
+
+    /// Handles `textDocument/didChange`. Returns `false` if the document is
+    /// not open. A dialect from a modeline or the settings is kept, unless the
+    /// change edits the modeline, and a detected dialect is detected again.
+    pub fn change(&mut self, params: DidChangeTextDocumentParams, dialects: &Dialects) -> bool {
+        let uri = &params.text_document.uri;
+        let Some(document) = self.docs.get_mut(uri) else {
+            return false;
+        };
+        let modeline = dialect::modeline(&document.text);
+        for change in params.content_changes {
+            document.apply(change);
+        }
+        document.version = params.text_document.version;
+        let new_modeline = dialect::modeline(&document.text);
+        let detected = new_modeline.is_none() && dialects.configured(uri).is_none();
+        if new_modeline != modeline || detected {
+            document.set_dialect(dialects.select(uri, &document.text));
+        }
+        true
+    }
+
+    /// Chooses the dialect of every document again, after the settings
+    /// changed.
+    pub fn reselect_dialects(&mut self, dialects: &Dialects) {
+        for (uri, document) in &mut self.docs {
+            document.set_dialect(dialects.select(uri, &document.text));
+        }
+    }
+
+    /// Handles `textDocument/didClose`.
+    pub fn close(&mut self, params: DidCloseTextDocumentParams) {
+        self.docs.remove(&params.text_document.uri);
+    }
+
+    /// Iterates the open documents.
+    pub fn iter(&self) -> impl Iterator<Item = (&Uri, &Document)> {
+        self.docs.iter()
+    }
+
+    /// Gets an open document.
+    pub fn get(&self, uri: &Uri) -> Option<&Document> {
+        self.docs.get(uri)
+    }
+
+    /// Reads a document, which is not open, from disk. Only `file:` URIs are
+    /// supported.
+    pub fn load(&self, uri: &Uri, dialects: &Dialects) -> Option<Document> {
+        if !uri.scheme()?.as_str().eq_ignore_ascii_case("file") {
+            return None;
+        }
+        let path = uri.path().as_estr().decode().into_string().ok()?;
+        let text = fs::read_to_string(&*path).ok()?;
+        let dialect = dialects.select(uri, &text);
+        Some(Document::new(text, 0, self.encoding, dialect))
+    }
+}
+
+impl Document {
+    /// Constructs a document with its full text.
+    pub fn new(
+        text: String,
+        version: i32,
+        encoding: PositionEncoding,
+        dialect: DialectKind,
+    ) -> Self {
+        let mut doc = Document {
+            text,
+            version,
+            encoding,
+            dialect,
+            line_starts: Vec::new(),
+            symbols: OnceCell::new(),
+        };
+        doc.index_lines();
+        doc
+    }
+
+    /// Gets the text of this document.
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    /// Gets the version of this document, which increases with each change.
+    pub fn version(&self) -> i32 {
+        self.version
+    }
+
+    /// Gets the dialect chosen for this document.
+    pub fn dialect(&self) -> DialectKind {
+        self.dialect
+    }
+
+    /// Gets the symbols in this document, which are collected once per
+    /// version.
+    pub fn symbols(&self, dialects: &Dialects) -> &Symbols {
+        self.symbols.get_or_init(|| {
+            let cst = dialects.parse(self.dialect, self.text.as_bytes());
+            Symbols::new(&cst)
+        })
+    }
+
+    /// Converts a position to a byte offset. Positions past the end of a line
+    /// are clamped to the end of that line and positions past the last line
+    /// are clamped to the end of the document.
+    pub fn offset(&self, pos: Position) -> usize {
+        let Some(&start) = self.line_starts.get(pos.line as usize) else {
+            return self.text.len();
+        };
+        let mut units = 0;
+        for (i, ch) in self.text[start..].char_indices() {
+            if units >= pos.character || ch == '\n' || ch == '\r' {
+                return start + i;
+            }
+            units += match self.encoding {
+                PositionEncoding::Utf8 => ch.len_utf8(),
+                PositionEncoding::Utf16 => ch.len_utf16(),
+            } as u32;
+        }
+        self.text.len()
+    }
+
+    /// Converts a byte offset to a position. Offsets past the end or within a
+    /// char are clamped to the previous char boundary.
+    pub fn position(&self, offset: usize) -> Position {
+        let mut offset = offset.min(self.text.len());
+        while !self.text.is_char_boundary(offset) {
+            offset -= 1;
+        }
+        let line = self.line_starts.partition_point(|&start| start <= offset) - 1;
+        let prefix = &self.text[self.line_starts[line]..offset];
+        let character = match self.encoding {
+            PositionEncoding::Utf8 => prefix.len(),
+            PositionEncoding::Utf16 => prefix.chars().map(char::len_utf16).sum(),
+        };
+        Position::new(line as u32, character as u32)
+    }
+
+    /// Converts a byte range to a range of positions.
+    pub fn range(&self, range: OffsetRange<usize>) -> Range {
+        Range::new(self.position(range.start), self.position(range.end))
+    }
+
+    /// Applies an incremental or full change.
+    fn apply(&mut self, change: TextDocumentContentChangeEvent) {
+        match change.range {
+            Some(range) => {
+                let start = self.offset(range.start);
+                let end = self.offset(range.end).max(start);
+                self.text.replace_range(start..end, &change.text);
+            }
+            None => self.text = change.text,
+        }
+        self.index_lines();
+        self.symbols = OnceCell::new();
+    }
+
+    fn set_dialect(&mut self, dialect: DialectKind) {
+        if dialect != self.dialect {
+            self.dialect = dialect;
+            self.symbols = OnceCell::new();
+        }
+    }
+
+    fn index_lines(&mut self) {
+        self.line_starts.clear();
+        self.line_starts.push(0);
+        (self.line_starts).extend(self.text.match_indices('\n').map(|(i, _)| i + 1));
+    }
+}
+
+impl PositionEncoding {
+    /// Chooses UTF-8 if the client supports it, otherwise the default of
+    /// UTF-16.
+    pub fn negotiate(params: &InitializeParams) -> Self {
+        let encodings = (params.capabilities.general.as_ref())
+            .and_then(|general| general.position_encodings.as_ref());
+        if encodings.is_some_and(|encodings| encodings.contains(&PositionEncodingKind::UTF8)) {
+            PositionEncoding::Utf8
+        } else {
+            PositionEncoding::Utf16
+        }
+    }
+
+    /// Gets the protocol kind of this encoding.
+    pub fn kind(self) -> PositionEncodingKind {
+        match self {
+            PositionEncoding::Utf8 => PositionEncodingKind::UTF8,
+            PositionEncoding::Utf16 => PositionEncodingKind::UTF16,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{ptr, str::FromStr};
+
+    use lsp_types::{
+        DidChangeTextDocumentParams, DidOpenTextDocumentParams, Position, Range,
+        TextDocumentContentChangeEvent, TextDocumentItem, Uri, VersionedTextDocumentIdentifier,
+    };
+    use serde_json::json;
+
+    use crate::{
+        dialect::{DialectKind, Dialects},
+        documents::{Document, Documents, PositionEncoding},
+    };
+
+    fn doc(text: &str, encoding: PositionEncoding) -> Document {
+        Document::new(text.to_owned(), 0, encoding, DialectKind::Burghard)
+    }
+
+    fn change(
+        range: Option<((u32, u32), (u32, u32))>,
+        text: &str,
+    ) -> TextDocumentContentChangeEvent {
+        TextDocumentContentChangeEvent {
+            range: range.map(|((l0, c0), (l1, c1))| {
+                Range::new(Position::new(l0, c0), Position::new(l1, c1))
+            }),
+            range_length: None,
+            text: text.to_owned(),
+        }
+    }
+
+    #[test]
+    fn offset_position() {
+        // `草` is 3 bytes and 1 UTF-16 unit; `😀` is 4 bytes and 2 UTF-16 units.
+        let text = "a草\n😀b\r\nc";
+        let utf8 = doc(text, PositionEncoding::Utf8);
+        let utf16 = doc(text, PositionEncoding::Utf16);
+
+        assert_eq!(utf8.position(9), Position::new(1, 4));
+        assert_eq!(utf16.position(9), Position::new(1, 2));
+        assert_eq!(utf8.offset(Position::new(1, 4)), 9);
+        assert_eq!(utf16.offset(Position::new(1, 2)), 9);
+        assert_eq!(utf16.position(12), Position::new(2, 0));
+        assert_eq!(utf16.position(text.len()), Position::new(2, 1));
+
+        // Offsets within a char are clamped to its start.
+        assert_eq!(utf16.position(2), Position::new(0, 1));
+        // Positions past the end of a line stop before its line terminator.
+        assert_eq!(utf8.offset(Position::new(0, 100)), 4);
+        assert_eq!(utf16.offset(Position::new(1, 100)), 10);
+        assert_eq!(utf16.offset(Position::new(5, 0)), text.len());
+    }
+
+    #[test]
+    fn apply() {
+        let mut utf16 = doc("push 1\nend\n", PositionEncoding::Utf16);
+        utf16.apply(change(Some(((0, 5), (0, 6))), "\"草😀\""));
+        assert_eq!(utf16.text(), "push \"草😀\"\nend\n");
+        utf16.apply(change(Some(((0, 10), (1, 3))), " ; x\ndup"));
+        assert_eq!(utf16.text(), "push \"草😀\" ; x\ndup\n");
+        assert_eq!(utf16.position(utf16.text().len()), Position::new(2, 0));
+
+        let mut utf8 = doc("push \"草\"\n", PositionEncoding::Utf8);
+        utf8.apply(change(Some(((0, 10), (0, 10))), " ; x"));
+        assert_eq!(utf8.text(), "push \"草\" ; x\n");
+        utf8.apply(change(Some(((0, 5), (1, 0))), ""));
+        assert_eq!(utf8.text(), "push ");
+        utf8.apply(change(None, "end\nend"));
+        assert_eq!(utf8.text(), "end\nend");
+        assert_eq!(utf8.position(7), Position::new(1, 3));
+    }
+
+    #[test]
+    fn keep_dialect() {
+        let mut dialects = Dialects::new();
+        dialects.configure(&json!({ "dialects": { "*.pal": "palaiologos" } }));
+        let mut documents = Documents::new(PositionEncoding::Utf16);
+        let open = |documents: &mut Documents, uri: &Uri, text: &str| {
+            let text_document = TextDocumentItem::new(uri.clone(), "wsa".into(), 1, text.into());
+            documents.open(DidOpenTextDocumentParams { text_document }, &dialects);
+            documents.get(uri).unwrap().dialect()
+        };
+        let edit = |documents: &mut Documents, uri: &Uri, text: &str| {
+            let version = documents.get(uri).unwrap().version() + 1;
+            let params = DidChangeTextDocumentParams {
+                text_document: VersionedTextDocumentIdentifier::new(uri.clone(), version),
+                content_changes: vec![change(None, text)],
+            };
+            assert!(documents.change(params, &dialects));
+            documents.get(uri).unwrap().dialect()
+        };
+
+        // A detected dialect is detected again on each change.
+        let uri = Uri::from_str("file:///a.wsa").unwrap();
+        assert_eq!(open(&mut documents, &uri, "@a\n"), DialectKind::Palaiologos);
+        let doc = documents.get(&uri).unwrap();
+        assert!(ptr::eq(doc.symbols(&dialects), doc.symbols(&dialects)));
+        assert_eq!(doc.symbols(&dialects).symbols.len(), 1);
+        assert_eq!(
+            edit(&mut documents, &uri, "label a\njump a\n"),
+            DialectKind::Burghard,
+        );
+        assert_eq!(
+            edit(&mut documents, &uri, "@a\njmp %a\n"),
+            DialectKind::Palaiologos,
+        );
+        let doc = documents.get(&uri).unwrap();
+        assert_eq!(doc.symbols(&dialects).symbols.len(), 2);
+
+        // A dialect from a modeline is kept until the modeline changes.
+        let modeline = "; omniwsa: dialect=burghard\n";
+        assert_eq!(
+            edit(&mut documents, &uri, &format!("{modeline}label a\n")),
+            DialectKind::Burghard,
+        );
+        assert_eq!(
+            edit(&mut documents, &uri, &format!("{modeline}@a\njmp %a\n")),
+            DialectKind::Burghard,
+        );
+        assert_eq!(
+            edit(&mut documents, &uri, "@a\njmp %a\n"),
+            DialectKind::Palaiologos,
+        );
+
+        // A dialect from the settings is kept.
+        let uri = Uri::from_str("file:///a.pal").unwrap();
+        assert_eq!(open(&mut documents, &uri, "@a\n"), DialectKind::Palaiologos);
+        assert_eq!(
+            edit(&mut documents, &uri, "label a\njump a\n"),
+            DialectKind::Palaiologos,
+        );
+    }
+}
@@ -0,0 +1,482 @@
+//! Hover information for mnemonics and integer literals.
+
+use std::{
+    collections::{HashMap, HashSet},
+    convert::Infallible,
+    fmt::Write,
+    ops::Range,
+    ptr, slice,
+};
+
+use lsp_types::{Hover, HoverContents, MarkupContent, MarkupKind};
+use omniwsa::{
+    codegen::{CodegenConfig, Inst as WsInst, InstWrite, IntegerBits, LabelMap, Token as WsToken},
+    syntax::{Cst, HasError, Inst, Opcode, Pretty},
+    tokens::{
+        GroupError, Token,
+        integer::{Base, IntegerToken},
+    },
+};
+
+use crate::{
+    diagnostics::integer_error_message,
+    dialect::{DialectKind, Dialects},
+    documents::Document,
+    symbols::Symbols,
+};
+
+// TODO:
+// - Hover labels and variables with their definitions.
+// - Generate code for instructions with variable arguments, once codegen
+//   supports them.
+
+/// Computes the hover for the mnemonic or integer literal at a byte offset.
+pub fn hover(dialects: &Dialects, doc: &Document, offset: usize) -> Option<Hover> {
+    let dialect = doc.dialect();
+    let cst = dialects.parse(dialect, doc.text().as_bytes());
+    let mut finder = Finder {
+        target: offset,
+        offset: 0,
+        buf: Vec::new(),
+        found: None,
+    };
+    finder.cst(&cst);
+    let (inst, index, tok, range) = finder.found?;
+    let value = match tok {
+        Token::Integer(int) => integer_hover(int, dialects, dialect),
+        Token::Mnemonic(_) if index == 0 => mnemonic_hover(&cst, inst),
+        _ => return None,
+    };
+    Some(Hover {
+        contents: HoverContents::Markup(MarkupContent {
+            kind: MarkupKind::Markdown,
+            value,
+        }),
+        range: Some(doc.range(range)),
+    })
+}
+
+/// Describes a mnemonic with the documentation of its opcode and the
+/// Whitespace it generates.
+fn mnemonic_hover(cst: &Cst<'_>, inst: &Inst<'_>) -> String {
+    let mut s = format!("`{}`", inst.opcode.name());
+    if let Some(overload) = inst.overload {
+        write!(s, " with overload `{}`", overload.name()).unwrap();
+    }
+    s.push_str("\n\n");
+    for line in inst.opcode.doc() {
+        s.push_str(line.strip_prefix(' ').unwrap_or(line));
+        s.push('\n');
+    }
+
+    // Labels can only be numbered, when all options are well-formed. The
+    // index of the instruction identifies its internal labels.
+    let mut labels = LabelMap::default();
+    let mut index = None;
+    if !cst.has_error() {
+        let options = Symbols::new(cst).options;
+        let options = options
+            .iter()
+            .map(|option| &option[..])
+            .collect::<HashSet<_>>();
+        labels = LabelMap::new(cst, &options, &CodegenConfig::default());
+        let Ok(()) = cst.for_each_inst::<Infallible>(&options, &mut |i, other| {
+            if ptr::eq(inst, other) {
+                index = Some(i);
+            }
+            Ok(())
+        });
+    }
+    if !generates(inst, index, &labels) {
+        return s;
+    }
+    // `rep` repeats up to 2^31 times, so it is summarized instead of generated.
+    if inst.opcode == Opcode::PalaiologosRep {
+        let Ok((repeated, count)) = inst.rep_args() else {
+            return s;
+        };
+        write!(s, "\n**Expansion:** `{} × {count}`\n", inst_text(&repeated)).unwrap();
+        let effect = stack_effect(slice::from_ref(&repeated));
+        let (pops, pushes) = repeat_effect(effect, count);
+        write!(s, "\n**Stack effect:** pops {pops}, pushes {pushes}\n").unwrap();
+        write!(s, "\n**Whitespace:** `{}` × {count}\n", stl(repeated)).unwrap();
+        return s;
+    }
+    let mut recorder = Recorder {
+        insts: Vec::new(),
+        truncated: false,
+    };
+    let index = index.unwrap_or_default();
+    let Ok(()) = inst.codegen(&mut recorder, index, &labels, &CodegenConfig::default());
+    let insts = recorder.insts;
+    let ellipsis = if recorder.truncated { " …" } else { "" };
+
+    if insts.len() != 1 || inst.overload.is_some() || inst.opcode.expansion().is_some() {
+        let expansion = insts.iter().map(inst_text).collect::<Vec<_>>();
+        write!(
+            s,
+            "\n**Expansion:** `{}{ellipsis}`\n",
+            expansion.join(" / ")
+        )
+        .unwrap();
+    }
+    if !recorder.truncated {
+        let (pops, pushes) = stack_effect(&insts);
+        write!(s, "\n**Stack effect:** pops {pops}, pushes {pushes}\n").unwrap();
+    }
+    let encoding = insts
+        .iter()
+        .map(|inst| stl(inst.clone()))
+        .collect::<Vec<_>>();
+    write!(s, "\n**Whitespace:** `{}{ellipsis}`\n", encoding.join(" ")).unwrap();
+    s
+}
+
+/// Describes an integer literal with its value and encoding.
+fn integer_hover(int: &IntegerToken<'_>, dialects: &Dialects, dialect: DialectKind) -> String {
+    let base = match int.base_style.base() {
+        Base::Binary => "binary",
+        Base::Octal => "octal",
+        Base::Decimal => "decimal",
+        Base::Hexadecimal => "hexadecimal",
+    };
+    let mut s = format!("`{}` ({base})\n", int.value);
+    write!(s, "\n**Binary:** `{}`\n", int.value.to_string_radix(2)).unwrap();
+    let mut encoding = stl(WsInst::Push(IntegerBits::from(&int.value)));
+    encoding.replace_range(..2, "");
+    write!(s, "\n**Whitespace:** `{encoding}`\n").unwrap();
+    if !int.errors.is_empty() {
+        s.push('\n');
+        let integers = dialects.integers(dialect);
+        for err in int.errors {
+            let message = integer_error_message(err, dialect.name(), integers);
+            writeln!(s, "- **Error:** {message}").unwrap();
+        }
+    }
+    s
+}
+
+/// Returns whether code can be generated for the instruction, which requires
+/// it to be free of errors and its labels to be numbered. Its index is `None`,
+/// when it is not enabled.
+fn generates(inst: &Inst<'_>, index: Option<usize>, labels: &LabelMap<'_>) -> bool {
+    if inst.has_error() {
+        return false;
+    }
+    match inst.opcode {
+        Opcode::BurghardInclude
+        | Opcode::RespaceInclude
+        | Opcode::VolivaInclude
+        | Opcode::WhitelipsInclude
+        | Opcode::DefineOption
+        | Opcode::IfOption
+        | Opcode::ElseIfOption
+        | Opcode::ElseOption
+        | Opcode::EndOption
+        | Opcode::BurghardValueInteger
+        | Opcode::BurghardValueString
+        | Opcode::VolivaValueInteger
+        | Opcode::VolivaValueString
+        | Opcode::Invalid => return false,
+        Opcode::PalaiologosRep => return inst.rep_args().is_ok(),
+        _ => {}
+    }
+    let args_resolved = (0..inst.len_args()).all(|i| match inst.arg(i).peel_groups() {
+        Token::Label(label) => labels.get(&label.label).is_some(),
+        Token::Variable(_) => false,
+        _ => true,
+    });
+    let internal_resolved = (0..inst.opcode.internal_labels())
+        .all(|i| index.is_some_and(|index| labels.get_internal(index, i).is_some()));
+    args_resolved && internal_resolved
+}
+
+/// Computes the stack effect of a sequence of generated instructions, as the
+/// number of values it pops and pushes. Branches within the sequence are
+/// followed and the effect is measured where it falls through.
+fn stack_effect(insts: &[WsInst<'_>]) -> (usize, usize) {
+    let internal = (insts.iter())
+        .filter_map(|inst| match inst {
+            WsInst::Label(l) => Some(l.value()),
+            _ => None,
+        })
+        .collect::<HashSet<_>>();
+    let mut at_label = HashMap::new();
+    let mut depth = Some(0isize);
+    let mut exit = None;
+    let mut min = 0;
+    for inst in insts {
+        if let WsInst::Label(l) = inst {
+            depth = depth.or_else(|| at_label.get(l.value()).copied());
+        }
+        let Some(d) = depth else {
+            continue;
+        };
+        let (pops, pushes) = inst_effect(inst);
+        min = min.min(d - pops);
+        let d = d - pops + pushes;
+        depth = Some(d);
+        match inst {
+            WsInst::Jz(l) | WsInst::Jn(l) | WsInst::Jmp(l) if internal.contains(l.value()) => {
+                at_label.entry(l.value()).or_insert(d);
+            }
+            _ => {}
+        }
+        if matches!(inst, WsInst::Jmp(_) | WsInst::Ret | WsInst::End) {
+            if !matches!(inst, WsInst::Jmp(l) if internal.contains(l.value())) {
+                exit.get_or_insert(d);
+            }
+            depth = None;
+        }
+    }
+    let end = depth.or(exit).unwrap_or(0);
+    ((-min) as usize, (end - min) as usize)
+}
+
+/// Computes the stack effect of repeating a sequence with the effect `count`
+/// times. The stack is lowest in the first repetition, when the sequence grows
+/// it, and in the last otherwise.
+fn repeat_effect((pops, pushes): (usize, usize), count: u32) -> (usize, usize) {
+    if count == 0 {
+        return (0, 0);
+    }
+    let (pops, count) = (pops as i64, count as i64);
+    let net = pushes as i64 - pops;
+    let min = -pops + net.min(0) * (count - 1);
+    ((-min) as usize, (net * count - min) as usize)
+}
+
+/// The number of values an instruction pops and pushes, counting those it
+/// reads below the top as popped and pushed again.
+fn inst_effect(inst: &WsInst<'_>) -> (isize, isize) {
+    let n = |value: &omniwsa::tokens::integer::Integer| value.to_isize().unwrap_or(0).max(0);
+    match inst {
+        WsInst::Push(_) => (0, 1),
+        WsInst::Dup => (1, 2),
+        WsInst::Copy(n_) => (n(n_.value()) + 1, n(n_.value()) + 2),
+        WsInst::Swap => (2, 2),
+        WsInst::Drop => (1, 0),
+        WsInst::Slide(n_) => (n(n_.value()) + 1, 1),
+        WsInst::Add | WsInst::Sub | WsInst::Mul | WsInst::Div | WsInst::Mod => (2, 1),
+        WsInst::Store => (2, 0),
+        WsInst::Retrieve => (1, 1),
+        WsInst::Jz(_) | WsInst::Jn(_) => (1, 0),
+        WsInst::Printc | WsInst::Printi | WsInst::Readc | WsInst::Readi => (1, 0),
+        WsInst::VolivaOr | WsInst::VolivaAnd => (2, 1),
+        WsInst::VolivaNot => (1, 1),
+        WsInst::Label(_)
+        | WsInst::Call(_)
+        | WsInst::Jmp(_)
+        | WsInst::Ret
+        | WsInst::End
+        | WsInst::BurghardPrintStack
+        | WsInst::BurghardPrintHeap
+        | WsInst::VolivaBreakpoint => (0, 0),
+    }
+}
+
+/// Formats a generated instruction in Whitespace assembly.
+fn inst_text(inst: &WsInst<'_>) -> String {
+    match inst.arg() {
+        Some(arg) => format!("{} {arg}", inst.name()),
+        None => inst.name().to_owned(),
+    }
+}
+
+/// Encodes a generated instruction as `S`, `T`, and `L` letters.
+fn stl(inst: WsInst<'_>) -> String {
+    let mut toks = Vec::new();
+    let Ok(()) = toks.write_inst(inst);
+    (toks.iter())
+        .map(|tok| match tok {
+            WsToken::S => 'S',
+            WsToken::T => 'T',
+            WsToken::L => 'L',
+        })
+        .collect()
+}
+
+/// Finds the word at an offset in a CST, tracking the offset of each token by
+/// the text it pretty-prints as.
+struct Finder<'c, 's> {
+    target: usize,
+    offset: usize,
+    buf: Vec<u8>,
+    found: Option<(&'c Inst<'s>, usize, &'c Token<'s>, Range<usize>)>,
+}
+
+impl<'c, 's> Finder<'c, 's> {
+    fn cst(&mut self, cst: &'c Cst<'s>) {
+        match cst {
+            Cst::Inst(inst) => self.inst(inst),
+            Cst::Block { nodes } => nodes.iter().for_each(|node| self.cst(node)),
+            Cst::OptionBlock(block) => {
+                for (option, nodes) in &block.options {
+                    self.inst(option);
+                    nodes.iter().for_each(|node| self.cst(node));
+                }
+                if let Some(end) = &block.end {
+                    self.inst(end);
+                }
+            }
+        }
+    }
+
+    fn inst(&mut self, inst: &'c Inst<'s>) {
+        self.skip(inst.words.space_before.tokens());
+        for (i, (word, space)) in inst.words.words.iter().enumerate() {
+            self.word(inst, i, word);
+            self.skip(space.tokens());
+        }
+    }
+
+    fn word(&mut self, inst: &'c Inst<'s>, i: usize, tok: &'c Token<'s>) {
+        let start = self.offset;
+        match tok {
+            Token::Group(group) => {
+                self.offset += group.delim.open().len();
+                self.word(inst, i, &group.inner);
+                if !group.errors.contains(GroupError::Unterminated) {
+                    self.offset += group.delim.close().len();
+                }
+                return;
+            }
+            Token::Splice(splice) => {
+                self.skip(&splice.tokens);
+                self.check(inst, i, splice.spliced.peel_groups(), start);
+                return;
+            }
+            _ => {}
+        }
+        self.skip(std::slice::from_ref(tok));
+        self.check(inst, i, tok, start);
+    }
+
+    fn check(&mut self, inst: &'c Inst<'s>, i: usize, tok: &'c Token<'s>, start: usize) {
+        if (start..self.offset).contains(&self.target) {
+            self.found = Some((inst, i, tok, start..self.offset));
+        }
+    }
+
+    fn skip(&mut self, toks: &[Token<'_>]) {
+        for tok in toks {
+            self.buf.clear();
+            tok.pretty(&mut self.buf);
+            self.offset += self.buf.len();
+        }
+    }
+}
+
+/// The most generated instructions shown in a hover.
+const MAX_EXPANSION: usize = 64;
+
+/// Records the instructions generated for an assembly instruction, up to
+/// `MAX_EXPANSION`.
+struct Recorder {
+    insts: Vec<WsInst<'static>>,
+    /// Whether instructions past the limit were dropped.
+    truncated: bool,
+}
+
+impl InstWrite for Recorder {
+    type Error = Infallible;
+
+    fn write_inst(&mut self, inst: WsInst<'_>) -> Result<(), Self::Error> {
+        if self.insts.len() < MAX_EXPANSION {
+            self.insts.push(inst.into_owned());
+        } else {
+            self.truncated = true;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use lsp_types::{HoverContents, MarkupContent};
+    use omniwsa::{
+        codegen::{Inst as WsInst, IntegerBits, LabelBits},
+        tokens::integer::Integer,
+    };
+
+    use crate::{
+        dialect::{DialectKind, Dialects},
+        documents::{Document, PositionEncoding},
+        hover::{hover, repeat_effect, stack_effect},
+    };
+
+    /// Computes the hover text at the start of the document.
+    fn hover_text(dialect: DialectKind, text: &str) -> String {
+        let doc = Document::new(text.to_owned(), 0, PositionEncoding::Utf16, dialect);
+        match hover(&Dialects::new(), &doc, 0).unwrap().contents {
+            HoverContents::Markup(MarkupContent { value, .. }) => value,
+            contents => panic!("unexpected contents {contents:?}"),
+        }
+    }
+
+    #[test]
+    fn stack_effects() {
+        let int = |n: i32| IntegerBits::from(Integer::from(n));
+        let label = |l: i32| LabelBits::owned(Integer::from(l), 0);
+        assert_eq!(stack_effect(&[]), (0, 0));
+        assert_eq!(stack_effect(&[WsInst::Push(int(1))]), (0, 1));
+        assert_eq!(stack_effect(&[WsInst::Dup, WsInst::Mul]), (1, 1));
+        assert_eq!(stack_effect(&[WsInst::Swap, WsInst::Drop]), (2, 1));
+        assert_eq!(stack_effect(&[WsInst::Copy(int(2))]), (3, 4));
+        assert_eq!(stack_effect(&[WsInst::Slide(int(2))]), (3, 1));
+
+        // The absolute value, which branches around a negation.
+        let abs = [
+            WsInst::Dup,
+            WsInst::Jn(label(0)),
+            WsInst::Jmp(label(1)),
+            WsInst::Label(label(0)),
+            WsInst::Push(int(0)),
+            WsInst::Swap,
+            WsInst::Sub,
+            WsInst::Label(label(1)),
+        ];
+        assert_eq!(stack_effect(&abs), (1, 1));
+        // Jumps to external labels exit, and the effect is measured there.
+        let exit = [
+            WsInst::Push(int(1)),
+            WsInst::Jz(label(2)),
+            WsInst::Push(int(2)),
+            WsInst::Jmp(label(3)),
+        ];
+        assert_eq!(stack_effect(&exit), (0, 1));
+    }
+
+    #[test]
+    fn repeat_effects() {
+        assert_eq!(repeat_effect((1, 2), 0), (0, 0));
+        assert_eq!(repeat_effect((1, 2), 3), (1, 4));
+        assert_eq!(repeat_effect((1, 0), 3), (3, 0));
+        assert_eq!(repeat_effect((2, 1), 100_000_000), (100_000_001, 1));
+    }
+
+    #[test]
+    fn summarize_rep() {
+        let value = hover_text(DialectKind::Palaiologos, "rep dup 100000000\n");
+        assert!(
+            value.contains("**Expansion:** `dup × 100000000`"),
+            "{value}"
+        );
+        assert!(value.contains("pops 1, pushes 100000001"), "{value}");
+        assert!(
+            value.contains("**Whitespace:** `SLS` × 100000000"),
+            "{value}"
+        );
+
+        let value = hover_text(DialectKind::Palaiologos, "rep jmp 3\n");
+        assert!(!value.contains("**Expansion:**"), "{value}");
+    }
+
+    #[test]
+    fn truncate_expansion() {
+        let text = format!("pushs \"{}\"\n", "a".repeat(100));
+        let value = hover_text(DialectKind::Burghard, &text);
+        assert!(value.contains(" …`"), "{value}");
+        assert!(!value.contains("**Stack effect:**"), "{value}");
+    }
+}
@@ -1,30 +1,51 @@
-use std::{
-    error::Error,
-    fs,
-    io::{self, ErrorKind},
-    process::exit,
-};
+// `Uri` caches its parsed authority in a `Cell`, which does not affect its hash.
+#![allow(clippy::mutable_key_type)]
+
+use std::{error::Error, process::exit};
 
-use bstr::ByteSlice;
-use lsp_server::{Connection, Message, Request, Response};
+use lsp_server::{Connection, ErrorCode, Message, Notification, Request, RequestId, Response};
 use lsp_types::{
-    InitializeParams, SemanticToken, SemanticTokenModifier, SemanticTokenType, SemanticTokens,
-    SemanticTokensFullOptions, SemanticTokensLegend, SemanticTokensOptions, SemanticTokensParams,
-    SemanticTokensResult, SemanticTokensServerCapabilities, ServerCapabilities,
-    request::{Request as _, SemanticTokensFullRequest},
-};
-use omniwsa::{
-    dialects::{Dialect, Palaiologos},
-    tokens::{
-        GroupError, Token,
-        comment::BlockCommentError,
-        string::{CharError, StringError},
+    CompletionOptions, CompletionParams, CompletionResponse, DidChangeConfigurationParams,
+    DidChangeTextDocumentParams, DidCloseTextDocumentParams, DidOpenTextDocumentParams,
+    DocumentHighlight, DocumentHighlightKind, DocumentHighlightParams, GotoDefinitionParams,
+    GotoDefinitionResponse, HoverParams, HoverProviderCapability, InitializeParams,
+    InitializeResult, MessageType, OneOf, PrepareRenameResponse, PublishDiagnosticsParams,
+    ReferenceParams, RenameOptions, RenameParams, SemanticTokens, SemanticTokensFullOptions,
+    SemanticTokensLegend, SemanticTokensOptions, SemanticTokensParams, SemanticTokensResult,
+    SemanticTokensServerCapabilities, ServerCapabilities, ShowMessageParams,
+    TextDocumentPositionParams, TextDocumentSyncCapability, TextDocumentSyncKind,
+    TextDocumentSyncOptions, Uri,
+    notification::{
+        DidChangeConfiguration, DidChangeTextDocument, DidCloseTextDocument, DidOpenTextDocument,
+        Notification as _, PublishDiagnostics, ShowMessage,
+    },
+    request::{
+        Completion, DocumentHighlightRequest, GotoDefinition, HoverRequest, PrepareRenameRequest,
+        References, Rename, Request as _, SemanticTokensFullRequest, SemanticTokensRefresh,
     },
 };
+use serde::Serialize;
 use serde_json::{from_value as from_json, to_value as to_json};
 
+use crate::{
+    completion::completions,
+    diagnostics::diagnostics,
+    dialect::Dialects,
+    documents::{Document, Documents, PositionEncoding},
+    hover::hover,
+    semantic_tokens::{TOKEN_MODIFIERS, TOKEN_TYPES, semantic_tokens},
+    symbols::Program,
+};
+
+mod completion;
+mod diagnostics;
+mod dialect;
+mod documents;
+mod hover;
+mod semantic_tokens;
+mod symbols;
+
 // TODO:
-// - Implement text document API, instead of reading from disk.
 // - Record spans in tokens.
 // - About lazy analyses and ties between syntax and semantics:
 //   https://rust-analyzer.github.io/blog/2023/12/26/the-heart-of-a-language-server.html
@@ -42,66 +63,80 @@ fn main() {
 fn do_main() -> Result<(), Box<dyn Error + Send + Sync>> {
     let (connection, io_threads) = Connection::stdio();
 
-    let server_capabilities = to_json(&server_capabilities())?;
-    let initialize_params: InitializeParams =
-        from_json(connection.initialize(server_capabilities)?)?;
+    let (id, initialize_params) = connection.initialize_start()?;
+    let initialize_params: InitializeParams = from_json(initialize_params)?;
+    let encoding = PositionEncoding::negotiate(&initialize_params);
+    let initialize_result = InitializeResult {
+        capabilities: server_capabilities(encoding),
+        server_info: None,
+    };
+    connection.initialize_finish(id, to_json(initialize_result)?)?;
+
+    let mut dialects = Dialects::new();
+    if let Some(settings) = &initialize_params.initialization_options {
+        for err in dialects.configure(settings) {
+            show_warning(&connection, err)?;
+        }
+    }
 
-    main_loop(connection, initialize_params)?;
+    main_loop(
+        connection,
+        initialize_params,
+        Documents::new(encoding),
+        dialects,
+    )?;
     io_threads.join()?;
     Ok(())
 }
 
-fn server_capabilities() -> ServerCapabilities {
+fn server_capabilities(encoding: PositionEncoding) -> ServerCapabilities {
     ServerCapabilities {
+        position_encoding: Some(encoding.kind()),
+        text_document_sync: Some(TextDocumentSyncCapability::Options(
+            TextDocumentSyncOptions {
+                open_close: Some(true),
+                change: Some(TextDocumentSyncKind::INCREMENTAL),
+                ..Default::default()
+            },
+        )),
         semantic_tokens_provider: Some(SemanticTokensServerCapabilities::SemanticTokensOptions(
             SemanticTokensOptions {
                 work_done_progress_options: Default::default(),
                 legend: SemanticTokensLegend {
-                    token_types: vec![
-                        SemanticTokenType::VARIABLE,
-                        SemanticTokenType::FUNCTION,
-                        SemanticTokenType::KEYWORD,
-                        SemanticTokenType::COMMENT,
-                        SemanticTokenType::STRING,
-                        SemanticTokenType::NUMBER,
-                        SemanticTokenType::OPERATOR,
-                    ],
-                    token_modifiers: vec![
-                        SemanticTokenModifier::DECLARATION,
-                        SemanticTokenModifier::DEFINITION,
-                    ],
+                    token_types: TOKEN_TYPES.to_vec(),
+                    token_modifiers: TOKEN_MODIFIERS.to_vec(),
                 },
                 range: Some(false),
                 full: Some(SemanticTokensFullOptions::Bool(true)),
             },
         )),
+        completion_provider: Some(CompletionOptions {
+            trigger_characters: Some(vec!["%".to_owned(), "_".to_owned()]),
+            ..Default::default()
+        }),
+        hover_provider: Some(HoverProviderCapability::Simple(true)),
+        definition_provider: Some(OneOf::Left(true)),
+        references_provider: Some(OneOf::Left(true)),
+        document_highlight_provider: Some(OneOf::Left(true)),
+        rename_provider: Some(OneOf::Right(RenameOptions {
+            prepare_provider: Some(true),
+            work_done_progress_options: Default::default(),
+        })),
         ..Default::default()
     }
 }
 
-// The order corresponds to the index in SemanticTokensLegend::token_types.
-#[derive(Clone, Copy, Debug)]
-enum TokenType {
-    Variable,
-    Function,
-    Keyword,
-    Comment,
-    String,
-    Number,
-    Operator,
-}
-
-// The order corresponds to the index in SemanticTokensLegend::token_modifiers.
-#[derive(Clone, Copy, Debug)]
-enum TokenModifier {
-    Declaration,
-    Definition,
-}
-
 fn main_loop(
     connection: Connection,
-    _initialize_params: InitializeParams,
+    initialize_params: InitializeParams,
+    mut documents: Documents,
+    mut dialects: Dialects,
 ) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let refresh_support = (initialize_params.capabilities.workspace.as_ref())
+        .and_then(|workspace| workspace.semantic_tokens.as_ref())
+        .and_then(|semantic_tokens| semantic_tokens.refresh_support)
+        .unwrap_or(false);
+    let mut next_request_id = 0;
     for msg in &connection.receiver {
         match msg {
             Message::Request(req) => {
@@ -118,87 +153,153 @@ fn main_loop(
                         // https://github.com/rust-lang/rust-analyzer/blob/c4e040ea8dc4651569514bd8a8d8b293b49390a6/crates/rust-analyzer/src/lsp/capabilities.rs#L123
 
                         let params: SemanticTokensParams = from_json(params)?;
-                        // TODO: Implement text document API, instead of reading
-                        // from disk.
-                        let path = params.text_document.uri.as_str();
-                        let path = path.strip_prefix("file://").unwrap_or(path);
-                        let src = fs::read(path)?;
-                        let tokens = Palaiologos::new().lex(&src);
-
-                        let mut tokens_out = Vec::with_capacity(tokens.len());
-                        let (mut curr_line, mut curr_col) = (0, 0);
-                        let (mut prev_line, mut prev_col) = (0, 0);
-                        for tok in &tokens {
-                            eprintln!("{tok:?}");
-                            let ungrouped = tok.peel_groups();
-                            let ty = match ungrouped {
-                                Token::Mnemonic(_) => Some(TokenType::Keyword),
-                                Token::Integer(_) => Some(TokenType::Number),
-                                Token::String(_) | Token::Char(_) => Some(TokenType::String),
-                                Token::Variable(_) => Some(TokenType::Variable),
-                                Token::Label(_) => Some(TokenType::Function),
-                                Token::LabelColon(_) => Some(TokenType::Operator),
-                                Token::Space(_) | Token::LineTerm(_) | Token::Eof(_) => None,
-                                Token::InstSep(_) | Token::ArgSep(_) => Some(TokenType::Operator),
-                                Token::LineComment(_) | Token::BlockComment(_) => {
-                                    Some(TokenType::Comment)
-                                }
-                                Token::Word(_) => Some(TokenType::Variable),
-                                Token::Group(_) | Token::Splice(_) => panic!("not ungrouped"),
-                                Token::Error(_) => None,
-                                Token::Placeholder => panic!("placeholder"),
-                            };
-                            let modifiers = match ungrouped {
-                                Token::Label(_) => TokenModifier::Declaration as _,
-                                Token::Variable(_) => TokenModifier::Definition as _,
-                                _ => 0,
-                            };
-                            let (len, hlen, vlen) = token_len(tok);
-                            let (mut next_line, mut next_col) = (curr_line, curr_col);
-                            if vlen != 0 {
-                                next_col = 0;
-                                next_line += vlen;
-                            }
-                            next_col += hlen;
-                            if let Some(ty) = ty {
-                                let token_out = SemanticToken {
-                                    delta_line: (curr_line - prev_line) as _,
-                                    delta_start: if curr_line == prev_line {
-                                        (curr_col - prev_col) as _
-                                    } else {
-                                        curr_col as _
-                                    },
-                                    length: len as _,
-                                    token_type: ty as _,
-                                    token_modifiers_bitset: modifiers,
-                                };
-                                eprintln!("=> {token_out:?}");
-                                tokens_out.push(token_out);
-                                prev_line = curr_line;
-                                prev_col = curr_col;
-                            }
-                            curr_line = next_line;
-                            curr_col = next_col;
-                        }
-
+                        let uri = &params.text_document.uri;
+                        let Some(doc) = documents.get(uri) else {
+                            send_not_open(&connection, id, uri)?;
+                            continue;
+                        };
                         let result = Some(SemanticTokensResult::Tokens(SemanticTokens {
                             result_id: None,
-                            data: tokens_out,
+                            data: semantic_tokens(&dialects, doc),
                         }));
-                        let resp = Response {
-                            id,
-                            result: Some(to_json(&result)?),
-                            error: None,
+                        send_result(&connection, id, result)?;
+                    }
+                    Completion::METHOD => {
+                        let params: CompletionParams = from_json(params)?;
+                        let TextDocumentPositionParams {
+                            text_document,
+                            position,
+                        } = params.text_document_position;
+                        let uri = &text_document.uri;
+                        let Some(program) = Program::load(&documents, &dialects, uri) else {
+                            send_not_open(&connection, id, uri)?;
+                            continue;
                         };
-                        eprintln!("Send {resp:?}");
-                        eprintln!();
-                        connection.sender.send(Message::Response(resp))?;
+                        let offset = program.files[0].doc.offset(position);
+                        let items = completions(&program, &dialects, offset);
+                        send_result(&connection, id, Some(CompletionResponse::Array(items)))?;
+                    }
+                    HoverRequest::METHOD => {
+                        let params: HoverParams = from_json(params)?;
+                        let TextDocumentPositionParams {
+                            text_document,
+                            position,
+                        } = params.text_document_position_params;
+                        let uri = &text_document.uri;
+                        let Some(doc) = documents.get(uri) else {
+                            send_not_open(&connection, id, uri)?;
+                            continue;
+                        };
+                        let hover = hover(&dialects, doc, doc.offset(position));
+                        send_result(&connection, id, hover)?;
+                    }
+                    GotoDefinition::METHOD => {
+                        let params: GotoDefinitionParams = from_json(params)?;
+                        let TextDocumentPositionParams {
+                            text_document,
+                            position,
+                        } = params.text_document_position_params;
+                        let uri = &text_document.uri;
+                        let Some(program) = Program::load(&documents, &dialects, uri) else {
+                            send_not_open(&connection, id, uri)?;
+                            continue;
+                        };
+                        let result = program.symbol_at(position).map(|symbol| {
+                            let locations = (program.occurrences(symbol))
+                                .filter(|(_, sym)| sym.is_def)
+                                .map(|(file, sym)| file.location(sym))
+                                .collect();
+                            GotoDefinitionResponse::Array(locations)
+                        });
+                        send_result(&connection, id, result)?;
+                    }
+                    References::METHOD => {
+                        let params: ReferenceParams = from_json(params)?;
+                        let TextDocumentPositionParams {
+                            text_document,
+                            position,
+                        } = params.text_document_position;
+                        let uri = &text_document.uri;
+                        let Some(program) = Program::load(&documents, &dialects, uri) else {
+                            send_not_open(&connection, id, uri)?;
+                            continue;
+                        };
+                        let include_declaration = params.context.include_declaration;
+                        let result = program.symbol_at(position).map(|symbol| {
+                            (program.occurrences(symbol))
+                                .filter(|(_, sym)| include_declaration || !sym.is_def)
+                                .map(|(file, sym)| file.location(sym))
+                                .collect::<Vec<_>>()
+                        });
+                        send_result(&connection, id, result)?;
+                    }
+                    DocumentHighlightRequest::METHOD => {
+                        let params: DocumentHighlightParams = from_json(params)?;
+                        let TextDocumentPositionParams {
+                            text_document,
+                            position,
+                        } = params.text_document_position_params;
+                        let uri = &text_document.uri;
+                        let Some(doc) = documents.get(uri) else {
+                            send_not_open(&connection, id, uri)?;
+                            continue;
+                        };
+                        let symbols = doc.symbols(&dialects);
+                        let result = symbols.at(doc.offset(position)).map(|symbol| {
+                            (symbols.symbols.iter())
+                                .filter(|sym| sym.same(symbol))
+                                .map(|sym| DocumentHighlight {
+                                    range: doc.range(sym.range.clone()),
+                                    kind: Some(if sym.is_def {
+                                        DocumentHighlightKind::WRITE
+                                    } else {
+                                        DocumentHighlightKind::READ
+                                    }),
+                                })
+                                .collect::<Vec<_>>()
+                        });
+                        send_result(&connection, id, result)?;
+                    }
+                    PrepareRenameRequest::METHOD => {
+                        let params: TextDocumentPositionParams = from_json(params)?;
+                        let uri = &params.text_document.uri;
+                        let Some(doc) = documents.get(uri) else {
+                            send_not_open(&connection, id, uri)?;
+                            continue;
+                        };
+                        let symbols = doc.symbols(&dialects);
+                        let result = (symbols.at(doc.offset(params.position))).map(|symbol| {
+                            PrepareRenameResponse::Range(doc.range(symbol.range.clone()))
+                        });
+                        send_result(&connection, id, result)?;
+                    }
+                    Rename::METHOD => {
+                        let params: RenameParams = from_json(params)?;
+                        let TextDocumentPositionParams {
+                            text_document,
+                            position,
+                        } = params.text_document_position;
+                        let uri = &text_document.uri;
+                        let Some(program) = Program::load(&documents, &dialects, uri) else {
+                            send_not_open(&connection, id, uri)?;
+                            continue;
+                        };
+                        match program.rename(&dialects, position, &params.new_name) {
+                            Ok(edit) => send_result(&connection, id, edit)?,
+                            Err(message) => {
+                                let resp =
+                                    Response::new_err(id, ErrorCode::RequestFailed as i32, message);
+                                connection.sender.send(Message::Response(resp))?;
+                            }
+                        }
                     }
                     _ => {
-                        return Err(Box::new(io::Error::new(
-                            ErrorKind::Unsupported,
+                        let resp = Response::new_err(
+                            id,
+                            ErrorCode::MethodNotFound as i32,
                             format!("unknown method {method}"),
-                        )));
+                        );
+                        connection.sender.send(Message::Response(resp))?;
                     }
                 }
             }
@@ -207,91 +308,174 @@ fn main_loop(
             }
             Message::Notification(notif) => {
                 eprintln!("Receive {notif:?}");
+                let Notification { method, params } = notif;
+                match &*method {
+                    DidOpenTextDocument::METHOD => {
+                        let params: DidOpenTextDocumentParams = from_json(params)?;
+                        let uri = params.text_document.uri.clone();
+                        documents.open(params, &dialects);
+                        let doc = documents.get(&uri);
+                        publish_diagnostics(&connection, &uri, doc, &dialects)?;
+                    }
+                    DidChangeTextDocument::METHOD => {
+                        let params: DidChangeTextDocumentParams = from_json(params)?;
+                        let uri = params.text_document.uri.clone();
+                        if documents.change(params, &dialects) {
+                            let doc = documents.get(&uri);
+                            publish_diagnostics(&connection, &uri, doc, &dialects)?;
+                        } else {
+                            eprintln!("Change to document not open: {}", uri.as_str());
+                        }
+                    }
+                    DidCloseTextDocument::METHOD => {
+                        let params: DidCloseTextDocumentParams = from_json(params)?;
+                        let uri = params.text_document.uri.clone();
+                        documents.close(params);
+                        publish_diagnostics(&connection, &uri, None, &dialects)?;
+                    }
+                    DidChangeConfiguration::METHOD => {
+                        let params: DidChangeConfigurationParams = from_json(params)?;
+                        // Clients which synchronize a section nest it by name.
+                        let settings = params.settings.get("omniwsa").unwrap_or(&params.settings);
+                        for err in dialects.configure(settings) {
+                            show_warning(&connection, err)?;
+                        }
+                        documents.reselect_dialects(&dialects);
+                        for (uri, doc) in documents.iter() {
+                            publish_diagnostics(&connection, uri, Some(doc), &dialects)?;
+                        }
+                        if refresh_support {
+                            next_request_id += 1;
+                            let req = Request::new(
+                                RequestId::from(next_request_id),
+                                SemanticTokensRefresh::METHOD.to_owned(),
+                                (),
+                            );
+                            connection.sender.send(Message::Request(req))?;
+                        }
+                    }
+                    _ => {}
+                }
             }
         }
     }
     Ok(())
 }
 
-/// Computes the length of the token. Returns the linear, horizontal, vertical
-/// lengths in chars.
-// TODO: Record spans in tokens instead of this hack.
-fn token_len(tok: &Token<'_>) -> (usize, usize, usize) {
-    let (text, len_before, len_after): (&[u8], usize, usize) = match tok {
-        Token::Mnemonic(tok) => (&tok.mnemonic, 0, 0),
-        Token::Integer(tok) => (&tok.literal, 0, 0),
-        Token::String(tok) => (
-            &tok.literal,
-            tok.quotes.quote().len(),
-            if tok.errors.contains(StringError::Unterminated) {
-                0
-            } else {
-                tok.quotes.quote().len()
-            },
-        ),
-        Token::Char(tok) => (
-            &tok.literal,
-            tok.quotes.quote().len(),
-            if tok.errors.contains(CharError::Unterminated) {
-                0
-            } else {
-                tok.quotes.quote().len()
-            },
-        ),
-        Token::Variable(tok) => (&tok.ident, tok.style.sigil().len(), 0),
-        Token::Label(tok) => (&tok.label, tok.style.sigil().len(), 0),
-        Token::LabelColon(_) => (b":", 0, 0),
-        Token::Space(tok) => (&tok.space, 0, 0),
-        Token::LineTerm(tok) => (tok.style.as_str().as_bytes(), 0, 0),
-        Token::Eof(_) => (b"", 0, 0),
-        Token::InstSep(tok) => (tok.style.as_str().as_bytes(), 0, 0),
-        Token::ArgSep(tok) => (tok.style.as_str().as_bytes(), 0, 0),
-        Token::LineComment(tok) => (tok.text, tok.style.prefix().len(), 0),
-        Token::BlockComment(tok) => (
-            tok.text,
-            tok.style.open().len(),
-            if tok.errors.contains(BlockCommentError::Unterminated) {
-                0
-            } else {
-                tok.style.close().len()
-            },
-        ),
-        Token::Word(tok) => (&tok.word, 0, 0),
-        Token::Group(tok) => {
-            let (len, hlen, vlen) = token_len(&tok.inner);
-            let mut quotes = 0;
-            if vlen != 0 {
-                quotes += tok.delim.open().len();
-            }
-            if !tok.errors.contains(GroupError::Unterminated) {
-                quotes += tok.delim.close().len();
-            }
-            return (len + quotes, hlen + quotes, vlen);
-        }
-        Token::Splice(tok) => {
-            let (mut spliced_len, mut spliced_hlen, mut spliced_vlen) = (0, 0, 0);
-            for tok in &tok.tokens {
-                let (len, hlen, vlen) = token_len(tok);
-                if vlen != 0 {
-                    spliced_hlen = 0;
-                    spliced_vlen += vlen;
-                }
-                spliced_len += len;
-                spliced_hlen += hlen;
-            }
-            return (spliced_len, spliced_hlen, spliced_vlen);
+/// Publishes the diagnostics for a document, or clears them, when it has been
+/// closed.
+fn publish_diagnostics(
+    connection: &Connection,
+    uri: &Uri,
+    doc: Option<&Document>,
+    dialects: &Dialects,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let params = match doc {
+        Some(doc) => {
+            let dialect = doc.dialect();
+            let cst = dialects.parse(dialect, doc.text().as_bytes());
+            let diagnostics = diagnostics(&cst, dialect, dialects.integers(dialect), doc);
+            PublishDiagnosticsParams::new(uri.clone(), diagnostics, Some(doc.version()))
         }
-        Token::Error(tok) => (&tok.text, 0, 0),
-        Token::Placeholder => panic!("placeholder"),
+        None => PublishDiagnosticsParams::new(uri.clone(), Vec::new(), None),
     };
-    let (len, hlen, vlen) =
-        text.chars()
-            .fold((len_before, len_before, 0), |(len, hlen, vlen), ch| {
-                if ch == '\n' {
-                    (len + 1, 0, vlen + 1)
-                } else {
-                    (len + 1, hlen + 1, vlen)
-                }
-            });
-    (len + len_after, hlen + len_after, vlen)
+    let notif = Notification::new(PublishDiagnostics::METHOD.to_owned(), params);
+    connection.sender.send(Message::Notification(notif))?;
+    Ok(())
+}
+
+/// Responds to a request with its result.
+fn send_result<T: Serialize>(
+    connection: &Connection,
+    id: RequestId,
+    result: T,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let resp = Response::new_ok(id, result);
+    eprintln!("Send {resp:?}");
+    eprintln!();
+    connection.sender.send(Message::Response(resp))?;
+    Ok(())
+}
+
+/// Responds to a request for a document, which is not open, with an error.
+fn send_not_open(
+    connection: &Connection,
+    id: RequestId,
+    uri: &Uri,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let resp = Response::new_err(
+        id,
+        ErrorCode::InvalidParams as i32,
+        format!("document not open: {}", uri.as_str()),
+    );
+    connection.sender.send(Message::Response(resp))?;
+    Ok(())
+}
+
+/// Shows a warning message to the user.
+fn show_warning(
+    connection: &Connection,
+    message: String,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let params = ShowMessageParams {
+        typ: MessageType::WARNING,
+        message,
+    };
+    let notif = Notification::new(ShowMessage::METHOD.to_owned(), params);
+    connection.sender.send(Message::Notification(notif))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use lsp_server::{Connection, ErrorCode, Message, Notification, Request, RequestId};
+    use lsp_types::{
+        InitializeParams,
+        notification::{Exit, Notification as _},
+        request::{Request as _, Shutdown},
+    };
+
+    use crate::{
+        dialect::Dialects,
+        documents::{Documents, PositionEncoding},
+        main_loop,
+    };
+
+    #[test]
+    fn unknown_method() {
+        let (server, client) = Connection::memory();
+        let server = thread::spawn(move || {
+            main_loop(
+                server,
+                InitializeParams::default(),
+                Documents::new(PositionEncoding::Utf16),
+                Dialects::new(),
+            )
+            .map_err(|err| err.to_string())
+        });
+        let request = |id: i32, method: &str| {
+            let req = Request::new(RequestId::from(id), method.to_owned(), ());
+            client.sender.send(Message::Request(req)).unwrap();
+            match client.receiver.recv().unwrap() {
+                Message::Response(resp) => resp,
+                msg => panic!("unexpected message: {msg:?}"),
+            }
+        };
+
+        let resp = request(1, "omniwsa/unknown");
+        assert_eq!(resp.id, RequestId::from(1));
+        assert_eq!(
+            resp.error.map(|err| err.code),
+            Some(ErrorCode::MethodNotFound as i32),
+        );
+
+        // The server is still running after the unknown method.
+        let resp = request(2, Shutdown::METHOD);
+        assert!(resp.error.is_none());
+        let exit = Notification::new(Exit::METHOD.to_owned(), ());
+        client.sender.send(Message::Notification(exit)).unwrap();
+        assert_eq!(server.join().unwrap(), Ok(()));
+    }
 }
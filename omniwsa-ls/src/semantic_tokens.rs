@@ -0,0 +1,167 @@
+//! Semantic tokens for highlighting the lexed tokens of a document.
+
+use lsp_types::{Position, SemanticToken, SemanticTokenModifier, SemanticTokenType};
+use omniwsa::{syntax::Pretty, tokens::Token};
+
+use crate::{dialect::Dialects, documents::Document};
+
+// TODO:
+// - Highlight from the CST instead of the tokens, so that label definitions
+//   and references can be distinguished.
+
+/// The token types, in the order of their indices in the legend.
+pub const TOKEN_TYPES: [SemanticTokenType; 7] = [
+    SemanticTokenType::VARIABLE,
+    SemanticTokenType::FUNCTION,
+    SemanticTokenType::KEYWORD,
+    SemanticTokenType::COMMENT,
+    SemanticTokenType::STRING,
+    SemanticTokenType::NUMBER,
+    SemanticTokenType::OPERATOR,
+];
+
+/// The token modifiers, in the order of their bits in the legend.
+pub const TOKEN_MODIFIERS: [SemanticTokenModifier; 2] = [
+    SemanticTokenModifier::DECLARATION,
+    SemanticTokenModifier::DEFINITION,
+];
+
+// The order corresponds to the index in `TOKEN_TYPES`.
+#[derive(Clone, Copy, Debug)]
+enum TokenType {
+    Variable,
+    Function,
+    Keyword,
+    Comment,
+    String,
+    Number,
+    Operator,
+}
+
+// The order corresponds to the index in `TOKEN_MODIFIERS`.
+#[derive(Clone, Copy, Debug)]
+enum TokenModifier {
+    Declaration,
+    Definition,
+}
+
+/// Computes the semantic tokens of a document, with positions in its
+/// encoding. Tokens which span lines are split at line ends, since not every
+/// client supports multiline tokens.
+pub fn semantic_tokens(dialects: &Dialects, doc: &Document) -> Vec<SemanticToken> {
+    let tokens = dialects.lex(doc.dialect(), doc.text().as_bytes());
+    let mut tokens_out = Vec::with_capacity(tokens.len());
+    let mut prev = Position::new(0, 0);
+    let mut offset = 0;
+    let mut buf = Vec::new();
+    for tok in &tokens {
+        buf.clear();
+        tok.pretty(&mut buf);
+        let (start, end) = (offset, offset + buf.len());
+        offset = end;
+        let Some(ty) = token_type(tok.peel_groups()) else {
+            continue;
+        };
+        let modifiers = match tok.peel_groups() {
+            Token::Label(_) => 1 << TokenModifier::Declaration as u32,
+            Token::Variable(_) => 1 << TokenModifier::Definition as u32,
+            _ => 0,
+        };
+        let (start, end) = (doc.position(start), doc.position(end));
+        for line in start.line..=end.line {
+            let line_start = if line == start.line {
+                start
+            } else {
+                Position::new(line, 0)
+            };
+            let line_end = if line == end.line {
+                end
+            } else {
+                doc.position(doc.offset(Position::new(line, u32::MAX)))
+            };
+            if line_end.character <= line_start.character {
+                continue;
+            }
+            tokens_out.push(SemanticToken {
+                delta_line: line_start.line - prev.line,
+                delta_start: if line_start.line == prev.line {
+                    line_start.character - prev.character
+                } else {
+                    line_start.character
+                },
+                length: line_end.character - line_start.character,
+                token_type: ty as u32,
+                token_modifiers_bitset: modifiers,
+            });
+            prev = line_start;
+        }
+    }
+    tokens_out
+}
+
+/// Classifies a token, which is not a group or splice.
+fn token_type(tok: &Token<'_>) -> Option<TokenType> {
+    match tok {
+        Token::Mnemonic(_) => Some(TokenType::Keyword),
+        Token::Integer(_) => Some(TokenType::Number),
+        Token::String(_) | Token::Char(_) => Some(TokenType::String),
+        Token::Variable(_) => Some(TokenType::Variable),
+        Token::Label(_) => Some(TokenType::Function),
+        Token::LabelColon(_) => Some(TokenType::Operator),
+        Token::Space(_) | Token::LineTerm(_) | Token::Eof(_) => None,
+        Token::InstSep(_) | Token::ArgSep(_) => Some(TokenType::Operator),
+        Token::LineComment(_) | Token::BlockComment(_) => Some(TokenType::Comment),
+        Token::Word(_) => Some(TokenType::Variable),
+        Token::Group(_) | Token::Splice(_) => panic!("not ungrouped"),
+        Token::Error(_) => None,
+        Token::Placeholder => panic!("placeholder"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        dialect::{DialectKind, Dialects},
+        documents::{Document, PositionEncoding},
+        semantic_tokens::semantic_tokens,
+    };
+
+    /// Computes the semantic tokens with absolute positions, as (line, start,
+    /// length).
+    fn tokens(text: &str, encoding: PositionEncoding) -> Vec<(u32, u32, u32)> {
+        let doc = Document::new(text.to_owned(), 0, encoding, DialectKind::Burghard);
+        let (mut line, mut start) = (0, 0);
+        (semantic_tokens(&Dialects::new(), &doc).iter())
+            .map(|tok| {
+                if tok.delta_line != 0 {
+                    start = 0;
+                }
+                line += tok.delta_line;
+                start += tok.delta_start;
+                (line, start, tok.length)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn columns_in_encoding() {
+        let text = "push \"草草\" ; x\nend";
+        assert_eq!(
+            tokens(text, PositionEncoding::Utf8),
+            [(0, 0, 4), (0, 5, 8), (0, 14, 3), (1, 0, 3)],
+        );
+        assert_eq!(
+            tokens(text, PositionEncoding::Utf16),
+            [(0, 0, 4), (0, 5, 4), (0, 10, 3), (1, 0, 3)],
+        );
+    }
+
+    #[test]
+    fn split_multiline() {
+        let text = "push 1 {- a\n草 -} end";
+        assert_eq!(
+            tokens(text, PositionEncoding::Utf16),
+            [(0, 0, 4), (0, 5, 1), (0, 7, 4), (1, 0, 4), (1, 5, 3)],
+        );
+    }
+}
@@ -0,0 +1,496 @@
+//! Labels and variables, and the files which share them through includes.
+
+use std::{
+    borrow::Cow,
+    collections::{HashMap, VecDeque},
+    ops::Range,
+    str::FromStr,
+};
+
+use lsp_types::{Location, Position, TextEdit, Uri, WorkspaceEdit};
+use omniwsa::{
+    syntax::{Cst, HasError, Inst, Opcode, Pretty},
+    tokens::{GroupError, Token},
+};
+
+use crate::{
+    dialect::{DialectKind, Dialects},
+    documents::{Document, Documents},
+};
+
+// TODO:
+// - Resolve includes for dialects other than Burghard, once they parse.
+// - Find the files which include a document, when they are not open, by
+//   scanning the workspace folders.
+
+/// The labels, variables, includes, and options in a document.
+#[derive(Clone, Debug, Default)]
+pub struct Symbols {
+    /// Every occurrence of a label or variable, in source order.
+    pub symbols: Vec<Symbol>,
+    /// The names of the files included by the document, in source order.
+    pub includes: Vec<Vec<u8>>,
+    /// The names of the options defined by `option` (Burghard), in source
+    /// order.
+    pub options: Vec<Vec<u8>>,
+}
+
+/// An occurrence of a label or variable.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Symbol {
+    /// Whether it is a label or variable.
+    pub kind: SymbolKind,
+    /// The name, with its sigil removed.
+    pub name: Vec<u8>,
+    /// The byte range of the name in the document, excluding its sigil.
+    pub range: Range<usize>,
+    /// Whether this occurrence defines the symbol.
+    pub is_def: bool,
+}
+
+/// The namespace of a symbol.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SymbolKind {
+    /// A label, which is defined by a `label` instruction (Burghard) or with an
+    /// `@` sigil (Palaiologos).
+    Label,
+    /// A variable, which is defined by `valueinteger` or `valuestring`
+    /// (Burghard).
+    Variable,
+}
+
+/// The files which form a program with a document, by including each other.
+#[derive(Debug)]
+pub struct Program<'a> {
+    /// The files in the program. The first is the document, which the program
+    /// was loaded for.
+    pub files: Vec<File<'a>>,
+}
+
+/// A file in a program.
+#[derive(Debug)]
+pub struct File<'a> {
+    /// The URI of the file.
+    pub uri: Uri,
+    /// The text of the file, which is borrowed when it is open in the client
+    /// and read from disk otherwise.
+    pub doc: Cow<'a, Document>,
+    /// The symbols in the file.
+    pub symbols: Symbols,
+}
+
+impl Symbols {
+    /// Collects the symbols in a CST.
+    pub fn new(cst: &Cst<'_>) -> Self {
+        let mut c = Collector {
+            offset: 0,
+            buf: Vec::new(),
+            symbols: Symbols::default(),
+        };
+        c.cst(cst);
+        c.symbols
+    }
+
+    /// Finds the symbol touching a byte offset.
+    pub fn at(&self, offset: usize) -> Option<&Symbol> {
+        let i = self.symbols.partition_point(|sym| sym.range.end < offset);
+        self.symbols.get(i).filter(|sym| sym.range.start <= offset)
+    }
+}
+
+impl Symbol {
+    /// Returns whether the other symbol is an occurrence of the same label or
+    /// variable.
+    pub fn same(&self, other: &Symbol) -> bool {
+        self.kind == other.kind && self.name == other.name
+    }
+}
+
+impl SymbolKind {
+    /// A name for this kind of symbol, for messages.
+    pub fn name(self) -> &'static str {
+        match self {
+            SymbolKind::Label => "label",
+            SymbolKind::Variable => "variable",
+        }
+    }
+}
+
+impl<'a> Program<'a> {
+    /// Collects the files which form a program with an open document. These are
+    /// the files connected to it by includes, either way, among the open
+    /// documents and the files they include. Returns `None`, if the document is
+    /// not open.
+    pub fn load(documents: &'a Documents, dialects: &Dialects, uri: &Uri) -> Option<Self> {
+        documents.get(uri)?;
+        let mut files = Vec::new();
+        let mut indices = HashMap::new();
+        let mut edges = Vec::new();
+        let mut queue = VecDeque::from([uri.clone()]);
+        queue.extend(documents.iter().map(|(uri, _)| uri.clone()));
+        while let Some(uri) = queue.pop_front() {
+            if indices.contains_key(&uri) {
+                continue;
+            }
+            let doc = match documents.get(&uri) {
+                Some(doc) => Cow::Borrowed(doc),
+                None => match documents.load(&uri, dialects) {
+                    Some(doc) => Cow::Owned(doc),
+                    None => continue,
+                },
+            };
+            let symbols = doc.symbols(dialects).clone();
+            let i = files.len();
+            for include in &symbols.includes {
+                if let Some(included) = resolve_include(&uri, include) {
+                    edges.push((i, included.clone()));
+                    queue.push_back(included);
+                }
+            }
+            indices.insert(uri.clone(), i);
+            files.push(File { uri, doc, symbols });
+        }
+
+        // Keep only the files connected to the document.
+        let mut adjacent = vec![Vec::new(); files.len()];
+        for (i, uri) in edges {
+            if let Some(&j) = indices.get(&uri) {
+                adjacent[i].push(j);
+                adjacent[j].push(i);
+            }
+        }
+        let mut connected = vec![false; files.len()];
+        let mut stack = vec![0];
+        connected[0] = true;
+        while let Some(i) = stack.pop() {
+            for &j in &adjacent[i] {
+                if !connected[j] {
+                    connected[j] = true;
+                    stack.push(j);
+                }
+            }
+        }
+        let mut connected = connected.into_iter();
+        files.retain(|_| connected.next().unwrap());
+        Some(Program { files })
+    }
+
+    /// Finds the symbol at a position in the document.
+    pub fn symbol_at(&self, position: Position) -> Option<&Symbol> {
+        let file = &self.files[0];
+        file.symbols.at(file.doc.offset(position))
+    }
+
+    /// Iterates every occurrence of the same label or variable as the symbol,
+    /// in all files of the program.
+    pub fn occurrences<'p>(
+        &'p self,
+        symbol: &'p Symbol,
+    ) -> impl Iterator<Item = (&'p File<'a>, &'p Symbol)> {
+        self.files.iter().flat_map(move |file| {
+            (file.symbols.symbols.iter())
+                .filter(move |sym| sym.same(symbol))
+                .map(move |sym| (file, sym))
+        })
+    }
+
+    /// Renames the symbol at a position in the document, in all files of the
+    /// program. Returns `None`, if there is no symbol at the position, and an
+    /// error message, if the name is not valid in the dialect of every file or
+    /// is already used by another symbol of the same kind.
+    pub fn rename(
+        &self,
+        dialects: &Dialects,
+        position: Position,
+        new_name: &str,
+    ) -> Result<Option<WorkspaceEdit>, String> {
+        let Some(symbol) = self.symbol_at(position) else {
+            return Ok(None);
+        };
+        // Renaming to another symbol would merge them.
+        let collides = (self.files.iter())
+            .flat_map(|file| &file.symbols.symbols)
+            .any(|sym| sym.kind == symbol.kind && sym.name == new_name.as_bytes())
+            && symbol.name != new_name.as_bytes();
+        if collides {
+            return Err(format!(
+                "`{new_name}` is already a {} in the program",
+                symbol.kind.name(),
+            ));
+        }
+        let mut changes = HashMap::<_, Vec<_>>::new();
+        for (file, sym) in self.occurrences(symbol) {
+            let dialect = file.doc.dialect();
+            if !is_valid_name(dialects, dialect, sym.kind, new_name) {
+                return Err(format!(
+                    "`{new_name}` is not a valid {} in {}",
+                    symbol.kind.name(),
+                    dialect.name(),
+                ));
+            }
+            (changes.entry(file.uri.clone()).or_default()).push(TextEdit::new(
+                file.doc.range(sym.range.clone()),
+                new_name.to_owned(),
+            ));
+        }
+        Ok(Some(WorkspaceEdit::new(changes)))
+    }
+}
+
+impl File<'_> {
+    /// The location of a symbol in this file.
+    pub fn location(&self, symbol: &Symbol) -> Location {
+        Location::new(self.uri.clone(), self.doc.range(symbol.range.clone()))
+    }
+}
+
+/// Returns whether the name is valid for a symbol in the dialect. It checks
+/// that a definition with the name parses without errors to exactly that
+/// symbol.
+fn is_valid_name(dialects: &Dialects, dialect: DialectKind, kind: SymbolKind, name: &str) -> bool {
+    let src = match (dialect, kind) {
+        (DialectKind::Burghard, SymbolKind::Label) => format!("label {name}\n"),
+        (DialectKind::Burghard, SymbolKind::Variable) => format!("valueinteger _{name} 0\n"),
+        (DialectKind::Palaiologos, SymbolKind::Label) => format!("@{name}\n"),
+        (DialectKind::Palaiologos, SymbolKind::Variable) => return false,
+    };
+    // `HasError` does not count label errors, so check them separately.
+    let label_errors = (dialects.lex(dialect, src.as_bytes()).iter())
+        .any(|tok| matches!(tok.peel_groups(), Token::Label(label) if !label.errors.is_empty()));
+    let cst = dialects.parse(dialect, src.as_bytes());
+    if label_errors || cst.has_error() {
+        return false;
+    }
+    match &*Symbols::new(&cst).symbols {
+        [sym] => sym.kind == kind && sym.is_def && sym.name == name.as_bytes(),
+        _ => false,
+    }
+}
+
+/// Resolves the URI of a file included from another, by appending `.wsa` to
+/// its name relative to the directory of the including file (Burghard).
+fn resolve_include(base: &Uri, name: &[u8]) -> Option<Uri> {
+    let base = base.as_str();
+    let path_end = base.find(['?', '#']).unwrap_or(base.len());
+    let dir_end = base[..path_end].rfind('/')? + 1;
+    let mut uri = base[..dir_end].to_owned();
+    for &b in name.iter().chain(b".wsa") {
+        if b.is_ascii_alphanumeric() || b"-._~!$&'()*+,;=:@/".contains(&b) {
+            uri.push(b as char);
+        } else {
+            uri.push_str(&format!("%{b:02X}"));
+        }
+    }
+    Uri::from_str(&uri).ok()
+}
+
+/// Walks a CST in source order, tracking the offset of each token by the text
+/// it pretty-prints as.
+struct Collector {
+    offset: usize,
+    buf: Vec<u8>,
+    symbols: Symbols,
+}
+
+impl Collector {
+    fn cst(&mut self, cst: &Cst<'_>) {
+        match cst {
+            Cst::Inst(inst) => self.inst(inst),
+            Cst::Block { nodes } => nodes.iter().for_each(|node| self.cst(node)),
+            Cst::OptionBlock(block) => {
+                for (option, nodes) in &block.options {
+                    self.inst(option);
+                    nodes.iter().for_each(|node| self.cst(node));
+                }
+                if let Some(end) = &block.end {
+                    self.inst(end);
+                }
+            }
+        }
+    }
+
+    fn inst(&mut self, inst: &Inst<'_>) {
+        self.skip(inst.words.space_before.tokens());
+        for (i, (word, space)) in inst.words.words.iter().enumerate() {
+            self.word(inst, i, word);
+            self.skip(space.tokens());
+        }
+    }
+
+    fn word(&mut self, inst: &Inst<'_>, i: usize, tok: &Token<'_>) {
+        let start = self.offset;
+        match tok {
+            Token::Group(group) => {
+                self.offset += group.delim.open().len();
+                self.word(inst, i, &group.inner);
+                if !group.errors.contains(GroupError::Unterminated) {
+                    self.offset += group.delim.close().len();
+                }
+                return;
+            }
+            Token::Splice(splice) => {
+                // The spliced token is not contiguous in the source, so the
+                // range covers all of its parts.
+                self.skip(&splice.tokens);
+                self.symbol(inst, i, splice.spliced.peel_groups(), start..self.offset);
+                return;
+            }
+            _ => {}
+        }
+        self.buf.clear();
+        tok.pretty(&mut self.buf);
+        self.offset += self.buf.len();
+        let sigil_len = match tok {
+            Token::Label(label) => label.style.sigil().len(),
+            Token::Variable(var) => var.style.sigil().len(),
+            _ => 0,
+        };
+        self.symbol(inst, i, tok, start + sigil_len..self.offset);
+    }
+
+    fn symbol(&mut self, inst: &Inst<'_>, i: usize, tok: &Token<'_>, range: Range<usize>) {
+        let (kind, name, is_def) = match tok {
+            Token::Label(label) => (
+                SymbolKind::Label,
+                &label.label,
+                inst.opcode == Opcode::Label,
+            ),
+            Token::Variable(var) => (
+                SymbolKind::Variable,
+                &var.ident,
+                i == 1
+                    && matches!(
+                        inst.opcode,
+                        Opcode::BurghardValueInteger | Opcode::BurghardValueString,
+                    ),
+            ),
+            Token::Word(word) if i == 1 && inst.opcode == Opcode::BurghardInclude => {
+                self.symbols.includes.push(word.word.to_vec());
+                return;
+            }
+            Token::Word(word) if i == 1 && inst.opcode == Opcode::DefineOption => {
+                self.symbols.options.push(word.word.to_vec());
+                return;
+            }
+            _ => return,
+        };
+        self.symbols.symbols.push(Symbol {
+            kind,
+            name: name.to_vec(),
+            range,
+            is_def,
+        });
+    }
+
+    fn skip(&mut self, toks: &[Token<'_>]) {
+        for tok in toks {
+            self.buf.clear();
+            tok.pretty(&mut self.buf);
+            self.offset += self.buf.len();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use lsp_types::{DidOpenTextDocumentParams, Position, TextDocumentItem, TextEdit, Uri};
+
+    use crate::{
+        dialect::{DialectKind, Dialects},
+        documents::{Documents, PositionEncoding},
+        symbols::{Program, Symbol, SymbolKind, Symbols},
+    };
+
+    fn open(documents: &mut Documents, dialects: &Dialects, uri: &str, text: &str) -> Uri {
+        let uri = Uri::from_str(uri).unwrap();
+        let text_document = TextDocumentItem::new(uri.clone(), "wsa".into(), 1, text.into());
+        documents.open(DidOpenTextDocumentParams { text_document }, dialects);
+        uri
+    }
+
+    #[test]
+    fn symbols() {
+        let dialects = Dialects::new();
+        let src = "label a\njump a\nvalueinteger _x 1\npush _x\ninclude \"lib\"\n";
+        let cst = dialects.parse(DialectKind::Burghard, src.as_bytes());
+        let symbols = Symbols::new(&cst);
+        let sym = |kind, name: &str, range, is_def| Symbol {
+            kind,
+            name: name.into(),
+            range,
+            is_def,
+        };
+        assert_eq!(
+            symbols.symbols,
+            [
+                sym(SymbolKind::Label, "a", 6..7, true),
+                sym(SymbolKind::Label, "a", 13..14, false),
+                sym(SymbolKind::Variable, "x", 29..30, true),
+                sym(SymbolKind::Variable, "x", 39..40, false),
+            ],
+        );
+        assert_eq!(symbols.includes, [b"lib"]);
+        assert_eq!(symbols.at(7), Some(&symbols.symbols[0]));
+        assert_eq!(symbols.at(9), None);
+
+        // The range excludes the sigil.
+        let src = "@a\njmp %a\n";
+        let cst = dialects.parse(DialectKind::Palaiologos, src.as_bytes());
+        let ranges = (Symbols::new(&cst).symbols.iter())
+            .map(|sym| (sym.range.clone(), sym.is_def))
+            .collect::<Vec<_>>();
+        assert_eq!(ranges, [(1..2, true), (8..9, false)]);
+    }
+
+    #[test]
+    fn rename() {
+        let dialects = Dialects::new();
+        let mut documents = Documents::new(PositionEncoding::Utf16);
+        let main = open(
+            &mut documents,
+            &dialects,
+            "file:///main.wsa",
+            "include \"lib\"\ncall f\n",
+        );
+        let lib = open(
+            &mut documents,
+            &dialects,
+            "file:///lib.wsa",
+            "label f\nret\nlabel h\n",
+        );
+        let other = open(&mut documents, &dialects, "file:///other.wsa", "label f\n");
+        let program = Program::load(&documents, &dialects, &main).unwrap();
+
+        let edit = program
+            .rename(&dialects, Position::new(1, 5), "g")
+            .unwrap()
+            .unwrap();
+        let changes = edit.changes.unwrap();
+        let edit = |line, start, end| {
+            TextEdit::new(
+                lsp_types::Range::new(Position::new(line, start), Position::new(line, end)),
+                "g".to_owned(),
+            )
+        };
+        assert_eq!(changes[&main], [edit(1, 5, 6)]);
+        assert_eq!(changes[&lib], [edit(0, 6, 7)]);
+        assert!(!changes.contains_key(&other));
+
+        assert_eq!(
+            program.rename(&dialects, Position::new(1, 0), "g"),
+            Ok(None)
+        );
+        assert_eq!(
+            program.rename(&dialects, Position::new(1, 5), "g h"),
+            Err("`g h` is not a valid label in Burghard".to_owned()),
+        );
+        // Renaming onto another label would merge them.
+        assert_eq!(
+            program.rename(&dialects, Position::new(1, 5), "h"),
+            Err("`h` is already a label in the program".to_owned()),
+        );
+        assert!(program.rename(&dialects, Position::new(1, 5), "f").is_ok());
+    }
+}
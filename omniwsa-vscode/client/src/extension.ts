@@ -1,5 +1,5 @@
 import { env } from "process";
-import { ExtensionContext } from "vscode";
+import { DebugAdapterExecutable, ExtensionContext, debug, workspace } from "vscode";
 
 import {
   LanguageClient,
@@ -19,6 +19,8 @@ export function activate(context: ExtensionContext) {
 
   const clientOptions: LanguageClientOptions = {
     documentSelector: [{ scheme: "file", language: "whitespace-assembly" }],
+    initializationOptions: workspace.getConfiguration("omniwsa"),
+    synchronize: { configurationSection: "omniwsa" },
   };
 
   client = new LanguageClient(
@@ -28,6 +30,13 @@ export function activate(context: ExtensionContext) {
     clientOptions,
   );
   client.start();
+
+  const dapCommand = env.OMNIWSA_DAP_DEBUG_PATH ?? "omniwsa-dap";
+  context.subscriptions.push(
+    debug.registerDebugAdapterDescriptorFactory("omniwsa", {
+      createDebugAdapterDescriptor: () => new DebugAdapterExecutable(dapCommand),
+    }),
+  );
 }
 
 export function deactivate(): Thenable<void> | undefined {
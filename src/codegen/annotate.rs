@@ -0,0 +1,83 @@
+//! Whitespace output annotated with the assembly it was generated from.
+
+use std::collections::HashSet;
+
+use bstr::ByteSlice;
+
+use crate::{
+    codegen::{CodegenConfig, InstWrite, LabelMap},
+    syntax::{Cst, Pretty},
+};
+
+impl Cst<'_> {
+    /// Generates a stream of Whitespace tokens for this CST, like
+    /// [`Cst::codegen_with_config`], and writes a comment with the mnemonic
+    /// and arguments of each assembly instruction before its tokens.
+    pub fn codegen_annotated<T: InstWrite>(
+        &self,
+        w: &mut T,
+        options: &HashSet<&[u8]>,
+        config: &CodegenConfig,
+    ) -> Result<(), T::Error> {
+        let labels = LabelMap::new(self, options, config);
+        let mut buf = Vec::new();
+        self.for_each_inst(options, &mut |i, inst| {
+            if !inst.words.words.is_empty() {
+                buf.clear();
+                for (i, (word, _)) in inst.words.words.iter().enumerate() {
+                    if i != 0 {
+                        buf.push(b' ');
+                    }
+                    word.pretty(&mut buf);
+                }
+                w.write_comment(&buf.to_str_lossy())?;
+            }
+            inst.codegen(w, i, &labels, config)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use crate::{
+        codegen::{CodegenConfig, EncodingWriter, InstStream, Peephole},
+        dialects::{Burghard, Dialect as _},
+        ws::encoding::TokenEncoding,
+    };
+
+    #[test]
+    fn annotated() {
+        let src = b"push 5 ; five\ndoub\npop\nlabel Loop\noutN\nexit\n";
+        let cst = Burghard::new().parse(src);
+        let options = HashSet::new();
+        let config = CodegenConfig::default();
+        let annotate = |encoding, peephole| {
+            let mut stream = InstStream::with_peephole(peephole);
+            let Ok(()) = cst.codegen_annotated(&mut stream, &options, &config);
+            let mut w = EncodingWriter::new(Vec::new(), encoding);
+            stream.write_to(&mut w).unwrap();
+            String::from_utf8(w.finish().unwrap()).unwrap()
+        };
+
+        assert_eq!(
+            annotate(TokenEncoding::Stl, Peephole::NONE),
+            "push 5SSSTSTLdoubSLSpopSLLlabel ＬoopLSSLoutNTLSTexitLLL",
+        );
+        assert_eq!(
+            annotate(TokenEncoding::Stl, Peephole::ALL),
+            "push 5SSSTSTLdoubpoplabel ＬoopLSSLoutNTLSTexitLLL",
+        );
+
+        let ws = annotate(TokenEncoding::Standard, Peephole::NONE);
+        assert!(ws.starts_with("push\u{a0}5   \t \t\ndoub \n "));
+        let tokens = ws
+            .chars()
+            .filter(|ch| " \t\n".contains(*ch))
+            .collect::<String>();
+        let mut plain = String::new();
+        let Ok(()) = cst.codegen(&mut plain, &options);
+        assert_eq!(tokens, plain);
+    }
+}
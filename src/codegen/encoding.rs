@@ -0,0 +1,194 @@
+//! Writing Whitespace tokens in alternative encodings.
+
+use std::io::{self, Write};
+
+use crate::{
+    codegen::{Inst, Token, TokenWrite},
+    ws::encoding::TokenEncoding,
+};
+
+/// A token writer, which encodes tokens to bytes in a [`TokenEncoding`] and
+/// optionally comments each instruction.
+#[derive(Debug)]
+pub struct EncodingWriter<W> {
+    w: W,
+    encoding: TokenEncoding,
+    comments: bool,
+    /// Bits of a partial byte in the packed encoding, aligned to the most
+    /// significant bit.
+    pending: u8,
+    /// The number of bits in `pending`.
+    pending_len: u32,
+    /// A buffer for encoding instructions.
+    scratch: Vec<Token>,
+}
+
+impl<W: Write> EncodingWriter<W> {
+    /// Constructs a writer, which encodes tokens in the encoding.
+    pub fn new(w: W, encoding: TokenEncoding) -> Self {
+        EncodingWriter {
+            w,
+            encoding,
+            comments: false,
+            pending: 0,
+            pending_len: 0,
+            scratch: Vec::new(),
+        }
+    }
+
+    /// Interleaves a comment with the mnemonic and argument of each
+    /// instruction between its tokens, like `push(S)5(S)(T)(S)(T)(L)`. The
+    /// packed encoding has no comments, so this is ignored for it.
+    pub fn with_comments(mut self) -> Self {
+        self.comments = true;
+        self
+    }
+
+    /// Writes the final partial byte of the packed encoding, then returns the
+    /// inner writer.
+    pub fn finish(mut self) -> io::Result<W> {
+        if self.encoding == TokenEncoding::Packed {
+            self.write_bits(0b1, 1)?;
+            if self.pending_len != 0 {
+                self.w.write_all(&[self.pending])?;
+            }
+        }
+        self.w.flush()?;
+        Ok(self.w)
+    }
+
+    /// Writes the low `len` bits of `bits` in the packed encoding.
+    fn write_bits(&mut self, bits: u8, len: u32) -> io::Result<()> {
+        for i in (0..len).rev() {
+            let bit = (bits >> i) & 1;
+            self.pending |= bit << (7 - self.pending_len);
+            self.pending_len += 1;
+            if self.pending_len == 8 {
+                self.w.write_all(&[self.pending])?;
+                self.pending = 0;
+                self.pending_len = 0;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<W: Write> TokenWrite for EncodingWriter<W> {
+    type Error = io::Error;
+
+    fn write_token(&mut self, token: Token) -> Result<(), Self::Error> {
+        if self.encoding == TokenEncoding::Packed {
+            return match token {
+                Token::S => self.write_bits(0b0, 1),
+                Token::T => self.write_bits(0b10, 2),
+                Token::L => self.write_bits(0b11, 2),
+            };
+        }
+        let [s, t, l] = self.encoding.lexemes();
+        self.w.write_all(match token {
+            Token::S => s,
+            Token::T => t,
+            Token::L => l,
+        })
+    }
+
+    fn write_inst(&mut self, inst: Inst<'_>) -> Result<(), Self::Error> {
+        if !self.comments || self.encoding == TokenEncoding::Packed {
+            self.scratch.clear();
+            let Ok(()) = self.scratch.write_inst(inst);
+            return (0..self.scratch.len()).try_for_each(|i| self.write_token(self.scratch[i]));
+        }
+        let name = inst.name();
+        let arg = inst.arg().map(|arg| arg.to_string());
+        self.scratch.clear();
+        let Ok(()) = self.scratch.write_inst(inst);
+        let mut words = [Some(name), arg.as_deref()].into_iter().flatten();
+        for i in 0..self.scratch.len() {
+            if let Some(word) = words.next() {
+                self.w.write_all(word.as_bytes())?;
+            }
+            self.write_token(self.scratch[i])?;
+        }
+        Ok(())
+    }
+
+    fn write_comment(&mut self, comment: &str) -> Result<(), Self::Error> {
+        if self.encoding == TokenEncoding::Packed {
+            return Ok(());
+        }
+        self.w
+            .write_all(self.encoding.escape_comment(comment).as_bytes())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use crate::{
+        codegen::EncodingWriter,
+        dialects::{Burghard, Dialect as _},
+        ws::{
+            encoding::TokenEncoding,
+            token_source::{Token, TokenSource},
+        },
+    };
+
+    #[test]
+    fn encode() {
+        let cst = Burghard::new().parse(b"push 5\nlabel l\ncall l\nexit\n");
+        let encode = |encoding, comments| {
+            let mut w = EncodingWriter::new(Vec::new(), encoding);
+            if comments {
+                w = w.with_comments();
+            }
+            cst.codegen(&mut w, &HashSet::new()).unwrap();
+            w.finish().unwrap()
+        };
+
+        let standard = encode(TokenEncoding::Standard, false);
+        assert_eq!(standard, b"   \t \t\n\n  \n\n \t\n\n\n\n");
+        assert_eq!(encode(TokenEncoding::Stl, false), b"SSSTSTLLSSLLSTLLLL");
+        assert_eq!(
+            encode(TokenEncoding::GrassMudHorse, false),
+            "草草草泥草泥马马草草马马草泥马马马马".as_bytes(),
+        );
+        // SSSTSTL LSSL LSTL LLL, then the terminator.
+        assert_eq!(
+            encode(TokenEncoding::Packed, false),
+            [0b00010010, 0b11110011, 0b11010111, 0b11111100],
+        );
+
+        let commented = encode(TokenEncoding::Standard, true);
+        assert_eq!(
+            commented,
+            b"push 5  \t \t\nlabel\n0  \ncall\n0 \t\nend\n\n\n",
+        );
+        let tokens = (commented.iter())
+            .filter(|b| b" \t\n".contains(b))
+            .copied()
+            .collect::<Vec<_>>();
+        assert_eq!(tokens, standard);
+
+        let decode = |encoding, src: &[u8]| {
+            let mut tokens = TokenSource::new();
+            for &b in src {
+                let (tok, i) = match b {
+                    b' ' => (Token::S, 0),
+                    b'\t' => (Token::T, 1),
+                    _ => (Token::L, 2),
+                };
+                tokens.push(tok, TokenEncoding::lexemes(encoding)[i]);
+            }
+            tokens
+        };
+        for encoding in [
+            TokenEncoding::Stl,
+            TokenEncoding::GrassMudHorse,
+            TokenEncoding::Packed,
+        ] {
+            let decoded = encoding.decode(&encode(encoding, false));
+            assert_eq!(decoded, decode(encoding, &standard), "{encoding:?}");
+        }
+    }
+}
@@ -1,13 +1,11 @@
 //! Whitespace instructions for code generation.
 
+use std::borrow::Cow;
+
 use rug::Integer;
 
 use crate::tokens::integer::Sign;
 
-// TODO:
-// - Distinction between Haskell Integer for `push` and Int for `copy` and
-//   `slide`. Perhaps issue a warning when the index would wrap.
-
 /// A Whitespace instruction for code generation.
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub enum Inst<'a> {
@@ -74,6 +72,91 @@ pub enum Inst<'a> {
     VolivaBreakpoint,
 }
 
+impl Inst<'_> {
+    /// Converts this instruction to one which owns its arguments.
+    pub fn into_owned(self) -> Inst<'static> {
+        match self {
+            Inst::Push(n) => Inst::Push(IntegerBits(n.0.into_owned())),
+            Inst::Dup => Inst::Dup,
+            Inst::Copy(n) => Inst::Copy(IntegerBits(n.0.into_owned())),
+            Inst::Swap => Inst::Swap,
+            Inst::Drop => Inst::Drop,
+            Inst::Slide(n) => Inst::Slide(IntegerBits(n.0.into_owned())),
+            Inst::Add => Inst::Add,
+            Inst::Sub => Inst::Sub,
+            Inst::Mul => Inst::Mul,
+            Inst::Div => Inst::Div,
+            Inst::Mod => Inst::Mod,
+            Inst::Store => Inst::Store,
+            Inst::Retrieve => Inst::Retrieve,
+            Inst::Label(l) => Inst::Label(LabelBits(l.0.into_owned())),
+            Inst::Call(l) => Inst::Call(LabelBits(l.0.into_owned())),
+            Inst::Jmp(l) => Inst::Jmp(LabelBits(l.0.into_owned())),
+            Inst::Jz(l) => Inst::Jz(LabelBits(l.0.into_owned())),
+            Inst::Jn(l) => Inst::Jn(LabelBits(l.0.into_owned())),
+            Inst::Ret => Inst::Ret,
+            Inst::End => Inst::End,
+            Inst::Printc => Inst::Printc,
+            Inst::Printi => Inst::Printi,
+            Inst::Readc => Inst::Readc,
+            Inst::Readi => Inst::Readi,
+            Inst::BurghardPrintStack => Inst::BurghardPrintStack,
+            Inst::BurghardPrintHeap => Inst::BurghardPrintHeap,
+            Inst::VolivaOr => Inst::VolivaOr,
+            Inst::VolivaNot => Inst::VolivaNot,
+            Inst::VolivaAnd => Inst::VolivaAnd,
+            Inst::VolivaBreakpoint => Inst::VolivaBreakpoint,
+        }
+    }
+
+    /// Gets the mnemonic of this instruction, as documented on its variant.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Inst::Push(_) => "push",
+            Inst::Dup => "dup",
+            Inst::Copy(_) => "copy",
+            Inst::Swap => "swap",
+            Inst::Drop => "drop",
+            Inst::Slide(_) => "slide",
+            Inst::Add => "add",
+            Inst::Sub => "sub",
+            Inst::Mul => "mul",
+            Inst::Div => "div",
+            Inst::Mod => "mod",
+            Inst::Store => "store",
+            Inst::Retrieve => "retrieve",
+            Inst::Label(_) => "label",
+            Inst::Call(_) => "call",
+            Inst::Jmp(_) => "jmp",
+            Inst::Jz(_) => "jz",
+            Inst::Jn(_) => "jn",
+            Inst::Ret => "ret",
+            Inst::End => "end",
+            Inst::Printc => "printc",
+            Inst::Printi => "printi",
+            Inst::Readc => "readc",
+            Inst::Readi => "readi",
+            Inst::BurghardPrintStack => "debug_printstack",
+            Inst::BurghardPrintHeap => "debug_printheap",
+            Inst::VolivaOr => "or",
+            Inst::VolivaNot => "not",
+            Inst::VolivaAnd => "and",
+            Inst::VolivaBreakpoint => "dbg",
+        }
+    }
+
+    /// Gets the value of the argument of this instruction, if it has one.
+    pub fn arg(&self) -> Option<&Integer> {
+        match self {
+            Inst::Push(n) | Inst::Copy(n) | Inst::Slide(n) => Some(n.value()),
+            Inst::Label(l) | Inst::Call(l) | Inst::Jmp(l) | Inst::Jz(l) | Inst::Jn(l) => {
+                Some(l.value())
+            }
+            _ => None,
+        }
+    }
+}
+
 /// A signed integer value for code generation, encoded with explicit leading
 /// zeros.
 #[derive(Clone, Debug, PartialEq, Eq)]
@@ -87,17 +170,27 @@ pub struct LabelBits<'a>(pub(super) ArgBits<'a>);
 /// An integer or label for code generation.
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub(super) struct ArgBits<'a> {
-    pub(super) value: &'a Integer,
+    pub(super) value: Cow<'a, Integer>,
     pub(super) sign: Sign,
     pub(super) leading_zeros: usize,
 }
 
+impl ArgBits<'_> {
+    fn into_owned(self) -> ArgBits<'static> {
+        ArgBits {
+            value: Cow::Owned(self.value.into_owned()),
+            sign: self.sign,
+            leading_zeros: self.leading_zeros,
+        }
+    }
+}
+
 impl<'a> IntegerBits<'a> {
     /// Creates a signed integer, encoded with a number of leading zeros.
     #[inline]
-    pub const fn new(value: &'a Integer, leading_zeros: usize) -> Self {
+    pub fn new(value: &'a Integer, leading_zeros: usize) -> Self {
         IntegerBits(ArgBits {
-            value,
+            value: Cow::Borrowed(value),
             sign: if value.is_negative() {
                 Sign::Neg
             } else {
@@ -112,16 +205,30 @@ impl<'a> IntegerBits<'a> {
     pub fn zero(sign: Sign) -> Self {
         static ZERO: Integer = Integer::ZERO;
         IntegerBits(ArgBits {
-            value: &ZERO,
+            value: Cow::Borrowed(&ZERO),
             sign,
             leading_zeros: 0,
         })
     }
 
+    /// Creates an owned signed integer, encoded with an explicit sign and a
+    /// number of leading zeros. The sign must match the sign of the value,
+    /// unless it is zero.
+    #[inline]
+    pub fn with_sign(value: Integer, sign: Sign, leading_zeros: usize) -> IntegerBits<'static> {
+        debug_assert!(sign != Sign::None);
+        debug_assert!(value.is_zero() || value.is_negative() == (sign == Sign::Neg));
+        IntegerBits(ArgBits {
+            value: Cow::Owned(value),
+            sign,
+            leading_zeros,
+        })
+    }
+
     /// Gets the value of this integer.
     #[inline]
-    pub const fn value(&self) -> &'a Integer {
-        self.0.value
+    pub fn value(&self) -> &Integer {
+        &self.0.value
     }
 
     /// Gets the sign of this integer.
@@ -145,6 +252,23 @@ impl<'a> From<&'a Integer> for IntegerBits<'a> {
     }
 }
 
+/// Creates an owned signed integer, encoded with no leading zeros.
+impl From<Integer> for IntegerBits<'static> {
+    #[inline]
+    fn from(value: Integer) -> Self {
+        let sign = if value.is_negative() {
+            Sign::Neg
+        } else {
+            Sign::Pos
+        };
+        IntegerBits(ArgBits {
+            value: Cow::Owned(value),
+            sign,
+            leading_zeros: 0,
+        })
+    }
+}
+
 impl<'a> LabelBits<'a> {
     /// Creates an unsigned integer, encoded with no sign and a number of
     /// leading zeros.
@@ -152,7 +276,19 @@ impl<'a> LabelBits<'a> {
     pub fn new(value: &'a Integer, leading_zeros: usize) -> Self {
         debug_assert!(!value.is_negative());
         LabelBits(ArgBits {
-            value,
+            value: Cow::Borrowed(value),
+            sign: Sign::None,
+            leading_zeros,
+        })
+    }
+
+    /// Creates an owned unsigned integer, encoded with no sign and a number
+    /// of leading zeros.
+    #[inline]
+    pub fn owned(value: Integer, leading_zeros: usize) -> LabelBits<'static> {
+        debug_assert!(!value.is_negative());
+        LabelBits(ArgBits {
+            value: Cow::Owned(value),
             sign: Sign::None,
             leading_zeros,
         })
@@ -160,8 +296,8 @@ impl<'a> LabelBits<'a> {
 
     /// Gets the value of this label.
     #[inline]
-    pub const fn value(&self) -> &'a Integer {
-        self.0.value
+    pub fn value(&self) -> &Integer {
+        &self.0.value
     }
 
     /// Gets the number of leading zeros this label has.
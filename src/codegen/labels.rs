@@ -0,0 +1,87 @@
+//! Resolution of label names to integers.
+
+use std::{
+    collections::{HashMap, HashSet},
+    convert::Infallible,
+};
+
+use rug::Integer;
+
+use crate::{
+    codegen::CodegenConfig,
+    syntax::{Cst, Opcode},
+    tokens::Token,
+};
+
+// TODO:
+// - Implement the label numbering schemes of each dialect, e.g., Palaiologos
+//   numbers labels by most references.
+
+/// A mapping from label names to the integers they are encoded as.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct LabelMap<'a> {
+    labels: HashMap<&'a [u8], Integer>,
+    /// Labels internal to macro expansions, keyed by the index of the
+    /// instruction, as numbered by [`Cst::for_each_inst`], and the index of
+    /// the label in its expansion.
+    internal: HashMap<(usize, usize), Integer>,
+}
+
+impl<'a> LabelMap<'a> {
+    /// Assigns integers to the labels in the instructions enabled by the
+    /// options, incrementing from 0 in definition order. Labels internal to
+    /// macro expansions are defined where the macro is. Labels which are
+    /// referenced, but never defined, are assigned after, in order of first
+    /// reference; [`Cst::check_labels`] reports them.
+    pub fn new(cst: &'a Cst<'_>, options: &HashSet<&[u8]>, config: &CodegenConfig) -> Self {
+        let mut map = LabelMap::default();
+        let mut refs = Vec::new();
+        let Ok(()) = cst.for_each_inst::<Infallible>(options, &mut |index, inst| {
+            for i in 0..inst.len_args() {
+                if let Token::Label(label) = inst.arg(i).peel_groups() {
+                    if inst.opcode == Opcode::Label {
+                        map.insert(&label.label);
+                    } else {
+                        refs.push(&label.label[..]);
+                    }
+                }
+            }
+            for i in 0..inst.internal_labels(config, map.len()) {
+                map.internal.insert((index, i), Integer::from(map.len()));
+            }
+            Ok(())
+        });
+        for label in refs {
+            map.insert(label);
+        }
+        map
+    }
+
+    /// Assigns the next integer to a label, unless it is already assigned.
+    fn insert(&mut self, label: &'a [u8]) {
+        let next = Integer::from(self.len());
+        self.labels.entry(label).or_insert(next);
+    }
+
+    /// Gets the integer assigned to the indexed label internal to the expansion
+    /// of the macro instruction at an index, as numbered by
+    /// [`Cst::for_each_inst`].
+    pub fn get_internal(&self, inst: usize, index: usize) -> Option<&Integer> {
+        self.internal.get(&(inst, index))
+    }
+
+    /// Gets the integer assigned to a label.
+    pub fn get(&self, label: &[u8]) -> Option<&Integer> {
+        self.labels.get(label)
+    }
+
+    /// Returns the number of labels in this map.
+    pub fn len(&self) -> usize {
+        self.labels.len() + self.internal.len()
+    }
+
+    /// Returns whether this map has no labels.
+    pub fn is_empty(&self) -> bool {
+        self.labels.is_empty() && self.internal.is_empty()
+    }
+}
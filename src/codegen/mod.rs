@@ -1,8 +1,21 @@
 //! Whitespace code generation.
 
+mod annotate;
+mod encoding;
 mod inst;
+mod labels;
+mod rep;
+mod source_map;
+mod stream;
+mod validate;
 mod write;
 mod wsa;
 
+pub use encoding::*;
 pub use inst::*;
+pub use labels::*;
+pub use rep::*;
+pub use source_map::*;
+pub use stream::*;
+pub use validate::*;
 pub use write::*;
@@ -0,0 +1,336 @@
+//! Lowering of Palaiologos `rep` to unrolled instructions or a counter loop.
+
+use std::{
+    error::Error,
+    fmt::{self, Display, Formatter},
+};
+
+use rug::Integer;
+
+use crate::{
+    codegen::{Inst, InstSpans, InstWrite, IntegerBits, LabelBits, LabelMap},
+    syntax::{Cst, Inst as WsaInst, Opcode},
+    tokens::Token,
+};
+
+/// Configuration for code generation.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct CodegenConfig {
+    /// How to lower Palaiologos `rep`.
+    pub rep: RepConfig,
+}
+
+/// Configuration for lowering Palaiologos `rep op n`, which repeats `op` `n`
+/// times. The default always unrolls, like Palaiologos.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct RepConfig {
+    /// When to use a loop instead of unrolling, or `None` to always unroll.
+    pub loops: Option<RepLoop>,
+}
+
+/// Configuration for lowering `rep` to a loop, which counts down in a heap
+/// cell.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RepLoop {
+    /// Use a loop when the count is greater than this.
+    pub max_unroll: Option<u32>,
+    /// Use a loop when it would be shorter than unrolling.
+    pub by_size: bool,
+    /// The heap address of the loop counter. It must not otherwise be used by
+    /// the program and is 0 after the loop.
+    pub counter_address: Integer,
+}
+
+/// A `rep` instruction, for which code cannot be generated.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RepError {
+    /// The line of the instruction, starting at 1.
+    pub line: usize,
+    /// The kind of the error.
+    pub kind: RepErrorKind,
+}
+
+/// The kind of an error in a `rep` instruction.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum RepErrorKind {
+    /// The repeated opcode is not a primitive instruction without arguments.
+    Opcode(Opcode),
+    /// The count is out of the range of Palaiologos `int32_t`.
+    CountRange(Integer),
+    /// The arguments are not a mnemonic and an integer.
+    Args,
+}
+
+/// The number of labels internal to a `rep` loop.
+const LOOP_LABELS: usize = 2;
+
+impl Cst<'_> {
+    /// Checks that every `rep` instruction repeats a supported opcode with a
+    /// count in range, so code can be generated for it.
+    pub fn check_reps(&self) -> Vec<RepError> {
+        let spans = InstSpans::new(self);
+        let mut errors = Vec::new();
+        self.for_each_rep(&mut |inst| {
+            if let Err(kind) = inst.rep_args() {
+                errors.push(RepError {
+                    line: spans.get(inst).line,
+                    kind,
+                });
+            }
+        });
+        errors
+    }
+
+    fn for_each_rep(&self, f: &mut impl FnMut(&WsaInst<'_>)) {
+        match self {
+            Cst::Inst(inst) if inst.opcode == Opcode::PalaiologosRep => f(inst),
+            Cst::Inst(_) => {}
+            Cst::Block { nodes } => nodes.iter().for_each(|node| node.for_each_rep(f)),
+            Cst::OptionBlock(block) => {
+                for (_, nodes) in &block.options {
+                    nodes.iter().for_each(|node| node.for_each_rep(f));
+                }
+            }
+        }
+    }
+}
+
+impl WsaInst<'_> {
+    /// Returns the number of labels internal to the lowering of this
+    /// instruction, when the next label is assigned `next`.
+    pub(super) fn internal_labels(&self, config: &CodegenConfig, next: usize) -> usize {
+        if self.opcode == Opcode::PalaiologosRep {
+            let top = Integer::from(next);
+            let end = Integer::from(next + 1);
+            if self.rep_loops(&config.rep, &top, &end) {
+                LOOP_LABELS
+            } else {
+                0
+            }
+        } else {
+            self.opcode.internal_labels()
+        }
+    }
+
+    /// Generates `rep` as unrolled instructions or, when its internal labels
+    /// are assigned, a counter loop. Panics when it is invalid, as reported by
+    /// [`Cst::check_reps`].
+    pub(super) fn codegen_rep<T: InstWrite>(
+        &self,
+        w: &mut T,
+        index: usize,
+        labels: &LabelMap<'_>,
+        config: &RepConfig,
+    ) -> Result<(), T::Error> {
+        let (inst, count) = self.rep_args().unwrap_or_else(|kind| panic!("{kind}"));
+        let top = labels.get_internal(index, 0);
+        let end = labels.get_internal(index, 1);
+        if let (Some(top), Some(end), Some(loops)) = (top, end, &config.loops) {
+            let count = Integer::from(count);
+            for inst in rep_loop(inst, &count, &loops.counter_address, top, end) {
+                w.write_inst(inst)?;
+            }
+        } else {
+            for _ in 0..count {
+                w.write_inst(inst.clone())?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns whether `rep` is lowered to a loop with the labels.
+    fn rep_loops(&self, config: &RepConfig, top: &Integer, end: &Integer) -> bool {
+        let (Some(loops), Ok((inst, count))) = (&config.loops, self.rep_args()) else {
+            return false;
+        };
+        if count == 0 {
+            return false;
+        }
+        if loops.max_unroll.is_some_and(|max| count > max) {
+            return true;
+        }
+        if loops.by_size {
+            let count_int = Integer::from(count);
+            let loop_len = rep_loop(inst.clone(), &count_int, &loops.counter_address, top, end)
+                .map(|inst| inst_len(&inst))
+                .sum::<usize>();
+            let unrolled_len = inst_len(&inst).saturating_mul(count as usize);
+            return loop_len < unrolled_len;
+        }
+        false
+    }
+
+    /// Gets the repeated instruction and the count of `rep`. Counts are
+    /// `int32_t` and negative counts repeat 0 times.
+    pub fn rep_args(&self) -> Result<(Inst<'static>, u32), RepErrorKind> {
+        let (Token::Mnemonic(m), Token::Integer(count)) =
+            (self.arg(0).peel_groups(), self.arg(1).peel_groups())
+        else {
+            return Err(RepErrorKind::Args);
+        };
+        let inst = super::wsa::simple_inst(m.opcode).ok_or(RepErrorKind::Opcode(m.opcode))?;
+        let count =
+            (count.value.to_i32()).ok_or_else(|| RepErrorKind::CountRange(count.value.clone()))?;
+        Ok((inst, count.max(0) as u32))
+    }
+}
+
+impl Display for RepError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.kind)
+    }
+}
+
+impl Error for RepError {}
+
+impl Display for RepErrorKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            RepErrorKind::Opcode(opcode) => {
+                write!(f, "`rep` of unsupported opcode {}", opcode.name())
+            }
+            RepErrorKind::CountRange(count) => write!(f, "`rep` count out of range: {count}"),
+            RepErrorKind::Args => write!(f, "`rep` requires a mnemonic and a count"),
+        }
+    }
+}
+
+impl Error for RepErrorKind {}
+
+/// Constructs a loop, which executes the instruction `count` times:
+///
+/// ```wsa
+///     push addr; push count; store
+/// top:
+///     push addr; retrieve; jz end
+///     inst
+///     push addr; push addr; retrieve; push 1; sub; store
+///     jmp top
+/// end:
+/// ```
+fn rep_loop<'a>(
+    inst: Inst<'a>,
+    count: &'a Integer,
+    addr: &'a Integer,
+    top: &'a Integer,
+    end: &'a Integer,
+) -> impl Iterator<Item = Inst<'a>> {
+    let addr = || Inst::Push(IntegerBits::from(addr));
+    [
+        addr(),
+        Inst::Push(IntegerBits::from(count)),
+        Inst::Store,
+        Inst::Label(LabelBits::from(top)),
+        addr(),
+        Inst::Retrieve,
+        Inst::Jz(LabelBits::from(end)),
+        inst,
+        addr(),
+        addr(),
+        Inst::Retrieve,
+        Inst::Push(IntegerBits::from(Integer::ONE)),
+        Inst::Sub,
+        Inst::Store,
+        Inst::Jmp(LabelBits::from(top)),
+        Inst::Label(LabelBits::from(end)),
+    ]
+    .into_iter()
+}
+
+/// Computes the number of tokens in the encoding of an instruction.
+fn inst_len(inst: &Inst<'_>) -> usize {
+    let mut tokens = Vec::new();
+    let Ok(()) = tokens.write_inst(inst.clone());
+    tokens.len()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use rug::Integer;
+
+    use crate::{
+        codegen::{CodegenConfig, Inst, InstStream, RepConfig, RepError, RepErrorKind, RepLoop},
+        dialects::{Dialect as _, Palaiologos},
+        exec::{Program, Vm},
+        syntax::Opcode,
+    };
+
+    fn rep(src: &[u8], rep: RepConfig) -> (String, usize) {
+        let cst = Palaiologos::new().parse(src);
+        let mut stream = InstStream::new();
+        let config = CodegenConfig { rep };
+        let Ok(()) = cst.codegen_with_config(&mut stream, &HashSet::new(), &config);
+        let mut program = Program::new();
+        let Ok(()) = stream.write_to(&mut program);
+        let mut out = Vec::new();
+        Vm::new(&program, &b""[..], &mut out).run().unwrap();
+        let len = (stream.insts().iter())
+            .filter(|inst| matches!(inst, Inst::Dup))
+            .count();
+        (String::from_utf8(out).unwrap(), len)
+    }
+
+    fn loops(max_unroll: Option<u32>, by_size: bool) -> RepConfig {
+        RepConfig {
+            loops: Some(RepLoop {
+                max_unroll,
+                by_size,
+                counter_address: Integer::from(100),
+            }),
+        }
+    }
+
+    #[test]
+    fn unroll_or_loop() {
+        let src = b"push 2\nrep dup 40\nrep add 40\nputn\nend\n";
+        let unrolled = rep(src, RepConfig::default());
+        assert_eq!(unrolled, ("82".to_owned(), 40));
+
+        assert_eq!(rep(src, loops(None, true)), ("82".to_owned(), 1));
+
+        let short = b"push 2\nrep dup 2\nrep add 2\nputn\nend\n";
+        assert_eq!(rep(short, loops(None, true)), ("6".to_owned(), 2));
+        assert_eq!(rep(short, loops(Some(1), false)), ("6".to_owned(), 1));
+        assert_eq!(rep(short, loops(None, false)), ("6".to_owned(), 2));
+    }
+
+    #[test]
+    fn by_size_labels() {
+        // The loop is estimated with the labels it is assigned, which are
+        // longer after many labels.
+        let src = "push 2\nrep dup 35\nend\n";
+        assert_eq!(rep(src.as_bytes(), loops(None, true)).1, 1);
+        let labels = (0..64).map(|i| format!("@l{i}\n")).collect::<String>();
+        let src = labels + src;
+        assert_eq!(rep(src.as_bytes(), loops(None, true)).1, 35);
+    }
+
+    #[test]
+    fn check_reps() {
+        let cst = Palaiologos::new()
+            .parse(b"rep dup 3\nrep jmp 3\nrep dup 2147483648\nrep dup -1\nrep dup -99999999999\n");
+        assert_eq!(
+            cst.check_reps(),
+            [
+                RepError {
+                    line: 2,
+                    kind: RepErrorKind::Opcode(Opcode::Jmp),
+                },
+                RepError {
+                    line: 3,
+                    kind: RepErrorKind::CountRange(Integer::from(1u64 << 31)),
+                },
+                RepError {
+                    line: 5,
+                    kind: RepErrorKind::CountRange(Integer::from(-99999999999i64)),
+                },
+            ],
+        );
+        assert_eq!(
+            cst.check_reps()[1].to_string(),
+            "line 3: `rep` count out of range: 2147483648",
+        );
+    }
+}
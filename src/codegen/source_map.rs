@@ -0,0 +1,383 @@
+//! Source maps from generated Whitespace back to assembly instructions.
+
+use std::{
+    collections::{HashMap, HashSet},
+    error::Error,
+    fmt::{self, Display, Formatter},
+    io::{self, Write},
+    ops::Range,
+    str::FromStr,
+};
+
+use crate::{
+    codegen::{CodegenConfig, Inst, InstStream, InstWrite, LabelMap, Peephole, Token},
+    syntax::{Cst, Inst as WsaInst, Opcode, Pretty},
+};
+
+/// The header line of a serialized source map.
+const HEADER: &str = "omniwsa source map v1";
+
+/// A mapping from each Whitespace instruction in generated output to the
+/// assembly instruction which produced it.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct SourceMap {
+    /// The entries, in output order.
+    pub entries: Vec<SourceMapEntry>,
+}
+
+/// A Whitespace instruction in generated output and its source.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SourceMapEntry {
+    /// The byte range of the instruction in the output, where each token is
+    /// one byte.
+    pub output: Range<usize>,
+    /// The span of the assembly instruction, which produced it.
+    pub source: Span,
+    /// The opcode of the assembly instruction, which produced it.
+    pub opcode: Opcode,
+}
+
+/// A span of an assembly instruction in its source text, from the start of
+/// its first word to the end of its last word.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Span {
+    /// The byte offset of the start.
+    pub start: usize,
+    /// The byte offset of the end.
+    pub end: usize,
+    /// The line of the start, starting at 1.
+    pub line: usize,
+}
+
+/// An error from parsing a serialized source map.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SourceMapError {
+    /// The line of the error, starting at 1.
+    pub line: usize,
+    /// The kind of the error.
+    pub kind: SourceMapErrorKind,
+}
+
+/// The kind of an error from parsing a serialized source map.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SourceMapErrorKind {
+    /// The header line is missing or has an unsupported version.
+    Header,
+    /// An entry does not have the expected number of fields.
+    FieldCount,
+    /// A field is not an integer.
+    Integer(String),
+    /// An unknown opcode name.
+    Opcode(String),
+}
+
+impl<'s> Cst<'s> {
+    /// Generates a stream of Whitespace tokens for this CST, like
+    /// [`Cst::codegen_with_config`] through an [`InstStream`] with the
+    /// optimizations, and records the source of each instruction.
+    pub fn codegen_with_source_map<T: InstWrite>(
+        &self,
+        w: &mut T,
+        options: &HashSet<&[u8]>,
+        config: &CodegenConfig,
+        peephole: Peephole,
+    ) -> Result<SourceMap, T::Error> {
+        let labels = LabelMap::new(self, options, config);
+        let spans = InstSpans::new(self);
+        let mut stream = InstStream::with_peephole(peephole);
+        let mut sources = Vec::new();
+        let Ok(()) = self.for_each_inst(options, &mut |i, inst| {
+            sources.push((spans.get(inst), inst.opcode));
+            stream.set_source(i);
+            inst.codegen(&mut stream, i, &labels, config)
+        });
+        let mut w = SourceMapWriter {
+            inner: w,
+            offset: 0,
+            scratch: Vec::new(),
+            sources: stream.sources().iter().map(|&i| sources[i]).collect(),
+            map: SourceMap::default(),
+        };
+        stream.write_to(&mut w)?;
+        Ok(w.map)
+    }
+}
+
+impl SourceMap {
+    /// Gets the entry for the instruction containing the output offset.
+    pub fn find(&self, offset: usize) -> Option<&SourceMapEntry> {
+        let i = self.entries.partition_point(|e| e.output.end <= offset);
+        self.entries.get(i).filter(|e| e.output.contains(&offset))
+    }
+
+    /// Serializes this source map in a line-oriented text format, with a
+    /// header line followed by one line per entry:
+    /// `output_start output_end source_start source_end line opcode`.
+    pub fn write<W: Write>(&self, mut w: W) -> io::Result<()> {
+        writeln!(w, "{HEADER}")?;
+        for e in &self.entries {
+            writeln!(
+                w,
+                "{} {} {} {} {} {}",
+                e.output.start,
+                e.output.end,
+                e.source.start,
+                e.source.end,
+                e.source.line,
+                e.opcode.name(),
+            )?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for SourceMap {
+    type Err = SourceMapError;
+
+    /// Parses a source map serialized by [`SourceMap::write`].
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut lines = s.lines().enumerate();
+        if lines.next().is_none_or(|(_, header)| header != HEADER) {
+            return Err(SourceMapError {
+                line: 1,
+                kind: SourceMapErrorKind::Header,
+            });
+        }
+        let mut entries = Vec::new();
+        for (i, line) in lines {
+            let err = |kind| SourceMapError { line: i + 1, kind };
+            if line.is_empty() {
+                continue;
+            }
+            let fields = line.split(' ').collect::<Vec<_>>();
+            let &[output_start, output_end, start, end, src_line, opcode] = &fields[..] else {
+                return Err(err(SourceMapErrorKind::FieldCount));
+            };
+            let int = |field: &str| {
+                (field.parse::<usize>())
+                    .map_err(|_| err(SourceMapErrorKind::Integer(field.to_owned())))
+            };
+            entries.push(SourceMapEntry {
+                output: int(output_start)?..int(output_end)?,
+                source: Span {
+                    start: int(start)?,
+                    end: int(end)?,
+                    line: int(src_line)?,
+                },
+                opcode: Opcode::from_name(opcode)
+                    .ok_or_else(|| err(SourceMapErrorKind::Opcode(opcode.to_owned())))?,
+            });
+        }
+        Ok(SourceMap { entries })
+    }
+}
+
+impl Display for SourceMapError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: ", self.line)?;
+        match &self.kind {
+            SourceMapErrorKind::Header => write!(f, "expected header {HEADER:?}"),
+            SourceMapErrorKind::FieldCount => write!(f, "expected 6 fields"),
+            SourceMapErrorKind::Integer(field) => write!(f, "invalid integer {field:?}"),
+            SourceMapErrorKind::Opcode(name) => write!(f, "unknown opcode {name:?}"),
+        }
+    }
+}
+
+impl Error for SourceMapError {}
+
+/// An instruction writer, which records the source of each instruction
+/// written through it.
+struct SourceMapWriter<'w, T> {
+    inner: &'w mut T,
+    /// The offset in the output of the next token.
+    offset: usize,
+    /// A buffer for encoding instructions to measure them.
+    scratch: Vec<Token>,
+    /// The source of each instruction to be written.
+    sources: Vec<(Span, Opcode)>,
+    map: SourceMap,
+}
+
+impl<T: InstWrite> InstWrite for SourceMapWriter<'_, T> {
+    type Error = T::Error;
+
+    fn write_inst(&mut self, inst: Inst<'_>) -> Result<(), Self::Error> {
+        self.scratch.clear();
+        let Ok(()) = self.scratch.write_inst(inst.clone());
+        let start = self.offset;
+        self.offset += self.scratch.len();
+        let (source, opcode) = self.sources[self.map.entries.len()];
+        self.map.entries.push(SourceMapEntry {
+            output: start..self.offset,
+            source,
+            opcode,
+        });
+        self.inner.write_inst(inst)
+    }
+}
+
+/// The spans of every instruction in a CST, including those in disabled
+/// option blocks, keyed by address.
+pub(crate) struct InstSpans(HashMap<usize, Span>);
+
+impl InstSpans {
+    /// Computes the span of every instruction from the text it pretty-prints
+    /// as, which is the same as its source.
+    pub(crate) fn new(cst: &Cst<'_>) -> Self {
+        let mut spans = SpanCollector {
+            spans: HashMap::new(),
+            offset: 0,
+            line: 1,
+            buf: Vec::new(),
+        };
+        spans.cst(cst);
+        InstSpans(spans.spans)
+    }
+
+    /// Gets the span of an instruction in the CST.
+    pub(crate) fn get(&self, inst: &WsaInst<'_>) -> Span {
+        self.0[&inst_key(inst)]
+    }
+}
+
+struct SpanCollector {
+    spans: HashMap<usize, Span>,
+    offset: usize,
+    line: usize,
+    buf: Vec<u8>,
+}
+
+impl SpanCollector {
+    fn cst(&mut self, cst: &Cst<'_>) {
+        match cst {
+            Cst::Inst(inst) => self.inst(inst),
+            Cst::Block { nodes } => nodes.iter().for_each(|node| self.cst(node)),
+            Cst::OptionBlock(block) => {
+                for (option, nodes) in &block.options {
+                    self.inst(option);
+                    nodes.iter().for_each(|node| self.cst(node));
+                }
+                if let Some(end) = &block.end {
+                    self.inst(end);
+                }
+            }
+        }
+    }
+
+    fn inst(&mut self, inst: &WsaInst<'_>) {
+        self.advance(&inst.words.space_before);
+        let (start, line) = (self.offset, self.line);
+        let mut end = start;
+        for (word, space) in &inst.words.words {
+            self.advance(word);
+            end = self.offset;
+            self.advance(space);
+        }
+        self.spans.insert(inst_key(inst), Span { start, end, line });
+    }
+
+    fn advance<T: Pretty>(&mut self, node: &T) {
+        self.buf.clear();
+        node.pretty(&mut self.buf);
+        self.offset += self.buf.len();
+        self.line += self.buf.iter().filter(|&&b| b == b'\n').count();
+    }
+}
+
+/// Identifies an instruction by its address, which is stable for the lifetime
+/// of the borrowed CST.
+fn inst_key(inst: &WsaInst<'_>) -> usize {
+    inst as *const WsaInst<'_> as usize
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use crate::{
+        codegen::{CodegenConfig, InstStream, Peephole, SourceMap, Span},
+        dialects::{Burghard, Dialect, Palaiologos},
+        syntax::Opcode,
+    };
+
+    #[test]
+    fn source_map() {
+        let src = b"push 1 ; one\n\n  jumpp l\nlabel l\nexit\n";
+        let cst = Burghard::new().parse(src);
+        let mut out = String::new();
+        let map = cst
+            .codegen_with_source_map(
+                &mut out,
+                &HashSet::new(),
+                &CodegenConfig::default(),
+                Peephole::NONE,
+            )
+            .unwrap();
+
+        let push = &map.entries[0];
+        assert_eq!(push.opcode, Opcode::Push);
+        assert_eq!(&src[push.source.start..push.source.end], b"push 1");
+        assert_eq!(&out[push.output.clone()], "   \t\n");
+
+        let jumpp = (map.entries.iter())
+            .filter(|e| e.opcode == Opcode::BurghardJmpPos)
+            .collect::<Vec<_>>();
+        assert_eq!(jumpp.len(), 8);
+        let span = Span {
+            start: 16,
+            end: 23,
+            line: 3,
+        };
+        assert!(jumpp.iter().all(|e| e.source == span));
+        assert_eq!(&src[span.start..span.end], b"jumpp l");
+
+        let end = map.entries.last().unwrap();
+        assert_eq!((end.opcode, end.source.line), (Opcode::End, 5));
+        assert_eq!(end.output.end, out.len());
+        assert_eq!(map.find(end.output.start).unwrap().opcode, Opcode::End);
+        assert_eq!(map.find(out.len()), None);
+
+        let mut text = Vec::new();
+        map.write(&mut text).unwrap();
+        let parsed = str::from_utf8(&text).unwrap().parse::<SourceMap>();
+        assert_eq!(parsed, Ok(map));
+    }
+
+    #[test]
+    fn peephole() {
+        let src = b"push 1\npush 2\nswap\nswap\nsub\nputn\n";
+        let cst = Palaiologos::new().parse(src);
+        let (options, config) = (HashSet::new(), CodegenConfig::default());
+        let codegen = |peephole| {
+            let mut stream = InstStream::with_peephole(peephole);
+            let Ok(()) = cst.codegen_with_config(&mut stream, &options, &config);
+            let mut expect = String::new();
+            stream.write_to(&mut expect).unwrap();
+            let mut out = String::new();
+            let map = cst
+                .codegen_with_source_map(&mut out, &options, &config, peephole)
+                .unwrap();
+            assert_eq!(out, expect);
+            assert_eq!(map.entries.last().unwrap().output.end, out.len());
+            (map.entries.iter())
+                .map(|e| (e.opcode, e.source.line))
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            codegen(Palaiologos::PEEPHOLE),
+            [
+                (Opcode::Push, 1),
+                (Opcode::Push, 2),
+                (Opcode::Sub, 5),
+                (Opcode::Printi, 6),
+            ],
+        );
+        // A folded instruction maps to the last instruction folded into it.
+        assert_eq!(
+            codegen(Peephole::ALL),
+            [(Opcode::Sub, 5), (Opcode::Printi, 6)],
+        );
+    }
+}
@@ -0,0 +1,301 @@
+//! Buffered streams of Whitespace instructions with peephole optimization.
+
+use std::convert::Infallible;
+
+use rug::{
+    Integer,
+    ops::{DivRounding, RemRounding},
+};
+
+use crate::codegen::{Inst, InstWrite, IntegerBits};
+
+// TODO:
+// - Fold `push a / push b / swap` → `push b / push a`.
+// - Remove unreachable instructions after `jmp`, `ret`, and `end`.
+// - Allow dialect configs to select their strict optimizations.
+
+/// A buffered stream of Whitespace instructions, which are optimized as they
+/// are appended.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct InstStream {
+    insts: Vec<Inst<'static>>,
+    /// Comments and the index of the instruction they precede. Comments, which
+    /// precede removed instructions, are kept.
+    comments: Vec<(usize, String)>,
+    /// The source of each instruction, as set by [`InstStream::set_source`].
+    sources: Vec<usize>,
+    /// The source of the next instructions.
+    source: usize,
+    peephole: Peephole,
+}
+
+/// The peephole optimizations to apply to an instruction stream. Each fold is
+/// toggled individually. The default applies none, which is compatible with
+/// every dialect, and [`Dialect::PEEPHOLE`](crate::dialects::Dialect::PEEPHOLE)
+/// applies exactly those a dialect always applies.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Peephole {
+    /// Remove `swap / swap`. Palaiologos always applies it.
+    pub swap_swap: bool,
+    /// Fold `push a / push b / op` → `push (a op b)` for `add`, `sub`, `mul`,
+    /// and, when `b` is not 0, `div` and `mod`.
+    pub const_fold: bool,
+    /// Remove `dup / drop`.
+    pub dup_drop: bool,
+    /// Remove `slide 0`.
+    pub slide_zero: bool,
+    /// Remove a `jmp` to a label defined immediately after it.
+    pub jmp_next: bool,
+}
+
+impl Peephole {
+    /// No optimizations.
+    pub const NONE: Peephole = Peephole {
+        swap_swap: false,
+        const_fold: false,
+        dup_drop: false,
+        slide_zero: false,
+        jmp_next: false,
+    };
+
+    /// All optimizations.
+    pub const ALL: Peephole = Peephole {
+        swap_swap: true,
+        const_fold: true,
+        dup_drop: true,
+        slide_zero: true,
+        jmp_next: true,
+    };
+
+    /// Enables the optimizations of both.
+    pub fn union(self, other: Peephole) -> Peephole {
+        Peephole {
+            swap_swap: self.swap_swap || other.swap_swap,
+            const_fold: self.const_fold || other.const_fold,
+            dup_drop: self.dup_drop || other.dup_drop,
+            slide_zero: self.slide_zero || other.slide_zero,
+            jmp_next: self.jmp_next || other.jmp_next,
+        }
+    }
+}
+
+impl InstStream {
+    /// Constructs an empty stream, which applies no optimizations.
+    pub fn new() -> Self {
+        InstStream::default()
+    }
+
+    /// Constructs an empty stream, which applies the optimizations.
+    pub fn with_peephole(peephole: Peephole) -> Self {
+        InstStream {
+            insts: Vec::new(),
+            comments: Vec::new(),
+            sources: Vec::new(),
+            source: 0,
+            peephole,
+        }
+    }
+
+    /// Sets the source of the instructions appended after, e.g., the index of
+    /// the assembly instruction being generated. An instruction produced by
+    /// folding others takes the source of the last.
+    pub fn set_source(&mut self, source: usize) {
+        self.source = source;
+    }
+
+    /// Appends an instruction and optimizes the end of the stream.
+    pub fn push(&mut self, inst: Inst<'_>) {
+        if self.peephole.slide_zero
+            && let Inst::Slide(n) = &inst
+            && n.value().is_zero()
+        {
+            return;
+        }
+        self.insts.push(inst.into_owned());
+        self.sources.push(self.source);
+        while self.optimize_tail() {}
+        let len = self.insts.len();
+        for (i, _) in self.comments.iter_mut().rev() {
+            if *i <= len {
+                break;
+            }
+            *i = len;
+        }
+    }
+
+    /// Gets the instructions in this stream.
+    pub fn insts(&self) -> &[Inst<'static>] {
+        &self.insts
+    }
+
+    /// Gets the source of each instruction in this stream.
+    pub fn sources(&self) -> &[usize] {
+        &self.sources
+    }
+
+    /// Appends a comment before the next instruction.
+    pub fn push_comment(&mut self, comment: &str) {
+        self.comments.push((self.insts.len(), comment.to_owned()));
+    }
+
+    /// Writes the instructions and comments in this stream.
+    pub fn write_to<T: InstWrite>(&self, w: &mut T) -> Result<(), T::Error> {
+        let mut comments = self.comments.iter().peekable();
+        for (i, inst) in self.insts.iter().enumerate() {
+            while let Some((_, comment)) = comments.next_if(|&&(j, _)| j <= i) {
+                w.write_comment(comment)?;
+            }
+            w.write_inst(inst.clone())?;
+        }
+        for (_, comment) in comments {
+            w.write_comment(comment)?;
+        }
+        Ok(())
+    }
+
+    /// Applies one optimization to the end of the stream. Returns whether it
+    /// changed.
+    fn optimize_tail(&mut self) -> bool {
+        let p = self.peephole;
+        let len = self.insts.len();
+        match &self.insts[..] {
+            [.., Inst::Swap, Inst::Swap] if p.swap_swap => {}
+            [.., Inst::Dup, Inst::Drop] if p.dup_drop => {}
+            [.., Inst::Push(a), Inst::Push(b), op] if p.const_fold => {
+                let Some(n) = const_fold(a.value(), b.value(), op) else {
+                    return false;
+                };
+                self.insts.truncate(len - 3);
+                self.insts.push(Inst::Push(IntegerBits::from(n)));
+                self.sources.truncate(len - 3);
+                self.sources.push(self.source);
+                return true;
+            }
+            [.., Inst::Label(_)] if p.jmp_next => {
+                let labels = (self.insts.iter().rev())
+                    .take_while(|inst| matches!(inst, Inst::Label(_)))
+                    .count();
+                let Some(Inst::Jmp(target)) = self.insts.get(len.wrapping_sub(labels + 1)) else {
+                    return false;
+                };
+                let is_next = self.insts[len - labels..]
+                    .iter()
+                    .any(|inst| matches!(inst, Inst::Label(l) if l.value() == target.value()));
+                if !is_next {
+                    return false;
+                }
+                self.insts.remove(len - labels - 1);
+                self.sources.remove(len - labels - 1);
+                return true;
+            }
+            _ => return false,
+        }
+        self.insts.truncate(len - 2);
+        self.sources.truncate(len - 2);
+        true
+    }
+}
+
+/// Evaluates a binary arithmetic instruction on constant operands.
+fn const_fold(a: &Integer, b: &Integer, op: &Inst<'_>) -> Option<Integer> {
+    Some(match op {
+        Inst::Add => Integer::from(a + b),
+        Inst::Sub => Integer::from(a - b),
+        Inst::Mul => Integer::from(a * b),
+        Inst::Div if !b.is_zero() => a.clone().div_floor(b),
+        Inst::Mod if !b.is_zero() => a.clone().rem_floor(b),
+        _ => return None,
+    })
+}
+
+/// Collects instructions from code generation.
+impl InstWrite for InstStream {
+    type Error = Infallible;
+
+    fn write_inst(&mut self, inst: Inst<'_>) -> Result<(), Self::Error> {
+        self.push(inst);
+        Ok(())
+    }
+
+    fn write_comment(&mut self, comment: &str) -> Result<(), Self::Error> {
+        self.push_comment(comment);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use crate::{
+        codegen::{Inst, InstStream, IntegerBits, Peephole},
+        dialects::{Burghard, Dialect, Palaiologos},
+        exec::{Program, Vm},
+        tokens::integer::Sign,
+    };
+
+    /// Assembles and optimizes the program, then returns its output and the
+    /// number of instructions.
+    fn optimize<D: Dialect>(src: &[u8], peephole: Peephole) -> (String, usize) {
+        let cst = D::new().parse(src);
+        let mut stream = InstStream::with_peephole(peephole);
+        let Ok(()) = cst.codegen(&mut stream, &HashSet::new());
+        let mut program = Program::new();
+        let Ok(()) = stream.write_to(&mut program);
+        let mut out = Vec::new();
+        Vm::new(&program, &b""[..], &mut out).run().unwrap();
+        (String::from_utf8(out).unwrap(), stream.insts().len())
+    }
+
+    #[test]
+    fn palaiologos_strict() {
+        let src = b"push 1\npush 2\nswap\nswap\nsub\nputn\nend\n";
+        let strict = optimize::<Palaiologos>(src, Palaiologos::PEEPHOLE);
+        assert_eq!(strict, ("-1".to_owned(), 5));
+        let none = optimize::<Palaiologos>(src, Peephole::NONE);
+        assert_eq!(none, ("-1".to_owned(), 7));
+        assert_eq!(Burghard::PEEPHOLE, Peephole::NONE);
+    }
+
+    #[test]
+    fn folds() {
+        let src = b"push 7
+push 2
+push 3
+mul
+sub
+push -2
+mod
+doub
+pop
+jump l
+label m
+label l
+outN
+exit
+";
+        assert_eq!(
+            optimize::<Burghard>(src, Peephole::NONE),
+            ("-1".to_owned(), 14)
+        );
+        assert_eq!(
+            optimize::<Burghard>(src, Peephole::ALL),
+            ("-1".to_owned(), 5)
+        );
+        let dup_drop = Peephole {
+            dup_drop: true,
+            ..Peephole::NONE
+        };
+        assert_eq!(optimize::<Burghard>(src, dup_drop), ("-1".to_owned(), 12));
+
+        let div_zero = b"push 1\npush 0\ndiv\nexit\n";
+        let cst = Burghard::new().parse(div_zero);
+        let mut stream = InstStream::with_peephole(Peephole::ALL);
+        let Ok(()) = cst.codegen(&mut stream, &HashSet::new());
+        assert_eq!(stream.insts().len(), 4);
+
+        let mut stream = InstStream::with_peephole(Peephole::ALL);
+        stream.push(Inst::Slide(IntegerBits::zero(Sign::Neg)));
+        assert!(stream.insts().is_empty());
+    }
+}
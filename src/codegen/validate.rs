@@ -0,0 +1,469 @@
+//! Validation of syntax, label references, and integer arguments against
+//! dialect and interpreter limits.
+
+use std::{
+    collections::HashSet,
+    convert::Infallible,
+    error::Error,
+    fmt::{self, Display, Formatter},
+};
+
+use bstr::ByteSlice;
+use rug::Integer;
+
+use crate::{
+    codegen::InstSpans,
+    syntax::{ArgType, Cst, HasError, Inst, Opcode, OptionBlock},
+    tokens::{
+        Token,
+        integer::{IntegerError, IntegerSyntax},
+    },
+};
+
+/// The integer limits of a target Whitespace interpreter.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct TargetLimits {
+    /// The range of values, or `None` when unbounded.
+    pub integer: Option<(Integer, Integer)>,
+    /// The range of `copy` and `slide` indices, or `None` when unbounded.
+    /// Indices outside of this range wrap.
+    pub index: Option<(Integer, Integer)>,
+}
+
+/// An argument out of range of the dialect or target interpreter.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RangeError {
+    /// The line of the instruction, starting at 1.
+    pub line: usize,
+    /// The opcode of the instruction.
+    pub opcode: Opcode,
+    /// The value of the argument.
+    pub value: Integer,
+    /// The kind of the error.
+    pub kind: RangeErrorKind,
+}
+
+/// The kind of a range error.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RangeErrorKind {
+    /// The value is out of the range the dialect assembler accepts.
+    Dialect,
+    /// The value is out of the range the target interpreter supports.
+    Target,
+    /// A `copy` or `slide` index wraps in the target interpreter. This is a
+    /// warning.
+    IndexWrap,
+}
+
+/// An instruction with syntax errors, for which code cannot be generated.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SyntaxError {
+    /// The line of the instruction, starting at 1.
+    pub line: usize,
+    /// The opcode of the instruction.
+    pub opcode: Opcode,
+}
+
+/// A reference to a label, which no enabled instruction defines.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LabelError {
+    /// The line of the instruction, starting at 1.
+    pub line: usize,
+    /// The opcode of the instruction.
+    pub opcode: Opcode,
+    /// The undefined label, without its sigil.
+    pub label: Vec<u8>,
+}
+
+impl TargetLimits {
+    /// Limits for an interpreter with arbitrary-precision integers and
+    /// indices, like [`Vm`](crate::exec::Vm).
+    pub fn unbounded() -> Self {
+        TargetLimits::default()
+    }
+
+    /// Limits for the Haskell reference interpreter, which has
+    /// arbitrary-precision `Integer` values and 64-bit `Int` indices.
+    pub fn haskell() -> Self {
+        TargetLimits {
+            integer: None,
+            index: Some((Integer::from(i64::MIN), Integer::from(i64::MAX))),
+        }
+    }
+
+    /// Limits for an interpreter with 32-bit integers, like Palaiologos.
+    pub fn int32() -> Self {
+        let range = (Integer::from(i32::MIN), Integer::from(i32::MAX));
+        TargetLimits {
+            integer: Some(range.clone()),
+            index: Some(range),
+        }
+    }
+
+    /// Limits for an interpreter with 64-bit integers.
+    pub fn int64() -> Self {
+        let range = (Integer::from(i64::MIN), Integer::from(i64::MAX));
+        TargetLimits {
+            integer: Some(range.clone()),
+            index: Some(range),
+        }
+    }
+}
+
+impl Cst<'_> {
+    /// Checks that the integer arguments of every instruction are in the range
+    /// of the dialect and the target interpreter, and that `copy` and `slide`
+    /// indices do not wrap.
+    pub fn check_ranges(&self, integers: &IntegerSyntax, target: &TargetLimits) -> Vec<RangeError> {
+        let mut checker = RangeChecker {
+            spans: InstSpans::new(self),
+            integers,
+            target,
+            errors: Vec::new(),
+        };
+        checker.cst(self);
+        checker.errors
+    }
+}
+
+impl Cst<'_> {
+    /// Checks that every instruction is free of syntax errors and that option
+    /// blocks are well-formed. A malformed option block is reported at its
+    /// first branch.
+    pub fn check_syntax(&self) -> Vec<SyntaxError> {
+        let mut checker = SyntaxChecker {
+            spans: InstSpans::new(self),
+            errors: Vec::new(),
+        };
+        checker.cst(self);
+        checker.errors
+    }
+}
+
+impl Cst<'_> {
+    /// Checks that every label referenced by an instruction enabled by the
+    /// options is defined by an enabled instruction.
+    pub fn check_labels(&self, options: &HashSet<&[u8]>) -> Vec<LabelError> {
+        let mut defined = HashSet::new();
+        let mut refs = Vec::new();
+        let Ok(()) = self.for_each_inst::<Infallible>(options, &mut |_, inst| {
+            for i in 0..inst.len_args() {
+                if let Token::Label(label) = inst.arg(i).peel_groups() {
+                    if inst.opcode == Opcode::Label {
+                        defined.insert(&label.label[..]);
+                    } else {
+                        refs.push((inst, &label.label[..]));
+                    }
+                }
+            }
+            Ok(())
+        });
+        let spans = InstSpans::new(self);
+        (refs.into_iter())
+            .filter(|(_, label)| !defined.contains(label))
+            .map(|(inst, label)| LabelError {
+                line: spans.get(inst).line,
+                opcode: inst.opcode,
+                label: label.to_vec(),
+            })
+            .collect()
+    }
+}
+
+impl RangeError {
+    /// Returns whether this is a warning, instead of an error.
+    pub fn is_warning(&self) -> bool {
+        self.kind == RangeErrorKind::IndexWrap
+    }
+}
+
+impl Display for RangeError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "line {}: {} argument {} ",
+            self.line,
+            self.opcode.name(),
+            self.value
+        )?;
+        match self.kind {
+            RangeErrorKind::Dialect => write!(f, "is out of range for the dialect"),
+            RangeErrorKind::Target => write!(f, "is out of range for the target interpreter"),
+            RangeErrorKind::IndexWrap => write!(f, "wraps in the target interpreter"),
+        }
+    }
+}
+
+impl Error for RangeError {}
+
+impl Display for SyntaxError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "line {}: {} has syntax errors",
+            self.line,
+            self.opcode.name()
+        )
+    }
+}
+
+impl Error for SyntaxError {}
+
+impl Display for LabelError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "line {}: {} references undefined label {:?}",
+            self.line,
+            self.opcode.name(),
+            self.label.as_bstr(),
+        )
+    }
+}
+
+impl Error for LabelError {}
+
+struct SyntaxChecker {
+    spans: InstSpans,
+    errors: Vec<SyntaxError>,
+}
+
+impl SyntaxChecker {
+    fn cst(&mut self, cst: &Cst<'_>) {
+        match cst {
+            Cst::Inst(inst) => self.inst(inst),
+            Cst::Block { nodes } => nodes.iter().for_each(|node| self.cst(node)),
+            Cst::OptionBlock(block) => self.option_block(block),
+        }
+    }
+
+    fn option_block(&mut self, block: &OptionBlock<'_>) {
+        let errors = self.errors.len();
+        for (option, nodes) in &block.options {
+            self.inst(option);
+            nodes.iter().for_each(|node| self.cst(node));
+        }
+        if let Some(end) = &block.end {
+            self.inst(end);
+        }
+        if self.errors.len() == errors
+            && block.has_error()
+            && let Some(first) = block
+                .options
+                .first()
+                .map(|(inst, _)| inst)
+                .or(block.end.as_ref())
+        {
+            self.push(first);
+        }
+    }
+
+    fn inst(&mut self, inst: &Inst<'_>) {
+        // Integers out of range are reported by `Cst::check_ranges`.
+        let word_error = |word: &Token<'_>| match word {
+            Token::Integer(int) => int.errors.iter().any(|err| err != IntegerError::Range),
+            _ => word.has_error(),
+        };
+        let has_error = !inst.errors.is_empty()
+            || inst.opcode == Opcode::Invalid
+            || inst.words.space_before.has_error()
+            || (inst.words.words.iter()).any(|(word, space)| word_error(word) || space.has_error());
+        if has_error {
+            self.push(inst);
+        }
+    }
+
+    fn push(&mut self, inst: &Inst<'_>) {
+        self.errors.push(SyntaxError {
+            line: self.spans.get(inst).line,
+            opcode: inst.opcode,
+        });
+    }
+}
+
+struct RangeChecker<'a> {
+    spans: InstSpans,
+    integers: &'a IntegerSyntax,
+    target: &'a TargetLimits,
+    errors: Vec<RangeError>,
+}
+
+impl RangeChecker<'_> {
+    fn cst(&mut self, cst: &Cst<'_>) {
+        match cst {
+            Cst::Inst(inst) => self.inst(inst),
+            Cst::Block { nodes } => nodes.iter().for_each(|node| self.cst(node)),
+            Cst::OptionBlock(block) => {
+                for (_, nodes) in &block.options {
+                    nodes.iter().for_each(|node| self.cst(node));
+                }
+            }
+        }
+    }
+
+    fn inst(&mut self, inst: &Inst<'_>) {
+        let arg_types = match inst.overload {
+            Some(overload) => overload.arg_types(),
+            None => inst.opcode.arg_types(),
+        };
+        for (i, &ty) in arg_types.iter().enumerate().take(inst.len_args()) {
+            let Token::Integer(int) = inst.arg(i).peel_groups() else {
+                continue;
+            };
+            if ty != ArgType::Integer {
+                continue;
+            }
+            let kind = if !in_range(
+                &int.value,
+                self.integers.min_value.as_ref(),
+                self.integers.max_value.as_ref(),
+            ) {
+                RangeErrorKind::Dialect
+            } else if !in_limits(&int.value, self.target.integer.as_ref()) {
+                RangeErrorKind::Target
+            } else if matches!(inst.opcode, Opcode::Copy | Opcode::Slide)
+                && !in_limits(&int.value, self.target.index.as_ref())
+            {
+                RangeErrorKind::IndexWrap
+            } else {
+                continue;
+            };
+            self.errors.push(RangeError {
+                line: self.spans.get(inst).line,
+                opcode: inst.opcode,
+                value: int.value.clone(),
+                kind,
+            });
+        }
+    }
+}
+
+fn in_range(value: &Integer, min: Option<&Integer>, max: Option<&Integer>) -> bool {
+    min.is_none_or(|min| value >= min) && max.is_none_or(|max| value <= max)
+}
+
+fn in_limits(value: &Integer, limits: Option<&(Integer, Integer)>) -> bool {
+    limits.is_none_or(|(min, max)| in_range(value, Some(min), Some(max)))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use rug::Integer;
+
+    use crate::{
+        codegen::{RangeErrorKind, TargetLimits},
+        dialects::{Burghard, Dialect as _, Palaiologos},
+        syntax::Opcode,
+    };
+
+    #[test]
+    fn check_syntax() {
+        let src = b"push 1
+push 1 2
+doub
+ifoption a
+    pop
+";
+        let errors = Burghard::new().parse(src).check_syntax();
+        let lines = (errors.iter())
+            .map(|err| (err.line, err.opcode))
+            .collect::<Vec<_>>();
+        assert_eq!(lines, [(2, Opcode::Push), (4, Opcode::IfOption)]);
+        assert_eq!(errors[0].to_string(), "line 2: Push has syntax errors");
+        assert!(Burghard::new().parse(b"push 1\n").check_syntax().is_empty());
+        let cst = Palaiologos::new().parse(b"push 5000000000\n");
+        assert!(cst.check_syntax().is_empty());
+    }
+
+    #[test]
+    fn check_labels() {
+        let src = b"ifoption a
+    label a
+endoption
+jump a
+call c
+label c
+jumpz d
+";
+        let cst = Burghard::new().parse(src);
+        let errors = |options: &[&str]| {
+            let options = options.iter().map(|o| o.as_bytes()).collect();
+            (cst.check_labels(&options).into_iter())
+                .map(|err| (err.line, err.opcode, err.label))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            errors(&[]),
+            [
+                (4, Opcode::Jmp, b"a".to_vec()),
+                (7, Opcode::Jz, b"d".to_vec())
+            ],
+        );
+        assert_eq!(errors(&["a"]), [(7, Opcode::Jz, b"d".to_vec())]);
+        assert_eq!(
+            cst.check_labels(&HashSet::new())[1].to_string(),
+            "line 7: Jz references undefined label \"d\"",
+        );
+    }
+
+    #[test]
+    fn check_ranges() {
+        let dialect = Palaiologos::new();
+        let src = b"push 2147483648
+push 5000000000
+copy 1000
+slide 3
+jmp 2147483647
+rep dup 4294967296
+";
+        let cst = dialect.parse(src);
+        let kinds = |target| {
+            (cst.check_ranges(dialect.integers(), &target).into_iter())
+                .map(|err| (err.line, err.kind))
+                .collect::<Vec<_>>()
+        };
+        let dialect_errors = [
+            (1, RangeErrorKind::Dialect),
+            (2, RangeErrorKind::Dialect),
+            (6, RangeErrorKind::Dialect),
+        ];
+        assert_eq!(kinds(TargetLimits::unbounded()), dialect_errors);
+        assert_eq!(kinds(TargetLimits::haskell()), dialect_errors);
+
+        let int8 = TargetLimits {
+            integer: Some((Integer::from(-128), Integer::from(127))),
+            index: Some((Integer::ZERO, Integer::from(127))),
+        };
+        let errors = cst.check_ranges(dialect.integers(), &int8);
+        let kinds = errors
+            .iter()
+            .map(|err| (err.line, err.kind))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            kinds,
+            [
+                (1, RangeErrorKind::Dialect),
+                (2, RangeErrorKind::Dialect),
+                (3, RangeErrorKind::Target),
+                (6, RangeErrorKind::Dialect),
+            ],
+        );
+        assert!(errors.iter().all(|err| !err.is_warning()));
+        assert_eq!(errors[3].value, Integer::from(1u64 << 32));
+        assert_eq!(
+            errors[3].to_string(),
+            "line 6: PalaiologosRep argument 4294967296 is out of range for the dialect",
+        );
+
+        let wrap = TargetLimits {
+            integer: None,
+            index: Some((Integer::ZERO, Integer::from(127))),
+        };
+        let errors = cst.check_ranges(dialect.integers(), &wrap);
+        assert_eq!(errors[2].kind, RangeErrorKind::IndexWrap);
+        assert_eq!(errors[2].line, 3);
+        assert!(errors[2].is_warning());
+    }
+}
@@ -15,6 +15,25 @@ pub enum Token {
     L,
 }
 
+/// A type, which can write whole instructions. Every [`TokenWrite`] writes
+/// instructions as their tokens, while types like
+/// [`InstStream`](crate::codegen::InstStream) and
+/// [`Program`](crate::exec::Program) only collect whole instructions.
+pub trait InstWrite {
+    /// An error returned from writing an instruction.
+    type Error;
+
+    /// Writes an instruction.
+    fn write_inst(&mut self, inst: Inst<'_>) -> Result<(), Self::Error>;
+
+    /// Writes a comment, which does not change the meaning of the program.
+    /// Writers, which cannot represent comments, ignore it.
+    fn write_comment(&mut self, comment: &str) -> Result<(), Self::Error> {
+        let _ = comment;
+        Ok(())
+    }
+}
+
 /// A type, which can write tokens.
 pub trait TokenWrite {
     /// An error returned from writing a token.
@@ -87,6 +106,25 @@ pub trait TokenWrite {
         }
         Ok(())
     }
+
+    /// Writes a comment, which does not change the meaning of the program.
+    /// Writers, which cannot represent comments, ignore it.
+    fn write_comment(&mut self, comment: &str) -> Result<(), Self::Error> {
+        let _ = comment;
+        Ok(())
+    }
+}
+
+impl<T: TokenWrite + ?Sized> InstWrite for T {
+    type Error = T::Error;
+
+    fn write_inst(&mut self, inst: Inst<'_>) -> Result<(), Self::Error> {
+        TokenWrite::write_inst(self, inst)
+    }
+
+    fn write_comment(&mut self, comment: &str) -> Result<(), Self::Error> {
+        TokenWrite::write_comment(self, comment)
+    }
 }
 
 impl TokenWrite for Vec<Token> {
@@ -6,8 +6,8 @@ use bstr::ByteSlice;
 use rug::integer::MiniInteger;
 
 use crate::{
-    codegen::{Inst, IntegerBits, LabelBits, TokenWrite},
-    syntax::{Cst, Inst as WsaInst, Opcode, Overload},
+    codegen::{CodegenConfig, Inst, InstWrite, IntegerBits, LabelBits, LabelMap},
+    syntax::{Cst, Inst as WsaInst, MacroStep, Opcode, Overload},
     tokens::{
         Token, WordToken,
         integer::Integer,
@@ -16,28 +16,56 @@ use crate::{
 };
 
 // TODO:
-// - Handle anything beyond integer literal arguments. Resolve labels.
+// - Handle anything beyond integer literal and label arguments.
 // - Validate arities.
 // - Handle options more robustly.
-// - Create an InstStream abstraction, which can be used to wrap tokenwrite,
-//   but is useful on its own.
-// - Configure PalaiologosRep count upper bound:
-//   - Use a loop when it would be shorter.
-//   - Use a loop when over some configurable limit, e.g., 10 or i32::MAX like
-//     Palaiologos.
 
-impl Cst<'_> {
+impl<'s> Cst<'s> {
     /// Generates a stream of Whitespace tokens for this CST.
-    pub fn codegen<T: TokenWrite>(
+    pub fn codegen<T: InstWrite>(
         &self,
         w: &mut T,
         options: &HashSet<&[u8]>,
     ) -> Result<(), T::Error> {
+        self.codegen_with_config(w, options, &CodegenConfig::default())
+    }
+
+    /// Generates a stream of Whitespace tokens for this CST with the
+    /// configuration.
+    pub fn codegen_with_config<T: InstWrite>(
+        &self,
+        w: &mut T,
+        options: &HashSet<&[u8]>,
+        config: &CodegenConfig,
+    ) -> Result<(), T::Error> {
+        let labels = LabelMap::new(self, options, config);
+        self.for_each_inst(options, &mut |i, inst| inst.codegen(w, i, &labels, config))
+    }
+
+    /// Calls the function for each instruction, which is enabled by the
+    /// options, with its index among them.
+    pub fn for_each_inst<'a, E>(
+        &'a self,
+        options: &HashSet<&[u8]>,
+        f: &mut impl FnMut(usize, &'a WsaInst<'s>) -> Result<(), E>,
+    ) -> Result<(), E> {
+        self.for_each_inst_from(options, &mut 0, f)
+    }
+
+    fn for_each_inst_from<'a, E>(
+        &'a self,
+        options: &HashSet<&[u8]>,
+        index: &mut usize,
+        f: &mut impl FnMut(usize, &'a WsaInst<'s>) -> Result<(), E>,
+    ) -> Result<(), E> {
         match self {
-            Cst::Inst(inst) => inst.codegen(w),
+            Cst::Inst(inst) => {
+                *index += 1;
+                f(*index - 1, inst)
+            }
             Cst::Block { nodes } => {
                 for node in nodes {
-                    node.codegen(w, options)?;
+                    node.for_each_inst_from(options, index, f)?;
                 }
                 Ok(())
             }
@@ -54,7 +82,7 @@ impl Cst<'_> {
                         _ => todo!(),
                     };
                     for node in block {
-                        node.codegen(w, options)?;
+                        node.for_each_inst_from(options, index, f)?;
                     }
                 }
                 Ok(())
@@ -64,8 +92,17 @@ impl Cst<'_> {
 }
 
 impl<'s> WsaInst<'s> {
-    /// Generates a stream of Whitespace tokens for this instruction.
-    pub fn codegen<T: TokenWrite>(&self, w: &mut T) -> Result<(), T::Error> {
+    /// Generates a stream of Whitespace tokens for this instruction, with
+    /// labels resolved by the map. The index of the instruction, as numbered
+    /// by [`Cst::for_each_inst`], identifies its internal labels in the map.
+    /// Panics if the map was not built for the CST of this instruction.
+    pub fn codegen<T: InstWrite>(
+        &self,
+        w: &mut T,
+        index: usize,
+        labels: &LabelMap<'_>,
+        config: &CodegenConfig,
+    ) -> Result<(), T::Error> {
         if let Some(overload) = self.overload {
             match overload {
                 Overload::UnaryConst | Overload::UnaryRef => assert!(matches!(
@@ -156,11 +193,11 @@ impl<'s> WsaInst<'s> {
             Opcode::Mod => w.write_inst(Inst::Mod),
             Opcode::Store => w.write_inst(Inst::Store),
             Opcode::Retrieve => w.write_inst(Inst::Retrieve),
-            Opcode::Label => w.write_inst(Inst::Label(self.label(0))),
-            Opcode::Call => w.write_inst(Inst::Call(self.label(0))),
-            Opcode::Jmp => w.write_inst(Inst::Jmp(self.label(0))),
-            Opcode::Jz => w.write_inst(Inst::Jz(self.label(0))),
-            Opcode::Jn => w.write_inst(Inst::Jn(self.label(0))),
+            Opcode::Label => w.write_inst(Inst::Label(self.label(0, labels))),
+            Opcode::Call => w.write_inst(Inst::Call(self.label(0, labels))),
+            Opcode::Jmp => w.write_inst(Inst::Jmp(self.label(0, labels))),
+            Opcode::Jz => w.write_inst(Inst::Jz(self.label(0, labels))),
+            Opcode::Jn => w.write_inst(Inst::Jn(self.label(0, labels))),
             Opcode::Ret => w.write_inst(Inst::Ret),
             Opcode::End => w.write_inst(Inst::End),
             Opcode::Printc => w.write_inst(Inst::Printc),
@@ -174,86 +211,34 @@ impl<'s> WsaInst<'s> {
             Opcode::VolivaAnd => w.write_inst(Inst::VolivaAnd),
             Opcode::VolivaBreakpoint => w.write_inst(Inst::VolivaBreakpoint),
             Opcode::Push0 => w.write_inst(Inst::Push((&Integer::ZERO).into())),
-            Opcode::PushString => each_char(self.string(0), |c| w.write_inst(Inst::Push(c.into()))),
-            Opcode::PushString0 => {
-                each_char(self.string(0), |c| w.write_inst(Inst::Push(c.into())))?;
-                w.write_inst(Inst::Push((&Integer::ZERO).into()))
+            Opcode::PushString => {
+                each_char(self.string(0), true, |c| w.write_inst(Inst::Push(c.into())))
             }
-            Opcode::StoreString0 => each_char(self.string(0), |c| {
-                w.write_inst(Inst::Dup)?;
-                w.write_inst(Inst::Push(c.into()))?;
-                w.write_inst(Inst::Store)?;
-                w.write_inst(Inst::Push(Integer::ONE.into()))?;
-                w.write_inst(Inst::Add)
-            }),
-            Opcode::BurghardJmpPos
-            | Opcode::BurghardJmpNonZero
-            | Opcode::BurghardJmpNonPos
-            | Opcode::BurghardJmpNonNeg => todo!(),
-            Opcode::VolivaJmpPos => {
+            Opcode::PushString0 => {
                 w.write_inst(Inst::Push((&Integer::ZERO).into()))?;
-                w.write_inst(Inst::Swap)?;
-                w.write_inst(Inst::Sub)?;
-                w.write_inst(Inst::Jn(self.label(0)))
-            }
-            Opcode::VolivaJmpNonZero => todo!(),
-            Opcode::VolivaJmpNonPos => {
-                w.write_inst(Inst::Push(Integer::ONE.into()))?;
-                w.write_inst(Inst::Sub)?;
-                w.write_inst(Inst::Jn(self.label(0)))
-            }
-            Opcode::VolivaJmpNonNeg => todo!(),
-            Opcode::BurghardTest => {
-                w.write_inst(Inst::Dup)?;
-                w.write_inst(Inst::Push(self.integer(0)))?;
-                w.write_inst(Inst::Sub)
+                each_char(self.string(0), true, |c| w.write_inst(Inst::Push(c.into())))
             }
-            Opcode::PalaiologosRep => {
-                let opcode = match self.arg(0) {
-                    Token::Mnemonic(m) => m.opcode,
-                    arg => panic!("not a mnemonic: {arg:?}"),
+            Opcode::StoreString0 => {
+                let mut store = |c: &Integer| {
+                    w.write_inst(Inst::Dup)?;
+                    w.write_inst(Inst::Push(c.into()))?;
+                    w.write_inst(Inst::Store)?;
+                    w.write_inst(Inst::Push(Integer::ONE.into()))?;
+                    w.write_inst(Inst::Add)
                 };
-                let count = match self.arg(1) {
-                    Token::Integer(count) => &count.value,
-                    arg => panic!("not an integer: {arg:?}"),
-                };
-                let inst = match opcode {
-                    Opcode::Dup => Inst::Dup,
-                    Opcode::Swap => Inst::Swap,
-                    Opcode::Drop => Inst::Drop,
-                    Opcode::Add => Inst::Add,
-                    Opcode::Sub => Inst::Sub,
-                    Opcode::Mul => Inst::Mul,
-                    Opcode::Div => Inst::Div,
-                    Opcode::Mod => Inst::Mod,
-                    Opcode::Store => Inst::Store,
-                    Opcode::Retrieve => Inst::Retrieve,
-                    Opcode::Ret => Inst::Ret,
-                    Opcode::End => Inst::End,
-                    Opcode::Printc => Inst::Printc,
-                    Opcode::Printi => Inst::Printi,
-                    Opcode::Readc => Inst::Readc,
-                    Opcode::Readi => Inst::Readi,
-                    Opcode::BurghardPrintStack => Inst::BurghardPrintStack,
-                    Opcode::BurghardPrintHeap => Inst::BurghardPrintHeap,
-                    Opcode::VolivaOr => Inst::VolivaOr,
-                    Opcode::VolivaNot => Inst::VolivaNot,
-                    Opcode::VolivaAnd => Inst::VolivaAnd,
-                    Opcode::VolivaBreakpoint => Inst::VolivaBreakpoint,
-                    opcode => panic!("unsupported opcode for `rep`: {opcode:?}"),
-                };
-                let count = if count.is_negative() {
-                    0
-                } else if let Some(count) = count.to_usize() {
-                    count
-                } else {
-                    panic!("too many repetitions");
-                };
-                for _ in 0..count {
-                    w.write_inst(inst.clone())?;
-                }
-                Ok(())
+                each_char(self.string(0), false, &mut store)?;
+                store(&Integer::ZERO)
             }
+            Opcode::BurghardJmpPos
+            | Opcode::BurghardJmpNonZero
+            | Opcode::BurghardJmpNonPos
+            | Opcode::BurghardJmpNonNeg
+            | Opcode::VolivaJmpPos
+            | Opcode::VolivaJmpNonZero
+            | Opcode::VolivaJmpNonPos
+            | Opcode::VolivaJmpNonNeg
+            | Opcode::BurghardTest => self.codegen_expansion(w, index, labels),
+            Opcode::PalaiologosRep => self.codegen_rep(w, index, labels, &config.rep),
             Opcode::BurghardInclude
             | Opcode::RespaceInclude
             | Opcode::VolivaInclude
@@ -272,6 +257,40 @@ impl<'s> WsaInst<'s> {
         }
     }
 
+    /// Generates the expansion of a macro instruction to primitive
+    /// instructions.
+    fn codegen_expansion<T: InstWrite>(
+        &self,
+        w: &mut T,
+        index: usize,
+        labels: &LabelMap<'_>,
+    ) -> Result<(), T::Error> {
+        let expansion = self.opcode.expansion().expect("not a macro");
+        for &step in expansion {
+            let internal = |i| {
+                LabelBits::from(
+                    labels
+                        .get_internal(index, i)
+                        .expect("unresolved internal label"),
+                )
+            };
+            let inst = match step {
+                MacroStep::Inst(opcode) => simple_inst(opcode).expect("not a simple opcode"),
+                MacroStep::PushArg(index) => Inst::Push(self.integer(index)),
+                MacroStep::PushConst(n) => {
+                    w.write_inst(Inst::Push((&Integer::from(n)).into()))?;
+                    continue;
+                }
+                MacroStep::JmpArg(opcode, index) => jump_inst(opcode, self.label(index, labels)),
+                MacroStep::JmpInternal(opcode, index) => jump_inst(opcode, internal(index)),
+                MacroStep::Label(index) => Inst::Label(internal(index)),
+                MacroStep::Op => unreachable!(),
+            };
+            w.write_inst(inst)?;
+        }
+        Ok(())
+    }
+
     /// Gets the value of the indexed argument as an integer.
     fn integer(&self, index: usize) -> IntegerBits<'_> {
         match self.arg(index).peel_groups() {
@@ -281,10 +300,16 @@ impl<'s> WsaInst<'s> {
     }
 
     /// Gets the value of the indexed argument as a label.
-    fn label(&self, index: usize) -> LabelBits<'_> {
+    fn label<'a>(&'a self, index: usize, labels: &'a LabelMap<'_>) -> LabelBits<'a> {
         match self.arg(index).peel_groups() {
             Token::Integer(int) => LabelBits::from(&int.value),
-            arg => panic!("not an integer label: {arg:?}"),
+            Token::Label(label) => match labels.get(&label.label) {
+                Some(value) => LabelBits::from(value),
+                // `LabelMap::new` assigns every referenced label, including
+                // undefined ones, which `Cst::check_labels` reports.
+                None => panic!("label not in the map: {label:?}"),
+            },
+            arg => panic!("not a label: {arg:?}"),
         }
     }
 
@@ -305,25 +330,119 @@ impl<'s> WsaInst<'s> {
     }
 }
 
-/// Iterates the chars or bytes in the string literal.
+/// Converts an opcode without arguments to its Whitespace instruction.
+pub(super) fn simple_inst(opcode: Opcode) -> Option<Inst<'static>> {
+    Some(match opcode {
+        Opcode::Dup => Inst::Dup,
+        Opcode::Swap => Inst::Swap,
+        Opcode::Drop => Inst::Drop,
+        Opcode::Add => Inst::Add,
+        Opcode::Sub => Inst::Sub,
+        Opcode::Mul => Inst::Mul,
+        Opcode::Div => Inst::Div,
+        Opcode::Mod => Inst::Mod,
+        Opcode::Store => Inst::Store,
+        Opcode::Retrieve => Inst::Retrieve,
+        Opcode::Ret => Inst::Ret,
+        Opcode::End => Inst::End,
+        Opcode::Printc => Inst::Printc,
+        Opcode::Printi => Inst::Printi,
+        Opcode::Readc => Inst::Readc,
+        Opcode::Readi => Inst::Readi,
+        Opcode::BurghardPrintStack => Inst::BurghardPrintStack,
+        Opcode::BurghardPrintHeap => Inst::BurghardPrintHeap,
+        Opcode::VolivaOr => Inst::VolivaOr,
+        Opcode::VolivaNot => Inst::VolivaNot,
+        Opcode::VolivaAnd => Inst::VolivaAnd,
+        Opcode::VolivaBreakpoint => Inst::VolivaBreakpoint,
+        _ => return None,
+    })
+}
+
+/// Constructs a Whitespace jump instruction for a jump opcode.
+fn jump_inst(opcode: Opcode, label: LabelBits<'_>) -> Inst<'_> {
+    match opcode {
+        Opcode::Call => Inst::Call(label),
+        Opcode::Jmp => Inst::Jmp(label),
+        Opcode::Jz => Inst::Jz(label),
+        Opcode::Jn => Inst::Jn(label),
+        _ => panic!("not a jump opcode: {opcode:?}"),
+    }
+}
+
+/// Iterates the chars or bytes in the string literal, optionally in reverse.
 fn each_char<E, F: FnMut(&Integer) -> Result<(), E>>(
     s: &StringToken<'_>,
+    reverse: bool,
     mut f: F,
 ) -> Result<(), E> {
-    let mut int;
-    match s.encoding {
-        Encoding::Utf8 => {
-            for ch in s.unescaped.as_bstr().chars().rev() {
-                int = MiniInteger::from(ch as u32);
-                f(int.borrow_excl())?;
-            }
+    let mut chars: Vec<u32> = match s.encoding {
+        Encoding::Utf8 => s.unescaped.as_bstr().chars().map(|ch| ch as u32).collect(),
+        Encoding::Bytes => s.unescaped.iter().map(|&b| b as u32).collect(),
+    };
+    if reverse {
+        chars.reverse();
+    }
+    for c in chars {
+        f(MiniInteger::from(c).borrow_excl())?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use rug::Integer;
+
+    use crate::{
+        codegen::{Inst, TokenWrite},
+        dialects::{Burghard, Dialect as _},
+        syntax::{Cst, Opcode},
+    };
+
+    /// Generates code for the first instruction of a Burghard program, with
+    /// its opcode replaced.
+    fn codegen_as(src: &[u8], opcode: Opcode) -> String {
+        let mut cst = Burghard::new().parse(src);
+        let Cst::Block { nodes } = &mut cst else {
+            panic!("not a block");
+        };
+        let Cst::Inst(inst) = &mut nodes[0] else {
+            panic!("not an instruction");
+        };
+        inst.opcode = opcode;
+        let mut ws = String::new();
+        cst.codegen(&mut ws, &HashSet::new()).unwrap();
+        ws
+    }
+
+    fn push(w: &mut String, n: u8) {
+        w.write_inst(Inst::Push((&Integer::from(n)).into()))
+            .unwrap();
+    }
+
+    #[test]
+    fn push_string0() {
+        let mut expect = String::new();
+        for c in [0, b'b', b'a'] {
+            push(&mut expect, c);
         }
-        Encoding::Bytes => {
-            for &b in s.unescaped.iter().rev() {
-                int = MiniInteger::from(b);
-                f(int.borrow_excl())?;
-            }
+        assert_eq!(codegen_as(b"pushs \"ab\"\n", Opcode::PushString0), expect);
+    }
+
+    #[test]
+    fn store_string0() {
+        // voliva does not parse yet, so reuse the string argument of Burghard
+        // `pushs` for `storestr`.
+        let mut expect = String::new();
+        for c in [b'a', b'b', 0] {
+            expect.write_inst(Inst::Dup).unwrap();
+            push(&mut expect, c);
+            expect.write_inst(Inst::Store).unwrap();
+            push(&mut expect, 1);
+            expect.write_inst(Inst::Add).unwrap();
         }
+        assert_eq!(codegen_as(b"pushs \"ab\"\n", Opcode::StoreString0), expect);
     }
-    Ok(())
 }
@@ -2,14 +2,19 @@
 
 use crate::{
     dialects::{
-        Dialect,
-        burghard::{lex::Lexer, option::OptionNester, parse::Parser},
+        Dialect, DialectConfig,
+        burghard::{lex::Lexer, parse::Parser},
         define_mnemonics,
         dialect::DialectState,
+        option::OptionNester,
     },
     lex::Lex,
-    syntax::Cst,
-    tokens::{Token, integer::IntegerSyntax},
+    syntax::{Cst, Opcode, Overload},
+    tokens::{
+        Token, VariableStyle,
+        comment::{BlockCommentStyle, LineCommentStyle},
+        integer::IntegerSyntax,
+    },
 };
 
 // TODO:
@@ -64,7 +69,7 @@ impl Dialect for Burghard {
     }
 
     fn parse<'s>(src: &'s [u8], dialect: &DialectState<Self>) -> Cst<'s> {
-        OptionNester::new().nest(&mut Parser::new(src, dialect))
+        OptionNester::new().nest(Parser::new(src, dialect))
     }
 
     fn lex<'s>(src: &'s [u8], _dialect: &DialectState<Self>) -> Vec<Token<'s>> {
@@ -83,6 +88,24 @@ impl Dialect for Burghard {
     fn make_integers() -> IntegerSyntax {
         IntegerSyntax::haskell()
     }
+
+    fn make_config() -> DialectConfig {
+        DialectConfig {
+            overloads: vec![
+                (Opcode::Add, Overload::BinaryConstRhs),
+                (Opcode::Sub, Overload::BinaryConstRhs),
+                (Opcode::Mul, Overload::BinaryConstRhs),
+                (Opcode::Div, Overload::BinaryConstRhs),
+                (Opcode::Mod, Overload::BinaryConstRhs),
+                (Opcode::Store, Overload::BinaryConstLhs),
+                (Opcode::Retrieve, Overload::UnaryConst),
+            ],
+            line_comments: vec![LineCommentStyle::Semi, LineCommentStyle::DashDash],
+            block_comments: vec![BlockCommentStyle::Burghard],
+            variable_sigil: Some(VariableStyle::UnderscoreSigil),
+            ..DialectConfig::builtin::<Self>("Burghard")
+        }
+    }
 }
 
 #[cfg(test)]
@@ -2,7 +2,6 @@
 
 mod dialect;
 mod lex;
-mod option;
 mod parse;
 
 pub use dialect::Burghard;
@@ -1,12 +1,17 @@
 //! Parsing for the CensoredUsername Whitespace assembly dialect.
 
 use crate::{
-    dialects::{Dialect, censoredusername::lex::Lexer, define_mnemonics, dialect::DialectState},
+    dialects::{
+        Dialect, DialectConfig, censoredusername::lex::Lexer, define_mnemonics,
+        dialect::DialectState,
+    },
     lex::Lex,
     syntax::Cst,
     tokens::{
         Token,
+        comment::LineCommentStyle,
         integer::{BaseStyle, DigitSep, IntegerSyntax, SignStyle},
+        spaces::ArgSepStyle,
     },
 };
 
@@ -76,4 +81,13 @@ impl Dialect for CensoredUsername {
             max_value: None,
         }
     }
+
+    fn make_config() -> DialectConfig {
+        DialectConfig {
+            line_comments: vec![LineCommentStyle::Semi],
+            label_def_colon: true,
+            arg_sep: Some(ArgSepStyle::Comma),
+            ..DialectConfig::builtin::<Self>("CensoredUsername")
+        }
+    }
 }
@@ -0,0 +1,578 @@
+//! Description of a Whitespace assembly dialect, loaded from a config file.
+
+use std::{
+    error::Error,
+    fmt::{self, Display, Formatter},
+};
+
+use enumset::EnumSet;
+
+use crate::{
+    dialects::{
+        Dialect,
+        config::toml::{self, Entry, Table, Value},
+    },
+    syntax::{Opcode, Overload},
+    tokens::{
+        VariableStyle,
+        comment::{BlockCommentStyle, LineCommentStyle},
+        integer::{BaseStyle, DigitSep, Integer, IntegerSyntax, SignStyle},
+        label::LabelStyle,
+        mnemonics::{CaseFold, FoldedStr, MnemonicMap},
+        spaces::{ArgSepStyle, InstSepStyle},
+        string::Encoding,
+    },
+};
+
+// TODO:
+// - Support the other parsing styles from `docs/drafts/dynamic_parsing.md`,
+//   e.g., space-delimited instructions.
+// - Configure identifier first and follow sets for labels.
+
+/// A description of a Whitespace assembly dialect, which can be loaded from a
+/// TOML config file.
+///
+/// # Format
+///
+/// ```toml
+/// name = "example"
+/// # Case folding for mnemonics: "Exact", "Ascii", "AsciiK", or "AsciiIK".
+/// case_fold = "Ascii"
+///
+/// # Mnemonics, mapping to one or more opcodes with distinct arities, or a
+/// # table with `opcodes` and `fold` to override case folding.
+/// [mnemonics]
+/// push = ["Push", "Push0"]
+/// dup = "Dup"
+/// outC = { opcodes = ["Printc"], fold = "Exact" }
+///
+/// # Overloaded interpretations of the arguments of opcodes.
+/// [overloads]
+/// Add = ["BinaryConstRhs"]
+///
+/// [integers]
+/// sign = "Neg" # "Neg", "NegPos", or "Haskell"
+/// bases = ["Decimal", "HexPrefix_0x"]
+/// digit_sep = "None" # "None" or "Underscore"
+/// min = -2147483648
+/// max = 2147483647
+///
+/// [comments]
+/// line = [";", "#", "//", "--"]
+/// block = ["C", "Haskell", "Burghard"]
+///
+/// [labels]
+/// def_sigil = "@" # "", "@", or "%"
+/// def_colon = false
+/// ref_sigil = "%"
+///
+/// [variables]
+/// sigil = "_"
+///
+/// [separators]
+/// inst = "/" # "/" or ";"
+/// arg = ","
+///
+/// [strings]
+/// chars = true
+/// encoding = "Utf8" # "Utf8" or "Bytes"
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DialectConfig {
+    /// The name of the dialect.
+    pub name: String,
+    /// Mnemonics and the opcodes they resolve to, in order of preference.
+    pub mnemonics: Vec<MnemonicConfig>,
+    /// Overloaded interpretations of the arguments of opcodes.
+    pub overloads: Vec<(Opcode, Overload)>,
+    /// The syntax of integer literals.
+    pub integers: IntegerSyntax,
+    /// The supported styles of line comments.
+    pub line_comments: Vec<LineCommentStyle>,
+    /// The supported styles of block comments.
+    pub block_comments: Vec<BlockCommentStyle>,
+    /// The sigil prefixing label definitions.
+    pub label_def_sigil: LabelStyle,
+    /// Whether label definitions are followed by a colon.
+    pub label_def_colon: bool,
+    /// The sigil prefixing label references.
+    pub label_ref_sigil: LabelStyle,
+    /// The sigil prefixing variables, if variables are supported.
+    pub variable_sigil: Option<VariableStyle>,
+    /// The instruction separator, if any. Instructions are always separated by
+    /// line terminators.
+    pub inst_sep: Option<InstSepStyle>,
+    /// The argument separator, if any. Arguments are always separated by
+    /// spaces.
+    pub arg_sep: Option<ArgSepStyle>,
+    /// Whether `'`-quoted char literals are supported.
+    pub chars: bool,
+    /// The encoding of string and char literals.
+    pub encoding: Encoding,
+}
+
+/// A mnemonic in a dialect config.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MnemonicConfig {
+    /// The text of the mnemonic.
+    pub mnemonic: Vec<u8>,
+    /// The case folding used when comparing the mnemonic.
+    pub fold: CaseFold,
+    /// The opcodes the mnemonic is overloaded for, ordered by increasing arity.
+    pub opcodes: Vec<Opcode>,
+}
+
+/// An error from loading a dialect config.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ConfigError {
+    /// The line of the error, starting at 1.
+    pub line: usize,
+    /// A description of the error.
+    pub message: String,
+}
+
+impl DialectConfig {
+    /// Parses a dialect config from TOML source.
+    pub fn parse(src: &str) -> Result<Self, ConfigError> {
+        let root = toml::parse(src)?;
+        let mut config = DialectConfig::default();
+        let mut fold = CaseFold::Exact;
+        let mut mnemonics = None;
+        let mut overloads = None;
+        let mut seps_line = 0;
+        for entry in &root.entries {
+            match &*entry.key {
+                "name" => config.name = string(entry)?.to_owned(),
+                "case_fold" => fold = case_fold(entry, string(entry)?)?,
+                "mnemonics" => mnemonics = Some(table(entry)?),
+                "overloads" => overloads = Some(table(entry)?),
+                "integers" => config.integers = integer_syntax(table(entry)?)?,
+                "comments" => {
+                    for entry in &table(entry)?.entries {
+                        match &*entry.key {
+                            "line" => {
+                                config.line_comments = strings(entry)?
+                                    .into_iter()
+                                    .map(|s| line_comment_style(entry, s))
+                                    .collect::<Result<_, _>>()?;
+                            }
+                            "block" => {
+                                config.block_comments = strings(entry)?
+                                    .into_iter()
+                                    .map(|s| block_comment_style(entry, s))
+                                    .collect::<Result<_, _>>()?;
+                            }
+                            _ => return Err(unknown_key(entry)),
+                        }
+                    }
+                }
+                "labels" => {
+                    for entry in &table(entry)?.entries {
+                        match &*entry.key {
+                            "def_sigil" => config.label_def_sigil = label_sigil(entry)?,
+                            "def_colon" => config.label_def_colon = boolean(entry)?,
+                            "ref_sigil" => config.label_ref_sigil = label_sigil(entry)?,
+                            _ => return Err(unknown_key(entry)),
+                        }
+                    }
+                }
+                "variables" => {
+                    for entry in &table(entry)?.entries {
+                        match &*entry.key {
+                            "sigil" => {
+                                config.variable_sigil = match string(entry)? {
+                                    "_" => Some(VariableStyle::UnderscoreSigil),
+                                    s => return Err(invalid(entry, "variable sigil", s)),
+                                };
+                            }
+                            _ => return Err(unknown_key(entry)),
+                        }
+                    }
+                }
+                "separators" => {
+                    seps_line = entry.line;
+                    for entry in &table(entry)?.entries {
+                        match &*entry.key {
+                            "inst" => {
+                                config.inst_sep = Some(match string(entry)? {
+                                    ";" => InstSepStyle::Semi,
+                                    "/" => InstSepStyle::Slash,
+                                    s => return Err(invalid(entry, "instruction separator", s)),
+                                });
+                            }
+                            "arg" => {
+                                config.arg_sep = Some(match string(entry)? {
+                                    "," => ArgSepStyle::Comma,
+                                    s => return Err(invalid(entry, "argument separator", s)),
+                                });
+                            }
+                            _ => return Err(unknown_key(entry)),
+                        }
+                    }
+                }
+                "strings" => {
+                    for entry in &table(entry)?.entries {
+                        match &*entry.key {
+                            "chars" => config.chars = boolean(entry)?,
+                            "encoding" => {
+                                config.encoding = match string(entry)? {
+                                    "Utf8" => Encoding::Utf8,
+                                    "Bytes" => Encoding::Bytes,
+                                    s => return Err(invalid(entry, "encoding", s)),
+                                };
+                            }
+                            _ => return Err(unknown_key(entry)),
+                        }
+                    }
+                }
+                _ => return Err(unknown_key(entry)),
+            }
+        }
+
+        if let Some(mnemonics) = mnemonics {
+            let mut map = MnemonicMap::new();
+            let mut lines = Vec::with_capacity(mnemonics.entries.len());
+            for entry in &mnemonics.entries {
+                let mnemonic = mnemonic_config(entry, fold)?;
+                lines.push(entry.line);
+                config.mnemonics.push(mnemonic);
+            }
+            for (mnemonic, &line) in config.mnemonics.iter().zip(&lines) {
+                let key = FoldedStr::new(&mnemonic.mnemonic, mnemonic.fold);
+                if let Err(conflict) = map.insert(key, &mnemonic.opcodes) {
+                    return Err(ConfigError::new(
+                        line,
+                        format!(
+                            "mnemonic `{}` conflicts with `{}`",
+                            String::from_utf8_lossy(conflict.inserted.bytes),
+                            String::from_utf8_lossy(conflict.existing.bytes),
+                        ),
+                    ));
+                }
+            }
+        }
+        if let Some(overloads) = overloads {
+            for entry in &overloads.entries {
+                let Some(opcode) = Opcode::from_name(&entry.key) else {
+                    return Err(invalid(entry, "opcode", &entry.key));
+                };
+                for name in strings(entry)? {
+                    let Some(overload) = Overload::from_name(name) else {
+                        return Err(invalid(entry, "overload", name));
+                    };
+                    if config
+                        .overload(opcode, overload.arg_types().len())
+                        .is_some()
+                    {
+                        return Err(ConfigError::new(
+                            entry.line,
+                            format!("overloads for `{}` have the same arity", entry.key),
+                        ));
+                    }
+                    config.overloads.push((opcode, overload));
+                }
+            }
+        }
+
+        let seps = config.inst_sep.iter().map(|sep| match sep {
+            InstSepStyle::Semi => ";",
+            InstSepStyle::Slash => "/",
+        });
+        for sep in seps.chain(config.arg_sep.iter().map(|_| ",")) {
+            if let Some(style) = config
+                .line_comments
+                .iter()
+                .find(|style| style.prefix() == sep)
+            {
+                return Err(ConfigError::new(
+                    seps_line,
+                    format!(
+                        "separator `{sep}` conflicts with `{}` comments",
+                        style.prefix()
+                    ),
+                ));
+            }
+        }
+        Ok(config)
+    }
+
+    /// Constructs a dialect config with the mnemonics and integer syntax of a
+    /// built-in dialect. The rest of its syntax is left minimal.
+    pub fn builtin<D: Dialect>(name: &str) -> Self {
+        let mnemonics = D::MNEMONICS
+            .iter()
+            .map(|&(mnemonic, opcodes)| {
+                let mut opcodes = opcodes.to_vec();
+                opcodes.sort_by_key(|opcode| opcode.arg_types().len());
+                MnemonicConfig {
+                    mnemonic: mnemonic.bytes.to_vec(),
+                    fold: mnemonic.fold,
+                    opcodes,
+                }
+            })
+            .collect();
+        DialectConfig {
+            name: name.to_owned(),
+            mnemonics,
+            integers: D::make_integers(),
+            ..DialectConfig::default()
+        }
+    }
+
+    /// Gets the overload for an opcode, which has the given number of
+    /// arguments.
+    pub fn overload(&self, opcode: Opcode, arity: usize) -> Option<Overload> {
+        self.overloads
+            .iter()
+            .find(|&&(op, overload)| op == opcode && overload.arg_types().len() == arity)
+            .map(|&(_, overload)| overload)
+    }
+
+    /// Gets the preferred mnemonic for an opcode, i.e., the first listed.
+    pub fn mnemonic(&self, opcode: Opcode) -> Option<&MnemonicConfig> {
+        self.mnemonics.iter().find(|m| m.opcodes.contains(&opcode))
+    }
+}
+
+impl Default for DialectConfig {
+    /// Constructs a dialect config with no mnemonics and minimal syntax.
+    fn default() -> Self {
+        DialectConfig {
+            name: String::new(),
+            mnemonics: Vec::new(),
+            overloads: Vec::new(),
+            integers: IntegerSyntax {
+                sign_style: SignStyle::Neg,
+                base_styles: BaseStyle::Decimal.into(),
+                digit_sep: DigitSep::None,
+                min_value: None,
+                max_value: None,
+            },
+            line_comments: Vec::new(),
+            block_comments: Vec::new(),
+            label_def_sigil: LabelStyle::NoSigil,
+            label_def_colon: false,
+            label_ref_sigil: LabelStyle::NoSigil,
+            variable_sigil: None,
+            inst_sep: None,
+            arg_sep: None,
+            chars: false,
+            encoding: Encoding::Utf8,
+        }
+    }
+}
+
+impl ConfigError {
+    pub(super) fn new<S: Into<String>>(line: usize, message: S) -> Self {
+        ConfigError {
+            line,
+            message: message.into(),
+        }
+    }
+}
+
+impl Display for ConfigError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl Error for ConfigError {}
+
+fn mnemonic_config(entry: &Entry, fold: CaseFold) -> Result<MnemonicConfig, ConfigError> {
+    let mut mnemonic = MnemonicConfig {
+        mnemonic: entry.key.as_bytes().to_vec(),
+        fold,
+        opcodes: Vec::new(),
+    };
+    let opcodes = match &entry.value {
+        Value::Table(table) => {
+            let mut opcodes = None;
+            for entry in &table.entries {
+                match &*entry.key {
+                    "opcodes" => opcodes = Some(strings(entry)?),
+                    "fold" => mnemonic.fold = case_fold(entry, string(entry)?)?,
+                    _ => return Err(unknown_key(entry)),
+                }
+            }
+            let Some(opcodes) = opcodes else {
+                return Err(ConfigError::new(
+                    entry.line,
+                    format!("mnemonic `{}` has no opcodes", entry.key),
+                ));
+            };
+            opcodes
+        }
+        _ => strings(entry)?,
+    };
+    for name in opcodes {
+        let Some(opcode) = Opcode::from_name(name) else {
+            return Err(invalid(entry, "opcode", name));
+        };
+        mnemonic.opcodes.push(opcode);
+    }
+    if mnemonic.opcodes.is_empty() {
+        return Err(ConfigError::new(
+            entry.line,
+            format!("mnemonic `{}` has no opcodes", entry.key),
+        ));
+    }
+    mnemonic
+        .opcodes
+        .sort_by_key(|opcode| opcode.arg_types().len());
+    if mnemonic
+        .opcodes
+        .windows(2)
+        .any(|w| w[0].arg_types().len() == w[1].arg_types().len())
+    {
+        return Err(ConfigError::new(
+            entry.line,
+            format!("opcodes for `{}` have the same arity", entry.key),
+        ));
+    }
+    Ok(mnemonic)
+}
+
+fn integer_syntax(table: &Table) -> Result<IntegerSyntax, ConfigError> {
+    let mut integers = DialectConfig::default().integers;
+    for entry in &table.entries {
+        match &*entry.key {
+            "sign" => {
+                integers.sign_style = match string(entry)? {
+                    "Neg" => SignStyle::Neg,
+                    "NegPos" => SignStyle::NegPos,
+                    "Haskell" => SignStyle::Haskell,
+                    s => return Err(invalid(entry, "sign style", s)),
+                };
+            }
+            "bases" => {
+                integers.base_styles = EnumSet::empty();
+                for s in strings(entry)? {
+                    let Some(base) = EnumSet::<BaseStyle>::all()
+                        .iter()
+                        .find(|base| format!("{base:?}") == s)
+                    else {
+                        return Err(invalid(entry, "base style", s));
+                    };
+                    integers.base_styles |= base;
+                }
+            }
+            "digit_sep" => {
+                integers.digit_sep = match string(entry)? {
+                    "None" => DigitSep::None,
+                    "Underscore" => DigitSep::Underscore,
+                    s => return Err(invalid(entry, "digit separator", s)),
+                };
+            }
+            "min" => integers.min_value = Some(integer(entry)?.clone()),
+            "max" => integers.max_value = Some(integer(entry)?.clone()),
+            _ => return Err(unknown_key(entry)),
+        }
+    }
+    Ok(integers)
+}
+
+fn case_fold(entry: &Entry, s: &str) -> Result<CaseFold, ConfigError> {
+    match s {
+        "Exact" => Ok(CaseFold::Exact),
+        "Ascii" => Ok(CaseFold::Ascii),
+        "AsciiK" => Ok(CaseFold::AsciiK),
+        "AsciiIK" => Ok(CaseFold::AsciiIK),
+        _ => Err(invalid(entry, "case folding", s)),
+    }
+}
+
+fn line_comment_style(entry: &Entry, s: &str) -> Result<LineCommentStyle, ConfigError> {
+    [
+        LineCommentStyle::Semi,
+        LineCommentStyle::Hash,
+        LineCommentStyle::SlashSlash,
+        LineCommentStyle::DashDash,
+    ]
+    .into_iter()
+    .find(|style| style.prefix() == s)
+    .ok_or_else(|| invalid(entry, "line comment style", s))
+}
+
+fn block_comment_style(entry: &Entry, s: &str) -> Result<BlockCommentStyle, ConfigError> {
+    match s {
+        "C" => Ok(BlockCommentStyle::C),
+        "Haskell" => Ok(BlockCommentStyle::Haskell),
+        "Burghard" => Ok(BlockCommentStyle::Burghard),
+        _ => Err(invalid(entry, "block comment style", s)),
+    }
+}
+
+fn label_sigil(entry: &Entry) -> Result<LabelStyle, ConfigError> {
+    let s = string(entry)?;
+    [
+        LabelStyle::NoSigil,
+        LabelStyle::AtSigil,
+        LabelStyle::PercentSigil,
+    ]
+    .into_iter()
+    .find(|style| style.sigil() == s)
+    .ok_or_else(|| invalid(entry, "label sigil", s))
+}
+
+fn string(entry: &Entry) -> Result<&str, ConfigError> {
+    match &entry.value {
+        Value::String(s) => Ok(s),
+        value => Err(mismatched_type(entry, "string", value)),
+    }
+}
+
+/// Gets a string or an array of strings.
+fn strings(entry: &Entry) -> Result<Vec<&str>, ConfigError> {
+    match &entry.value {
+        Value::String(s) => Ok(vec![s]),
+        Value::Array(values) => values
+            .iter()
+            .map(|value| match value {
+                Value::String(s) => Ok(&**s),
+                value => Err(mismatched_type(entry, "string", value)),
+            })
+            .collect(),
+        value => Err(mismatched_type(entry, "array", value)),
+    }
+}
+
+fn integer(entry: &Entry) -> Result<&Integer, ConfigError> {
+    match &entry.value {
+        Value::Integer(n) => Ok(n),
+        value => Err(mismatched_type(entry, "integer", value)),
+    }
+}
+
+fn boolean(entry: &Entry) -> Result<bool, ConfigError> {
+    match &entry.value {
+        Value::Boolean(b) => Ok(*b),
+        value => Err(mismatched_type(entry, "boolean", value)),
+    }
+}
+
+fn table(entry: &Entry) -> Result<&Table, ConfigError> {
+    match &entry.value {
+        Value::Table(table) => Ok(table),
+        value => Err(mismatched_type(entry, "table", value)),
+    }
+}
+
+fn mismatched_type(entry: &Entry, expect: &str, value: &Value) -> ConfigError {
+    ConfigError::new(
+        entry.line,
+        format!(
+            "expected {expect} for `{}`, but got {}",
+            entry.key,
+            value.type_name(),
+        ),
+    )
+}
+
+fn invalid(entry: &Entry, kind: &str, value: &str) -> ConfigError {
+    ConfigError::new(entry.line, format!("invalid {kind} `{value}`"))
+}
+
+fn unknown_key(entry: &Entry) -> ConfigError {
+    ConfigError::new(entry.line, format!("unknown key `{}`", entry.key))
+}
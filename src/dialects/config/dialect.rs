@@ -0,0 +1,211 @@
+//! Parsing for Whitespace assembly dialects described by a config.
+
+use crate::{
+    dialects::{
+        config::{DialectConfig, lex::Lexer, parse::Parser},
+        option::OptionNester,
+    },
+    lex::Lex,
+    syntax::Cst,
+    tokens::{
+        Token,
+        integer::IntegerSyntax,
+        mnemonics::{FoldedStr, MnemonicConflict, MnemonicMap},
+    },
+};
+
+/// State for parsing in a Whitespace assembly dialect described by a config.
+/// Only one needs to be constructed for parsing any number of programs.
+#[derive(Clone, Debug)]
+pub struct ConfigDialect<'c> {
+    config: &'c DialectConfig,
+    mnemonics: MnemonicMap<'c>,
+}
+
+impl<'c> ConfigDialect<'c> {
+    /// Constructs state for the dialect described by the config.
+    pub fn new(config: &'c DialectConfig) -> Result<Self, MnemonicConflict<'c>> {
+        let mut mnemonics = MnemonicMap::new();
+        for mnemonic in &config.mnemonics {
+            mnemonics.insert(
+                FoldedStr::new(&mnemonic.mnemonic, mnemonic.fold),
+                &mnemonic.opcodes,
+            )?;
+        }
+        Ok(ConfigDialect { config, mnemonics })
+    }
+
+    /// Parses a Whitespace assembly program in the dialect.
+    pub fn parse<'s>(&self, src: &'s [u8]) -> Cst<'s> {
+        OptionNester::new().nest(Parser::new(src, self))
+    }
+
+    /// Lexes a Whitespace assembly program in the dialect.
+    pub fn lex<'s>(&self, src: &'s [u8]) -> Vec<Token<'s>> {
+        let mut lex = Lexer::new(src, self.config);
+        let mut toks = Vec::new();
+        loop {
+            let tok = lex.next_token();
+            if let Token::Eof(_) = tok {
+                break;
+            }
+            toks.push(tok);
+        }
+        toks
+    }
+
+    /// Gets the config describing this dialect.
+    pub fn config(&self) -> &'c DialectConfig {
+        self.config
+    }
+
+    /// Gets the mnemonic map for this dialect.
+    pub fn mnemonics(&self) -> &MnemonicMap<'c> {
+        &self.mnemonics
+    }
+
+    /// Gets the integer syntax description for this dialect.
+    pub fn integers(&self) -> &'c IntegerSyntax {
+        &self.config.integers
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use crate::{
+        codegen::{Inst, IntegerBits, LabelBits, TokenWrite},
+        dialects::{ConfigDialect, ConfigError, DialectConfig},
+        syntax::{Opcode, Overload, Pretty},
+        tokens::integer::Integer,
+    };
+
+    const CONFIG: &str = r##"
+name = "test"
+case_fold = "Ascii"
+
+[mnemonics]
+push = "Push"
+dup = "Dup"
+sub = "Sub"
+printi = "Printi"
+jmp = "Jmp"
+jz = "Jz"
+exit = "End"
+outC = { opcodes = "Printc", fold = "Exact" }
+
+[overloads]
+Sub = ["BinaryConstRhs"]
+
+[integers]
+sign = "NegPos"
+bases = ["Decimal", "HexPrefix_0x"]
+
+[comments]
+line = [";", "#"]
+
+[labels]
+def_colon = true
+
+[separators]
+inst = "/"
+"##;
+
+    #[test]
+    fn parse_and_codegen() {
+        let config = DialectConfig::parse(CONFIG).unwrap();
+        assert_eq!(config.name, "test");
+        assert_eq!(
+            config.overload(Opcode::Sub, 1),
+            Some(Overload::BinaryConstRhs)
+        );
+        let dialect = ConfigDialect::new(&config).unwrap();
+        assert_eq!(
+            dialect.mnemonics().get_opcodes(b"PUSH"),
+            Some(&[Opcode::Push][..])
+        );
+        assert_eq!(dialect.mnemonics().get_opcodes(b"OUTC"), None);
+
+        let src = b"; count down
+start:
+    Push +0x3
+loop:  dup / printi ; print
+    sub 1 # decrement
+    dup / jz end
+    jmp loop
+end: exit outC
+";
+        let cst = dialect.parse(src);
+        let mut pretty = Vec::new();
+        cst.pretty(&mut pretty);
+        assert_eq!(pretty, src);
+
+        let mut ws = String::new();
+        cst.codegen(&mut ws, &HashSet::new()).unwrap();
+        let (zero, one, two, three) = (
+            Integer::from(0),
+            Integer::from(1),
+            Integer::from(2),
+            Integer::from(3),
+        );
+        let mut expect = String::new();
+        for inst in [
+            Inst::Label(LabelBits::from(&zero)),
+            Inst::Push(IntegerBits::from(&three)),
+            Inst::Label(LabelBits::from(&one)),
+            Inst::Dup,
+            Inst::Printi,
+            Inst::Push(IntegerBits::from(&one)),
+            Inst::Sub,
+            Inst::Dup,
+            Inst::Jz(LabelBits::from(&two)),
+            Inst::Jmp(LabelBits::from(&one)),
+            Inst::Label(LabelBits::from(&two)),
+        ] {
+            expect.write_inst(inst).unwrap();
+        }
+        // `exit outC` has too many arguments, so is not generated.
+        assert!(ws.starts_with(&expect));
+    }
+
+    #[test]
+    fn config_errors() {
+        let err = |src: &str, line: usize, message: &str| {
+            assert_eq!(
+                DialectConfig::parse(src),
+                Err(ConfigError {
+                    line,
+                    message: message.into(),
+                }),
+            );
+        };
+        err("[mnemonics]\npush = \"Psh\"\n", 2, "invalid opcode `Psh`");
+        err(
+            "case_fold = \"Ascii\"\n[mnemonics]\npush = \"Push\"\nPUSH = \"Push\"\n",
+            4,
+            "mnemonic `PUSH` conflicts with `push`",
+        );
+        err(
+            "[mnemonics]\npush = [\"Push\", \"Copy\"]\n",
+            2,
+            "opcodes for `push` have the same arity",
+        );
+        err(
+            "[comments]\nline = [\";\"]\n[separators]\ninst = \";\"\n",
+            3,
+            "separator `;` conflicts with `;` comments",
+        );
+        err(
+            "[labels]\ndef_colon = 1\n",
+            2,
+            "expected boolean for `def_colon`, but got integer",
+        );
+        err(
+            "name = \"x\nname = 1\n",
+            1,
+            "invalid basic string, expected `\"`",
+        );
+        err("[a]\n[a]\n", 2, "duplicate key");
+    }
+}
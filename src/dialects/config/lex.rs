@@ -0,0 +1,245 @@
+//! Lexer for Whitespace assembly dialects described by a config.
+
+use enumset::EnumSet;
+
+use crate::{
+    dialects::config::DialectConfig,
+    lex::{Lex, Scanner},
+    tokens::{
+        Token, WordError, WordToken,
+        comment::{
+            BlockCommentError, BlockCommentStyle, BlockCommentToken, LineCommentError,
+            LineCommentStyle, LineCommentToken,
+        },
+        label::LabelColonToken,
+        spaces::{
+            ArgSepStyle, ArgSepToken, EofToken, InstSepStyle, InstSepToken, LineTermStyle,
+            LineTermToken, SpaceToken,
+        },
+    },
+};
+
+/// A lexer for tokens in a Whitespace assembly dialect described by a config.
+#[derive(Clone, Debug)]
+pub struct Lexer<'s, 'd> {
+    config: &'d DialectConfig,
+    scan: Scanner<'s>,
+}
+
+impl<'s, 'd> Lexer<'s, 'd> {
+    /// Constructs a new lexer for source text in a dialect described by a
+    /// config.
+    pub fn new(src: &'s [u8], config: &'d DialectConfig) -> Self {
+        Lexer {
+            config,
+            scan: Scanner::new(src),
+        }
+    }
+
+    /// Returns the line comment style starting the text, if any.
+    fn line_comment_at(&self, text: &[u8]) -> Option<LineCommentStyle> {
+        (self.config.line_comments.iter().copied())
+            .find(|style| text.starts_with(style.prefix().as_bytes()))
+    }
+
+    /// Returns the block comment style opening at the start of the text, if
+    /// any.
+    fn block_comment_at(&self, text: &[u8]) -> Option<BlockCommentStyle> {
+        (self.config.block_comments.iter().copied()).find(|&style| is_open(text, style))
+    }
+
+    /// Returns whether a word ends at the start of the text.
+    fn is_word_end(&self, text: &[u8]) -> bool {
+        let config = self.config;
+        match text {
+            [] | [b' ' | b'\t' | b'\x0b' | b'\x0c' | b'\r' | b'\n' | b'"', ..] => true,
+            [b'\'', ..] if config.chars => true,
+            [b':', ..] if config.label_def_colon => true,
+            [b',', ..] if config.arg_sep == Some(ArgSepStyle::Comma) => true,
+            [b';', ..] if config.inst_sep == Some(InstSepStyle::Semi) => true,
+            [b'/', ..] if config.inst_sep == Some(InstSepStyle::Slash) => true,
+            _ => {
+                self.line_comment_at(text).is_some()
+                    || (config.block_comments.iter()).any(|&style| {
+                        is_open(text, style) || text.starts_with(style.close().as_bytes())
+                    })
+            }
+        }
+    }
+}
+
+impl<'s> Lex<'s> for Lexer<'s, '_> {
+    fn next_token(&mut self) -> Token<'s> {
+        self.scan.start_next();
+
+        if self.scan.eof() {
+            return EofToken.into();
+        }
+
+        let rest = self.scan.rest();
+        if let Some(style) = self.line_comment_at(rest) {
+            let scan = &mut self.scan;
+            scan.bump_ascii_no_lf(style.prefix().len());
+            let text = scan.bump_until_lf();
+            let mut errors = EnumSet::new();
+            if scan.has_invalid_utf8() {
+                errors |= LineCommentError::InvalidUtf8;
+            }
+            return Token::from(LineCommentToken {
+                text,
+                style,
+                errors,
+            });
+        }
+        if let Some(style) = self.block_comment_at(rest) {
+            self.scan.bump_ascii_no_lf(style.open().len());
+            return block_comment(&mut self.scan, style).into();
+        }
+        for &style in &self.config.block_comments {
+            if rest.starts_with(style.close().as_bytes()) {
+                self.scan.bump_ascii_no_lf(style.close().len());
+                return Token::from(BlockCommentToken {
+                    text: b"",
+                    style,
+                    errors: BlockCommentError::Unopened.into(),
+                });
+            }
+        }
+
+        let config = self.config;
+        let scan = &mut self.scan;
+        match rest {
+            [b'\n', ..] => {
+                scan.next_char();
+                Token::from(LineTermToken::from(LineTermStyle::Lf))
+            }
+            [b'\r', b'\n', ..] => {
+                scan.next_char();
+                scan.next_char();
+                Token::from(LineTermToken::from(LineTermStyle::Crlf))
+            }
+            [b' ' | b'\t' | b'\x0b' | b'\x0c' | b'\r', ..] => {
+                loop {
+                    scan.bump_while_ascii(|ch| matches!(ch, b' ' | b'\t' | b'\x0b' | b'\x0c'));
+                    // Only a CR not followed by LF is a space.
+                    if !matches!(scan.rest(), [b'\r', rest @ ..] if !rest.starts_with(b"\n")) {
+                        break;
+                    }
+                    scan.bump_ascii();
+                }
+                Token::from(SpaceToken::from(scan.text()))
+            }
+            [b'"', ..] => {
+                scan.bump_ascii();
+                scan.string_lit_oneline()
+                    .unescape_simple(unescape(true), config.encoding)
+                    .into()
+            }
+            [b'\'', ..] if config.chars => {
+                scan.bump_ascii();
+                scan.char_lit_oneline()
+                    .unescape_simple(unescape(false), config.encoding)
+                    .into()
+            }
+            [b':', ..] if config.label_def_colon => {
+                scan.bump_ascii();
+                LabelColonToken.into()
+            }
+            [b',', ..] if config.arg_sep == Some(ArgSepStyle::Comma) => {
+                scan.bump_ascii();
+                ArgSepToken::from(ArgSepStyle::Comma).into()
+            }
+            [b';', ..] if config.inst_sep == Some(InstSepStyle::Semi) => {
+                scan.bump_ascii();
+                InstSepToken::from(InstSepStyle::Semi).into()
+            }
+            [b'/', ..] if config.inst_sep == Some(InstSepStyle::Slash) => {
+                scan.bump_ascii();
+                InstSepToken::from(InstSepStyle::Slash).into()
+            }
+            _ => {
+                self.scan.next_char();
+                while !self.is_word_end(self.scan.rest()) {
+                    self.scan.next_char();
+                }
+                let scan = &self.scan;
+                Token::from(WordToken {
+                    word: scan.text().into(),
+                    errors: if scan.has_invalid_utf8() {
+                        WordError::InvalidUtf8.into()
+                    } else {
+                        EnumSet::empty()
+                    },
+                })
+            }
+        }
+    }
+}
+
+/// Returns whether a block comment of the style opens at the start of the text.
+fn is_open(text: &[u8], style: BlockCommentStyle) -> bool {
+    match text.strip_prefix(style.open().as_bytes()) {
+        // `{--` starts a line comment in Burghard.
+        Some(rest) => style != BlockCommentStyle::Burghard || !rest.starts_with(b"-"),
+        None => false,
+    }
+}
+
+/// Consumes a block comment. The cursor must start just after the opening
+/// delimiter.
+fn block_comment<'s>(scan: &mut Scanner<'s>, style: BlockCommentStyle) -> BlockCommentToken<'s> {
+    let (open, close) = (style.open().as_bytes(), style.close().as_bytes());
+    let mut errors = EnumSet::empty();
+    let mut level = 1;
+    let text = loop {
+        let rest = scan.rest();
+        if rest.starts_with(close) {
+            let text = scan.text();
+            scan.bump_ascii_no_lf(close.len());
+            level -= 1;
+            if level == 0 {
+                break &text[open.len()..];
+            }
+        } else if style.can_nest() && is_open(rest, style) {
+            scan.bump_ascii_no_lf(open.len());
+            level += 1;
+        } else if style == BlockCommentStyle::Burghard
+            && (rest.starts_with(b"--") || rest.starts_with(b";"))
+        {
+            // Line comments take precedence over block comment markers.
+            scan.bump_until_lf();
+        } else if rest.is_empty() {
+            errors |= BlockCommentError::Unterminated;
+            break &scan.text()[open.len()..];
+        } else {
+            scan.next_char();
+        }
+    };
+    if scan.has_invalid_utf8() {
+        errors |= BlockCommentError::InvalidUtf8;
+    }
+    BlockCommentToken {
+        text,
+        style,
+        errors,
+    }
+}
+
+/// Resolves a backslash-escaped char to its represented value.
+#[inline]
+fn unescape(double_quote: bool) -> impl Fn(char) -> Option<char> {
+    move |b| match b {
+        '"' if double_quote => Some('"'),
+        '\'' if !double_quote => Some('\''),
+        '\\' => Some('\\'),
+        '0' => Some('\0'),
+        'a' => Some('\x07'),
+        'b' => Some('\x08'),
+        'f' => Some('\x0c'),
+        'n' => Some('\n'),
+        'r' => Some('\r'),
+        't' => Some('\t'),
+        'v' => Some('\x0b'),
+        _ => None,
+    }
+}
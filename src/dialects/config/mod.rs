@@ -0,0 +1,10 @@
+//! Parsing for Whitespace assembly dialects described by a config file.
+
+mod config;
+mod dialect;
+mod lex;
+mod parse;
+mod toml;
+
+pub use config::*;
+pub use dialect::ConfigDialect;
@@ -0,0 +1,321 @@
+//! Parser for Whitespace assembly dialects described by a config.
+
+use std::{borrow::Cow, collections::HashSet, mem};
+
+use enumset::EnumSet;
+
+use crate::{
+    dialects::config::{ConfigDialect, lex::Lexer},
+    lex::TokenStream,
+    syntax::{ArgLayout, ArgType, HasError, Inst, InstError, Opcode},
+    tokens::{
+        Token, VariableToken,
+        label::{LabelError, LabelStyle, LabelToken},
+        mnemonics::MnemonicToken,
+        spaces::Spaces,
+        string::{QuoteStyle, StringToken},
+        words::Words,
+    },
+};
+
+/// A parser for a Whitespace assembly dialect described by a config.
+/// Instructions are terminated by line terminators or, if configured,
+/// instruction separators. Label definitions may be followed by an instruction
+/// on the same line.
+#[derive(Clone, Debug)]
+pub struct Parser<'s, 'd> {
+    dialect: &'d ConfigDialect<'d>,
+    toks: TokenStream<'s, Lexer<'s, 'd>>,
+    label_defs: HashSet<Cow<'s, [u8]>>,
+    digit_buf: Vec<u8>,
+}
+
+impl<'s, 'd> Parser<'s, 'd> {
+    /// Constructs a new parser for source text in a dialect described by a
+    /// config.
+    pub fn new(src: &'s [u8], dialect: &'d ConfigDialect<'d>) -> Self {
+        Parser {
+            dialect,
+            toks: TokenStream::new(Lexer::new(src, dialect.config())),
+            label_defs: HashSet::new(),
+            digit_buf: Vec::new(),
+        }
+    }
+}
+
+impl<'s> Iterator for Parser<'s, '_> {
+    type Item = Inst<'s>;
+
+    /// Parses the next instruction.
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.toks.eof() {
+            return None;
+        }
+
+        let mut words = Words::new(self.space());
+        let mut label_def = false;
+        loop {
+            match self.toks.curr() {
+                Token::Word(_) | Token::String(_) | Token::Char(_) => {
+                    let word = self.toks.advance();
+                    let is_first = words.is_empty();
+                    label_def |= is_first && self.is_label_def(&word);
+                    words.push(word, self.space());
+                    if label_def && self.at_word() {
+                        break;
+                    }
+                }
+                Token::LabelColon(_) => {
+                    label_def |= words.len() == 1;
+                    let colon = self.toks.advance();
+                    words.push(colon, self.space());
+                    if label_def && self.at_word() {
+                        break;
+                    }
+                }
+                Token::InstSep(_) => {
+                    words.push_space(self.toks.advance());
+                    let space = self.space();
+                    words.trailing_spaces_mut().tokens.extend(space.tokens);
+                    break;
+                }
+                Token::LineComment(_) => {
+                    words.push_space(self.toks.advance());
+                    words.push_space(self.toks.advance());
+                    break;
+                }
+                Token::LineTerm(_) | Token::Eof(_) => {
+                    words.push_space(self.toks.advance());
+                    break;
+                }
+                _ => panic!("unhandled token"),
+            }
+        }
+
+        let mut inst = Inst {
+            opcode: Opcode::Invalid,
+            words,
+            arg_layout: ArgLayout::Mnemonic,
+            overload: None,
+            errors: EnumSet::empty(),
+        };
+        if label_def {
+            self.parse_label_def(&mut inst);
+        } else {
+            self.parse_inst(&mut inst);
+        }
+        Some(inst)
+    }
+}
+
+impl<'s> Parser<'s, '_> {
+    /// Consumes space, block comment, and argument separator tokens.
+    fn space(&mut self) -> Spaces<'s> {
+        let mut space = Spaces::new();
+        while matches!(
+            self.toks.curr(),
+            Token::Space(_) | Token::BlockComment(_) | Token::ArgSep(_),
+        ) {
+            space.push(self.toks.advance());
+        }
+        space
+    }
+
+    /// Returns whether the current token starts another instruction on the
+    /// same line.
+    fn at_word(&self) -> bool {
+        matches!(
+            self.toks.curr(),
+            Token::Word(_) | Token::String(_) | Token::Char(_),
+        )
+    }
+
+    /// Returns whether a word is a label definition without a colon, i.e.,
+    /// prefixed with the definition sigil.
+    fn is_label_def(&self, word: &Token<'s>) -> bool {
+        let config = self.dialect.config();
+        match word {
+            Token::Word(w) => {
+                !config.label_def_colon
+                    && config.label_def_sigil != LabelStyle::NoSigil
+                    && w.word
+                        .starts_with(config.label_def_sigil.sigil().as_bytes())
+            }
+            _ => false,
+        }
+    }
+
+    /// Parses a label definition.
+    fn parse_label_def(&mut self, inst: &mut Inst<'s>) {
+        inst.opcode = Opcode::Label;
+        inst.arg_layout = ArgLayout::Bare;
+        let sigil = self.dialect.config().label_def_sigil;
+        let label = &mut inst.words[0];
+        let Token::Word(w) = label else {
+            inst.errors |= InstError::InvalidTypes;
+            return;
+        };
+        let Some(ident) = strip_prefix(&mut w.word, sigil.sigil().as_bytes()) else {
+            inst.errors |= InstError::InvalidTypes;
+            return;
+        };
+        let mut errors = EnumSet::empty();
+        if ident.is_empty() {
+            errors |= LabelError::Empty;
+        } else if !self.label_defs.insert(ident.clone()) {
+            errors |= LabelError::Redefined;
+        }
+        *label = Token::from(LabelToken {
+            label: ident,
+            style: sigil,
+            errors,
+        });
+    }
+
+    /// Parses the mnemonic and arguments of an instruction.
+    fn parse_inst(&mut self, inst: &mut Inst<'s>) {
+        if inst.words.is_empty() {
+            inst.opcode = Opcode::Nop;
+            inst.arg_layout = ArgLayout::Bare;
+            return;
+        }
+        let ((mnemonic, _), args) = inst.words.words.split_first_mut().unwrap();
+        let Token::Word(mnemonic_word) = mnemonic else {
+            inst.arg_layout = ArgLayout::Bare;
+            return;
+        };
+        let opcodes = self
+            .dialect
+            .mnemonics()
+            .get_opcodes(&mnemonic_word.word)
+            .unwrap_or(&[Opcode::Invalid]);
+        debug_assert!(!opcodes.is_empty());
+
+        // Iterate signatures by the largest arity first.
+        let mut opcode = opcodes[0];
+        for &op in opcodes.iter().rev() {
+            if args.len() >= op.arg_types().len() {
+                opcode = op;
+                break;
+            }
+        }
+        let mut types = opcode.arg_types();
+        if args.len() != types.len() {
+            if let Some(overload) = self.dialect.config().overload(opcode, args.len()) {
+                inst.overload = Some(overload);
+                types = overload.arg_types();
+            } else if opcode != Opcode::Invalid {
+                inst.errors |= InstError::InvalidArity;
+            }
+        }
+        inst.opcode = opcode;
+        *mnemonic = Token::from(MnemonicToken {
+            mnemonic: mem::take(&mut mnemonic_word.word),
+            opcode,
+        });
+
+        let mut valid = true;
+        for ((arg, _), &ty) in args.iter_mut().zip(types) {
+            valid &= self.parse_arg(arg, ty, opcode == Opcode::Label);
+        }
+        // Process the remaining arguments.
+        let rest = args.len().min(types.len());
+        for (arg, _) in &mut args[rest..] {
+            self.parse_arg(arg, ArgType::Variable, false);
+        }
+        if !valid {
+            inst.errors |= InstError::InvalidTypes;
+        }
+    }
+
+    /// Parses an argument according to its type and returns whether it is
+    /// valid. Labels are parsed as definitions when `is_def` is set.
+    fn parse_arg(&mut self, tok: &mut Token<'s>, ty: ArgType, is_def: bool) -> bool {
+        let config = self.dialect.config();
+        let word = match tok {
+            Token::Word(w) => &mut w.word,
+            Token::String(_) => return ty == ArgType::String,
+            Token::Char(_) => return ty == ArgType::Integer,
+            _ => return true,
+        };
+
+        match ty {
+            ArgType::Include | ArgType::Option => return true,
+            ArgType::Label => {
+                let sigil = if is_def {
+                    config.label_def_sigil
+                } else {
+                    config.label_ref_sigil
+                };
+                let (label, valid) = match strip_prefix(word, sigil.sigil().as_bytes()) {
+                    Some(label) => (label, true),
+                    None => (mem::take(word), false),
+                };
+                let mut errors = EnumSet::empty();
+                if label.is_empty() {
+                    errors |= LabelError::Empty;
+                } else if is_def && !self.label_defs.insert(label.clone()) {
+                    errors |= LabelError::Redefined;
+                }
+                *tok = Token::from(LabelToken {
+                    label,
+                    style: if valid { sigil } else { LabelStyle::NoSigil },
+                    errors,
+                });
+                return valid;
+            }
+            ArgType::Mnemonic => {
+                let opcode = match self.dialect.mnemonics().get_opcodes(word) {
+                    Some(opcodes) => opcodes[0],
+                    None => Opcode::Invalid,
+                };
+                *tok = Token::from(MnemonicToken {
+                    mnemonic: mem::take(word),
+                    opcode,
+                });
+                return opcode != Opcode::Invalid;
+            }
+            _ => {}
+        }
+
+        // Try to parse it as a variable.
+        if let Some(style) = config.variable_sigil
+            && let Some(ident) = strip_prefix(word, style.sigil().as_bytes())
+        {
+            *tok = Token::from(VariableToken { ident, style });
+            return true;
+        }
+
+        // Try to parse it as an integer.
+        if ty == ArgType::Integer || ty == ArgType::Variable {
+            let int = (self.dialect.integers()).parse(word.clone(), &mut self.digit_buf);
+            if ty == ArgType::Integer || !int.has_error() {
+                *tok = Token::from(int);
+                return ty == ArgType::Integer;
+            }
+        }
+
+        // Otherwise, it is a bare string.
+        let word = mem::take(word);
+        *tok = Token::from(StringToken {
+            literal: word.clone(),
+            unescaped: word,
+            encoding: config.encoding,
+            quotes: QuoteStyle::Bare,
+            errors: EnumSet::empty(),
+        });
+        ty == ArgType::String
+    }
+}
+
+/// Removes the prefix from the text, if it has it, and returns the remaining
+/// text. The original text is left empty when stripped.
+fn strip_prefix<'s>(text: &mut Cow<'s, [u8]>, prefix: &[u8]) -> Option<Cow<'s, [u8]>> {
+    if !text.starts_with(prefix) {
+        return None;
+    }
+    Some(match mem::take(text) {
+        Cow::Borrowed(text) => text[prefix.len()..].into(),
+        Cow::Owned(text) => text[prefix.len()..].to_vec().into(),
+    })
+}
@@ -0,0 +1,112 @@
+//! Adapter from the `toml` crate to the values used by dialect config files.
+
+use rug::Integer;
+use toml::{
+    Spanned,
+    de::{DeTable, DeValue},
+};
+
+use crate::dialects::config::ConfigError;
+
+/// A TOML value.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Value {
+    /// A basic or literal string.
+    String(String),
+    /// An integer of arbitrary precision.
+    Integer(Integer),
+    /// A boolean.
+    Boolean(bool),
+    /// An array of values.
+    Array(Vec<Value>),
+    /// A table, from a header or inline.
+    Table(Table),
+}
+
+/// A TOML table, which preserves the order of its entries.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Table {
+    /// The entries in the order they were defined.
+    pub entries: Vec<Entry>,
+}
+
+/// A key-value pair in a table.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Entry {
+    /// The key.
+    pub key: String,
+    /// The value.
+    pub value: Value,
+    /// The line the key was defined on, starting at 1.
+    pub line: usize,
+}
+
+/// Parses a TOML document into its root table.
+pub fn parse(src: &str) -> Result<Table, ConfigError> {
+    let root = DeTable::parse(src).map_err(|err| {
+        let line = err.span().map(|span| line(src, span.start)).unwrap_or(1);
+        let message = err.message().trim_end().to_owned();
+        ConfigError::new(line, message)
+    })?;
+    table(src, root.into_inner())
+}
+
+impl Value {
+    /// Returns the TOML type name of this value.
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Value::String(_) => "string",
+            Value::Integer(_) => "integer",
+            Value::Boolean(_) => "boolean",
+            Value::Array(_) => "array",
+            Value::Table(_) => "table",
+        }
+    }
+}
+
+fn table(src: &str, table: DeTable<'_>) -> Result<Table, ConfigError> {
+    let entries = table
+        .into_iter()
+        .map(|(key, value)| {
+            let line = line(src, key.span().start);
+            let value = self::value(src, value, line)?;
+            Ok(Entry {
+                key: key.into_inner().into_owned(),
+                value,
+                line,
+            })
+        })
+        .collect::<Result<_, _>>()?;
+    Ok(Table { entries })
+}
+
+fn value(src: &str, value: Spanned<DeValue<'_>>, line: usize) -> Result<Value, ConfigError> {
+    Ok(match value.into_inner() {
+        DeValue::String(s) => Value::String(s.into_owned()),
+        DeValue::Integer(int) => {
+            let value = Integer::from_str_radix(int.as_str(), int.radix() as i32)
+                .map_err(|_| ConfigError::new(line, "invalid integer"))?;
+            Value::Integer(value)
+        }
+        DeValue::Boolean(b) => Value::Boolean(b),
+        DeValue::Array(array) => Value::Array(
+            (array.into_iter())
+                .map(|v| self::value(src, v, line))
+                .collect::<Result<_, _>>()?,
+        ),
+        DeValue::Table(t) => Value::Table(table(src, t)?),
+        DeValue::Float(_) => return Err(ConfigError::new(line, "floats are not supported")),
+        DeValue::Datetime(_) => {
+            return Err(ConfigError::new(line, "datetimes are not supported"));
+        }
+    })
+}
+
+/// Computes the line number of a byte offset, starting at 1.
+fn line(src: &str, offset: usize) -> usize {
+    src.as_bytes()[..offset.min(src.len())]
+        .iter()
+        .filter(|&&b| b == b'\n')
+        .count()
+        + 1
+}
@@ -3,6 +3,8 @@
 use std::marker::PhantomData;
 
 use crate::{
+    codegen::Peephole,
+    dialects::DialectConfig,
     syntax::{Cst, Opcode},
     tokens::{
         Token,
@@ -20,6 +22,10 @@ pub trait Dialect {
     /// The mnemonic map for this dialect.
     const MNEMONICS: &[(FoldedStr<'_>, &[Opcode])];
 
+    /// The peephole optimizations the reference assembler for this dialect
+    /// always applies.
+    const PEEPHOLE: Peephole = Peephole::NONE;
+
     /// Constructs state for the dialect. Only one needs to be constructed for
     /// parsing any number of programs.
     fn new() -> DialectState<Self> {
@@ -38,13 +44,17 @@ pub trait Dialect {
 
     /// Constructs an integer syntax description for this dialect.
     fn make_integers() -> IntegerSyntax;
+
+    /// Constructs a description of the syntax of this dialect, which is used
+    /// for converting between dialects.
+    fn make_config() -> DialectConfig;
 }
 
 /// State for parsing in a Whitespace assembly dialect.
 #[derive(Clone, Debug)]
 pub struct DialectState<D: ?Sized> {
     dialect: PhantomData<D>,
-    mnemonics: MnemonicMap,
+    mnemonics: MnemonicMap<'static>,
     integers: IntegerSyntax,
 }
 
@@ -60,7 +70,7 @@ impl<D: Dialect> DialectState<D> {
     }
 
     /// Gets the mnemonic map for this dialect.
-    pub fn mnemonics(&self) -> &MnemonicMap {
+    pub fn mnemonics(&self) -> &MnemonicMap<'static> {
         &self.mnemonics
     }
 
@@ -2,7 +2,9 @@
 
 mod burghard;
 mod censoredusername;
+mod config;
 mod dialect;
+mod option;
 mod palaiologos;
 mod voliva;
 mod wconrad;
@@ -10,6 +12,7 @@ mod wsf;
 
 pub use burghard::Burghard;
 pub use censoredusername::CensoredUsername;
+pub use config::{ConfigDialect, ConfigError, DialectConfig, MnemonicConfig};
 pub use dialect::*;
 pub use palaiologos::Palaiologos;
 pub use voliva::Voliva;
@@ -2,10 +2,7 @@
 
 use std::mem;
 
-use crate::{
-    dialects::burghard::parse::Parser,
-    syntax::{Cst, Opcode, OptionBlock},
-};
+use crate::syntax::{Cst, Inst, Opcode, OptionBlock};
 
 /// A builder, which structures options into blocks.
 #[derive(Clone, Debug)]
@@ -24,8 +21,8 @@ impl<'s> OptionNester<'s> {
     }
 
     /// Nests instructions into structured option blocks.
-    pub fn nest(&mut self, parser: &mut Parser<'s, '_>) -> Cst<'s> {
-        for inst in parser {
+    pub fn nest<I: IntoIterator<Item = Inst<'s>>>(&mut self, insts: I) -> Cst<'s> {
+        for inst in insts {
             match inst.opcode {
                 Opcode::IfOption => {
                     self.option_stack.push(OptionBlock {
@@ -1,16 +1,21 @@
 //! Parsing for the Palaiologos Whitespace assembly dialect.
 
 use crate::{
+    codegen::Peephole,
     dialects::{
-        Dialect, define_mnemonics,
+        Dialect, DialectConfig, define_mnemonics,
         dialect::DialectState,
         palaiologos::{lex::Lexer, parse::Parser},
     },
     lex::Lex,
-    syntax::Cst,
+    syntax::{Cst, Opcode, Overload},
     tokens::{
         Token,
+        comment::LineCommentStyle,
         integer::{BaseStyle, DigitSep, Integer, IntegerSyntax, SignStyle},
+        label::LabelStyle,
+        spaces::{ArgSepStyle, InstSepStyle},
+        string::Encoding,
     },
 };
 
@@ -19,17 +24,22 @@ use crate::{
 pub struct Palaiologos;
 
 impl Dialect for Palaiologos {
+    const PEEPHOLE: Peephole = Peephole {
+        swap_swap: true,
+        ..Peephole::NONE
+    };
+
     define_mnemonics! {
         fold = Ascii,
-        b"psh" => [Push, Push0],
         b"push" => [Push, Push0],
+        b"psh" => [Push, Push0],
         b"dup" => [Dup],
         b"copy" => [Copy],
         b"take" => [Copy],
         b"pull" => [Copy],
+        b"swap" => [Swap],
         b"xchg" => [Swap],
         b"swp" => [Swap],
-        b"swap" => [Swap],
         b"drop" => [Drop],
         b"dsc" => [Drop],
         b"slide" => [Slide],
@@ -102,6 +112,33 @@ impl Dialect for Palaiologos {
             max_value: Some(Integer::from(i32::MAX)),
         }
     }
+
+    fn make_config() -> DialectConfig {
+        DialectConfig {
+            overloads: vec![
+                (Opcode::Add, Overload::BinaryConstRhs),
+                (Opcode::Sub, Overload::BinaryConstRhs),
+                (Opcode::Mul, Overload::BinaryConstRhs),
+                (Opcode::Div, Overload::BinaryConstRhs),
+                (Opcode::Mod, Overload::BinaryConstRhs),
+                (Opcode::Store, Overload::BinaryConstRhs),
+                (Opcode::Store, Overload::BinaryConstConst),
+                (Opcode::Retrieve, Overload::UnaryConst),
+                (Opcode::Printc, Overload::UnaryConst),
+                (Opcode::Printi, Overload::UnaryConst),
+                (Opcode::Readc, Overload::UnaryConst),
+                (Opcode::Readi, Overload::UnaryConst),
+            ],
+            line_comments: vec![LineCommentStyle::Semi],
+            label_def_sigil: LabelStyle::AtSigil,
+            label_ref_sigil: LabelStyle::PercentSigil,
+            inst_sep: Some(InstSepStyle::Slash),
+            arg_sep: Some(ArgSepStyle::Comma),
+            chars: true,
+            encoding: Encoding::Bytes,
+            ..DialectConfig::builtin::<Self>("Palaiologos")
+        }
+    }
 }
 
 pub(super) const MAX_MNEMONIC_LEN: usize = {
@@ -168,7 +168,21 @@ fn analyze_inst(inst: &mut Inst<'_>) {
 fn analyze_spaces(spaces: &mut Spaces<'_>, leading: bool, trailing: bool, between_args: bool) {
     let mut has_comma = false;
     let mut has_slash = false;
-    for space in &mut spaces.tokens {
+    // A separator at the end of the line is only followed by spaces and a line
+    // terminator or comment.
+    let ends_line = (spaces.tokens.iter()).any(|tok| {
+        matches!(
+            tok,
+            Token::LineTerm(_) | Token::Eof(_) | Token::LineComment(_)
+        )
+    });
+    let line_end = spaces.tokens.iter().rposition(|tok| {
+        !matches!(
+            tok,
+            Token::Space(_) | Token::LineTerm(_) | Token::Eof(_) | Token::LineComment(_),
+        )
+    });
+    for (i, space) in spaces.tokens.iter_mut().enumerate() {
         match space {
             Token::ArgSep(sep) => {
                 if has_comma {
@@ -184,7 +198,7 @@ fn analyze_spaces(spaces: &mut Spaces<'_>, leading: bool, trailing: bool, betwee
                 }
                 if leading {
                     sep.errors |= InstSepError::StartOfLine;
-                } else if trailing {
+                } else if trailing && Some(i) == line_end && ends_line {
                     sep.errors |= InstSepError::EndOfLine;
                 }
                 has_slash = true;
@@ -193,3 +207,20 @@ fn analyze_spaces(spaces: &mut Spaces<'_>, leading: bool, trailing: bool, betwee
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        dialects::{Dialect as _, Palaiologos},
+        syntax::HasError,
+    };
+
+    #[test]
+    fn inst_sep_at_end_of_line() {
+        let dialect = Palaiologos::new();
+        assert!(!dialect.parse(b"push 1 / push 2\n").has_error());
+        assert!(!dialect.parse(b"push 1 / push 2 ; x\n").has_error());
+        assert!(dialect.parse(b"push 1 /\npush 2\n").has_error());
+        assert!(dialect.parse(b"push 1 / ; x\npush 2\n").has_error());
+    }
+}
@@ -1,11 +1,14 @@
 //! Parsing for the voliva Whitespace assembly dialect.
 
 use crate::{
-    dialects::{Dialect, define_mnemonics, dialect::DialectState, voliva::lex::Lexer},
+    dialects::{
+        Dialect, DialectConfig, define_mnemonics, dialect::DialectState, voliva::lex::Lexer,
+    },
     lex::Lex,
-    syntax::Cst,
+    syntax::{Cst, Opcode, Overload},
     tokens::{
         Token,
+        comment::LineCommentStyle,
         integer::{BaseStyle, DigitSep, IntegerSyntax, SignStyle},
     },
 };
@@ -103,4 +106,23 @@ impl Dialect for Voliva {
             max_value: None,
         }
     }
+
+    fn make_config() -> DialectConfig {
+        DialectConfig {
+            overloads: vec![
+                (Opcode::Add, Overload::BinaryConstRhs),
+                (Opcode::Sub, Overload::BinaryConstRhs),
+                (Opcode::Mul, Overload::BinaryConstRhs),
+                (Opcode::Div, Overload::BinaryConstRhs),
+                (Opcode::Mod, Overload::BinaryConstRhs),
+                (Opcode::VolivaOr, Overload::BinaryConstRhs),
+                (Opcode::VolivaAnd, Overload::BinaryConstRhs),
+                (Opcode::Store, Overload::BinaryConstLhs),
+                (Opcode::Retrieve, Overload::UnaryConst),
+            ],
+            line_comments: vec![LineCommentStyle::Semi],
+            chars: true,
+            ..DialectConfig::builtin::<Self>("voliva")
+        }
+    }
 }
@@ -106,13 +106,32 @@ impl<'s> Lex<'s> for Lexer<'s, '_> {
 /// [`RegExp` `\s`](https://tc39.es/ecma262/multipage/text-processing.html#sec-compiletocharset),
 /// excluding `\n`.
 fn is_space(ch: char) -> bool {
-    match ch {
-        '\t' | '\u{000b}' | '\u{000c}' | '\r' | ' ' | '\u{00a0}' | '\u{1680}' | '\u{2000}'
-        | '\u{2001}' | '\u{2002}' | '\u{2003}' | '\u{2004}' | '\u{2005}' | '\u{2006}'
-        | '\u{2007}' | '\u{2008}' | '\u{2009}' | '\u{200a}' | '\u{2028}' | '\u{2029}'
-        | '\u{202f}' | '\u{205f}' | '\u{3000}' | '\u{feff}' => true,
-        _ => false,
-    }
+    matches!(
+        ch,
+        '\t' | '\u{000b}'
+            | '\u{000c}'
+            | '\r'
+            | ' '
+            | '\u{00a0}'
+            | '\u{1680}'
+            | '\u{2000}'
+            | '\u{2001}'
+            | '\u{2002}'
+            | '\u{2003}'
+            | '\u{2004}'
+            | '\u{2005}'
+            | '\u{2006}'
+            | '\u{2007}'
+            | '\u{2008}'
+            | '\u{2009}'
+            | '\u{200a}'
+            | '\u{2028}'
+            | '\u{2029}'
+            | '\u{202f}'
+            | '\u{205f}'
+            | '\u{3000}'
+            | '\u{feff}'
+    )
 }
 
 /// Resolves a backslash-escaped char to its represented value.
@@ -1,11 +1,12 @@
 //! Parsing for the wconrad Whitespace assembly dialect.
 
 use crate::{
-    dialects::{Dialect, DialectState, define_mnemonics, wconrad::lex::Lexer},
+    dialects::{Dialect, DialectConfig, DialectState, define_mnemonics, wconrad::lex::Lexer},
     lex::Lex,
     syntax::Cst,
     tokens::{
         Token,
+        comment::LineCommentStyle,
         integer::{BaseStyle, DigitSep, IntegerSyntax, SignStyle},
     },
 };
@@ -86,4 +87,11 @@ impl Dialect for WConrad {
             max_value: None,
         }
     }
+
+    fn make_config() -> DialectConfig {
+        DialectConfig {
+            line_comments: vec![LineCommentStyle::Hash],
+            ..DialectConfig::builtin::<Self>("wconrad")
+        }
+    }
 }
@@ -1,12 +1,14 @@
 //! Parsing for the wsf Whitespace assembly dialect.
 
 use crate::{
-    dialects::{Dialect, define_mnemonics, dialect::DialectState, wsf::lex::Lexer},
+    dialects::{Dialect, DialectConfig, define_mnemonics, dialect::DialectState, wsf::lex::Lexer},
     lex::Lex,
     syntax::Cst,
     tokens::{
         Token,
+        comment::LineCommentStyle,
         integer::{BaseStyle, DigitSep, IntegerSyntax, SignStyle},
+        string::Encoding,
     },
 };
 
@@ -83,4 +85,14 @@ impl Dialect for Wsf {
             max_value: None,
         }
     }
+
+    fn make_config() -> DialectConfig {
+        DialectConfig {
+            line_comments: vec![LineCommentStyle::Hash],
+            label_def_colon: true,
+            chars: true,
+            encoding: Encoding::Bytes,
+            ..DialectConfig::builtin::<Self>("wsf")
+        }
+    }
 }
@@ -81,7 +81,7 @@ impl<'s> Lex<'s> for Lexer<'s, '_> {
                 };
                 Token::from(LineCommentToken {
                     text,
-                    style: LineCommentStyle::Semi,
+                    style: LineCommentStyle::Hash,
                     errors,
                 })
             }
@@ -132,3 +132,29 @@ fn unescape(double_quote: bool) -> impl Fn(char) -> Option<char> {
         _ => None,
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use enumset::EnumSet;
+
+    use crate::{
+        dialects::{Dialect as _, Wsf},
+        tokens::{
+            Token,
+            comment::{LineCommentStyle, LineCommentToken},
+        },
+    };
+
+    #[test]
+    fn hash_comment() {
+        let toks = Wsf::new().lex(b"# comment");
+        assert_eq!(
+            toks,
+            [Token::from(LineCommentToken {
+                text: b" comment",
+                style: LineCommentStyle::Hash,
+                errors: EnumSet::empty(),
+            })],
+        );
+    }
+}
@@ -0,0 +1,404 @@
+//! Source-level debugging of Whitespace assembly programs.
+
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet},
+    convert::Infallible,
+    io::{BufRead, Write},
+};
+
+use rug::Integer;
+
+use crate::{
+    codegen::{CodegenConfig, InstSpans, LabelMap},
+    exec::{ExecError, Exit, Program, Vm},
+    syntax::{Cst, Opcode},
+    tokens::Token,
+};
+
+// TODO:
+// - Support conditional breakpoints and watchpoints on heap addresses.
+
+/// A program for execution, with each instruction mapped back to the assembly
+/// instruction which generated it.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct DebugProgram {
+    program: Program,
+    /// The source of each instruction in `program`.
+    sources: Vec<SourceInst>,
+    /// A mapping from line to the first instruction generated by an assembly
+    /// instruction on that line.
+    lines: BTreeMap<usize, usize>,
+    /// A mapping from label name to the index of the instruction after its
+    /// definition.
+    labels: HashMap<Vec<u8>, usize>,
+    /// A mapping from the integer a label is encoded as to its name.
+    label_names: HashMap<Integer, Vec<u8>>,
+}
+
+/// The assembly instruction, which generated an instruction in a program.
+/// Overloads and macros generate several instructions with the same source.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SourceInst {
+    /// The line of the assembly instruction, starting at 1.
+    pub line: usize,
+    /// The opcode of the assembly instruction.
+    pub opcode: Opcode,
+    /// The index of the first instruction generated by the assembly
+    /// instruction.
+    pub start: usize,
+}
+
+/// A debugger, which executes a program and stops at source-level boundaries.
+#[derive(Debug)]
+pub struct Debugger<'p, R, W> {
+    program: &'p DebugProgram,
+    vm: Vm<'p, R, W>,
+    /// Instruction indices to stop before executing.
+    breakpoints: BTreeSet<usize>,
+    /// The instruction index where execution last stopped, so that resuming
+    /// does not immediately stop at the same breakpoint.
+    stopped_at: Option<usize>,
+}
+
+/// The reason a debugger stopped without an error.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Stop {
+    /// The program executed `end`.
+    End,
+    /// Execution reached a breakpoint or executed voliva `dbg`.
+    Breakpoint,
+    /// A step completed.
+    Step,
+}
+
+/// A frame in the call stack.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Frame {
+    /// The index of the current instruction in this frame. For frames other
+    /// than the innermost, this is the `call`.
+    pub pc: usize,
+    /// The label called to enter this frame, or `None` for the outermost
+    /// frame.
+    pub label: Option<Integer>,
+}
+
+impl DebugProgram {
+    /// Generates a program for the instructions in the CST enabled by the
+    /// options and records the source of each instruction.
+    pub fn new(cst: &Cst<'_>, options: &HashSet<&[u8]>, config: &CodegenConfig) -> Self {
+        let spans = InstSpans::new(cst);
+        let label_map = LabelMap::new(cst, options, config);
+
+        let mut p = DebugProgram::default();
+        let Ok(()) = cst.for_each_inst::<Infallible>(options, &mut |i, inst| {
+            let start = p.program.len();
+            if inst.opcode == Opcode::Label
+                && let Token::Label(l) = inst.arg(0).peel_groups()
+            {
+                p.labels.entry(l.label.to_vec()).or_insert(start);
+                if let Some(n) = label_map.get(&l.label) {
+                    p.label_names.insert(n.clone(), l.label.to_vec());
+                }
+            }
+            let Ok(()) = inst.codegen(&mut p.program, i, &label_map, config);
+            let line = spans.get(inst).line;
+            let opcode = inst.opcode;
+            for _ in start..p.program.len() {
+                p.sources.push(SourceInst {
+                    line,
+                    opcode,
+                    start,
+                });
+            }
+            if p.program.len() != start {
+                p.lines.entry(line).or_insert(start);
+            }
+            Ok(())
+        });
+        p
+    }
+
+    /// Gets the executable program.
+    pub fn program(&self) -> &Program {
+        &self.program
+    }
+
+    /// Gets the source of the instruction at the index.
+    pub fn source(&self, pc: usize) -> Option<SourceInst> {
+        self.sources.get(pc).copied()
+    }
+
+    /// Gets the first instruction generated on the line, or, if the line has
+    /// no instructions, on the next line which does. Returns the line found
+    /// and the instruction index.
+    pub fn line_pc(&self, line: usize) -> Option<(usize, usize)> {
+        let (&line, &pc) = self.lines.range(line..).next()?;
+        Some((line, pc))
+    }
+
+    /// Gets the index of the instruction after the definition of a named
+    /// label.
+    pub fn label_pc(&self, label: &[u8]) -> Option<usize> {
+        self.labels.get(label).copied()
+    }
+
+    /// Gets the name of the label encoded as the integer.
+    pub fn label_name(&self, label: &Integer) -> Option<&[u8]> {
+        self.label_names.get(label).map(|name| name.as_slice())
+    }
+}
+
+impl<'p, R: BufRead, W: Write> Debugger<'p, R, W> {
+    /// Constructs a debugger for the program, which reads from the input and
+    /// writes to the output.
+    pub fn new(program: &'p DebugProgram, input: R, output: W) -> Self {
+        Debugger {
+            program,
+            vm: Vm::new(&program.program, input, output),
+            breakpoints: BTreeSet::new(),
+            stopped_at: None,
+        }
+    }
+
+    /// Sets a breakpoint on a line. When the line has no instructions, the
+    /// breakpoint is moved to the next line which does. Returns the line of
+    /// the breakpoint.
+    pub fn set_line_breakpoint(&mut self, line: usize) -> Option<usize> {
+        let (line, pc) = self.program.line_pc(line)?;
+        self.breakpoints.insert(pc);
+        Some(line)
+    }
+
+    /// Sets a breakpoint after the definition of a label. Returns whether the
+    /// label is defined.
+    pub fn set_label_breakpoint(&mut self, label: &[u8]) -> bool {
+        match self.program.label_pc(label) {
+            Some(pc) => {
+                self.breakpoints.insert(pc);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Removes all breakpoints.
+    pub fn clear_breakpoints(&mut self) {
+        self.breakpoints.clear();
+    }
+
+    /// Executes until a breakpoint or the end of the program.
+    pub fn cont(&mut self) -> Result<Stop, ExecError> {
+        self.resume(|_| false)
+    }
+
+    /// Executes one assembly instruction, including all instructions
+    /// generated by it, and enters calls.
+    pub fn step_in(&mut self) -> Result<Stop, ExecError> {
+        self.resume(|_| true)
+    }
+
+    /// Executes one assembly instruction, including all instructions
+    /// generated by it, and steps over calls.
+    pub fn step_over(&mut self) -> Result<Stop, ExecError> {
+        let depth = self.vm.calls().len();
+        self.resume(move |calls| calls <= depth)
+    }
+
+    /// Executes until the current call returns.
+    pub fn step_out(&mut self) -> Result<Stop, ExecError> {
+        let depth = self.vm.calls().len();
+        self.resume(move |calls| calls < depth)
+    }
+
+    /// Executes one instruction, regardless of the assembly instruction which
+    /// generated it.
+    pub fn step_inst(&mut self) -> Result<Stop, ExecError> {
+        let res = match self.vm.step() {
+            Ok(None) => Ok(Stop::Step),
+            Ok(Some(exit)) => Ok(exit.into()),
+            Err(err) => Err(err),
+        };
+        self.stop(res)
+    }
+
+    /// Executes instructions until a breakpoint, the end of the program, or
+    /// the start of an assembly instruction where `done` returns true, given
+    /// the call depth.
+    fn resume(&mut self, done: impl Fn(usize) -> bool) -> Result<Stop, ExecError> {
+        let mut first = true;
+        let res = loop {
+            let pc = self.vm.pc();
+            if !(first && self.stopped_at == Some(pc)) && self.breakpoints.contains(&pc) {
+                break Ok(Stop::Breakpoint);
+            }
+            if !first
+                && self.program.source(pc).is_some_and(|src| src.start == pc)
+                && done(self.vm.calls().len())
+            {
+                break Ok(Stop::Step);
+            }
+            first = false;
+            match self.vm.step() {
+                Ok(None) => {}
+                Ok(Some(exit)) => break Ok(exit.into()),
+                Err(err) => break Err(err),
+            }
+        };
+        self.stop(res)
+    }
+
+    fn stop(&mut self, res: Result<Stop, ExecError>) -> Result<Stop, ExecError> {
+        self.stopped_at = Some(self.vm.pc());
+        self.vm.flush()?;
+        res
+    }
+
+    /// Gets the index of the next instruction to execute.
+    pub fn pc(&self) -> usize {
+        self.vm.pc()
+    }
+
+    /// Gets the source of the next instruction to execute.
+    pub fn source(&self) -> Option<SourceInst> {
+        self.program.source(self.vm.pc())
+    }
+
+    /// Gets the frames of the call stack, with the innermost first.
+    pub fn frames(&self) -> Vec<Frame> {
+        let calls = self.vm.calls();
+        let mut frames = Vec::with_capacity(calls.len() + 1);
+        let mut pc = self.vm.pc();
+        for &ret in calls.iter().rev() {
+            let call = ret - 1;
+            frames.push(Frame {
+                pc,
+                label: self.program.program.call_target(call).cloned(),
+            });
+            pc = call;
+        }
+        frames.push(Frame { pc, label: None });
+        frames
+    }
+
+    /// Gets the stack, with the top last.
+    pub fn stack(&self) -> &[Integer] {
+        self.vm.stack()
+    }
+
+    /// Gets the heap. Addresses which have not been stored to are 0.
+    pub fn heap(&self) -> &BTreeMap<Integer, Integer> {
+        self.vm.heap()
+    }
+
+    /// Gets a mutable reference to the output.
+    pub fn output_mut(&mut self) -> &mut W {
+        self.vm.output_mut()
+    }
+
+    /// Gets the program being debugged.
+    pub fn program(&self) -> &'p DebugProgram {
+        self.program
+    }
+}
+
+impl From<Exit> for Stop {
+    fn from(exit: Exit) -> Self {
+        match exit {
+            Exit::End => Stop::End,
+            Exit::Breakpoint => Stop::Breakpoint,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use crate::{
+        codegen::CodegenConfig,
+        dialects::{Burghard, ConfigDialect, Dialect, Voliva},
+        exec::{DebugProgram, Debugger, Stop},
+        syntax::Opcode,
+    };
+
+    const SRC: &[u8] = b"push 3
+call count
+exit
+
+label count
+    doub
+    jumpp more
+    ret
+label more
+    debug_printstack
+    push 1
+    sub
+    jump count
+";
+
+    #[test]
+    fn macro_sources() {
+        let cst = Burghard::new().parse(SRC);
+        let program = DebugProgram::new(&cst, &HashSet::new(), &CodegenConfig::default());
+        let (line, pc) = program.line_pc(7).unwrap();
+        assert_eq!(line, 7);
+        for i in pc..pc + 7 {
+            let src = program.source(i).unwrap();
+            assert_eq!(
+                (src.line, src.opcode, src.start),
+                (7, Opcode::BurghardJmpPos, pc)
+            );
+        }
+        assert_eq!(program.line_pc(4), Some((6, 3)));
+        assert_eq!(program.label_pc(b"more"), Some(pc + 8));
+    }
+
+    #[test]
+    fn breakpoints_and_steps() {
+        let cst = Burghard::new().parse(SRC);
+        let program = DebugProgram::new(&cst, &HashSet::new(), &CodegenConfig::default());
+        let mut out = Vec::new();
+        let mut dbg = Debugger::new(&program, &b""[..], &mut out);
+        assert_eq!(dbg.set_line_breakpoint(9), Some(10));
+        assert!(dbg.set_label_breakpoint(b"count"));
+
+        assert_eq!(dbg.cont().unwrap(), Stop::Breakpoint);
+        assert_eq!(dbg.source().unwrap().line, 6);
+        let frames = dbg.frames();
+        assert_eq!(frames.len(), 2);
+        assert_eq!(
+            program.label_name(frames[0].label.as_ref().unwrap()),
+            Some(&b"count"[..])
+        );
+        assert_eq!(program.source(frames[1].pc).unwrap().line, 2);
+
+        assert_eq!(dbg.step_in().unwrap(), Stop::Step);
+        assert_eq!(dbg.source().unwrap().line, 7);
+        assert_eq!(dbg.stack(), [3, 3]);
+        assert_eq!(dbg.step_over().unwrap(), Stop::Breakpoint);
+        assert_eq!(dbg.source().unwrap().line, 10);
+        assert_eq!(dbg.stack(), [3]);
+
+        dbg.clear_breakpoints();
+        assert_eq!(dbg.step_out().unwrap(), Stop::Step);
+        assert_eq!(dbg.source().unwrap().line, 3);
+        assert!(dbg.frames().len() == 1 && dbg.stack() == [0]);
+        assert_eq!(dbg.cont().unwrap(), Stop::End);
+        drop(dbg);
+        assert_eq!(out, b"stack: 3\nstack: 2\nstack: 1\n");
+    }
+
+    #[test]
+    fn voliva_breakpoint() {
+        let config = Voliva::make_config();
+        let cst = ConfigDialect::new(&config)
+            .unwrap()
+            .parse(b"push 1\ndbg\npush 2\nexit\n");
+        let program = DebugProgram::new(&cst, &HashSet::new(), &CodegenConfig::default());
+        let mut dbg = Debugger::new(&program, &b""[..], Vec::new());
+        assert_eq!(dbg.cont().unwrap(), Stop::Breakpoint);
+        assert_eq!(dbg.source().unwrap().line, 3);
+        assert_eq!(dbg.cont().unwrap(), Stop::End);
+        assert_eq!(dbg.stack(), [1, 2]);
+    }
+}
@@ -0,0 +1,9 @@
+//! Execution of Whitespace programs.
+
+mod debug;
+mod program;
+mod vm;
+
+pub use debug::*;
+pub use program::*;
+pub use vm::*;
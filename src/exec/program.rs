@@ -0,0 +1,134 @@
+//! Programs of Whitespace instructions for execution.
+
+use std::{collections::HashMap, convert::Infallible};
+
+use rug::Integer;
+
+use crate::codegen::{Inst, InstWrite};
+
+/// A Whitespace program for execution, with labels resolved to instruction
+/// indices.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Program {
+    pub(super) ops: Vec<Op>,
+    /// A mapping from label to the index of the instruction after its
+    /// definition. When a label is defined more than once, the first
+    /// definition is used, like the reference interpreter.
+    labels: HashMap<Integer, usize>,
+}
+
+/// An instruction in an executable program.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(super) enum Op {
+    Push(Integer),
+    Dup,
+    Copy(Integer),
+    Swap,
+    Drop,
+    Slide(Integer),
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Mod,
+    Store,
+    Retrieve,
+    Call(Integer),
+    Jmp(Integer),
+    Jz(Integer),
+    Jn(Integer),
+    Ret,
+    End,
+    Printc,
+    Printi,
+    Readc,
+    Readi,
+    BurghardPrintStack,
+    BurghardPrintHeap,
+    VolivaOr,
+    VolivaNot,
+    VolivaAnd,
+    VolivaBreakpoint,
+}
+
+impl Program {
+    /// Constructs an empty program.
+    pub fn new() -> Self {
+        Program::default()
+    }
+
+    /// Appends an instruction to the program.
+    pub fn push(&mut self, inst: Inst<'_>) {
+        let op = match inst {
+            Inst::Push(n) => Op::Push(n.value().clone()),
+            Inst::Dup => Op::Dup,
+            Inst::Copy(n) => Op::Copy(n.value().clone()),
+            Inst::Swap => Op::Swap,
+            Inst::Drop => Op::Drop,
+            Inst::Slide(n) => Op::Slide(n.value().clone()),
+            Inst::Add => Op::Add,
+            Inst::Sub => Op::Sub,
+            Inst::Mul => Op::Mul,
+            Inst::Div => Op::Div,
+            Inst::Mod => Op::Mod,
+            Inst::Store => Op::Store,
+            Inst::Retrieve => Op::Retrieve,
+            Inst::Label(l) => {
+                let pc = self.ops.len();
+                self.labels.entry(l.value().clone()).or_insert(pc);
+                return;
+            }
+            Inst::Call(l) => Op::Call(l.value().clone()),
+            Inst::Jmp(l) => Op::Jmp(l.value().clone()),
+            Inst::Jz(l) => Op::Jz(l.value().clone()),
+            Inst::Jn(l) => Op::Jn(l.value().clone()),
+            Inst::Ret => Op::Ret,
+            Inst::End => Op::End,
+            Inst::Printc => Op::Printc,
+            Inst::Printi => Op::Printi,
+            Inst::Readc => Op::Readc,
+            Inst::Readi => Op::Readi,
+            Inst::BurghardPrintStack => Op::BurghardPrintStack,
+            Inst::BurghardPrintHeap => Op::BurghardPrintHeap,
+            Inst::VolivaOr => Op::VolivaOr,
+            Inst::VolivaNot => Op::VolivaNot,
+            Inst::VolivaAnd => Op::VolivaAnd,
+            Inst::VolivaBreakpoint => Op::VolivaBreakpoint,
+        };
+        self.ops.push(op);
+    }
+
+    /// Gets the index of the instruction after the definition of a label.
+    pub fn label(&self, label: &Integer) -> Option<usize> {
+        self.labels.get(label).copied()
+    }
+
+    /// Gets the label called by the instruction at the index, if it is a
+    /// `call`.
+    pub fn call_target(&self, pc: usize) -> Option<&Integer> {
+        match self.ops.get(pc)? {
+            Op::Call(l) => Some(l),
+            _ => None,
+        }
+    }
+
+    /// Returns the number of instructions in the program, excluding labels.
+    pub fn len(&self) -> usize {
+        self.ops.len()
+    }
+
+    /// Returns whether the program has no instructions, excluding labels.
+    pub fn is_empty(&self) -> bool {
+        self.ops.is_empty()
+    }
+}
+
+/// Collects instructions from code generation.
+impl InstWrite for Program {
+    type Error = Infallible;
+
+    fn write_inst(&mut self, inst: Inst<'_>) -> Result<(), Self::Error> {
+        self.push(inst);
+        Ok(())
+    }
+}
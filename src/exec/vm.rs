@@ -0,0 +1,499 @@
+//! Virtual machine for executing Whitespace programs.
+
+use std::{
+    collections::BTreeMap,
+    error::Error,
+    fmt::{self, Display, Formatter},
+    io::{self, BufRead, Write},
+    mem,
+};
+
+use rug::{
+    Integer,
+    ops::{DivRounding, RemRounding},
+};
+
+use crate::exec::program::{Op, Program};
+
+// TODO:
+// - Configure the semantics of other interpreters, e.g., wrapping integers,
+//   implicit `end`, and reading beyond the heap.
+
+/// A virtual machine for executing a Whitespace program with arbitrary-precision
+/// integers and the semantics of the Haskell reference interpreter.
+#[derive(Debug)]
+pub struct Vm<'p, R, W> {
+    program: &'p Program,
+    pc: usize,
+    stack: Vec<Integer>,
+    heap: BTreeMap<Integer, Integer>,
+    calls: Vec<usize>,
+    input: R,
+    output: W,
+    line_buf: String,
+}
+
+/// The reason a virtual machine stopped without an error.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Exit {
+    /// The program executed `end`.
+    End,
+    /// The program executed voliva `dbg`. Execution can be resumed.
+    Breakpoint,
+}
+
+/// An error from executing a Whitespace program.
+#[derive(Debug)]
+pub struct ExecError {
+    /// The index of the instruction, which failed. When execution continued
+    /// past the last instruction, it is the length of the program.
+    pub pc: usize,
+    /// The kind of the error.
+    pub kind: ExecErrorKind,
+}
+
+/// The kind of an error from executing a Whitespace program.
+#[derive(Debug)]
+pub enum ExecErrorKind {
+    /// Too few values on the stack for the instruction.
+    StackUnderflow,
+    /// `copy` with an index outside of the stack.
+    CopyOutOfBounds,
+    /// `ret` without a corresponding `call`.
+    CallStackUnderflow,
+    /// A jump to a label, which is not defined.
+    UndefinedLabel(Integer),
+    /// Division or modulo by zero.
+    DivisionByZero,
+    /// `store` or `retrieve` at a negative address.
+    NegativeAddress(Integer),
+    /// `printc` of a value, which is not a Unicode scalar value.
+    InvalidChar(Integer),
+    /// `readc` or `readi` at the end of input.
+    UnexpectedEof,
+    /// `readi` of a line, which is not an integer.
+    InvalidInteger(String),
+    /// Execution continued past the last instruction without `end`.
+    ImplicitEnd,
+    /// An error from reading input or writing output.
+    Io(io::Error),
+}
+
+impl<'p, R: BufRead, W: Write> Vm<'p, R, W> {
+    /// Constructs a virtual machine for the program, which reads from the
+    /// input and writes to the output.
+    pub fn new(program: &'p Program, input: R, output: W) -> Self {
+        Vm {
+            program,
+            pc: 0,
+            stack: Vec::new(),
+            heap: BTreeMap::new(),
+            calls: Vec::new(),
+            input,
+            output,
+            line_buf: String::new(),
+        }
+    }
+
+    /// Runs the program until it exits or encounters an error. Output is
+    /// flushed before returning.
+    pub fn run(&mut self) -> Result<Exit, ExecError> {
+        let res = loop {
+            match self.step() {
+                Ok(None) => {}
+                Ok(Some(exit)) => break Ok(exit),
+                Err(err) => break Err(err),
+            }
+        };
+        self.flush()?;
+        res
+    }
+
+    /// Flushes the output.
+    pub fn flush(&mut self) -> Result<(), ExecError> {
+        (self.output.flush()).map_err(|err| ExecError {
+            pc: self.pc,
+            kind: ExecErrorKind::Io(err),
+        })
+    }
+
+    /// Executes one instruction and returns the exit reason, if it stopped.
+    pub fn step(&mut self) -> Result<Option<Exit>, ExecError> {
+        let pc = self.pc;
+        self.exec().map_err(|kind| ExecError { pc, kind })
+    }
+
+    fn exec(&mut self) -> Result<Option<Exit>, ExecErrorKind> {
+        let Some(op) = self.program.ops.get(self.pc) else {
+            return Err(ExecErrorKind::ImplicitEnd);
+        };
+        self.pc += 1;
+        match op {
+            Op::Push(n) => self.stack.push(n.clone()),
+            Op::Dup => {
+                let top = self.top()?.clone();
+                self.stack.push(top);
+            }
+            Op::Copy(n) => {
+                let index = (n.to_usize())
+                    .and_then(|n| self.stack.len().checked_sub(n + 1))
+                    .ok_or(ExecErrorKind::CopyOutOfBounds)?;
+                self.stack.push(self.stack[index].clone());
+            }
+            Op::Swap => {
+                let (y, x) = (self.pop()?, self.pop()?);
+                self.stack.push(y);
+                self.stack.push(x);
+            }
+            Op::Drop => {
+                self.pop()?;
+            }
+            Op::Slide(n) => {
+                let top = self.pop()?;
+                if !n.is_negative() {
+                    let n = n.to_usize().unwrap_or(usize::MAX).min(self.stack.len());
+                    self.stack.truncate(self.stack.len() - n);
+                }
+                self.stack.push(top);
+            }
+            Op::Add => self.arith(|x, y| Ok(x + y))?,
+            Op::Sub => self.arith(|x, y| Ok(x - y))?,
+            Op::Mul => self.arith(|x, y| Ok(x * y))?,
+            Op::Div => self.arith(|x, y| {
+                if y.is_zero() {
+                    return Err(ExecErrorKind::DivisionByZero);
+                }
+                Ok(x.div_floor(y))
+            })?,
+            Op::Mod => self.arith(|x, y| {
+                if y.is_zero() {
+                    return Err(ExecErrorKind::DivisionByZero);
+                }
+                Ok(x.rem_floor(y))
+            })?,
+            Op::Store => {
+                let (value, addr) = (self.pop()?, self.pop()?);
+                check_address(&addr)?;
+                self.heap.insert(addr, value);
+            }
+            Op::Retrieve => {
+                let addr = self.pop()?;
+                check_address(&addr)?;
+                let value = self.heap.get(&addr).cloned().unwrap_or_default();
+                self.stack.push(value);
+            }
+            Op::Call(l) => {
+                self.calls.push(self.pc);
+                self.pc = self.label(l)?;
+            }
+            Op::Jmp(l) => self.pc = self.label(l)?,
+            Op::Jz(l) => {
+                if self.pop()?.is_zero() {
+                    self.pc = self.label(l)?;
+                }
+            }
+            Op::Jn(l) => {
+                if self.pop()?.is_negative() {
+                    self.pc = self.label(l)?;
+                }
+            }
+            Op::Ret => self.pc = self.calls.pop().ok_or(ExecErrorKind::CallStackUnderflow)?,
+            Op::End => {
+                self.pc -= 1;
+                return Ok(Some(Exit::End));
+            }
+            Op::Printc => {
+                let n = self.pop()?;
+                let ch = (n.to_u32())
+                    .and_then(char::from_u32)
+                    .ok_or(ExecErrorKind::InvalidChar(n))?;
+                write!(self.output, "{ch}").map_err(ExecErrorKind::Io)?;
+            }
+            Op::Printi => {
+                let n = self.pop()?;
+                write!(self.output, "{n}").map_err(ExecErrorKind::Io)?;
+            }
+            Op::Readc => {
+                let addr = self.pop()?;
+                check_address(&addr)?;
+                self.output.flush().map_err(ExecErrorKind::Io)?;
+                let ch = self.read_char()?;
+                self.heap.insert(addr, Integer::from(ch as u32));
+            }
+            Op::Readi => {
+                let addr = self.pop()?;
+                check_address(&addr)?;
+                self.output.flush().map_err(ExecErrorKind::Io)?;
+                let n = self.read_integer()?;
+                self.heap.insert(addr, n);
+            }
+            Op::BurghardPrintStack => {
+                write!(self.output, "stack:").map_err(ExecErrorKind::Io)?;
+                for n in &self.stack {
+                    write!(self.output, " {n}").map_err(ExecErrorKind::Io)?;
+                }
+                writeln!(self.output).map_err(ExecErrorKind::Io)?;
+            }
+            Op::BurghardPrintHeap => {
+                write!(self.output, "heap:").map_err(ExecErrorKind::Io)?;
+                for (addr, n) in &self.heap {
+                    write!(self.output, " {addr}:{n}").map_err(ExecErrorKind::Io)?;
+                }
+                writeln!(self.output).map_err(ExecErrorKind::Io)?;
+            }
+            Op::VolivaOr => self.arith(|x, y| Ok(x | y))?,
+            Op::VolivaNot => {
+                let x = self.pop()?;
+                self.stack.push(!x);
+            }
+            Op::VolivaAnd => self.arith(|x, y| Ok(x & y))?,
+            Op::VolivaBreakpoint => return Ok(Some(Exit::Breakpoint)),
+        }
+        Ok(None)
+    }
+
+    /// Gets the index of the next instruction to execute.
+    pub fn pc(&self) -> usize {
+        self.pc
+    }
+
+    /// Gets the stack, with the top last.
+    pub fn stack(&self) -> &[Integer] {
+        &self.stack
+    }
+
+    /// Gets the heap. Addresses which have not been stored to are 0.
+    pub fn heap(&self) -> &BTreeMap<Integer, Integer> {
+        &self.heap
+    }
+
+    /// Gets the return addresses of the active calls, with the innermost last.
+    pub fn calls(&self) -> &[usize] {
+        &self.calls
+    }
+
+    /// Gets a mutable reference to the output.
+    pub fn output_mut(&mut self) -> &mut W {
+        &mut self.output
+    }
+
+    /// Consumes the virtual machine and returns its output.
+    pub fn into_output(self) -> W {
+        self.output
+    }
+
+    fn top(&self) -> Result<&Integer, ExecErrorKind> {
+        self.stack.last().ok_or(ExecErrorKind::StackUnderflow)
+    }
+
+    fn pop(&mut self) -> Result<Integer, ExecErrorKind> {
+        self.stack.pop().ok_or(ExecErrorKind::StackUnderflow)
+    }
+
+    /// Pops `y` then `x` and pushes the result of the operation on `x` and `y`.
+    fn arith(
+        &mut self,
+        f: impl FnOnce(Integer, Integer) -> Result<Integer, ExecErrorKind>,
+    ) -> Result<(), ExecErrorKind> {
+        if self.stack.len() < 2 {
+            return Err(ExecErrorKind::StackUnderflow);
+        }
+        let (y, x) = (self.pop()?, self.pop()?);
+        self.stack.push(f(x, y)?);
+        Ok(())
+    }
+
+    fn label(&self, label: &Integer) -> Result<usize, ExecErrorKind> {
+        (self.program.label(label)).ok_or_else(|| ExecErrorKind::UndefinedLabel(label.clone()))
+    }
+
+    /// Reads a UTF-8 char from the input.
+    fn read_char(&mut self) -> Result<char, ExecErrorKind> {
+        let buf = self.input.fill_buf().map_err(ExecErrorKind::Io)?;
+        let &first = buf.first().ok_or(ExecErrorKind::UnexpectedEof)?;
+        let len = match first {
+            ..0x80 => 1,
+            0xc0..0xe0 => 2,
+            0xe0..0xf0 => 3,
+            0xf0.. => 4,
+            _ => 1,
+        };
+        let mut bytes = [0; 4];
+        let mut n = 0;
+        while n < len {
+            let buf = self.input.fill_buf().map_err(ExecErrorKind::Io)?;
+            if buf.is_empty() {
+                break;
+            }
+            let k = (len - n).min(buf.len());
+            bytes[n..n + k].copy_from_slice(&buf[..k]);
+            self.input.consume(k);
+            n += k;
+        }
+        let ch = str::from_utf8(&bytes[..n])
+            .ok()
+            .and_then(|s| s.chars().next());
+        Ok(ch.unwrap_or(char::REPLACEMENT_CHARACTER))
+    }
+
+    /// Reads a line from the input and parses it as an integer like Haskell
+    /// `read`.
+    fn read_integer(&mut self) -> Result<Integer, ExecErrorKind> {
+        self.line_buf.clear();
+        let n = (self.input.read_line(&mut self.line_buf)).map_err(ExecErrorKind::Io)?;
+        if n == 0 {
+            return Err(ExecErrorKind::UnexpectedEof);
+        }
+        let line = self.line_buf.trim();
+        let digits = line.strip_prefix('-').unwrap_or(line);
+        if digits.is_empty() || !digits.bytes().all(|b| b.is_ascii_digit()) {
+            return Err(ExecErrorKind::InvalidInteger(mem::take(&mut self.line_buf)));
+        }
+        Ok(line.parse().unwrap())
+    }
+}
+
+fn check_address(addr: &Integer) -> Result<(), ExecErrorKind> {
+    if addr.is_negative() {
+        return Err(ExecErrorKind::NegativeAddress(addr.clone()));
+    }
+    Ok(())
+}
+
+impl Display for ExecError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        self.kind.fmt(f)
+    }
+}
+
+impl Error for ExecError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        self.kind.source()
+    }
+}
+
+impl Display for ExecErrorKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            ExecErrorKind::StackUnderflow => write!(f, "stack underflow"),
+            ExecErrorKind::CopyOutOfBounds => write!(f, "copy index out of bounds"),
+            ExecErrorKind::CallStackUnderflow => write!(f, "return outside of call"),
+            ExecErrorKind::UndefinedLabel(l) => write!(f, "undefined label {l}"),
+            ExecErrorKind::DivisionByZero => write!(f, "division by zero"),
+            ExecErrorKind::NegativeAddress(addr) => write!(f, "negative heap address {addr}"),
+            ExecErrorKind::InvalidChar(n) => write!(f, "invalid char {n}"),
+            ExecErrorKind::UnexpectedEof => write!(f, "unexpected end of input"),
+            ExecErrorKind::InvalidInteger(line) => {
+                write!(f, "invalid integer {:?}", line.trim_end())
+            }
+            ExecErrorKind::ImplicitEnd => write!(f, "execution reached the end without `end`"),
+            ExecErrorKind::Io(err) => write!(f, "{err}"),
+        }
+    }
+}
+
+impl Error for ExecErrorKind {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            ExecErrorKind::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use crate::{
+        dialects::{Burghard, Dialect as _},
+        exec::{ExecError, ExecErrorKind, Exit, Program, Vm},
+    };
+
+    fn run(src: &[u8], input: &[u8]) -> (Result<Exit, ExecError>, String) {
+        let cst = Burghard::new().parse(src);
+        let mut program = Program::new();
+        cst.codegen(&mut program, &HashSet::new()).unwrap();
+        let mut vm = Vm::new(&program, input, Vec::new());
+        let res = vm.run();
+        (res, String::from_utf8(vm.into_output()).unwrap())
+    }
+
+    #[test]
+    fn arithmetic() {
+        let src = b"push -7\npush 2\ndiv\noutN\npush 10\noutC\n\
+            push -7\npush 2\nmod\noutN\npush 10\noutC\n\
+            push 7\npush -2\nmod\noutN\nexit\n";
+        let (res, out) = run(src, b"");
+        assert!(matches!(res, Ok(Exit::End)));
+        assert_eq!(out, "-4\n1\n-1");
+    }
+
+    #[test]
+    fn control_flow_and_io() {
+        let src = b"push 0
+inN
+push 1
+inC
+push 0
+retrive
+call square
+outN
+push 1
+retrive
+outC
+exit
+label square
+doub
+mul
+ret
+";
+        let (res, out) = run(src, b"-12\n\xce\xbb");
+        assert!(matches!(res, Ok(Exit::End)));
+        assert_eq!(out, "144λ");
+    }
+
+    #[test]
+    fn errors() {
+        let (res, _) = run(b"push 1\npush 0\ndiv\n", b"");
+        assert!(matches!(
+            res,
+            Err(ExecError {
+                pc: 2,
+                kind: ExecErrorKind::DivisionByZero
+            })
+        ));
+        let (res, _) = run(b"push 1\n", b"");
+        assert!(matches!(
+            res,
+            Err(ExecError {
+                pc: 1,
+                kind: ExecErrorKind::ImplicitEnd
+            })
+        ));
+        let (res, _) = run(b"", b"");
+        assert!(matches!(
+            res,
+            Err(ExecError {
+                pc: 0,
+                kind: ExecErrorKind::ImplicitEnd
+            })
+        ));
+        let (res, _) = run(b"push 0\ninN\n", b"+1\n");
+        assert!(matches!(
+            res,
+            Err(ExecError {
+                pc: 1,
+                kind: ExecErrorKind::InvalidInteger(_)
+            })
+        ));
+        let (res, _) = run(b"ret\n", b"");
+        assert!(matches!(
+            res,
+            Err(ExecError {
+                pc: 0,
+                kind: ExecErrorKind::CallStackUnderflow
+            })
+        ));
+    }
+}
@@ -155,10 +155,10 @@ impl<'s> Node<'s> {
                 if let Some(entry_index) = fallback {
                     *last_match = Some((entry_index, text.len()));
                 }
-                if let Some((&b, text)) = text.split_first() {
-                    if sparse.contains(b) {
-                        dense[sparse.dense_index(b)].get_prefix(text, last_match);
-                    }
+                if let Some((&b, text)) = text.split_first()
+                    && sparse.contains(b)
+                {
+                    dense[sparse.dense_index(b)].get_prefix(text, last_match);
                 }
             }
             Node::Leaf { tail, entry_index } => {
@@ -3,6 +3,7 @@
 
 pub mod codegen;
 pub mod dialects;
+pub mod exec;
 pub mod lex;
 pub mod syntax;
 #[cfg(test)]
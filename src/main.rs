@@ -1,38 +1,127 @@
 #![doc = include_str!("../README.md")]
 
 use std::{
+    collections::HashSet,
     fs::{self, File},
     io::{self, BufWriter, Write},
     path::PathBuf,
     process::exit,
 };
 
-use clap::{Parser, ValueEnum};
+use clap::{Args, CommandFactory, Parser, Subcommand, ValueEnum, error::ErrorKind};
 use omniwsa::{
-    codegen::{Token, TokenWrite},
-    dialects::{Burghard, Dialect as _, Palaiologos},
+    codegen::{
+        CodegenConfig, EncodingWriter, InstStream, Peephole, RepConfig, RepLoop, TargetLimits,
+    },
+    dialects::{Burghard, ConfigDialect, Dialect as _, DialectConfig, Palaiologos},
+    exec::{Exit, Program, Vm},
+    syntax::Cst,
+    ws::{canonical::Canonicalize, encoding::TokenEncoding},
 };
+use rug::Integer;
 
 // TODO:
 // - Extract to separate crate with isolated clap dependency.
+// - Record source maps for optimized instruction streams.
 
 #[derive(Parser)]
-#[command(version, about, long_about = None)]
+#[command(
+    version,
+    about,
+    long_about = None,
+    args_conflicts_with_subcommands = true,
+    subcommand_negates_reqs = true,
+)]
 struct Cli {
-    /// Input Whitespace assembly program.
-    input: PathBuf,
-    /// Whitespace assembly dialect of the input program.
-    #[arg(short, long)]
-    dialect: Dialect,
+    #[command(subcommand)]
+    command: Option<Command>,
+    #[command(flatten)]
+    input: InputArgs,
     /// Output Whitespace program.
     #[arg(short, long, value_name = "FILE", group = "out")]
     output: Option<PathBuf>,
     /// Whether to print the output Whitespace program to stdout.
     #[arg(short, long, group = "out")]
     stdout: bool,
+    /// Write a source map, which maps each generated instruction to the
+    /// assembly instruction it came from. No optimizations are applied. Output
+    /// offsets count one byte per token, so it requires the standard or STL
+    /// encoding without comments.
+    #[arg(long, value_name = "FILE", conflicts_with_all = ["optimize", "comments"])]
+    source_map: Option<PathBuf>,
+    /// The token encoding of the output Whitespace program.
+    #[arg(long, value_name = "ENCODING", default_value = "standard")]
+    encoding: Encoding,
+    /// Comment each instruction in the output with its mnemonic and argument.
+    #[arg(long)]
+    comments: bool,
+    /// Comment the output with each assembly instruction before the tokens
+    /// generated from it.
+    #[arg(long, conflicts_with = "source_map")]
+    annotate: bool,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Assemble and run a Whitespace assembly program.
+    Run {
+        #[command(flatten)]
+        input: InputArgs,
+    },
+    /// Canonicalize a Whitespace program, removing comments and non-standard
+    /// lexemes.
+    Fmt {
+        /// Input Whitespace program.
+        input: PathBuf,
+        /// Output Whitespace program. Defaults to stdout.
+        #[arg(short, long, value_name = "FILE")]
+        output: Option<PathBuf>,
+        /// The token encoding of the input and output Whitespace programs.
+        #[arg(long, value_name = "ENCODING", default_value = "standard")]
+        encoding: Encoding,
+        /// Encode integers and labels without leading zeros.
+        #[arg(long)]
+        minimize: bool,
+        /// Renumber labels, so that the most used have the shortest encodings.
+        #[arg(long)]
+        renumber_labels: bool,
+    },
+}
+
+#[derive(Args)]
+struct InputArgs {
+    /// Input Whitespace assembly program.
+    #[arg(required = true)]
+    input: Option<PathBuf>,
+    /// Whitespace assembly dialect of the input program.
+    #[arg(short, long, required_unless_present = "dialect_config")]
+    dialect: Option<Dialect>,
+    /// Config file describing the Whitespace assembly dialect of the input
+    /// program.
+    #[arg(long, value_name = "FILE", conflicts_with = "dialect")]
+    dialect_config: Option<PathBuf>,
     /// Enable an option for conditional compilation.
     #[arg(short, long, value_name = "OPTION")]
     enable_option: Vec<Vec<u8>>,
+    /// Enable a peephole optimization, in addition to those the dialect always
+    /// applies.
+    #[arg(short = 'O', long, value_name = "FOLD")]
+    optimize: Vec<Optimization>,
+    /// Lower Palaiologos `rep` to a loop when its count is greater than this.
+    #[arg(long, value_name = "COUNT", requires = "rep_counter_address")]
+    rep_max_unroll: Option<u32>,
+    /// Lower Palaiologos `rep` to a loop when it would be shorter than
+    /// unrolling.
+    #[arg(long, requires = "rep_counter_address")]
+    rep_by_size: bool,
+    /// The heap address of the loop counter for Palaiologos `rep`, which must
+    /// not otherwise be used by the program. Required to lower `rep` to a loop.
+    #[arg(long, value_name = "ADDRESS")]
+    rep_counter_address: Option<Integer>,
+    /// The integer limits of the interpreter the program targets, which
+    /// arguments are checked against.
+    #[arg(long, value_name = "TARGET", default_value = "haskell")]
+    target: Target,
 }
 
 /// The dialect of Whitespace assembly.
@@ -44,26 +133,91 @@ enum Dialect {
     Palaiologos,
 }
 
+/// The integer limits of a target Whitespace interpreter.
+#[derive(Clone, Copy, Debug, ValueEnum)]
+enum Target {
+    /// Arbitrary-precision values and 64-bit `copy` and `slide` indices, like
+    /// the Haskell reference interpreter.
+    Haskell,
+    /// Arbitrary-precision values and indices.
+    Unbounded,
+    /// 32-bit values and indices.
+    Int32,
+    /// 64-bit values and indices.
+    Int64,
+}
+
+/// The token encoding of a Whitespace program.
+#[derive(Clone, Copy, Debug, ValueEnum)]
+enum Encoding {
+    /// Space, tab, and line feed.
+    Standard,
+    /// Visible letters `S`, `T`, and `L`.
+    Stl,
+    /// GrassMudHorse Chinese characters 草, 泥, and 马.
+    GrassMudHorse,
+    /// Packed bits.
+    Packed,
+}
+
+/// A peephole optimization.
+#[derive(Clone, Copy, Debug, ValueEnum)]
+enum Optimization {
+    /// Fold arithmetic on constants.
+    ConstFold,
+    /// Remove `dup / drop`.
+    DupDrop,
+    /// Remove `slide 0`.
+    SlideZero,
+    /// Remove jumps to the next instruction.
+    JmpNext,
+    /// All optimizations.
+    All,
+}
+
 fn main() {
     let cli = Cli::parse();
-    let src = match fs::read(&cli.input) {
-        Ok(src) => src,
-        Err(err) => {
-            eprintln!("Error: reading input {:?}: {err}", cli.input);
-            exit(2);
+    if cli.source_map.is_some() && !matches!(cli.encoding, Encoding::Standard | Encoding::Stl) {
+        Cli::command()
+            .error(
+                ErrorKind::ArgumentConflict,
+                "--source-map requires the standard or stl encoding",
+            )
+            .exit();
+    }
+    match &cli.command {
+        Some(Command::Run { input }) => run(input),
+        Some(Command::Fmt {
+            input,
+            output,
+            encoding,
+            minimize,
+            renumber_labels,
+        }) => {
+            let options = Canonicalize {
+                minimize_integers: *minimize,
+                minimize_labels: *minimize,
+                renumber_labels: *renumber_labels,
+            };
+            fmt(input, output.as_ref(), encoding.encoding(), options);
         }
-    };
-    let cst = match cli.dialect {
-        Dialect::Burghard => Burghard::new().parse(&src),
-        Dialect::Palaiologos => Palaiologos::new().parse(&src),
-    };
+        None => assemble(&cli),
+    }
+}
+
+/// Assembles the input program to a Whitespace program.
+fn assemble(cli: &Cli) {
+    let input = cli.input.input.as_ref().unwrap();
+    let src = read_input(input);
+    let config = read_config(&cli.input);
+    let cst = parse(&cli.input, &src, config.as_ref());
     let output: Box<BufWriter<dyn Write>> = if cli.stdout {
         Box::new(BufWriter::new(io::stdout()))
     } else {
-        let output = if let Some(output) = cli.output {
-            output
+        let output = if let Some(output) = &cli.output {
+            output.clone()
         } else {
-            let mut output = cli.input.clone();
+            let mut output = input.clone();
             output.set_extension("ws");
             output
         };
@@ -75,32 +229,231 @@ fn main() {
             }
         }
     };
-    let options = cli
-        .enable_option
-        .iter()
-        .map(|option| option.as_slice())
-        .collect();
-    match cst.codegen(&mut TokenWriter(output), &options) {
-        Ok(()) => {}
+    let mut w = EncodingWriter::new(output, cli.encoding.encoding());
+    if cli.comments {
+        w = w.with_comments();
+    }
+    let options = cli.input.options();
+    let res = match &cli.source_map {
+        Some(path) => cst
+            .codegen_with_source_map(&mut w, &options, &cli.input.config(), cli.input.peephole())
+            .map(|map| Some((path, map))),
+        None => {
+            let mut stream = InstStream::with_peephole(cli.input.peephole());
+            let config = cli.input.config();
+            let Ok(()) = if cli.annotate {
+                cst.codegen_annotated(&mut stream, &options, &config)
+            } else {
+                cst.codegen_with_config(&mut stream, &options, &config)
+            };
+            stream.write_to(&mut w).map(|()| None)
+        }
+    };
+    let map = match res.and_then(|map| w.finish().map(|_| map)) {
+        Ok(map) => map,
         Err(err) => {
             eprintln!("Error: writing: {err}");
             exit(1);
         }
+    };
+    if let Some((path, map)) = map {
+        let res = File::create(path).and_then(|f| {
+            let mut f = BufWriter::new(f);
+            map.write(&mut f)?;
+            f.flush()
+        });
+        if let Err(err) = res {
+            eprintln!("Error: writing source map {path:?}: {err}");
+            exit(2);
+        }
     }
 }
 
-struct TokenWriter<W>(W);
+/// Assembles the input program and runs it with standard I/O.
+fn run(args: &InputArgs) {
+    let src = read_input(args.input.as_ref().unwrap());
+    let config = read_config(args);
+    let cst = parse(args, &src, config.as_ref());
+    let mut stream = InstStream::with_peephole(args.peephole());
+    let Ok(()) = cst.codegen_with_config(&mut stream, &args.options(), &args.config());
+    let mut program = Program::new();
+    let Ok(()) = stream.write_to(&mut program);
+    let mut vm = Vm::new(&program, io::stdin().lock(), BufWriter::new(io::stdout()));
+    loop {
+        match vm.run() {
+            Ok(Exit::End) => break,
+            Ok(Exit::Breakpoint) => {}
+            Err(err) => {
+                eprintln!("Error: executing at instruction {}: {err}", err.pc);
+                exit(1);
+            }
+        }
+    }
+}
 
-impl<W: Write> TokenWrite for TokenWriter<W> {
-    type Error = io::Error;
+/// Canonicalizes a Whitespace program.
+fn fmt(input: &PathBuf, output: Option<&PathBuf>, encoding: TokenEncoding, options: Canonicalize) {
+    let src = read_input(input);
+    let out = match options.canonicalize(&src, encoding) {
+        Ok(out) => out,
+        Err(err) => {
+            eprintln!("Error: parsing {input:?}: {err}");
+            exit(1);
+        }
+    };
+    let res = match output {
+        Some(output) => fs::write(output, out),
+        None => io::stdout().write_all(&out),
+    };
+    if let Err(err) = res {
+        eprintln!("Error: writing: {err}");
+        exit(2);
+    }
+}
 
-    fn write_token(&mut self, token: Token) -> Result<(), Self::Error> {
-        self.0.write_all(match token {
-            Token::S => b" ",
-            Token::T => b"\t",
-            Token::L => b"\n",
+fn read_input(input: &PathBuf) -> Vec<u8> {
+    match fs::read(input) {
+        Ok(src) => src,
+        Err(err) => {
+            eprintln!("Error: reading input {input:?}: {err}");
+            exit(2);
+        }
+    }
+}
+
+fn read_config(args: &InputArgs) -> Option<DialectConfig> {
+    let path = args.dialect_config.as_ref()?;
+    let config = match fs::read_to_string(path) {
+        Ok(config) => config,
+        Err(err) => {
+            eprintln!("Error: reading dialect config {path:?}: {err}");
+            exit(2);
+        }
+    };
+    match DialectConfig::parse(&config) {
+        Ok(config) => Some(config),
+        Err(err) => {
+            eprintln!("Error: parsing dialect config {path:?}: {err}");
+            exit(2);
+        }
+    }
+}
+
+/// Parses the input program and reports syntax errors, undefined labels, and
+/// arguments out of range of the dialect or target interpreter. Exits when there are errors, so
+/// code is only generated for well-formed programs.
+fn parse<'s>(args: &InputArgs, src: &'s [u8], config: Option<&DialectConfig>) -> Cst<'s> {
+    let (cst, integers) = match (args.dialect, config) {
+        (Some(Dialect::Burghard), _) => {
+            let dialect = Burghard::new();
+            (dialect.parse(src), dialect.integers().clone())
+        }
+        (Some(Dialect::Palaiologos), _) => {
+            let dialect = Palaiologos::new();
+            (dialect.parse(src), dialect.integers().clone())
+        }
+        (None, Some(config)) => {
+            let dialect = ConfigDialect::new(config).unwrap();
+            (dialect.parse(src), dialect.integers().clone())
+        }
+        (None, None) => unreachable!(),
+    };
+    let mut failed = false;
+    for err in cst.check_syntax() {
+        eprintln!("Error: {err}");
+        failed = true;
+    }
+    for err in cst.check_reps() {
+        eprintln!("Error: {err}");
+        failed = true;
+    }
+    for err in cst.check_labels(&args.options()) {
+        eprintln!("Error: {err}");
+        failed = true;
+    }
+    for err in cst.check_ranges(&integers, &args.target.limits()) {
+        if err.is_warning() {
+            eprintln!("Warning: {err}");
+        } else {
+            eprintln!("Error: {err}");
+            failed = true;
+        }
+    }
+    if failed {
+        exit(1);
+    }
+    cst
+}
+
+impl InputArgs {
+    fn config(&self) -> CodegenConfig {
+        CodegenConfig {
+            rep: RepConfig {
+                loops: (self.rep_counter_address.clone()).map(|counter_address| RepLoop {
+                    max_unroll: self.rep_max_unroll,
+                    by_size: self.rep_by_size,
+                    counter_address,
+                }),
+            },
+        }
+    }
+
+    fn peephole(&self) -> Peephole {
+        let strict = match self.dialect {
+            Some(Dialect::Burghard) => Burghard::PEEPHOLE,
+            Some(Dialect::Palaiologos) => Palaiologos::PEEPHOLE,
+            None => Peephole::NONE,
+        };
+        self.optimize.iter().fold(strict, |p, opt| {
+            p.union(match opt {
+                Optimization::ConstFold => Peephole {
+                    const_fold: true,
+                    ..Peephole::NONE
+                },
+                Optimization::DupDrop => Peephole {
+                    dup_drop: true,
+                    ..Peephole::NONE
+                },
+                Optimization::SlideZero => Peephole {
+                    slide_zero: true,
+                    ..Peephole::NONE
+                },
+                Optimization::JmpNext => Peephole {
+                    jmp_next: true,
+                    ..Peephole::NONE
+                },
+                Optimization::All => Peephole::ALL,
+            })
         })
     }
+
+    fn options(&self) -> HashSet<&[u8]> {
+        (self.enable_option.iter())
+            .map(|option| option.as_slice())
+            .collect()
+    }
+}
+
+impl Target {
+    fn limits(self) -> TargetLimits {
+        match self {
+            Target::Haskell => TargetLimits::haskell(),
+            Target::Unbounded => TargetLimits::unbounded(),
+            Target::Int32 => TargetLimits::int32(),
+            Target::Int64 => TargetLimits::int64(),
+        }
+    }
+}
+
+impl Encoding {
+    fn encoding(self) -> TokenEncoding {
+        match self {
+            Encoding::Standard => TokenEncoding::Standard,
+            Encoding::Stl => TokenEncoding::Stl,
+            Encoding::GrassMudHorse => TokenEncoding::GrassMudHorse,
+            Encoding::Packed => TokenEncoding::Packed,
+        }
+    }
 }
 
 #[test]
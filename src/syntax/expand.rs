@@ -0,0 +1,105 @@
+//! Expansions of macro instructions to primitive Whitespace instructions.
+
+use crate::syntax::{Opcode, Overload};
+
+/// A step in the expansion of a macro instruction or overload to primitive
+/// Whitespace instructions.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MacroStep {
+    /// A primitive instruction without arguments.
+    Inst(Opcode),
+    /// `push` of the indexed argument of the macro.
+    PushArg(usize),
+    /// `push` of a constant.
+    PushConst(u8),
+    /// A jump to the indexed label argument of the macro.
+    JmpArg(Opcode, usize),
+    /// A jump to the indexed label internal to the expansion.
+    JmpInternal(Opcode, usize),
+    /// The definition of the indexed label internal to the expansion.
+    Label(usize),
+    /// The overloaded instruction itself, without arguments.
+    Op,
+}
+
+impl Opcode {
+    /// Returns the expansion of this macro opcode to primitive instructions,
+    /// if it is a macro with a fixed expansion.
+    pub fn expansion(&self) -> Option<&'static [MacroStep]> {
+        use MacroStep::*;
+        Some(match self {
+            Opcode::Push0 => &[PushConst(0)],
+            Opcode::BurghardJmpPos => &[
+                Inst(Opcode::Dup),
+                JmpInternal(Opcode::Jn, 0),
+                Inst(Opcode::Dup),
+                JmpInternal(Opcode::Jz, 0),
+                Inst(Opcode::Drop),
+                JmpArg(Opcode::Jmp, 0),
+                Label(0),
+                Inst(Opcode::Drop),
+            ],
+            Opcode::BurghardJmpNonZero | Opcode::VolivaJmpNonZero => {
+                &[JmpInternal(Opcode::Jz, 0), JmpArg(Opcode::Jmp, 0), Label(0)]
+            }
+            Opcode::BurghardJmpNonPos => &[
+                Inst(Opcode::Dup),
+                JmpInternal(Opcode::Jn, 0),
+                Inst(Opcode::Dup),
+                JmpInternal(Opcode::Jz, 0),
+                JmpInternal(Opcode::Jmp, 1),
+                Label(0),
+                Inst(Opcode::Drop),
+                JmpArg(Opcode::Jmp, 0),
+                Label(1),
+                Inst(Opcode::Drop),
+            ],
+            Opcode::BurghardJmpNonNeg | Opcode::VolivaJmpNonNeg => {
+                &[JmpInternal(Opcode::Jn, 0), JmpArg(Opcode::Jmp, 0), Label(0)]
+            }
+            Opcode::VolivaJmpPos => &[
+                PushConst(0),
+                Inst(Opcode::Swap),
+                Inst(Opcode::Sub),
+                JmpArg(Opcode::Jn, 0),
+            ],
+            Opcode::VolivaJmpNonPos => &[PushConst(1), Inst(Opcode::Sub), JmpArg(Opcode::Jn, 0)],
+            Opcode::BurghardTest => &[Inst(Opcode::Dup), PushArg(0), Inst(Opcode::Sub)],
+            _ => return None,
+        })
+    }
+
+    /// Returns the number of labels internal to the expansion of this macro
+    /// opcode.
+    pub fn internal_labels(&self) -> usize {
+        (self.expansion().unwrap_or_default().iter())
+            .filter(|step| matches!(step, MacroStep::Label(_)))
+            .count()
+    }
+}
+
+impl Overload {
+    /// Returns the expansion of an instruction with this overload to primitive
+    /// instructions.
+    pub fn expansion(&self) -> &'static [MacroStep] {
+        use MacroStep::*;
+        match self {
+            Overload::UnaryConst | Overload::BinaryConstRhs => &[PushArg(0), Op],
+            Overload::UnaryRef | Overload::BinaryRefRhs => {
+                &[PushArg(0), Inst(Opcode::Retrieve), Op]
+            }
+            Overload::BinaryConstLhs => &[PushArg(0), Inst(Opcode::Swap), Op],
+            Overload::BinaryRefLhs => &[PushArg(0), Inst(Opcode::Retrieve), Inst(Opcode::Swap), Op],
+            Overload::BinaryConstConst => &[PushArg(0), PushArg(1), Op],
+            Overload::BinaryRefConst => &[PushArg(0), Inst(Opcode::Retrieve), PushArg(1), Op],
+            Overload::BinaryConstRef => &[PushArg(0), PushArg(1), Inst(Opcode::Retrieve), Op],
+            Overload::BinaryRefRef => &[
+                PushArg(0),
+                Inst(Opcode::Retrieve),
+                PushArg(1),
+                Inst(Opcode::Retrieve),
+                Op,
+            ],
+        }
+    }
+}
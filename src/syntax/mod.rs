@@ -1,11 +1,13 @@
 //! Concrete syntax tree for interoperable Whitespace assembly.
 
 mod cst;
+mod expand;
 mod inst;
 mod opcode;
 mod pretty;
 
 pub use cst::*;
+pub use expand::*;
 pub use inst::*;
 pub use opcode::*;
 pub use pretty::*;
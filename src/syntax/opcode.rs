@@ -22,6 +22,29 @@ macro_rules! opcodes[
                     $(Opcode::$opcode => &[$($(ArgType::$arg),+)?]),*
                 }
             }
+
+            /// Returns the lines of the documentation of this opcode, as written
+            /// in its doc comment, including the space after `///`.
+            pub fn doc(&self) -> &'static [&'static str] {
+                match self {
+                    $(Opcode::$opcode => &[$($doc),*]),*
+                }
+            }
+
+            /// Returns the name of this opcode, as it is written in Rust.
+            pub fn name(&self) -> &'static str {
+                match self {
+                    $(Opcode::$opcode => stringify!($opcode)),*
+                }
+            }
+
+            /// Resolves an opcode by its name, as it is written in Rust.
+            pub fn from_name(name: &str) -> Option<Self> {
+                match name {
+                    $(stringify!($opcode) => Some(Opcode::$opcode),)*
+                    _ => None,
+                }
+            }
         }
     };
 ];
@@ -263,6 +286,61 @@ pub enum Overload {
     BinaryRefRef,
 }
 
+impl Overload {
+    /// All overloads.
+    pub const ALL: [Overload; 10] = [
+        Overload::UnaryConst,
+        Overload::UnaryRef,
+        Overload::BinaryConstLhs,
+        Overload::BinaryConstRhs,
+        Overload::BinaryRefLhs,
+        Overload::BinaryRefRhs,
+        Overload::BinaryConstConst,
+        Overload::BinaryRefConst,
+        Overload::BinaryConstRef,
+        Overload::BinaryRefRef,
+    ];
+
+    /// Returns the argument types expected by this overload.
+    pub fn arg_types(&self) -> &'static [ArgType] {
+        match self {
+            Overload::UnaryConst | Overload::BinaryConstLhs | Overload::BinaryConstRhs => {
+                &[ArgType::Integer]
+            }
+            Overload::UnaryRef | Overload::BinaryRefLhs | Overload::BinaryRefRhs => {
+                &[ArgType::Variable]
+            }
+            Overload::BinaryConstConst => &[ArgType::Integer, ArgType::Integer],
+            Overload::BinaryRefConst => &[ArgType::Variable, ArgType::Integer],
+            Overload::BinaryConstRef => &[ArgType::Integer, ArgType::Variable],
+            Overload::BinaryRefRef => &[ArgType::Variable, ArgType::Variable],
+        }
+    }
+
+    /// Returns the name of this overload, as it is written in Rust.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Overload::UnaryConst => "UnaryConst",
+            Overload::UnaryRef => "UnaryRef",
+            Overload::BinaryConstLhs => "BinaryConstLhs",
+            Overload::BinaryConstRhs => "BinaryConstRhs",
+            Overload::BinaryRefLhs => "BinaryRefLhs",
+            Overload::BinaryRefRhs => "BinaryRefRhs",
+            Overload::BinaryConstConst => "BinaryConstConst",
+            Overload::BinaryRefConst => "BinaryRefConst",
+            Overload::BinaryConstRef => "BinaryConstRef",
+            Overload::BinaryRefRef => "BinaryRefRef",
+        }
+    }
+
+    /// Resolves an overload by its name, as it is written in Rust.
+    pub fn from_name(name: &str) -> Option<Self> {
+        Overload::ALL
+            .into_iter()
+            .find(|overload| overload.name() == name)
+    }
+}
+
 /// The type of an argument in an instruction. A variable reference is
 /// considered to be the type of ite referent.
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
@@ -87,14 +87,14 @@ fn codegen() {
         File::open(&path)?.read_to_end(src)?;
         ws_expect.clear();
         File::open(path.with_extension("ws"))?.read_to_end(ws_expect)?;
-        let cst = dialect.parse(&src);
+        let cst = dialect.parse(src);
         ws_generated.clear();
         cst.codegen(ws_generated, &HashSet::new())?;
         if ws_generated.as_bytes() != ws_expect {
             println!(
                 "parse({path:?}).codegen()\n generated = {:?}\n    expect = {:?}",
                 DebugStl(ws_generated.as_bytes()),
-                DebugStl(&ws_expect),
+                DebugStl(ws_expect),
             );
             Ok(false)
         } else {
@@ -44,10 +44,10 @@ impl IntegerToken<'_> {
         // Both Haskell parentheses and Palaiologos bases use suffixes and would
         // need a refactor to combine.
         debug_assert!(
-            !(from.sign_style == SignStyle::Haskell
-                && !BaseStyle::suffix_family().contains(self.base_style)
-                || to.sign_style == SignStyle::Haskell
-                    && !to.base_styles.is_disjoint(BaseStyle::suffix_family()))
+            (from.sign_style != SignStyle::Haskell
+                || !BaseStyle::suffix_family().contains(self.base_style))
+                && (to.sign_style != SignStyle::Haskell
+                    || to.base_styles.is_disjoint(BaseStyle::suffix_family()))
         );
 
         let mut new_literal = Vec::with_capacity(self.literal.len());
@@ -116,7 +116,8 @@ impl IntegerToken<'_> {
             }
         }
 
-        // Append the leading zeros and converted digits.
+        // Append the base prefix, leading zeros, and converted digits.
+        new_literal.extend_from_slice(new_base_style.prefix().as_bytes());
         let mut new_has_digit_seps = self.has_digit_seps;
         let new_base = new_base_style.base();
         if self.base_style.base() == new_base
@@ -124,19 +125,20 @@ impl IntegerToken<'_> {
         {
             let mut s = s;
             if new_leading_zeros > self.leading_zeros {
-                new_literal.extend(iter::repeat(b'0').take(new_leading_zeros - self.leading_zeros));
+                new_literal.extend(iter::repeat_n(b'0', new_leading_zeros - self.leading_zeros));
             } else if new_leading_zeros < self.leading_zeros {
                 s = &s[self.leading_zeros - new_leading_zeros..];
             }
             new_literal.extend_from_slice(s);
         } else {
             let digits = self.value.to_string_radix(new_base as _);
-            new_literal.extend(iter::repeat(b'0').take(new_leading_zeros));
+            new_literal.extend(iter::repeat_n(b'0', new_leading_zeros));
             new_literal.extend_from_slice(digits.as_bytes());
             new_has_digit_seps = false;
         }
 
-        // Append the Haskell close parentheses or Palaiologos base suffix.
+        // Append the base suffix and the Haskell close parentheses.
+        new_literal.extend_from_slice(new_base_style.suffix().as_bytes());
         new_literal.extend_from_slice(suffix);
 
         IntegerToken {
@@ -180,3 +182,52 @@ fn most_significant_hex_digit(int: &Integer) -> u8 {
     let msb = most_significant_byte(int);
     if msb > 0x0f { msb >> 4 } else { msb }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::borrow::Cow;
+
+    use enumset::enum_set;
+
+    use crate::tokens::integer::{BaseStyle, DigitSep, Integer, IntegerSyntax, SignStyle};
+
+    #[test]
+    fn convert_from_haskell() {
+        let haskell = IntegerSyntax::haskell();
+        let c = IntegerSyntax {
+            sign_style: SignStyle::Neg,
+            base_styles: enum_set!(BaseStyle::Decimal),
+            digit_sep: DigitSep::None,
+            min_value: None,
+            max_value: None,
+        };
+        let int = haskell.parse(Cow::Borrowed(b"(-42)"), &mut Vec::new());
+        let converted = int.convert_syntax(&haskell, &c);
+        assert_eq!(converted.literal, &b"-42"[..]);
+        assert_eq!(converted.value, Integer::from(-42));
+    }
+
+    #[test]
+    fn convert_base_style() {
+        let c = IntegerSyntax {
+            sign_style: SignStyle::Neg,
+            base_styles: enum_set!(BaseStyle::Decimal | BaseStyle::HexPrefix_0x),
+            digit_sep: DigitSep::None,
+            min_value: None,
+            max_value: None,
+        };
+        let suffix = IntegerSyntax {
+            base_styles: enum_set!(BaseStyle::Decimal | BaseStyle::HexSuffix_h),
+            ..c.clone()
+        };
+        let haskell = IntegerSyntax::haskell();
+        let convert = |from: &IntegerSyntax, to: &IntegerSyntax, literal: &'static [u8]| {
+            let int = from.parse(Cow::Borrowed(literal), &mut Vec::new());
+            int.convert_syntax(from, to).literal.into_owned()
+        };
+        assert_eq!(convert(&haskell, &c, b"(-0x2A)"), b"-0x2A");
+        assert_eq!(convert(&c, &suffix, b"-0x2A"), b"-2Ah");
+        assert_eq!(convert(&c, &suffix, b"0xA"), b"0Ah");
+        assert_eq!(convert(&suffix, &c, b"0Ah"), b"0xA");
+    }
+}
@@ -2,7 +2,7 @@
 
 use std::{
     borrow::Cow,
-    collections::HashMap,
+    collections::{HashMap, hash_map::Entry},
     fmt::{self, Debug, Formatter},
     hash::{Hash, Hasher},
     iter::FusedIterator,
@@ -14,10 +14,7 @@ use derive_more::Debug as DebugCustom;
 use crate::syntax::{HasError, Opcode, Pretty};
 
 // TODO:
-// - Make mapping from opcode to mnemonics.
-// - Make mnemonics configurable on the fly.
-//   - Make the double-insertion panic an error.
-//   - Add general case folding.
+// - Add general case folding.
 //   - Find a better name for K and I/K folding for the CLI. Perhaps Unicode and
 //     UnicodeTr, respectively. The ASCII part is only relevant as an
 //     optimization with detection.
@@ -32,10 +29,22 @@ pub struct MnemonicToken<'s> {
     pub opcode: Opcode,
 }
 
-/// A mapping from instruction mnemonic to overloaded opcodes.
+/// A mapping from instruction mnemonic to overloaded opcodes and from opcode
+/// to its preferred mnemonic.
 #[derive(Clone, Debug)]
-pub struct MnemonicMap {
-    map: HashMap<FoldedStr<'static>, &'static [Opcode]>,
+pub struct MnemonicMap<'a> {
+    map: HashMap<FoldedStr<'a>, &'a [Opcode]>,
+    preferred: HashMap<Opcode, FoldedStr<'a>>,
+}
+
+/// An error from inserting a mnemonic, which conflicts with a mnemonic already
+/// in a map.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MnemonicConflict<'a> {
+    /// The mnemonic already in the map.
+    pub existing: FoldedStr<'a>,
+    /// The mnemonic which was inserted.
+    pub inserted: FoldedStr<'a>,
 }
 
 /// A conventionally UTF-8 string which compares with configurable case folding.
@@ -81,23 +90,45 @@ impl Pretty for MnemonicToken<'_> {
     }
 }
 
-impl MnemonicMap {
+impl<'a> MnemonicMap<'a> {
     /// Constructs an empty mnemonic map.
     pub fn new() -> Self {
         MnemonicMap {
             map: HashMap::new(),
+            preferred: HashMap::new(),
         }
     }
 
-    /// Associates a mnemonic with opcode overloads.
-    pub fn insert(&mut self, mnemonic: FoldedStr<'static>, opcodes: &'static [Opcode]) {
-        if self.map.insert(mnemonic, opcodes).is_some() {
-            panic!("conflicting mnemonics");
+    /// Associates a mnemonic with opcode overloads. Returns an error, if it
+    /// compares equal to a mnemonic already in the map. The first mnemonic
+    /// inserted for an opcode is its preferred mnemonic.
+    pub fn insert(
+        &mut self,
+        mnemonic: FoldedStr<'a>,
+        opcodes: &'a [Opcode],
+    ) -> Result<(), MnemonicConflict<'a>> {
+        match self.map.entry(mnemonic) {
+            Entry::Occupied(entry) => Err(MnemonicConflict {
+                existing: *entry.key(),
+                inserted: mnemonic,
+            }),
+            Entry::Vacant(entry) => {
+                entry.insert(opcodes);
+                for &opcode in opcodes {
+                    self.preferred.entry(opcode).or_insert(mnemonic);
+                }
+                Ok(())
+            }
         }
     }
 
+    /// Gets the preferred mnemonic for an opcode.
+    pub fn get_preferred(&self, opcode: Opcode) -> Option<FoldedStr<'a>> {
+        self.preferred.get(&opcode).copied()
+    }
+
     /// Gets the opcode overloads for a mnemonic.
-    pub fn get_opcodes(&self, mnemonic: &[u8]) -> Option<&'static [Opcode]> {
+    pub fn get_opcodes(&self, mnemonic: &[u8]) -> Option<&'a [Opcode]> {
         self.map.get(&FoldedStr::exact(mnemonic)).copied()
     }
 
@@ -112,16 +143,23 @@ impl MnemonicMap {
     }
 }
 
-impl Default for MnemonicMap {
+impl Default for MnemonicMap<'_> {
     fn default() -> Self {
         MnemonicMap::new()
     }
 }
 
-impl From<&'static [(FoldedStr<'static>, &'static [Opcode])]> for MnemonicMap {
-    fn from(map: &'static [(FoldedStr<'static>, &'static [Opcode])]) -> Self {
+impl<'a> From<&'a [(FoldedStr<'a>, &'a [Opcode])]> for MnemonicMap<'a> {
+    fn from(map: &'a [(FoldedStr<'a>, &'a [Opcode])]) -> Self {
+        let mut preferred = HashMap::new();
+        for &(mnemonic, opcodes) in map {
+            for &opcode in opcodes {
+                preferred.entry(opcode).or_insert(mnemonic);
+            }
+        }
         MnemonicMap {
             map: map.iter().copied().collect(),
+            preferred,
         }
     }
 }
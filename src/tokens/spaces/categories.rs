@@ -180,36 +180,37 @@ impl SpaceSet {
     /// set.
     pub fn contains(self, ch: char) -> bool {
         if ch <= ' ' {
-            self.contains_ascii(ch as u8)
-        } else {
-            self.0.is_superset(match ch {
-                '\u{0085}' => enum_set!(SpaceCategory::NextLine),
-                '\u{00A0}' | '\u{1680}' | '\u{2000}' | '\u{2001}' | '\u{2002}' | '\u{2003}'
-                | '\u{2004}' | '\u{2005}' | '\u{2006}' | '\u{2007}' | '\u{2008}' | '\u{2009}'
-                | '\u{200A}' | '\u{202F}' | '\u{205F}' | '\u{3000}' => {
-                    enum_set!(SpaceCategory::SpaceSeparatorMinusSpace)
-                }
-                '\u{2028}' => enum_set!(SpaceCategory::LineSeparator),
-                '\u{2029}' => enum_set!(SpaceCategory::ParagraphSeparator),
-                '\u{FEFF}' => enum_set!(SpaceCategory::ZeroWidthNoBreakSpace),
-                _ => EnumSet::empty(),
-            })
+            return self.contains_ascii(ch as u8);
         }
+        let category = match ch {
+            '\u{0085}' => SpaceCategory::NextLine,
+            '\u{00A0}' | '\u{1680}' | '\u{2000}' | '\u{2001}' | '\u{2002}' | '\u{2003}'
+            | '\u{2004}' | '\u{2005}' | '\u{2006}' | '\u{2007}' | '\u{2008}' | '\u{2009}'
+            | '\u{200A}' | '\u{202F}' | '\u{205F}' | '\u{3000}' => {
+                SpaceCategory::SpaceSeparatorMinusSpace
+            }
+            '\u{2028}' => SpaceCategory::LineSeparator,
+            '\u{2029}' => SpaceCategory::ParagraphSeparator,
+            '\u{FEFF}' => SpaceCategory::ZeroWidthNoBreakSpace,
+            _ => return false,
+        };
+        self.0.contains(category)
     }
 
     /// Returns whether the byte matches an ASCII whitespace character in this
     /// set.
     pub fn contains_ascii(&self, b: u8) -> bool {
-        self.0.is_superset(match b {
-            b'\0' => enum_set!(SpaceCategory::Nul),
-            b'\t' => enum_set!(SpaceCategory::Tab),
-            b'\n' => enum_set!(SpaceCategory::LineFeed),
-            b'\x0b' => enum_set!(SpaceCategory::VerticalTab),
-            b'\x0c' => enum_set!(SpaceCategory::FormFeed),
-            b'\r' => enum_set!(SpaceCategory::CarriageReturn),
-            b' ' => enum_set!(SpaceCategory::Space),
-            _ => EnumSet::empty(),
-        })
+        let category = match b {
+            b'\0' => SpaceCategory::Nul,
+            b'\t' => SpaceCategory::Tab,
+            b'\n' => SpaceCategory::LineFeed,
+            b'\x0b' => SpaceCategory::VerticalTab,
+            b'\x0c' => SpaceCategory::FormFeed,
+            b'\r' => SpaceCategory::CarriageReturn,
+            b' ' => SpaceCategory::Space,
+            _ => return false,
+        };
+        self.0.contains(category)
     }
 
     /// Returns whether this set of whitespace characters is entirely ASCII.
@@ -268,3 +269,20 @@ impl<'s> Scanner<'s> {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::tokens::spaces::SpaceSet;
+
+    #[test]
+    fn contains() {
+        assert!(SpaceSet::C_ISSPACE.contains(' '));
+        assert!(SpaceSet::C_ISSPACE.contains_ascii(b'\t'));
+        assert!(!SpaceSet::C_ISSPACE.contains('a'));
+        assert!(!SpaceSet::C_ISSPACE.contains('草'));
+        assert!(!SpaceSet::C_ISSPACE.contains_ascii(b'a'));
+        assert!(!SpaceSet::C_ISSPACE.contains_ascii(b'\0'));
+        assert!(!SpaceSet::C_ISSPACE.contains('\u{3000}'));
+        assert!(SpaceSet::UNICODE_WHITE_SPACE.contains('\u{3000}'));
+    }
+}
@@ -0,0 +1,910 @@
+//! A transformation that converts between dialects.
+
+use std::{
+    borrow::Cow,
+    collections::HashSet,
+    error::Error,
+    fmt::{self, Display, Formatter},
+    mem,
+};
+
+use bstr::ByteSlice;
+use enumset::EnumSet;
+
+use crate::{
+    dialects::{DialectConfig, MnemonicConfig},
+    syntax::{ArgLayout, Cst, HasError, Inst, InstError, MacroStep, Opcode, Overload, Pretty},
+    tokens::{
+        Token,
+        comment::{BlockCommentStyle, BlockCommentToken, LineCommentToken},
+        integer::{BaseStyle, Integer, IntegerToken, Sign},
+        label::{LabelColonToken, LabelStyle, LabelToken},
+        mnemonics::MnemonicToken,
+        spaces::{InstSepToken, LineTermStyle, LineTermToken, SpaceToken, Spaces},
+        string::{CharData, Encoding, QuoteStyle, StringToken},
+        words::Words,
+    },
+    transform::{Fold, Visitor},
+};
+
+// TODO:
+// - Describe string escapes and bare strings in `DialectConfig`, so string
+//   literals are only requoted when needed.
+// - Validate label identifiers against the target dialect.
+// - Lower variables and `valueinteger`/`valuestring` to heap addresses.
+// - Lower `UnaryRef` and `BinaryRef*` overloads.
+
+impl<'s> Cst<'s> {
+    /// Converts this CST from the syntax of one dialect to another. Mnemonics,
+    /// label sigils and colons, integer literals, string quoting, comments, and
+    /// separators are rewritten into the target syntax, while keeping comments
+    /// and layout. Instructions with no equivalent in the target dialect are
+    /// lowered to primitive instructions.
+    ///
+    /// Instructions which cannot be expressed in the target dialect are left
+    /// unchanged and reported.
+    pub fn convert(&mut self, from: &DialectConfig, to: &DialectConfig) -> Vec<ConvertError> {
+        self.normalize_strange();
+        let mut labels = LabelCollector(HashSet::new());
+        self.visit(&mut labels);
+        let mut conv = Converter {
+            from,
+            to,
+            labels: labels.0,
+            next_label: 0,
+            line: 1,
+            errors: Vec::new(),
+        };
+        self.fold(&mut conv);
+        conv.errors
+    }
+}
+
+/// A construct in an instruction, which could not be expressed in the target
+/// dialect.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ConvertError {
+    /// The line of the instruction, starting at 1.
+    pub line: usize,
+    /// The construct which could not be expressed.
+    pub kind: ConvertErrorKind,
+}
+
+/// A kind of construct, which could not be expressed in the target dialect.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ConvertErrorKind {
+    /// The instruction has syntax errors.
+    Invalid,
+    /// The opcode has no mnemonic in the target dialect and cannot be lowered.
+    Opcode(Opcode),
+    /// The overload is not supported by the target dialect and cannot be
+    /// lowered.
+    Overload(Overload),
+    /// The target dialect has no variables.
+    Variable,
+    /// The integer is out of the range of the target dialect.
+    IntegerRange,
+    /// The string contains characters, which cannot be represented in the
+    /// encoding of the target dialect.
+    StringEncoding,
+    /// The target dialect has no comment style, which can contain the comment.
+    Comment,
+}
+
+impl Display for ConvertError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: ", self.line)?;
+        match self.kind {
+            ConvertErrorKind::Invalid => write!(f, "instruction has syntax errors"),
+            ConvertErrorKind::Opcode(opcode) => {
+                write!(f, "`{}` is not supported", opcode.name())
+            }
+            ConvertErrorKind::Overload(overload) => {
+                write!(f, "`{}` overload is not supported", overload.name())
+            }
+            ConvertErrorKind::Variable => write!(f, "variables are not supported"),
+            ConvertErrorKind::IntegerRange => write!(f, "integer is out of range"),
+            ConvertErrorKind::StringEncoding => {
+                write!(f, "string cannot be represented in the encoding")
+            }
+            ConvertErrorKind::Comment => write!(f, "comment cannot be represented"),
+        }
+    }
+}
+
+impl Error for ConvertError {}
+
+struct Converter<'d> {
+    from: &'d DialectConfig,
+    to: &'d DialectConfig,
+    /// All labels in the program, for generating fresh labels.
+    labels: HashSet<Vec<u8>>,
+    next_label: usize,
+    /// The line of the current instruction.
+    line: usize,
+    errors: Vec<ConvertError>,
+}
+
+/// An instruction produced by lowering.
+enum Lowered<'s> {
+    Inst(Opcode, Vec<Token<'s>>),
+    Label(Cow<'s, [u8]>),
+}
+
+struct LabelCollector(HashSet<Vec<u8>>);
+
+impl<'s> Visitor<'s> for LabelCollector {
+    fn visit_inst(&mut self, inst: &mut Inst<'s>) {
+        for (word, _) in &inst.words.words {
+            if let Token::Label(l) = word.peel_groups() {
+                self.0.insert(l.label.to_vec());
+            }
+        }
+    }
+}
+
+impl<'s> Fold<'s> for Converter<'_> {
+    fn fold_inst(&mut self, inst: Inst<'s>) -> Vec<Cst<'s>> {
+        self.convert_inst(inst).into_iter().map(Cst::Inst).collect()
+    }
+
+    /// Converts an option instruction, which cannot be lowered.
+    fn fold_option(&mut self, option: Inst<'s>) -> Inst<'s> {
+        let mut insts = self.convert_inst(option);
+        debug_assert_eq!(insts.len(), 1);
+        insts.pop().unwrap()
+    }
+}
+
+impl<'s> Converter<'_> {
+    /// Converts an instruction to a sequence of instructions in the target
+    /// dialect. When it cannot be expressed, it is returned unchanged and the
+    /// error is recorded.
+    fn convert_inst(&mut self, inst: Inst<'s>) -> Vec<Inst<'s>> {
+        let line = self.line;
+        let mut text = Vec::new();
+        inst.pretty(&mut text);
+        self.line += text.iter().filter(|&&b| b == b'\n').count();
+        match self.lower_inst(inst.clone()) {
+            Ok(insts) => insts,
+            Err(kind) => {
+                self.errors.push(ConvertError { line, kind });
+                vec![inst]
+            }
+        }
+    }
+
+    fn lower_inst(&mut self, mut inst: Inst<'s>) -> Result<Vec<Inst<'s>>, ConvertErrorKind> {
+        self.convert_spaces(&mut inst.words.space_before)?;
+        for (_, space) in &mut inst.words.words {
+            self.convert_spaces(space)?;
+        }
+        if inst.opcode == Opcode::Nop {
+            return Ok(vec![inst]);
+        }
+
+        self.resolve_arity(&mut inst);
+        let arity = match inst.overload {
+            Some(overload) => overload.arg_types().len(),
+            None => inst.opcode.arg_types().len(),
+        };
+        if inst.has_error() || inst.opcode == Opcode::Invalid || inst.len_args() != arity {
+            return Err(ConvertErrorKind::Invalid);
+        }
+
+        if inst.opcode == Opcode::Label {
+            self.convert_label_def(&mut inst)?;
+            self.terminate(&mut inst);
+            return Ok(vec![inst]);
+        }
+
+        let supported = match inst.overload {
+            Some(overload) => self.to.overload(inst.opcode, arity) == Some(overload),
+            None => true,
+        };
+        if supported && let Some(mnemonic) = self.find_mnemonic(inst.opcode, &inst.words[0]) {
+            let mnemonic = Token::from(MnemonicToken {
+                mnemonic: mnemonic.into(),
+                opcode: inst.opcode,
+            });
+            match inst.arg_layout {
+                ArgLayout::Mnemonic => inst.words[0] = mnemonic,
+                ArgLayout::Bare => {
+                    let space = Spaces::from(Token::from(SpaceToken::from(b" ")));
+                    inst.words.words.insert(0, (mnemonic, space));
+                    inst.arg_layout = ArgLayout::Mnemonic;
+                }
+            }
+            for (arg, _) in &mut inst.words.words[1..] {
+                self.convert_arg(arg, false)?;
+            }
+            self.terminate(&mut inst);
+            return Ok(vec![inst]);
+        }
+
+        let lowered = self.lower(&inst)?;
+        self.build(inst, lowered)
+    }
+
+    /// Resolves the opcode or overload of an instruction with an arity, which
+    /// does not match its opcode, according to the source dialect.
+    fn resolve_arity(&self, inst: &mut Inst<'s>) {
+        let arity = inst.len_args();
+        if inst.overload.is_some() || arity == inst.opcode.arg_types().len() {
+            return;
+        }
+        let mnemonic = match inst.words.first() {
+            Some(Token::Mnemonic(m)) if inst.arg_layout == ArgLayout::Mnemonic => &m.mnemonic,
+            _ => &Cow::Borrowed(&b""[..]),
+        };
+        let opcode = (self.from.mnemonics.iter())
+            .filter(|m| m.opcodes.contains(&inst.opcode) && m.fold.compare(&m.mnemonic, mnemonic))
+            .flat_map(|m| m.opcodes.iter().copied())
+            .find(|opcode| opcode.arg_types().len() == arity);
+        if let Some(opcode) = opcode {
+            inst.opcode = opcode;
+            if let Token::Mnemonic(m) = &mut inst.words[0] {
+                m.opcode = opcode;
+            }
+        } else if let Some(overload) = self.from.overload(inst.opcode, arity) {
+            inst.overload = Some(overload);
+        } else {
+            return;
+        }
+        inst.errors -= InstError::InvalidArity;
+    }
+
+    /// Finds a mnemonic for the opcode in the target dialect, preferring the
+    /// spelling of the current mnemonic, if it matches.
+    fn find_mnemonic(&self, opcode: Opcode, current: &Token<'s>) -> Option<Vec<u8>> {
+        let mut mnemonics = (self.to.mnemonics.iter()).filter(|m| m.opcodes.contains(&opcode));
+        let first = mnemonics.clone().next()?;
+        if let Token::Mnemonic(current) = current
+            && let Some(m) = mnemonics.find(|m| m.fold.compare(&m.mnemonic, &current.mnemonic))
+        {
+            debug_assert!(m.opcodes.contains(&opcode));
+            return Some(current.mnemonic.to_vec());
+        }
+        Some(MnemonicConfig::clone(first).mnemonic)
+    }
+
+    /// Converts a label definition instruction to the form used by the target
+    /// dialect.
+    fn convert_label_def(&self, inst: &mut Inst<'s>) -> Result<(), ConvertErrorKind> {
+        let bare = self.to.label_def_colon || self.to.label_def_sigil != LabelStyle::NoSigil;
+        let label_index = (inst.arg_layout == ArgLayout::Mnemonic) as usize;
+        self.convert_arg(&mut inst.words[label_index], true)?;
+        match (inst.arg_layout, bare) {
+            (ArgLayout::Mnemonic, false) => {
+                let mnemonic = self
+                    .find_mnemonic(Opcode::Label, &inst.words[0])
+                    .ok_or(ConvertErrorKind::Opcode(Opcode::Label))?;
+                inst.words[0] = Token::from(MnemonicToken {
+                    mnemonic: mnemonic.into(),
+                    opcode: Opcode::Label,
+                });
+            }
+            (ArgLayout::Bare, true) => {
+                let has_colon = matches!(inst.words.words.get(1), Some((Token::LabelColon(_), _)));
+                if self.to.label_def_colon && !has_colon {
+                    let space = mem::take(&mut inst.words.words[0].1);
+                    inst.words
+                        .words
+                        .insert(1, (Token::from(LabelColonToken), space));
+                } else if !self.to.label_def_colon && has_colon {
+                    let (_, mut space) = inst.words.words.remove(1);
+                    inst.words.words[0].1.tokens.append(&mut space.tokens);
+                }
+            }
+            (ArgLayout::Mnemonic, true) => {
+                let (_, space) = inst.words.words.remove(0);
+                let label = &mut inst.words.words[0];
+                label.1.tokens.splice(0..0, non_space(space));
+                if self.to.label_def_colon {
+                    let space = mem::take(&mut label.1);
+                    inst.words
+                        .words
+                        .insert(1, (Token::from(LabelColonToken), space));
+                }
+                inst.arg_layout = ArgLayout::Bare;
+            }
+            (ArgLayout::Bare, false) => {
+                let mnemonic = self
+                    .find_mnemonic(Opcode::Label, &Token::Placeholder)
+                    .ok_or(ConvertErrorKind::Opcode(Opcode::Label))?;
+                if let Some((Token::LabelColon(_), _)) = inst.words.words.get(1) {
+                    let (_, space) = inst.words.words.remove(1);
+                    let label_space = &mut inst.words.words[0].1;
+                    label_space.tokens = non_space(mem::take(label_space))
+                        .chain(space.tokens)
+                        .collect();
+                }
+                let mnemonic = Token::from(MnemonicToken {
+                    mnemonic: mnemonic.into(),
+                    opcode: Opcode::Label,
+                });
+                let space = Spaces::from(Token::from(SpaceToken::from(b" ")));
+                inst.words.words.insert(0, (mnemonic, space));
+                inst.arg_layout = ArgLayout::Mnemonic;
+            }
+        }
+        Ok(())
+    }
+
+    /// Converts an argument to the syntax of the target dialect. Labels are
+    /// converted as definitions when `is_def` is set.
+    fn convert_arg(&self, tok: &mut Token<'s>, is_def: bool) -> Result<(), ConvertErrorKind> {
+        match tok {
+            Token::Mnemonic(m) => {
+                let opcode = m.opcode;
+                let mnemonic = self
+                    .find_mnemonic(opcode, tok)
+                    .ok_or(ConvertErrorKind::Opcode(opcode))?;
+                *tok = Token::from(MnemonicToken {
+                    mnemonic: mnemonic.into(),
+                    opcode,
+                });
+            }
+            Token::Integer(int) => {
+                let (from, to) = (&self.from.integers, &self.to.integers);
+                if !int.compatible_syntax(from, to) {
+                    *int = int.convert_syntax(from, to);
+                }
+                if to.min_value.as_ref().is_some_and(|min| int.value < *min)
+                    || to.max_value.as_ref().is_some_and(|max| int.value > *max)
+                {
+                    return Err(ConvertErrorKind::IntegerRange);
+                }
+            }
+            Token::String(s) => self.convert_string(s)?,
+            Token::Char(c) if !self.to.chars => {
+                let value = match c.unescaped {
+                    CharData::Unicode(ch) => ch as u32,
+                    CharData::Byte(b) => b as u32,
+                };
+                *tok = Token::from(self.integer(Integer::from(value)));
+            }
+            Token::Variable(v) => match self.to.variable_sigil {
+                Some(style) => v.style = style,
+                None => return Err(ConvertErrorKind::Variable),
+            },
+            Token::Label(l) => {
+                l.style = if is_def {
+                    self.to.label_def_sigil
+                } else {
+                    self.to.label_ref_sigil
+                };
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// Converts a string literal to the quoting and encoding of the target
+    /// dialect.
+    fn convert_string(&self, s: &mut StringToken<'s>) -> Result<(), ConvertErrorKind> {
+        if s.encoding != self.to.encoding {
+            if !s.unescaped.is_ascii() {
+                return Err(ConvertErrorKind::StringEncoding);
+            }
+            s.encoding = self.to.encoding;
+        }
+        let needs_escape = |b: u8| b == b'"' || b == b'\\' || b.is_ascii_control();
+        if s.quotes == QuoteStyle::Double && !s.literal.iter().any(|&b| needs_escape(b)) {
+            return Ok(());
+        }
+        let mut literal = Vec::with_capacity(s.unescaped.len());
+        for &b in s.unescaped.iter() {
+            match b {
+                b'"' => literal.extend_from_slice(b"\\\""),
+                b'\\' => literal.extend_from_slice(b"\\\\"),
+                b'\n' => literal.extend_from_slice(b"\\n"),
+                b'\r' => literal.extend_from_slice(b"\\r"),
+                b'\t' => literal.extend_from_slice(b"\\t"),
+                _ => literal.push(b),
+            }
+        }
+        s.literal = literal.into();
+        s.quotes = QuoteStyle::Double;
+        Ok(())
+    }
+
+    /// Converts comments and separators to the styles of the target dialect.
+    fn convert_spaces(&self, spaces: &mut Spaces<'s>) -> Result<(), ConvertErrorKind> {
+        let mut i = 0;
+        while i < spaces.tokens.len() {
+            let after_space = i > 0 && matches!(spaces.tokens[i - 1], Token::Space(_));
+            let (tok, rest) = spaces.tokens[i..].split_first_mut().unwrap();
+            match tok {
+                Token::LineComment(c) if !self.to.line_comments.contains(&c.style) => {
+                    if let Some(&style) = self.to.line_comments.first() {
+                        c.style = style;
+                    } else if let Some(style) = self.block_comment_style(c.text, None) {
+                        *tok = Token::from(BlockCommentToken {
+                            text: c.text,
+                            style,
+                            errors: EnumSet::empty(),
+                        });
+                    } else {
+                        return Err(ConvertErrorKind::Comment);
+                    }
+                }
+                Token::BlockComment(c) if !self.to.block_comments.contains(&c.style) => {
+                    if let Some(style) = self.block_comment_style(c.text, Some(c.style))
+                        && c.errors.is_empty()
+                    {
+                        c.style = style;
+                    } else if let Some(&style) = self.to.line_comments.first()
+                        && c.errors.is_empty()
+                        && !c.text.contains(&b'\n')
+                        && ends_line(rest)
+                    {
+                        *tok = Token::from(LineCommentToken {
+                            text: c.text,
+                            style,
+                            errors: EnumSet::empty(),
+                        });
+                    } else {
+                        return Err(ConvertErrorKind::Comment);
+                    }
+                }
+                Token::InstSep(sep) => match self.to.inst_sep {
+                    Some(style) => sep.style = style,
+                    None => {
+                        *tok = Token::from(LineTermToken::from(LineTermStyle::Lf));
+                        if after_space {
+                            spaces.tokens.remove(i - 1);
+                            i -= 1;
+                        }
+                    }
+                },
+                Token::ArgSep(sep) => match self.to.arg_sep {
+                    Some(style) => sep.style = style,
+                    None => {
+                        if after_space || matches!(rest.first(), Some(Token::Space(_))) {
+                            spaces.tokens.remove(i);
+                            continue;
+                        }
+                        *tok = Token::from(SpaceToken::from(b" "));
+                    }
+                },
+                _ => {}
+            }
+            i += 1;
+        }
+        Ok(())
+    }
+
+    /// Chooses a block comment style of the target dialect, which can contain
+    /// the text of a comment of the given style.
+    fn block_comment_style(
+        &self,
+        text: &[u8],
+        from: Option<BlockCommentStyle>,
+    ) -> Option<BlockCommentStyle> {
+        (self.to.block_comments.iter().copied()).find(|style| {
+            let same_delims = from.is_some_and(|from| {
+                from.open() == style.open()
+                    && from.close() == style.close()
+                    && from.can_nest() == style.can_nest()
+            });
+            same_delims
+                || !text.contains_str(style.close())
+                    && (!style.can_nest() || !text.contains_str(style.open()))
+        })
+    }
+
+    /// Lowers an instruction to instructions supported by the target dialect.
+    fn lower(&mut self, inst: &Inst<'s>) -> Result<Vec<Lowered<'s>>, ConvertErrorKind> {
+        use Lowered::Inst as I;
+        let opcode = inst.opcode;
+
+        if let Some(overload) = inst.overload {
+            if overload
+                .expansion()
+                .contains(&MacroStep::Inst(Opcode::Retrieve))
+            {
+                return Err(ConvertErrorKind::Overload(overload));
+            }
+            return self.lower_expansion(inst, overload.expansion());
+        }
+        if let Some(expansion) = opcode.expansion() {
+            return self.lower_expansion(inst, expansion);
+        }
+
+        let push = |n: u32| {
+            I(
+                Opcode::Push,
+                vec![Token::from(self.integer(Integer::from(n)))],
+            )
+        };
+        Ok(match opcode {
+            Opcode::PushString | Opcode::PushString0 => {
+                let mut lowered = Vec::new();
+                if opcode == Opcode::PushString0 {
+                    lowered.push(push(0));
+                }
+                lowered.extend(string_chars(inst.arg(0))?.into_iter().rev().map(push));
+                lowered
+            }
+            Opcode::StoreString0 => {
+                let mut chars = string_chars(inst.arg(0))?;
+                chars.push(0);
+                let mut lowered = Vec::with_capacity(chars.len() * 5);
+                for c in chars {
+                    lowered.push(I(Opcode::Dup, vec![]));
+                    lowered.push(push(c));
+                    lowered.push(I(Opcode::Store, vec![]));
+                    lowered.push(push(1));
+                    lowered.push(I(Opcode::Add, vec![]));
+                }
+                lowered
+            }
+            Opcode::PalaiologosRep => {
+                let (Token::Mnemonic(m), Token::Integer(count)) = (inst.arg(0), inst.arg(1)) else {
+                    return Err(ConvertErrorKind::Invalid);
+                };
+                // Palaiologos counts are `int32_t` and negative counts repeat
+                // 0 times.
+                let count = count.value.to_i32().ok_or(ConvertErrorKind::IntegerRange)?;
+                (0..count.max(0)).map(|_| I(m.opcode, vec![])).collect()
+            }
+            _ => return Err(ConvertErrorKind::Opcode(opcode)),
+        })
+    }
+
+    /// Lowers an instruction by the expansion of its macro opcode or overload,
+    /// with fresh labels for those internal to the expansion.
+    fn lower_expansion(
+        &mut self,
+        inst: &Inst<'s>,
+        expansion: &[MacroStep],
+    ) -> Result<Vec<Lowered<'s>>, ConvertErrorKind> {
+        use Lowered::Inst as I;
+        let internal = (0..inst.opcode.internal_labels())
+            .map(|_| self.fresh_label())
+            .collect::<Vec<_>>();
+        expansion
+            .iter()
+            .map(|&step| {
+                Ok(match step {
+                    MacroStep::Inst(opcode) => I(opcode, vec![]),
+                    MacroStep::PushArg(i) => I(Opcode::Push, vec![self.converted_arg(inst, i)?]),
+                    MacroStep::PushConst(n) => I(
+                        Opcode::Push,
+                        vec![Token::from(self.integer(Integer::from(n)))],
+                    ),
+                    MacroStep::JmpArg(opcode, i) => I(opcode, vec![self.converted_arg(inst, i)?]),
+                    MacroStep::JmpInternal(opcode, i) => {
+                        I(opcode, vec![self.label_ref(&internal[i])])
+                    }
+                    MacroStep::Label(i) => Lowered::Label(internal[i].clone()),
+                    MacroStep::Op => I(inst.opcode, vec![]),
+                })
+            })
+            .collect()
+    }
+
+    /// Builds the instructions for a lowered instruction. The first takes the
+    /// leading spaces of the original instruction and the last takes its
+    /// trailing spaces. When it lowers to nothing, a `Nop` takes its spaces.
+    fn build(
+        &self,
+        mut inst: Inst<'s>,
+        lowered: Vec<Lowered<'s>>,
+    ) -> Result<Vec<Inst<'s>>, ConvertErrorKind> {
+        if lowered.is_empty() {
+            let mut spaces = mem::take(&mut inst.words.space_before);
+            (spaces.tokens).extend(mem::take(inst.words.trailing_spaces_mut()).tokens);
+            return Ok(vec![Inst::nop(spaces)]);
+        }
+        let mut insts = Vec::with_capacity(lowered.len());
+        for lowered in lowered {
+            let inst = match lowered {
+                Lowered::Inst(opcode, args) => {
+                    let mnemonic = self
+                        .find_mnemonic(opcode, &Token::Placeholder)
+                        .ok_or(ConvertErrorKind::Opcode(inst.opcode))?;
+                    let mut words = Words::new(Spaces::new());
+                    words.push_word(Token::from(MnemonicToken {
+                        mnemonic: mnemonic.into(),
+                        opcode,
+                    }));
+                    for arg in args {
+                        words.push_space(Token::from(SpaceToken::from(b" ")));
+                        words.push_word(arg);
+                    }
+                    Inst {
+                        opcode,
+                        words,
+                        arg_layout: ArgLayout::Mnemonic,
+                        overload: None,
+                        errors: EnumSet::empty(),
+                    }
+                }
+                Lowered::Label(label) => {
+                    let mut words = Words::new(Spaces::new());
+                    words.push_word(Token::from(LabelToken {
+                        label,
+                        style: LabelStyle::NoSigil,
+                        errors: EnumSet::empty(),
+                    }));
+                    let mut inst = Inst {
+                        opcode: Opcode::Label,
+                        words,
+                        arg_layout: ArgLayout::Bare,
+                        overload: None,
+                        errors: EnumSet::empty(),
+                    };
+                    self.convert_label_def(&mut inst)
+                        .map_err(|_| ConvertErrorKind::Opcode(Opcode::Label))?;
+                    inst
+                }
+            };
+            insts.push(inst);
+        }
+
+        // Separate the instructions by instruction separators or line
+        // terminators, keeping the indentation of the original.
+        let indent = (inst.words.space_before.tokens.iter())
+            .rev()
+            .take_while(|tok| matches!(tok, Token::Space(_)))
+            .cloned()
+            .collect::<Vec<_>>();
+        let line_term = (inst.words.trailing_spaces().tokens.iter())
+            .find_map(|tok| match tok {
+                Token::LineTerm(lt) => Some(lt.style),
+                _ => None,
+            })
+            .unwrap_or(LineTermStyle::Lf);
+        let n = insts.len();
+        for (i, lowered) in insts.iter_mut().enumerate() {
+            if i == 0 {
+                lowered.words.space_before = mem::take(&mut inst.words.space_before);
+            } else if self.to.inst_sep.is_none() {
+                lowered.words.space_before.tokens = indent.iter().rev().cloned().collect();
+            }
+            if i == n - 1 {
+                *lowered.words.trailing_spaces_mut() = mem::take(inst.words.trailing_spaces_mut());
+                self.terminate(lowered);
+            } else if let Some(style) = self.to.inst_sep {
+                let trailing = lowered.words.trailing_spaces_mut();
+                trailing.push(Token::from(SpaceToken::from(b" ")));
+                trailing.push(Token::from(InstSepToken::from(style)));
+                trailing.push(Token::from(SpaceToken::from(b" ")));
+            } else {
+                let trailing = lowered.words.trailing_spaces_mut();
+                trailing.push(Token::from(LineTermToken::from(line_term)));
+            }
+        }
+        Ok(insts)
+    }
+
+    /// Terminates an instruction with a line terminator or instruction
+    /// separator, if it is followed by another instruction on the same line and
+    /// the target dialect requires it.
+    fn terminate(&self, inst: &mut Inst<'s>) {
+        let bare_label = inst.opcode == Opcode::Label && inst.arg_layout == ArgLayout::Bare;
+        if bare_label && self.to.label_def_colon {
+            return;
+        }
+        let trailing = inst.words.trailing_spaces_mut();
+        let terminated = trailing.tokens.iter().any(|tok| {
+            matches!(
+                tok,
+                Token::LineTerm(_) | Token::Eof(_) | Token::InstSep(_) | Token::LineComment(_),
+            )
+        });
+        if terminated {
+            return;
+        }
+        match self.to.inst_sep {
+            Some(style) => {
+                trailing.push(Token::from(InstSepToken::from(style)));
+                trailing.push(Token::from(SpaceToken::from(b" ")));
+            }
+            None => {
+                while let Some(Token::Space(_)) = trailing.tokens.last() {
+                    trailing.tokens.pop();
+                }
+                trailing.push(Token::from(LineTermToken::from(LineTermStyle::Lf)));
+            }
+        }
+    }
+
+    /// Generates a label, which is not used elsewhere in the program.
+    fn fresh_label(&mut self) -> Cow<'s, [u8]> {
+        loop {
+            let label = format!("__convert_{}", self.next_label).into_bytes();
+            self.next_label += 1;
+            if self.labels.insert(label.clone()) {
+                return label.into();
+            }
+        }
+    }
+
+    /// Constructs a label reference in the target dialect.
+    fn label_ref(&self, label: &Cow<'s, [u8]>) -> Token<'s> {
+        Token::from(LabelToken {
+            label: label.clone(),
+            style: self.to.label_ref_sigil,
+            errors: EnumSet::empty(),
+        })
+    }
+
+    /// Gets the indexed argument of an instruction, converted to the target
+    /// dialect.
+    fn converted_arg(&self, inst: &Inst<'s>, index: usize) -> Result<Token<'s>, ConvertErrorKind> {
+        let mut arg = inst.arg(index).clone();
+        self.convert_arg(&mut arg, false)?;
+        Ok(arg)
+    }
+
+    /// Constructs a decimal integer literal in the syntax of the target
+    /// dialect.
+    fn integer(&self, value: Integer) -> IntegerToken<'s> {
+        let int = IntegerToken {
+            literal: value.to_string().into_bytes().into(),
+            sign: if value.is_negative() {
+                Sign::Neg
+            } else {
+                Sign::None
+            },
+            value,
+            base_style: BaseStyle::Decimal,
+            leading_zeros: 0,
+            has_digit_seps: false,
+            errors: EnumSet::empty(),
+        };
+        let decimal = DialectConfig::default().integers;
+        if int.compatible_syntax(&decimal, &self.to.integers) {
+            int
+        } else {
+            int.convert_syntax(&decimal, &self.to.integers)
+        }
+    }
+}
+
+/// Gets the chars or bytes of a string argument.
+fn string_chars(arg: &Token<'_>) -> Result<Vec<u32>, ConvertErrorKind> {
+    let Token::String(s) = arg else {
+        return Err(ConvertErrorKind::Invalid);
+    };
+    Ok(match s.encoding {
+        Encoding::Utf8 => s.unescaped.chars().map(|ch| ch as u32).collect(),
+        Encoding::Bytes => s.unescaped.iter().map(|&b| b as u32).collect(),
+    })
+}
+
+/// Returns the tokens in the spaces, which are not horizontal whitespace.
+fn non_space<'s>(spaces: Spaces<'s>) -> impl Iterator<Item = Token<'s>> {
+    (spaces.tokens.into_iter()).filter(|tok| !matches!(tok, Token::Space(_)))
+}
+
+/// Returns whether the tokens are only horizontal whitespace until the end of
+/// the line.
+fn ends_line(tokens: &[Token<'_>]) -> bool {
+    for tok in tokens {
+        match tok {
+            Token::Space(_) => {}
+            Token::LineTerm(_) | Token::Eof(_) => return true,
+            _ => return false,
+        }
+    }
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use crate::{
+        dialects::{Burghard, Dialect, Palaiologos, Wsf},
+        syntax::{Opcode, Pretty},
+        transform::{ConvertError, ConvertErrorKind},
+    };
+
+    fn convert<From: Dialect, To: Dialect>(src: &[u8]) -> (String, Vec<ConvertError>) {
+        let mut cst = From::new().parse(src);
+        let errors = cst.convert(&From::make_config(), &To::make_config());
+        let mut out = Vec::new();
+        cst.pretty(&mut out);
+        (String::from_utf8(out).unwrap(), errors)
+    }
+
+    #[test]
+    fn burghard_to_wsf() {
+        let src = b"; count down
+label start
+  push 3 -- three
+  add 1
+  jumpp start
+  debug_printstack
+";
+        let expect = "# count down
+start:
+  push 3 # three
+  push 1
+  add
+  dup
+  jn __convert_0
+  dup
+  jz __convert_0
+  pop
+  jmp start
+  __convert_0:
+  pop
+  debug_printstack
+";
+        let (out, errors) = convert::<Burghard, Wsf>(src);
+        assert_eq!(out, expect);
+        assert_eq!(
+            errors,
+            [ConvertError {
+                line: 6,
+                kind: ConvertErrorKind::Opcode(Opcode::BurghardPrintStack),
+            }],
+        );
+    }
+
+    #[test]
+    fn burghard_to_palaiologos() {
+        let src = b"label start
+  push 3 -- three
+  add 1
+  jumpp start
+";
+        let expect = "@start
+  push 3 ; three
+  add 1
+  dup / jltz %__convert_0 / dup / jz %__convert_0 / drop / jmp %start / @__convert_0 / drop
+";
+        let (out, errors) = convert::<Burghard, Palaiologos>(src);
+        assert_eq!(out, expect);
+        assert_eq!(errors, []);
+    }
+
+    #[test]
+    fn palaiologos_rep() {
+        let src = b"rep dup 2\nrep dup -1\nrep dup 2147483648\n";
+        let expect = "dup\ndup\n\nrep dup 2147483648\n";
+        let (out, errors) = convert::<Palaiologos, Wsf>(src);
+        assert_eq!(out, expect);
+        assert_eq!(
+            errors,
+            [ConvertError {
+                line: 3,
+                kind: ConvertErrorKind::Invalid,
+            }],
+        );
+
+        // Counts are not limited below the range of `int32_t`.
+        let (out, errors) = convert::<Palaiologos, Wsf>(b"rep dup 70000\n");
+        assert_eq!(out, "dup\n".repeat(70000));
+        assert_eq!(errors, []);
+
+        // Empty repetitions keep their spaces and comments.
+        let src = b"push 1\n  rep dup 0 ; none\n\trep dup -1 ; never\nrep dup 1 ; once\n";
+        let expect = "push 1\n   # none\n\t # never\ndup # once\n";
+        let (out, errors) = convert::<Palaiologos, Wsf>(src);
+        assert_eq!(out, expect);
+        assert_eq!(errors, []);
+    }
+
+    #[test]
+    fn lowered_codegen() {
+        let primitive = b"push 3\npush 1\nadd\npush 2\nswap\nstore\npush 0\npush 105\npush 104\n";
+        let mut expect = String::new();
+        (Burghard::new().parse(primitive))
+            .codegen(&mut expect, &HashSet::new())
+            .unwrap();
+
+        let src = b"push 3\nadd 1\nstore 2\npushs \"hi\"\n";
+        let mut cst = Burghard::new().parse(src);
+        let errors = cst.convert(&Burghard::make_config(), &Wsf::make_config());
+        assert_eq!(errors, []);
+        let mut ws = String::new();
+        cst.codegen(&mut ws, &HashSet::new()).unwrap();
+        assert_eq!(ws, expect);
+    }
+}
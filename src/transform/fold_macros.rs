@@ -0,0 +1,396 @@
+//! A transformation that folds instruction sequences into macros.
+
+use std::{cmp::Reverse, collections::HashMap, mem};
+
+use enumset::EnumSet;
+
+use crate::{
+    dialects::DialectConfig,
+    syntax::{ArgLayout, ArgType, Cst, HasError, Inst, MacroStep, Opcode, Overload},
+    tokens::{
+        Token,
+        mnemonics::MnemonicToken,
+        spaces::{ArgSepToken, SpaceToken, Spaces},
+        words::Words,
+    },
+    transform::{Fold, Visitor, fold_nodes},
+};
+
+// TODO:
+// - Fold string macros, e.g., `push 0 / push 'i' / push 'h'` => Burghard
+//   `pushs "hi"`, when the pushed values are likely characters.
+
+/// A macro, which can be folded from the primitive instructions it expands to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Macro {
+    /// A macro opcode with a fixed expansion, e.g., Burghard `jumpnp l` from
+    /// `jz l1 / jmp l / l1:`.
+    Opcode(Opcode),
+    /// An overload of an opcode with constant arguments, e.g., Burghard `add n`
+    /// from `push n / add`.
+    Overload(Opcode, Overload),
+}
+
+impl Cst<'_> {
+    /// Folds sequences of instructions, which are equivalent to the expansion
+    /// of a macro in the dialect, into that macro. Only macros in the enabled
+    /// list are folded. Labels internal to an expansion must not be referenced
+    /// outside of the sequence. Sequences with comments between the
+    /// instructions are not folded.
+    ///
+    /// The generated Whitespace is the same before and after folding.
+    pub fn fold_macros(&mut self, dialect: &DialectConfig, enabled: &[Macro]) {
+        let mut candidates = Vec::new();
+        for &m in enabled {
+            let (opcode, overload, steps) = match m {
+                Macro::Opcode(opcode) => match opcode.expansion() {
+                    Some(steps) => (opcode, None, steps),
+                    None => continue,
+                },
+                Macro::Overload(opcode, overload) => {
+                    let arity = overload.arg_types().len();
+                    if overload.arg_types().contains(&ArgType::Variable)
+                        || dialect.overload(opcode, arity) != Some(overload)
+                    {
+                        continue;
+                    }
+                    (opcode, Some(overload), overload.expansion())
+                }
+            };
+            let Some(mnemonic) = dialect.mnemonic(opcode) else {
+                continue;
+            };
+            candidates.push(Candidate {
+                opcode,
+                overload,
+                steps,
+                mnemonic: mnemonic.mnemonic.clone(),
+            });
+        }
+        // Prefer the longest expansions.
+        candidates.sort_by_key(|c| Reverse(c.steps.len()));
+
+        let mut labels = LabelCounter::default();
+        self.visit(&mut labels);
+        self.fold(&mut Folder {
+            dialect,
+            candidates,
+            labels,
+        });
+    }
+}
+
+struct Candidate {
+    opcode: Opcode,
+    overload: Option<Overload>,
+    steps: &'static [MacroStep],
+    mnemonic: Vec<u8>,
+}
+
+struct Folder<'d> {
+    dialect: &'d DialectConfig,
+    candidates: Vec<Candidate>,
+    labels: LabelCounter,
+}
+
+/// Counts the definitions and references of each label.
+#[derive(Default)]
+struct LabelCounter {
+    defs: HashMap<Vec<u8>, usize>,
+    refs: HashMap<Vec<u8>, usize>,
+}
+
+impl<'s> Visitor<'s> for LabelCounter {
+    fn visit_inst(&mut self, inst: &mut Inst<'s>) {
+        for (word, _) in &inst.words.words {
+            if let Token::Label(l) = word.peel_groups() {
+                let counts = if inst.opcode == Opcode::Label {
+                    &mut self.defs
+                } else {
+                    &mut self.refs
+                };
+                *counts.entry(l.label.to_vec()).or_default() += 1;
+            }
+        }
+    }
+}
+
+/// The bindings from matching a sequence against an expansion.
+#[derive(Default)]
+struct Bindings<'s> {
+    args: Vec<Option<Token<'s>>>,
+    internal: Vec<Option<Vec<u8>>>,
+}
+
+impl<'s> Fold<'s> for Folder<'_> {
+    fn fold_block(&mut self, nodes: Vec<Cst<'s>>) -> Vec<Cst<'s>> {
+        let mut nodes = fold_nodes(self, nodes);
+        self.fold_sequence(&mut nodes);
+        nodes
+    }
+}
+
+impl<'s> Folder<'_> {
+    /// Folds the macros in a sequence of nodes.
+    fn fold_sequence(&self, nodes: &mut Vec<Cst<'s>>) {
+        let mut i = 0;
+        while i < nodes.len() {
+            let folded = self.candidates.iter().find_map(|candidate| {
+                let bindings = self.match_steps(candidate, &nodes[i..])?;
+                Some((candidate, bindings))
+            });
+            if let Some((candidate, bindings)) = folded {
+                let len = candidate.steps.len();
+                let insts = (nodes.drain(i..i + len))
+                    .map(|node| match node {
+                        Cst::Inst(inst) => inst,
+                        _ => unreachable!(),
+                    })
+                    .collect::<Vec<_>>();
+                nodes.insert(i, Cst::Inst(self.build(candidate, bindings, insts)));
+            }
+            i += 1;
+        }
+    }
+
+    /// Matches the nodes against the expansion of a candidate macro and returns
+    /// the bindings of its arguments.
+    fn match_steps(&self, candidate: &Candidate, nodes: &[Cst<'s>]) -> Option<Bindings<'s>> {
+        let steps = candidate.steps;
+        if nodes.len() < steps.len() {
+            return None;
+        }
+        let mut b = Bindings::default();
+        for (j, (&step, node)) in steps.iter().zip(nodes).enumerate() {
+            let Cst::Inst(inst) = node else {
+                return None;
+            };
+            if inst.has_error() || inst.overload.is_some() {
+                return None;
+            }
+            // Comments between the instructions would be lost.
+            let first = j == 0;
+            let last = j == steps.len() - 1;
+            let n = inst.words.words.len();
+            if !first && has_comment(&inst.words.space_before)
+                || (inst.words.words.iter().enumerate())
+                    .any(|(k, (_, space))| !(last && k == n - 1) && has_comment(space))
+            {
+                return None;
+            }
+
+            let arg = || match inst.len_args() {
+                1 => Some(inst.arg(0).peel_groups()),
+                _ => None,
+            };
+            match step {
+                MacroStep::Inst(opcode) => {
+                    (inst.opcode == opcode && inst.len_args() == 0).then_some(())?;
+                }
+                MacroStep::Op => {
+                    (inst.opcode == candidate.opcode && inst.len_args() == 0).then_some(())?;
+                }
+                MacroStep::PushArg(index) => {
+                    let arg @ Token::Integer(_) = arg()? else {
+                        return None;
+                    };
+                    (inst.opcode == Opcode::Push).then_some(())?;
+                    bind(&mut b.args, index, arg.clone())?;
+                }
+                MacroStep::PushConst(n) => {
+                    let Token::Integer(int) = arg()? else {
+                        return None;
+                    };
+                    (inst.opcode == Opcode::Push && int.value == n).then_some(())?;
+                }
+                MacroStep::JmpArg(opcode, index) => {
+                    let arg = arg()?;
+                    let is_label = matches!(arg, Token::Label(_) | Token::Integer(_));
+                    (inst.opcode == opcode && is_label).then_some(())?;
+                    bind(&mut b.args, index, arg.clone())?;
+                }
+                MacroStep::JmpInternal(opcode, index) => {
+                    let Token::Label(l) = arg()? else {
+                        return None;
+                    };
+                    (inst.opcode == opcode).then_some(())?;
+                    bind(&mut b.internal, index, l.label.to_vec())?;
+                }
+                MacroStep::Label(index) => {
+                    let Token::Label(l) = arg()? else {
+                        return None;
+                    };
+                    (inst.opcode == Opcode::Label).then_some(())?;
+                    bind(&mut b.internal, index, l.label.to_vec())?;
+                }
+            }
+        }
+
+        // Internal labels must be defined once and only referenced within the
+        // sequence.
+        for (index, label) in b.internal.iter().enumerate() {
+            let label = label.as_ref()?;
+            let refs = (steps.iter())
+                .filter(|&&step| matches!(step, MacroStep::JmpInternal(_, i) if i == index))
+                .count();
+            if self.labels.defs.get(label) != Some(&1) || self.labels.refs.get(label) != Some(&refs)
+            {
+                return None;
+            }
+            if (b.args.iter().flatten())
+                .any(|arg| matches!(arg, Token::Label(l) if *l.label == **label))
+            {
+                return None;
+            }
+        }
+        Some(b)
+    }
+
+    /// Builds the macro instruction for a matched sequence. It takes the
+    /// leading spaces of the first instruction and the trailing spaces of the
+    /// last.
+    fn build(
+        &self,
+        candidate: &Candidate,
+        bindings: Bindings<'s>,
+        mut insts: Vec<Inst<'s>>,
+    ) -> Inst<'s> {
+        let mut words = Words::new(mem::take(&mut insts[0].words.space_before));
+        words.push_word(Token::from(MnemonicToken {
+            mnemonic: candidate.mnemonic.clone().into(),
+            opcode: candidate.opcode,
+        }));
+        for (i, arg) in bindings.args.into_iter().enumerate() {
+            if i != 0
+                && let Some(style) = self.dialect.arg_sep
+            {
+                words.push_space(Token::from(ArgSepToken::from(style)));
+            }
+            words.push_space(Token::from(SpaceToken::from(b" ")));
+            words.push_word(arg.expect("unbound macro argument"));
+        }
+        let last = insts.last_mut().unwrap();
+        *words.trailing_spaces_mut() = mem::take(last.words.trailing_spaces_mut());
+        Inst {
+            opcode: candidate.opcode,
+            words,
+            arg_layout: ArgLayout::Mnemonic,
+            overload: candidate.overload,
+            errors: EnumSet::empty(),
+        }
+    }
+}
+
+/// Binds a value to the index, if it is unbound, or checks that it equals the
+/// bound value.
+fn bind<T: PartialEq>(bindings: &mut Vec<Option<T>>, index: usize, value: T) -> Option<()> {
+    if bindings.len() <= index {
+        bindings.resize_with(index + 1, || None);
+    }
+    match &bindings[index] {
+        Some(bound) => (*bound == value).then_some(()),
+        None => {
+            bindings[index] = Some(value);
+            Some(())
+        }
+    }
+}
+
+/// Returns whether the spaces contain a comment.
+fn has_comment(spaces: &Spaces<'_>) -> bool {
+    (spaces.tokens.iter()).any(|tok| matches!(tok, Token::LineComment(_) | Token::BlockComment(_)))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use crate::{
+        dialects::{Burghard, Dialect, Palaiologos},
+        syntax::{Opcode, Overload, Pretty},
+        transform::Macro,
+    };
+
+    fn fold<D: Dialect>(src: &[u8], enabled: &[Macro]) -> (String, String, String) {
+        let dialect = D::new();
+        let mut cst = dialect.parse(src);
+        let mut before = String::new();
+        cst.codegen(&mut before, &HashSet::new()).unwrap();
+        cst.fold_macros(&D::make_config(), enabled);
+        let mut after = String::new();
+        cst.codegen(&mut after, &HashSet::new()).unwrap();
+        let mut pretty = Vec::new();
+        cst.pretty(&mut pretty);
+        (String::from_utf8(pretty).unwrap(), before, after)
+    }
+
+    #[test]
+    fn burghard() {
+        let src = b"label start ; loop
+push 1
+add
+jumpz skip
+jump start
+label skip
+doub
+push 3
+sub
+push 5
+swap
+store
+doub
+jumpn over
+doub
+jumpz over
+pop
+jump start
+label over
+pop ; done
+";
+        let enabled = [
+            Macro::Overload(Opcode::Add, Overload::BinaryConstRhs),
+            Macro::Overload(Opcode::Store, Overload::BinaryConstLhs),
+            Macro::Opcode(Opcode::BurghardJmpNonZero),
+            Macro::Opcode(Opcode::BurghardJmpPos),
+            Macro::Opcode(Opcode::BurghardTest),
+        ];
+        let expect = "label start ; loop
+add 1
+jumpnp start
+test 3
+store 5
+jumpp start ; done
+";
+        let (pretty, before, after) = fold::<Burghard>(src, &enabled);
+        assert_eq!(pretty, expect);
+        assert_eq!(after, before);
+    }
+
+    #[test]
+    fn disabled_and_used_labels() {
+        let src = b"push 1
+add
+jumpz skip
+jump start
+label skip
+label start
+jumpz skip
+";
+        let enabled = [Macro::Opcode(Opcode::BurghardJmpNonZero)];
+        let (pretty, _, _) = fold::<Burghard>(src, &enabled);
+        assert_eq!(pretty.as_bytes(), src);
+    }
+
+    #[test]
+    fn palaiologos() {
+        let src = b"psh 1 / psh 2 / sto / psh 3 / rcl / putn\n";
+        let enabled = [
+            Macro::Overload(Opcode::Store, Overload::BinaryConstConst),
+            Macro::Overload(Opcode::Store, Overload::BinaryConstRhs),
+            Macro::Overload(Opcode::Retrieve, Overload::UnaryConst),
+        ];
+        let (pretty, before, after) = fold::<Palaiologos>(src, &enabled);
+        assert_eq!(pretty, "sto 1, 2 / rcl 3 / putn\n");
+        assert_eq!(after, before);
+    }
+}
@@ -1,7 +1,15 @@
 //! Transformations manipulating the CST.
 
+mod convert;
+mod fold_macros;
+mod norm_mnemonics;
 mod norm_space;
 mod norm_strange;
 mod visit;
 
-pub use visit::Visitor;
+pub use convert::{ConvertError, ConvertErrorKind};
+pub use fold_macros::Macro;
+pub use visit::{
+    Fold, Visitor, fold_node, fold_nodes, fold_option_block, walk_block, walk_inst,
+    walk_option_block,
+};
@@ -0,0 +1,127 @@
+//! A transformation that normalizes mnemonics.
+
+use crate::{
+    syntax::{Cst, Inst},
+    tokens::{
+        Token,
+        mnemonics::{CaseFold, MnemonicMap},
+    },
+    transform::Visitor,
+};
+
+impl Cst<'_> {
+    /// Normalizes mnemonics to the preferred mnemonic for their opcode in the
+    /// map. Aliases are replaced and case-insensitive mnemonics are lowercased,
+    /// unless `preserve_case` is set, in which case all-uppercase and
+    /// capitalized mnemonics keep that style.
+    pub fn normalize_mnemonics(&mut self, mnemonics: &MnemonicMap<'_>, preserve_case: bool) {
+        self.visit(&mut MnemonicVisitor {
+            mnemonics,
+            preserve_case,
+        });
+    }
+}
+
+struct MnemonicVisitor<'m, 'a> {
+    mnemonics: &'m MnemonicMap<'a>,
+    preserve_case: bool,
+}
+
+/// The case style of a mnemonic.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum CaseStyle {
+    Lower,
+    Upper,
+    Capitalized,
+}
+
+impl<'s> Visitor<'s> for MnemonicVisitor<'_, '_> {
+    fn visit_inst(&mut self, inst: &mut Inst<'s>) {
+        for (word, _) in &mut inst.words.words {
+            let Token::Mnemonic(m) = word else {
+                continue;
+            };
+            let Some(preferred) = self.mnemonics.get_preferred(m.opcode) else {
+                continue;
+            };
+            if preferred.fold == CaseFold::Exact {
+                if m.mnemonic != preferred.bytes {
+                    m.mnemonic = preferred.bytes.to_vec().into();
+                }
+                continue;
+            }
+            let style = if self.preserve_case {
+                CaseStyle::detect(&m.mnemonic)
+            } else {
+                CaseStyle::Lower
+            };
+            let mut normalized = preferred.iter().collect::<Vec<_>>();
+            match style {
+                CaseStyle::Lower => {}
+                CaseStyle::Upper => normalized.make_ascii_uppercase(),
+                CaseStyle::Capitalized => {
+                    if let Some(b) = normalized.first_mut() {
+                        b.make_ascii_uppercase();
+                    }
+                }
+            }
+            if *m.mnemonic != *normalized {
+                m.mnemonic = normalized.into();
+            }
+        }
+    }
+}
+
+impl CaseStyle {
+    /// Detects the case style of ASCII letters in a mnemonic. Mixed case is
+    /// treated as lowercase.
+    fn detect(mnemonic: &[u8]) -> Self {
+        let mut letters = mnemonic.iter().filter(|b| b.is_ascii_alphabetic());
+        let Some(first) = letters.next() else {
+            return CaseStyle::Lower;
+        };
+        let (mut upper, mut lower) = (true, true);
+        for b in letters {
+            upper &= b.is_ascii_uppercase();
+            lower &= b.is_ascii_lowercase();
+        }
+        match (first.is_ascii_uppercase(), upper, lower) {
+            (true, true, _) => CaseStyle::Upper,
+            (true, _, true) => CaseStyle::Capitalized,
+            _ => CaseStyle::Lower,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        dialects::{Burghard, Dialect as _, Palaiologos},
+        syntax::Pretty,
+    };
+
+    #[test]
+    fn burghard() {
+        let dialect = Burghard::new();
+        let src = b"outN\nInC\nPUSH 1\nRetrive 2\nDoUb\n";
+        let normalize = |preserve_case, expect: &[u8]| {
+            let mut cst = dialect.parse(src);
+            cst.normalize_mnemonics(dialect.mnemonics(), preserve_case);
+            let mut pretty = Vec::new();
+            cst.pretty(&mut pretty);
+            assert_eq!(pretty, expect);
+        };
+        normalize(false, b"outn\ninc\npush 1\nretrive 2\ndoub\n");
+        normalize(true, b"outn\ninc\nPUSH 1\nRetrive 2\ndoub\n");
+    }
+
+    #[test]
+    fn palaiologos_aliases() {
+        let dialect = Palaiologos::new();
+        let mut cst = dialect.parse(b"PSH 1 / xchg / rep swp 2 / Jmp %a\n");
+        cst.normalize_mnemonics(dialect.mnemonics(), true);
+        let mut pretty = Vec::new();
+        cst.pretty(&mut pretty);
+        assert_eq!(pretty, b"PUSH 1 / swap / rep swap 2 / Jmp %a\n");
+    }
+}
@@ -103,7 +103,7 @@ mod tests {
                             ]),
                         ),
                         (
-                            Token::from(Token::Integer(IntegerToken {
+                            Token::Integer(IntegerToken {
                                 literal: b"42".into(),
                                 value: Integer::from(42),
                                 sign: Sign::None,
@@ -111,7 +111,7 @@ mod tests {
                                 leading_zeros: 0,
                                 has_digit_seps: false,
                                 errors: EnumSet::empty(),
-                            })),
+                            }),
                             Spaces::from(Token::from(EofToken)),
                         ),
                     ],
@@ -1,35 +1,255 @@
-//! A visitor for traversing CST nodes.
+//! A visitor and a fold for traversing and rewriting CST nodes.
 
-use crate::syntax::{Cst, Inst};
+use std::mem;
 
-// TODO:
-// - Create pass that transforms instructions equivalent to a macro expansion
-//   into the macro. For example, `push n add` => Burghard `add n` or
-//   `jz l1 jmp l2 l1:` => Burghard `jumpnp` if `l1` is otherwise unused.
-// - Creates pass that normalizes mnemonics.
+use crate::{
+    syntax::{Cst, Inst, OptionBlock},
+    tokens::{Token, spaces::Spaces},
+};
 
-/// A visitor for traversing nodes in a `Cst`.
+/// A visitor for traversing nodes in a `Cst`. Each method defaults to walking
+/// the children of its node, so implementations only override the hooks they
+/// need and may call the corresponding `walk_*` function to continue the
+/// traversal.
 pub trait Visitor<'s> {
     /// Called when an instruction is visited.
-    fn visit_inst(&mut self, _inst: &mut Inst<'s>) {}
+    fn visit_inst(&mut self, inst: &mut Inst<'s>) {
+        walk_inst(self, inst);
+    }
+
+    /// Called when a block of nodes is visited. Nodes may be inserted or
+    /// removed.
+    fn visit_block(&mut self, nodes: &mut Vec<Cst<'s>>) {
+        walk_block(self, nodes);
+    }
+
+    /// Called when a conditionally compiled block is visited.
+    fn visit_option_block(&mut self, block: &mut OptionBlock<'s>) {
+        walk_option_block(self, block);
+    }
+
+    /// Called when the header of a branch or the end of a conditionally
+    /// compiled block is visited.
+    fn visit_option(&mut self, inst: &mut Inst<'s>) {
+        self.visit_inst(inst);
+    }
+
+    /// Called when a word in an instruction is visited.
+    fn visit_word(&mut self, _word: &mut Token<'s>) {}
+
+    /// Called when spaces, comments, and separators in an instruction are
+    /// visited.
+    fn visit_spaces(&mut self, _spaces: &mut Spaces<'s>) {}
+}
+
+/// A fold for rewriting nodes in a `Cst` by value. Each method defaults to
+/// folding the children of its node. Nodes returned in place of an instruction
+/// or a conditionally compiled block are spliced into the enclosing block. A
+/// nested block stays a block, which contains its folded nodes.
+pub trait Fold<'s> {
+    /// Folds an instruction into the nodes which replace it.
+    fn fold_inst(&mut self, inst: Inst<'s>) -> Vec<Cst<'s>> {
+        vec![Cst::Inst(inst)]
+    }
+
+    /// Folds the nodes of a block into the nodes it contains.
+    fn fold_block(&mut self, nodes: Vec<Cst<'s>>) -> Vec<Cst<'s>> {
+        fold_nodes(self, nodes)
+    }
+
+    /// Folds a conditionally compiled block into the nodes which replace it.
+    fn fold_option_block(&mut self, block: OptionBlock<'s>) -> Vec<Cst<'s>> {
+        vec![Cst::OptionBlock(fold_option_block(self, block))]
+    }
+
+    /// Folds the header of a branch or the end of a conditionally compiled
+    /// block.
+    fn fold_option(&mut self, inst: Inst<'s>) -> Inst<'s> {
+        inst
+    }
 }
 
 impl<'s> Cst<'s> {
     /// Traverses this `Cst` and calls the visitor when kinds of nodes are
     /// encountered.
-    pub fn visit<V: Visitor<'s>>(&mut self, visitor: &mut V) {
+    pub fn visit<V: Visitor<'s> + ?Sized>(&mut self, visitor: &mut V) {
         match self {
             Cst::Inst(inst) => visitor.visit_inst(inst),
-            Cst::Block { nodes } => {
-                nodes.iter_mut().for_each(|node| node.visit(visitor));
-            }
-            Cst::OptionBlock(block) => {
-                block.options.iter_mut().for_each(|(option, block)| {
-                    visitor.visit_inst(option);
-                    block.iter_mut().for_each(|node| node.visit(visitor));
-                });
-                block.end.as_mut().map(|end| visitor.visit_inst(end));
+            Cst::Block { nodes } => visitor.visit_block(nodes),
+            Cst::OptionBlock(block) => visitor.visit_option_block(block),
+        }
+    }
+
+    /// Rewrites this `Cst` with the fold. When the root is replaced by several
+    /// nodes, they are wrapped in a block.
+    pub fn fold<F: Fold<'s> + ?Sized>(&mut self, folder: &mut F) {
+        let node = mem::replace(self, Cst::Block { nodes: Vec::new() });
+        let mut nodes = fold_node(folder, node);
+        *self = if nodes.len() == 1 {
+            nodes.pop().unwrap()
+        } else {
+            Cst::Block { nodes }
+        };
+    }
+}
+
+/// Visits the spaces and words of an instruction.
+pub fn walk_inst<'s, V: Visitor<'s> + ?Sized>(visitor: &mut V, inst: &mut Inst<'s>) {
+    visitor.visit_spaces(&mut inst.words.space_before);
+    for (word, space) in &mut inst.words.words {
+        visitor.visit_word(word);
+        visitor.visit_spaces(space);
+    }
+}
+
+/// Visits the nodes of a block.
+pub fn walk_block<'s, V: Visitor<'s> + ?Sized>(visitor: &mut V, nodes: &mut [Cst<'s>]) {
+    nodes.iter_mut().for_each(|node| node.visit(visitor));
+}
+
+/// Visits the branches and end of a conditionally compiled block.
+pub fn walk_option_block<'s, V: Visitor<'s> + ?Sized>(
+    visitor: &mut V,
+    block: &mut OptionBlock<'s>,
+) {
+    for (option, nodes) in &mut block.options {
+        visitor.visit_option(option);
+        visitor.visit_block(nodes);
+    }
+    if let Some(end) = &mut block.end {
+        visitor.visit_option(end);
+    }
+}
+
+/// Folds a node into the nodes which replace it. A block is replaced by a
+/// block of its folded nodes.
+pub fn fold_node<'s, F: Fold<'s> + ?Sized>(folder: &mut F, node: Cst<'s>) -> Vec<Cst<'s>> {
+    match node {
+        Cst::Inst(inst) => folder.fold_inst(inst),
+        Cst::Block { nodes } => vec![Cst::Block {
+            nodes: folder.fold_block(nodes),
+        }],
+        Cst::OptionBlock(block) => folder.fold_option_block(block),
+    }
+}
+
+/// Folds each node in a block and splices the results.
+pub fn fold_nodes<'s, F: Fold<'s> + ?Sized>(folder: &mut F, nodes: Vec<Cst<'s>>) -> Vec<Cst<'s>> {
+    let mut folded = Vec::with_capacity(nodes.len());
+    for node in nodes {
+        folded.extend(fold_node(folder, node));
+    }
+    folded
+}
+
+/// Folds the branches and end of a conditionally compiled block.
+pub fn fold_option_block<'s, F: Fold<'s> + ?Sized>(
+    folder: &mut F,
+    block: OptionBlock<'s>,
+) -> OptionBlock<'s> {
+    OptionBlock {
+        options: (block.options.into_iter())
+            .map(|(option, nodes)| (folder.fold_option(option), folder.fold_block(nodes)))
+            .collect(),
+        end: block.end.map(|end| folder.fold_option(end)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        dialects::{Burghard, Dialect as _},
+        syntax::{Cst, Inst, Opcode, Pretty},
+        tokens::{Token, spaces::Spaces},
+        transform::{Fold, Visitor},
+    };
+
+    #[derive(Default)]
+    struct Counter {
+        options: usize,
+        words: usize,
+        comments: usize,
+    }
+
+    impl<'s> Visitor<'s> for Counter {
+        fn visit_option(&mut self, _inst: &mut Inst<'s>) {
+            self.options += 1;
+        }
+
+        fn visit_word(&mut self, _word: &mut Token<'s>) {
+            self.words += 1;
+        }
+
+        fn visit_spaces(&mut self, spaces: &mut Spaces<'s>) {
+            self.comments += (spaces.tokens.iter())
+                .filter(|tok| matches!(tok, Token::LineComment(_)))
+                .count();
+        }
+    }
+
+    const SRC: &[u8] = b"push 1 ; one
+ifoption a
+    doub
+elseoption
+    pop ; drop
+endoption
+exit
+";
+
+    #[test]
+    fn visit_hooks() {
+        let mut cst = Burghard::new().parse(SRC);
+        let mut counter = Counter::default();
+        cst.visit(&mut counter);
+        assert_eq!(counter.options, 3);
+        assert_eq!(counter.words, 5);
+        assert_eq!(counter.comments, 2);
+    }
+
+    struct Duplicate;
+
+    impl<'s> Fold<'s> for Duplicate {
+        fn fold_inst(&mut self, inst: Inst<'s>) -> Vec<Cst<'s>> {
+            match inst.opcode {
+                Opcode::Dup => vec![Cst::Inst(inst.clone()), Cst::Inst(inst)],
+                Opcode::Drop => vec![],
+                _ => vec![Cst::Inst(inst)],
             }
         }
     }
+
+    #[test]
+    fn fold_splice() {
+        let mut cst = Burghard::new().parse(SRC);
+        cst.fold(&mut Duplicate);
+        let mut pretty = Vec::new();
+        cst.pretty(&mut pretty);
+        let expect = b"push 1 ; one
+ifoption a
+    doub
+    doub
+elseoption
+endoption
+exit
+";
+        assert_eq!(pretty, expect);
+    }
+
+    #[test]
+    fn fold_nested_block() {
+        let inst = |cst: Cst<'static>| match cst {
+            Cst::Block { mut nodes } => nodes.remove(0),
+            _ => panic!("not a block"),
+        };
+        let doub = inst(Burghard::new().parse(b"doub\n"));
+        let mut cst = Cst::Block {
+            nodes: vec![Cst::Block { nodes: vec![doub] }],
+        };
+        cst.fold(&mut Duplicate);
+        let Cst::Block { nodes } = &cst else {
+            panic!("not a block");
+        };
+        assert_eq!(nodes.len(), 1);
+        assert!(matches!(&nodes[0], Cst::Block { nodes } if nodes.len() == 2));
+    }
 }
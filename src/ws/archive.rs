@@ -0,0 +1,441 @@
+//! Persistent binary format for token sources, which can be loaded without
+//! copying after validation.
+//!
+//! All integers are little-endian, so an archive can be read in place from a
+//! memory-mapped file on any platform:
+//!
+//! ```text
+//! magic:           b"OWSATOKS"
+//! version:         u32
+//! lexeme_count:    u32
+//! extension_count: u32
+//! token_count:     u64
+//! data_len:        u64
+//! strings_len:     u64
+//! string_ends:     [u32; lexeme_count + 2 * extension_count]
+//! data:            [u8; data_len]
+//! strings:         [u8; strings_len]
+//! ```
+//!
+//! `data` is the compact token encoding of [`TokenSource`]. `strings` is the
+//! concatenation of the lexemes, then the name and canonical lexeme of each
+//! extension, each ending at the corresponding offset in `string_ends`.
+
+use std::{
+    collections::HashSet,
+    error::Error,
+    fmt::{self, Display, Formatter},
+    io::{self, Write},
+    str,
+};
+
+use crate::ws::token_source::{ExtensionToken, Token, TokenId, TokenSource};
+
+// TODO:
+// - Store the checkpoints of `TokenSource`, so that position lookups in an
+//   archive do not need to scan from the start.
+
+/// A validated view of a token source archive, which borrows its tokens and
+/// lexemes from the archive bytes.
+#[derive(Clone, Debug)]
+pub struct TokenArchive<'a> {
+    len: usize,
+    lexeme_count: usize,
+    extension_count: usize,
+    strings: Strings<'a>,
+    data: &'a [u8],
+    /// A bitset of the offsets in `data`, at which tokens start.
+    token_starts: Vec<u64>,
+}
+
+/// The strings section of an archive.
+#[derive(Clone, Copy, Debug)]
+struct Strings<'a> {
+    /// Little-endian `u32` end offsets in `strings`.
+    ends: &'a [u8],
+    strings: &'a [u8],
+}
+
+/// An error from loading a token source archive.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ArchiveError {
+    /// The byte offset in the archive, at which the error was found.
+    pub offset: usize,
+    /// The kind of the error.
+    pub kind: ArchiveErrorKind,
+}
+
+/// The kind of an error from loading a token source archive.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ArchiveErrorKind {
+    /// The archive does not start with the magic bytes.
+    InvalidMagic,
+    /// The archive has a version, which is not supported.
+    UnsupportedVersion(u32),
+    /// The archive ends before a section is complete.
+    Truncated,
+    /// The archive has bytes after the last section.
+    TrailingBytes,
+    /// A string offset is out of bounds or decreasing.
+    InvalidString,
+    /// An extension name is not valid UTF-8.
+    InvalidName,
+    /// There are more extensions than can be identified.
+    TooManyExtensions,
+    /// A lexeme occurs more than once.
+    DuplicateLexeme,
+    /// A token has the reserved tag.
+    InvalidTag,
+    /// A token refers to an extension, which does not exist.
+    InvalidExtension,
+    /// A token refers to a lexeme, which does not exist or is not in the
+    /// shortest encoding.
+    InvalidLexeme,
+    /// The number of tokens differs from the header.
+    TokenCount,
+}
+
+impl TokenArchive<'_> {
+    /// The bytes at the start of every archive.
+    pub const MAGIC: [u8; 8] = *b"OWSATOKS";
+    /// The version of the format written by this version of the library.
+    pub const VERSION: u32 = 1;
+
+    const HEADER_LEN: usize = 8 + 4 + 4 + 4 + 8 + 8 + 8;
+}
+
+impl<'a> TokenArchive<'a> {
+    /// Validates an archive and constructs a view of it. Every token is
+    /// checked, so that later accesses cannot fail.
+    pub fn new(bytes: &'a [u8]) -> Result<Self, ArchiveError> {
+        let err = |offset, kind| ArchiveError { offset, kind };
+        if bytes.len() < Self::HEADER_LEN {
+            let kind = if bytes.starts_with(&Self::MAGIC[..bytes.len().min(8)]) {
+                ArchiveErrorKind::Truncated
+            } else {
+                ArchiveErrorKind::InvalidMagic
+            };
+            return Err(err(bytes.len().min(8), kind));
+        }
+        if bytes[..8] != Self::MAGIC {
+            return Err(err(0, ArchiveErrorKind::InvalidMagic));
+        }
+        let version = read_u32(bytes, 8);
+        if version != Self::VERSION {
+            return Err(err(8, ArchiveErrorKind::UnsupportedVersion(version)));
+        }
+        let lexeme_count = read_u32(bytes, 12) as usize;
+        let extension_count = read_u32(bytes, 16) as usize;
+        let token_count = read_u64(bytes, 20);
+        let data_len = read_u64(bytes, 28);
+        let strings_len = read_u64(bytes, 36);
+        if extension_count > 256 {
+            return Err(err(16, ArchiveErrorKind::TooManyExtensions));
+        }
+
+        let mut offset = Self::HEADER_LEN;
+        let mut section = |len: Option<usize>| {
+            let start = offset;
+            let end = len
+                .and_then(|len| start.checked_add(len))
+                .filter(|&end| end <= bytes.len())
+                .ok_or(err(bytes.len(), ArchiveErrorKind::Truncated))?;
+            offset = end;
+            Ok((start, &bytes[start..end]))
+        };
+        let string_count = lexeme_count + 2 * extension_count;
+        let (ends_offset, string_ends) = section(string_count.checked_mul(4))?;
+        let (data_offset, data) = section(usize::try_from(data_len).ok())?;
+        let (_, strings) = section(usize::try_from(strings_len).ok())?;
+        if offset != bytes.len() {
+            return Err(err(offset, ArchiveErrorKind::TrailingBytes));
+        }
+
+        let mut prev_end = 0;
+        for i in 0..string_count {
+            let end = read_u32(string_ends, 4 * i) as usize;
+            if end < prev_end || end > strings.len() {
+                return Err(err(ends_offset + 4 * i, ArchiveErrorKind::InvalidString));
+            }
+            prev_end = end;
+        }
+        let strings = Strings {
+            ends: string_ends,
+            strings,
+        };
+        let mut seen = HashSet::with_capacity(lexeme_count);
+        for i in 0..lexeme_count {
+            if !seen.insert(strings.get(i)) {
+                return Err(err(ends_offset + 4 * i, ArchiveErrorKind::DuplicateLexeme));
+            }
+        }
+        for i in 0..extension_count {
+            let name = lexeme_count + 2 * i;
+            if str::from_utf8(strings.get(name)).is_err() {
+                return Err(err(ends_offset + 4 * name, ArchiveErrorKind::InvalidName));
+            }
+        }
+
+        let mut token_starts = vec![0; data.len().div_ceil(64)];
+        let len = TokenSource::validate_data(data, lexeme_count, extension_count, |offset| {
+            token_starts[offset / 64] |= 1 << (offset % 64);
+        })
+        .map_err(|(offset, kind)| err(data_offset + offset, kind))?;
+        if len as u64 != token_count {
+            return Err(err(20, ArchiveErrorKind::TokenCount));
+        }
+        Ok(TokenArchive {
+            len,
+            lexeme_count,
+            extension_count,
+            strings,
+            data,
+            token_starts,
+        })
+    }
+
+    /// Returns the number of tokens.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns whether there are no tokens.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Gets the identified token and its lexeme. Returns `None` when the
+    /// identifier is not of a token in this archive, e.g., when it is from
+    /// another token source.
+    pub fn get(&self, id: TokenId) -> Option<(Token, &'a [u8])> {
+        let offset = id.as_usize();
+        let word = self.token_starts.get(offset / 64)?;
+        if word & (1 << (offset % 64)) == 0 {
+            return None;
+        }
+        let (tok, lexeme_id, _) = TokenSource::decode_data(self.data, offset);
+        Some((tok, self.strings.get(lexeme_id)))
+    }
+
+    /// Iterates the tokens with their identifiers and lexemes. The
+    /// identifiers are the same as in the archived token source.
+    pub fn iter(&self) -> impl Iterator<Item = (TokenId, Token, &'a [u8])> + use<'a> {
+        let (data, strings) = (self.data, self.strings);
+        let mut offset = 0;
+        (0..self.len).map(move |_| {
+            let (tok, lexeme_id, len) = TokenSource::decode_data(data, offset);
+            let id = TokenId::from_usize(offset);
+            offset += len;
+            (id, tok, strings.get(lexeme_id))
+        })
+    }
+
+    /// Iterates the names and canonical lexemes of the extensions.
+    pub fn extensions(&self) -> impl Iterator<Item = (&'a str, &'a [u8])> + use<'a> {
+        let (lexeme_count, strings) = (self.lexeme_count, self.strings);
+        (0..self.extension_count).map(move |i| {
+            let i = lexeme_count + 2 * i;
+            let name = str::from_utf8(strings.get(i)).unwrap();
+            (name, strings.get(i + 1))
+        })
+    }
+
+    /// Copies the archive to an owned token source.
+    pub fn to_token_source(&self) -> TokenSource {
+        let lexemes = (0..self.lexeme_count)
+            .map(|i| self.strings.get(i).to_owned())
+            .collect();
+        let extensions = (self.extensions())
+            .map(|(name, lexeme)| ExtensionToken::new(name.to_owned(), lexeme.to_owned()))
+            .collect();
+        TokenSource::from_parts(self.data.to_owned(), lexemes, extensions)
+    }
+}
+
+impl<'a> Strings<'a> {
+    /// Gets the string at the index in `ends`.
+    fn get(&self, i: usize) -> &'a [u8] {
+        let start = if i == 0 {
+            0
+        } else {
+            read_u32(self.ends, 4 * (i - 1)) as usize
+        };
+        let end = read_u32(self.ends, 4 * i) as usize;
+        &self.strings[start..end]
+    }
+}
+
+impl TokenSource {
+    /// Writes this token source in the archive format, which can be loaded
+    /// with [`TokenArchive::new`].
+    pub fn write_archive<W: Write>(&self, mut w: W) -> io::Result<()> {
+        let (data, lexemes, extensions) = self.parts();
+        let strings = (lexemes.iter().map(|lexeme| &lexeme[..]))
+            .chain((extensions.iter()).flat_map(|ext| [ext.name().as_bytes(), ext.canon_lexeme()]))
+            .collect::<Vec<_>>();
+        let too_large = || io::Error::new(io::ErrorKind::InvalidInput, "token source too large");
+        let lexeme_count = u32::try_from(lexemes.len()).map_err(|_| too_large())?;
+
+        w.write_all(&TokenArchive::MAGIC)?;
+        w.write_all(&TokenArchive::VERSION.to_le_bytes())?;
+        w.write_all(&lexeme_count.to_le_bytes())?;
+        w.write_all(&(extensions.len() as u32).to_le_bytes())?;
+        w.write_all(&(self.len() as u64).to_le_bytes())?;
+        w.write_all(&(data.len() as u64).to_le_bytes())?;
+        let strings_len = strings.iter().map(|s| s.len()).sum::<usize>();
+        w.write_all(&(strings_len as u64).to_le_bytes())?;
+        let mut end = 0u32;
+        for s in &strings {
+            let len = u32::try_from(s.len()).map_err(|_| too_large())?;
+            end = end.checked_add(len).ok_or_else(too_large)?;
+            w.write_all(&end.to_le_bytes())?;
+        }
+        w.write_all(data)?;
+        for s in &strings {
+            w.write_all(s)?;
+        }
+        Ok(())
+    }
+
+    /// Returns this token source in the archive format.
+    pub fn to_archive(&self) -> Vec<u8> {
+        let mut buf = Vec::new();
+        self.write_archive(&mut buf)
+            .expect("writing to a Vec does not fail");
+        buf
+    }
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap())
+}
+
+fn read_u64(bytes: &[u8], offset: usize) -> u64 {
+    u64::from_le_bytes(bytes[offset..offset + 8].try_into().unwrap())
+}
+
+impl Display for ArchiveError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "at byte {}: ", self.offset)?;
+        match self.kind {
+            ArchiveErrorKind::InvalidMagic => write!(f, "not a token source archive"),
+            ArchiveErrorKind::UnsupportedVersion(version) => {
+                write!(f, "unsupported archive version {version}")
+            }
+            ArchiveErrorKind::Truncated => write!(f, "truncated archive"),
+            ArchiveErrorKind::TrailingBytes => write!(f, "trailing bytes after archive"),
+            ArchiveErrorKind::InvalidString => write!(f, "invalid string offset"),
+            ArchiveErrorKind::InvalidName => write!(f, "extension name is not UTF-8"),
+            ArchiveErrorKind::TooManyExtensions => write!(f, "too many extensions"),
+            ArchiveErrorKind::DuplicateLexeme => write!(f, "duplicate lexeme"),
+            ArchiveErrorKind::InvalidTag => write!(f, "invalid token tag"),
+            ArchiveErrorKind::InvalidExtension => write!(f, "invalid extension token"),
+            ArchiveErrorKind::InvalidLexeme => write!(f, "invalid lexeme index"),
+            ArchiveErrorKind::TokenCount => write!(f, "token count mismatch"),
+        }
+    }
+}
+
+impl Error for ArchiveError {}
+
+#[cfg(test)]
+mod tests {
+    use crate::ws::{
+        archive::{ArchiveError, ArchiveErrorKind, TokenArchive},
+        token_source::{ExtensionToken, Token, TokenSource},
+    };
+
+    #[test]
+    fn round_trip() {
+        let mut source = TokenSource::new();
+        let crab = source.add_extension(ExtensionToken::new(
+            "crab".to_owned(),
+            "河蟹".as_bytes().to_vec(),
+        ));
+        source.push(Token::Comment, b"push");
+        for i in 0..100 {
+            source.push(Token::S, b" ");
+            source.push(Token::Comment, format!("{i}\n").as_bytes());
+            source.push(Token::Extension(crab), "河蟹".as_bytes());
+        }
+        let bytes = source.to_archive();
+
+        let archive = TokenArchive::new(&bytes).unwrap();
+        assert_eq!(archive.len(), source.len());
+        assert_eq!(
+            archive.iter().collect::<Vec<_>>(),
+            source.iter().collect::<Vec<_>>(),
+        );
+        assert_eq!(
+            archive.extensions().collect::<Vec<_>>(),
+            [("crab", "河蟹".as_bytes())],
+        );
+        for (id, tok, lexeme) in source.iter() {
+            assert_eq!(archive.get(id), Some((tok, lexeme)));
+        }
+        let loaded = archive.to_token_source();
+        assert_eq!(loaded, source);
+        assert_eq!(loaded.end(), source.end());
+        let (id, _, _) = source.iter().nth(150).unwrap();
+        assert_eq!(loaded.position(id), source.position(id));
+        assert!(
+            TokenArchive::new(&TokenSource::new().to_archive())
+                .unwrap()
+                .is_empty()
+        );
+
+        // Identifiers, which are not token boundaries in this archive, are
+        // rejected. Offset 4 is the extension byte of the first `crab`.
+        let mut other = TokenSource::new();
+        for _ in 0..4 {
+            other.push(Token::S, b" ");
+        }
+        let inner = other.push(Token::T, b"\t");
+        assert_eq!(archive.get(inner), None);
+        let mut far = TokenSource::new();
+        let past_end = (0..bytes.len())
+            .map(|_| far.push(Token::S, b" "))
+            .last()
+            .unwrap();
+        assert_eq!(archive.get(past_end), None);
+
+        let err = |offset, kind| Err(ArchiveError { offset, kind });
+        let header = TokenArchive::HEADER_LEN;
+        let strings = 4 * 105;
+        assert_eq!(
+            TokenArchive::new(&bytes[..bytes.len() - 1]).map(|_| ()),
+            err(bytes.len() - 1, ArchiveErrorKind::Truncated),
+        );
+        let mut bad = bytes.clone();
+        bad[8] = 2;
+        assert_eq!(
+            TokenArchive::new(&bad).map(|_| ()),
+            err(8, ArchiveErrorKind::UnsupportedVersion(2)),
+        );
+        let mut bad = bytes.clone();
+        bad[header + strings] = 0b111;
+        assert_eq!(
+            TokenArchive::new(&bad).map(|_| ()),
+            err(header + strings, ArchiveErrorKind::InvalidTag),
+        );
+        let mut bad = bytes.clone();
+        bad[header + strings] = 31 << 3 | 4;
+        assert_eq!(
+            TokenArchive::new(&bad).map(|_| ()),
+            err(header + strings, ArchiveErrorKind::InvalidLexeme),
+        );
+        let mut bad = bytes.clone();
+        bad[header + strings + 4] = 1;
+        assert_eq!(
+            TokenArchive::new(&bad).map(|_| ()),
+            err(header + strings + 4, ArchiveErrorKind::InvalidExtension),
+        );
+        let mut bad = bytes.clone();
+        bad[header..header + 4].copy_from_slice(&0u32.to_le_bytes());
+        bad[header + 4..header + 8].copy_from_slice(&0u32.to_le_bytes());
+        assert_eq!(
+            TokenArchive::new(&bad).map(|_| ()),
+            err(header + 4, ArchiveErrorKind::DuplicateLexeme),
+        );
+    }
+}
@@ -0,0 +1,199 @@
+//! Canonicalization of Whitespace programs, so that programs from different
+//! assemblers can be compared.
+
+use std::collections::HashMap;
+
+use rug::Integer;
+
+use crate::{
+    codegen::{EncodingWriter, Inst, IntegerBits, LabelBits, TokenWrite},
+    ws::{
+        encoding::TokenEncoding,
+        parse::{ParseError, parse_program},
+    },
+};
+
+/// Options for canonicalizing a Whitespace program. Comments and
+/// non-standard lexemes are always removed.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Canonicalize {
+    /// Encode integers with no leading zeros and zero with only a positive
+    /// sign.
+    pub minimize_integers: bool,
+    /// Encode labels with no leading zeros, except those that would merge
+    /// with a distinct label. Labels are bit strings, so `S T` and `T` differ.
+    pub minimize_labels: bool,
+    /// Renumber labels in order of how often they are used, so that the most
+    /// frequent get the shortest encodings. Labels are numbered by all bit
+    /// strings in order of length, starting with the empty label, so this
+    /// subsumes `minimize_labels`.
+    pub renumber_labels: bool,
+}
+
+impl Canonicalize {
+    /// Only remove comments and non-standard lexemes.
+    pub const NONE: Canonicalize = Canonicalize {
+        minimize_integers: false,
+        minimize_labels: false,
+        renumber_labels: false,
+    };
+    /// Apply all canonicalizations.
+    pub const ALL: Canonicalize = Canonicalize {
+        minimize_integers: true,
+        minimize_labels: true,
+        renumber_labels: true,
+    };
+
+    /// Canonicalizes a program in the encoding and writes it in the same
+    /// encoding.
+    pub fn canonicalize(&self, src: &[u8], encoding: TokenEncoding) -> Result<Vec<u8>, ParseError> {
+        let mut insts = parse_program(&encoding.decode(src))?;
+        self.apply(&mut insts);
+        let mut w = EncodingWriter::new(Vec::new(), encoding);
+        for inst in insts {
+            w.write_inst(inst).expect("writing to a Vec does not fail");
+        }
+        Ok(w.finish().expect("writing to a Vec does not fail"))
+    }
+
+    /// Canonicalizes the arguments of the instructions.
+    pub fn apply(&self, insts: &mut [Inst<'static>]) {
+        if self.minimize_integers {
+            for inst in insts.iter_mut() {
+                if let Inst::Push(n) | Inst::Copy(n) | Inst::Slide(n) = inst {
+                    *n = IntegerBits::from(n.value().clone());
+                }
+            }
+        }
+        if self.renumber_labels {
+            renumber_labels(insts);
+        } else if self.minimize_labels {
+            minimize_labels(insts);
+        }
+    }
+}
+
+/// Gets the label argument of an instruction.
+fn label_mut<'i, 'a>(inst: &'i mut Inst<'a>) -> Option<&'i mut LabelBits<'a>> {
+    match inst {
+        Inst::Label(l) | Inst::Call(l) | Inst::Jmp(l) | Inst::Jz(l) | Inst::Jn(l) => Some(l),
+        _ => None,
+    }
+}
+
+/// Identifies a label by its bits.
+fn label_key(l: &LabelBits<'_>) -> (Integer, usize) {
+    (l.value().clone(), l.leading_zeros())
+}
+
+fn renumber_labels(insts: &mut [Inst<'static>]) {
+    let mut uses = HashMap::new();
+    let mut order = Vec::new();
+    for inst in insts.iter_mut() {
+        if let Some(l) = label_mut(inst) {
+            let key = label_key(l);
+            *uses.entry(key.clone()).or_insert_with(|| {
+                order.push(key);
+                0usize
+            }) += 1;
+        }
+    }
+    // Stable, so ties keep the order of first use.
+    order.sort_by_key(|key| std::cmp::Reverse(uses[key]));
+    let renumbered = (order.into_iter().enumerate())
+        .map(|(i, key)| (key, nth_bit_string(i)))
+        .collect::<HashMap<_, _>>();
+    for inst in insts.iter_mut() {
+        if let Some(l) = label_mut(inst) {
+            *l = renumbered[&label_key(l)].clone();
+        }
+    }
+}
+
+fn minimize_labels(insts: &mut [Inst<'static>]) {
+    // The number of leading zeros for each label value, or `None` when
+    // distinct labels share the value.
+    let mut values = HashMap::new();
+    for inst in insts.iter_mut() {
+        if let Some(l) = label_mut(inst) {
+            let leading_zeros = Some(l.leading_zeros());
+            let entry = values.entry(l.value().clone()).or_insert(leading_zeros);
+            if *entry != leading_zeros {
+                *entry = None;
+            }
+        }
+    }
+    for inst in insts.iter_mut() {
+        if let Some(l) = label_mut(inst)
+            && values[l.value()].is_some()
+        {
+            *l = LabelBits::owned(l.value().clone(), 0);
+        }
+    }
+}
+
+/// Returns the nth bit string in order of length, then value: the empty
+/// string, `S`, `T`, `SS`, `ST`, `TS`, `TT`, and so on.
+fn nth_bit_string(n: usize) -> LabelBits<'static> {
+    let n = n + 1;
+    let len = n.ilog2();
+    let value = n - (1 << len);
+    let leading_zeros = len - value.checked_ilog2().map_or(0, |bits| bits + 1);
+    LabelBits::owned(Integer::from(value), leading_zeros as usize)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ws::{canonical::Canonicalize, encoding::TokenEncoding};
+
+    #[test]
+    fn canonicalize() {
+        let canonicalize = |options: Canonicalize, src: &str| {
+            String::from_utf8(
+                options
+                    .canonicalize(src.as_bytes(), TokenEncoding::Stl)
+                    .unwrap(),
+            )
+            .unwrap()
+        };
+        // push +0 (with a leading zero), push -3, call TT, label STS, jmp STS,
+        // label TT, end
+        let src = "SS S S L SS T TT L  # push\nLST TT L LSS STS L LSL STS L LSS TT L LLL";
+        assert_eq!(
+            canonicalize(Canonicalize::NONE, src),
+            "SSSSLSSTTTLLSTTTLLSSSTSLLSLSTSLLSSTTLLLL",
+        );
+        assert_eq!(
+            canonicalize(
+                Canonicalize {
+                    minimize_integers: true,
+                    minimize_labels: true,
+                    renumber_labels: false,
+                },
+                src,
+            ),
+            "SSSLSSTTTLLSTTTLLSSTSLLSLTSLLSSTTLLLL",
+        );
+        // `TT` and `STS` are both used twice, so `TT` is first by first use.
+        assert_eq!(
+            canonicalize(Canonicalize::ALL, src),
+            "SSSLSSTTTLLSTLLSSSLLSLSLLSSLLLL",
+        );
+        // `S T` and `T` are distinct labels, so they keep their leading zeros.
+        let src = "LSSSTLLSSTLLSLSTL";
+        let minimize = Canonicalize {
+            minimize_labels: true,
+            ..Canonicalize::NONE
+        };
+        assert_eq!(canonicalize(minimize, src), src);
+        // Only the colliding labels keep their leading zeros; `S S` is
+        // unambiguous and minimized.
+        let src = "LSSSTLLSSTLLSSSSLLSLSTL";
+        assert_eq!(canonicalize(minimize, src), "LSSSTLLSSTLLSSLLSLSTL");
+        assert!(
+            Canonicalize::ALL
+                .canonicalize(b"TL", TokenEncoding::Stl)
+                .is_err()
+        );
+    }
+}
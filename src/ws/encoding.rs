@@ -0,0 +1,174 @@
+//! Token encodings of Whitespace.
+
+use crate::ws::{
+    lex::Lexer,
+    token_source::{StandardToken, Token, TokenSource},
+};
+
+// TODO:
+// - Decode the GrassMudHorse river crab (河蟹) extension token.
+// - Decode szm tokens.
+
+/// A lexical format for the tokens of a Whitespace program.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum TokenEncoding {
+    /// Standard Whitespace: space, tab, and line feed. All other bytes are
+    /// comments.
+    #[default]
+    Standard,
+    /// Visible letters `S`, `T`, and `L`. All other bytes are comments.
+    Stl,
+    /// GrassMudHorse Chinese characters: 草 (space), 泥 (tab), and 马 (line
+    /// feed). All other characters are comments.
+    GrassMudHorse,
+    /// Packed bits, most significant first: 0 (space), 10 (tab), and 11 (line
+    /// feed). The final token is followed by a 1 bit and 0 bits padding to a
+    /// whole byte.
+    Packed,
+}
+
+impl TokenEncoding {
+    /// Gets the lexemes of space, tab, and line feed in this encoding. Packed
+    /// tokens are not byte-aligned, so have the lexemes of standard
+    /// Whitespace.
+    pub fn lexemes(self) -> [&'static [u8]; 3] {
+        match self {
+            TokenEncoding::Standard | TokenEncoding::Packed => [b" ", b"\t", b"\n"],
+            TokenEncoding::Stl => [b"S", b"T", b"L"],
+            TokenEncoding::GrassMudHorse => ["草".as_bytes(), "泥".as_bytes(), "马".as_bytes()],
+        }
+    }
+
+    /// Escapes comment text, so it contains no tokens in this encoding. Token
+    /// characters are replaced with lookalikes: spaces, tabs, and line feeds
+    /// with no-break spaces, `S`, `T`, and `L` with their fullwidth forms, and
+    /// GrassMudHorse characters with replacement characters.
+    pub fn escape_comment(self, comment: &str) -> String {
+        (comment.chars())
+            .map(|ch| match (self, ch) {
+                (TokenEncoding::Standard | TokenEncoding::Packed, ' ' | '\t' | '\n') => '\u{a0}',
+                (TokenEncoding::Stl, 'S') => 'Ｓ',
+                (TokenEncoding::Stl, 'T') => 'Ｔ',
+                (TokenEncoding::Stl, 'L') => 'Ｌ',
+                (TokenEncoding::GrassMudHorse, '草' | '泥' | '马') => '\u{fffd}',
+                _ => ch,
+            })
+            .collect()
+    }
+
+    /// Constructs a lexer for this encoding, or `None` for the packed
+    /// encoding, since its tokens are not whole bytes.
+    pub fn lexer(self) -> Option<Lexer> {
+        let [s, t, l] = self.lexemes();
+        let lexeme = |lexeme: &[u8], tok| (lexeme.to_vec(), tok);
+        let lexemes = vec![
+            lexeme(s, StandardToken::S),
+            lexeme(t, StandardToken::T),
+            lexeme(l, StandardToken::L),
+        ];
+        match self {
+            TokenEncoding::Standard | TokenEncoding::Stl => Some(Lexer {
+                lexemes,
+                ..Lexer::new()
+            }),
+            TokenEncoding::GrassMudHorse => Some(Lexer {
+                lexemes,
+                utf8: true,
+                ..Lexer::new()
+            }),
+            TokenEncoding::Packed => None,
+        }
+    }
+
+    /// Decodes a Whitespace program in this encoding. Runs of bytes, which
+    /// are not tokens, are comments.
+    pub fn decode(self, src: &[u8]) -> TokenSource {
+        match self.lexer() {
+            Some(lexer) => lexer.lex(src),
+            None => {
+                let mut tokens = TokenSource::new();
+                decode_packed(&mut tokens, src);
+                tokens
+            }
+        }
+    }
+}
+
+/// Decodes packed bits. A trailing incomplete token is invalid.
+fn decode_packed(tokens: &mut TokenSource, src: &[u8]) {
+    let Some(last) = src.iter().rposition(|&b| b != 0) else {
+        return;
+    };
+    let len = last * 8 + 7 - src[last].trailing_zeros() as usize;
+    let bit = |i: usize| src[i / 8] & (0x80 >> (i % 8)) != 0;
+    let [s, t, l] = TokenEncoding::Packed.lexemes();
+    let mut i = 0;
+    while i < len {
+        if !bit(i) {
+            tokens.push(Token::S, s);
+            i += 1;
+        } else if i + 1 == len {
+            tokens.push(Token::InvalidToken, b"1");
+            i += 1;
+        } else {
+            let (tok, lexeme) = if bit(i + 1) {
+                (Token::L, l)
+            } else {
+                (Token::T, t)
+            };
+            tokens.push(tok, lexeme);
+            i += 2;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ws::{
+        encoding::TokenEncoding,
+        token_source::{Token, TokenSource},
+    };
+
+    fn source(tokens: &[(Token, &[u8])]) -> TokenSource {
+        let mut source = TokenSource::new();
+        for &(tok, lexeme) in tokens {
+            source.push(tok, lexeme);
+        }
+        source
+    }
+
+    #[test]
+    fn decode() {
+        let stl = TokenEncoding::Stl.decode(b"push SS 5 STSTL");
+        let expect = source(&[
+            (Token::Comment, b"push "),
+            (Token::S, b"S"),
+            (Token::S, b"S"),
+            (Token::Comment, b" 5 "),
+            (Token::S, b"S"),
+            (Token::T, b"T"),
+            (Token::S, b"S"),
+            (Token::T, b"T"),
+            (Token::L, b"L"),
+        ]);
+        assert_eq!(stl, expect);
+
+        let gmh = TokenEncoding::GrassMudHorse
+            .decode(b"\xe8\x8d\x89\xe8\x8d\x89x\xe6\xb3\xa5\xff\xe9\xa9\xac");
+        let expect = source(&[
+            (Token::S, "草".as_bytes()),
+            (Token::S, "草".as_bytes()),
+            (Token::Comment, b"x"),
+            (Token::T, "泥".as_bytes()),
+            (Token::InvalidUtf8, b"\xff"),
+            (Token::L, "马".as_bytes()),
+        ]);
+        assert_eq!(gmh, expect);
+
+        // S T L, then the terminator: 0101 1100 → 0x5c.
+        let packed = TokenEncoding::Packed.decode(&[0x5c]);
+        let expect = source(&[(Token::S, b" "), (Token::T, b"\t"), (Token::L, b"\n")]);
+        assert_eq!(packed, expect);
+        assert_eq!(TokenEncoding::Packed.decode(&[0, 0]), TokenSource::new());
+    }
+}
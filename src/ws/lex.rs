@@ -0,0 +1,186 @@
+//! Lexing of Whitespace source files to token sources.
+
+use bstr::decode_utf8;
+
+use crate::{
+    tokens::spaces::SpaceSet,
+    ws::token_source::{ExtensionToken, StandardToken, Token, TokenSource},
+};
+
+/// A Whitespace lexer with a configurable mapping from lexemes to tokens.
+/// Lexing is lossless: every byte is in exactly one token.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Lexer {
+    /// Literal lexemes for standard tokens.
+    pub lexemes: Vec<(Vec<u8>, StandardToken)>,
+    /// Sets of whitespace characters for standard tokens, which are matched
+    /// after literal lexemes. Non-ASCII characters are only matched when
+    /// decoding UTF-8.
+    pub spaces: Vec<(SpaceSet, StandardToken)>,
+    /// Extension tokens, which are matched by their canonical lexemes and
+    /// registered in each token source.
+    pub extensions: Vec<ExtensionToken>,
+    /// How to lex runs of characters, which are not mapped to a token.
+    pub unmapped: Unmapped,
+    /// Whether to decode the source as UTF-8, so that invalid sequences are
+    /// [`Token::InvalidUtf8`] and unmapped runs end at character boundaries.
+    pub utf8: bool,
+}
+
+/// How a [`Lexer`] treats characters, which are not mapped to a token.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Unmapped {
+    /// Runs of unmapped characters are [`Token::Comment`].
+    #[default]
+    Comment,
+    /// Runs of unmapped characters are [`Token::InvalidToken`], for encodings
+    /// without comments.
+    Invalid,
+}
+
+impl Lexer {
+    /// Constructs a lexer with no mapped tokens.
+    pub fn new() -> Self {
+        Lexer {
+            lexemes: Vec::new(),
+            spaces: Vec::new(),
+            extensions: Vec::new(),
+            unmapped: Unmapped::Comment,
+            utf8: false,
+        }
+    }
+
+    /// Constructs a lexer for standard Whitespace, where space, tab, and line
+    /// feed are tokens and all other bytes are comments.
+    pub fn standard() -> Self {
+        Lexer {
+            lexemes: vec![
+                (b" ".to_vec(), StandardToken::S),
+                (b"\t".to_vec(), StandardToken::T),
+                (b"\n".to_vec(), StandardToken::L),
+            ],
+            ..Lexer::new()
+        }
+    }
+
+    /// Lexes a source file to a token source.
+    pub fn lex(&self, src: &[u8]) -> TokenSource {
+        let mut tokens = TokenSource::new();
+        let extensions = (self.extensions.iter())
+            .map(|ext| tokens.add_extension(ext.clone()))
+            .collect::<Vec<_>>();
+        let mut unmapped_start = 0;
+        let mut i = 0;
+        while i < src.len() {
+            let rest = &src[i..];
+            let mut best: Option<(Token, usize)> = None;
+            let mut consider = |tok: Token, len: usize| {
+                if len != 0 && best.is_none_or(|(_, best_len)| len > best_len) {
+                    best = Some((tok, len));
+                }
+            };
+            for (lexeme, tok) in &self.lexemes {
+                if rest.starts_with(lexeme) {
+                    consider(Token::from(*tok), lexeme.len());
+                }
+            }
+            for (ext, &id) in self.extensions.iter().zip(&extensions) {
+                if rest.starts_with(ext.canon_lexeme()) {
+                    consider(Token::from(id), ext.canon_lexeme().len());
+                }
+            }
+            let (ch, ch_len) = if self.utf8 {
+                decode_utf8(rest)
+            } else {
+                (Some(rest[0] as char).filter(|ch| ch.is_ascii()), 1)
+            };
+            if best.is_none()
+                && let Some(ch) = ch
+                && let Some(&(_, tok)) = self.spaces.iter().find(|(set, _)| set.contains(ch))
+            {
+                best = Some((Token::from(tok), ch_len));
+            }
+            let (tok, len) = match best {
+                Some(best) => best,
+                None if ch.is_none() && self.utf8 => (Token::InvalidUtf8, ch_len),
+                None => {
+                    i += ch_len;
+                    continue;
+                }
+            };
+            self.push_unmapped(&mut tokens, &src[unmapped_start..i]);
+            tokens.push(tok, &src[i..i + len]);
+            i += len;
+            unmapped_start = i;
+        }
+        self.push_unmapped(&mut tokens, &src[unmapped_start..]);
+        tokens
+    }
+
+    fn push_unmapped(&self, tokens: &mut TokenSource, text: &[u8]) {
+        if !text.is_empty() {
+            let tok = match self.unmapped {
+                Unmapped::Comment => Token::Comment,
+                Unmapped::Invalid => Token::InvalidToken,
+            };
+            tokens.push(tok, text);
+        }
+    }
+}
+
+impl Default for Lexer {
+    fn default() -> Self {
+        Lexer::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        tokens::spaces::SpaceSet,
+        ws::{
+            lex::{Lexer, Unmapped},
+            token_source::{ExtensionToken, StandardToken, Token, TokenSource},
+        },
+    };
+
+    #[test]
+    fn lex() {
+        let mut lexer = Lexer::standard();
+        lexer
+            .spaces
+            .push((SpaceSet::HASKELL_ISSPACE, StandardToken::S));
+        lexer.extensions.push(ExtensionToken::new(
+            "crab".to_owned(),
+            "河蟹".as_bytes().to_vec(),
+        ));
+        lexer.unmapped = Unmapped::Invalid;
+        lexer.utf8 = true;
+        let src = "a \u{3000}河蟹河\r\u{ff}\n".as_bytes();
+        let mut src = src.to_vec();
+        src.insert(src.len() - 1, 0xff);
+        let tokens = lexer.lex(&src);
+
+        let mut expect = TokenSource::new();
+        let crab = expect.add_extension(ExtensionToken::new(
+            "crab".to_owned(),
+            "河蟹".as_bytes().to_vec(),
+        ));
+        expect.push(Token::InvalidToken, b"a");
+        expect.push(Token::S, b" ");
+        expect.push(Token::S, "\u{3000}".as_bytes());
+        expect.push(Token::Extension(crab), "河蟹".as_bytes());
+        expect.push(Token::InvalidToken, "河".as_bytes());
+        expect.push(Token::S, b"\r");
+        expect.push(Token::InvalidToken, "\u{ff}".as_bytes());
+        expect.push(Token::InvalidUtf8, b"\xff");
+        expect.push(Token::L, b"\n");
+        assert_eq!(tokens, expect);
+
+        let bytes = Lexer::standard().lex(b"x\xff\x00 ");
+        let mut expect = TokenSource::new();
+        expect.push(Token::Comment, b"x\xff\x00");
+        expect.push(Token::S, b" ");
+        assert_eq!(bytes, expect);
+    }
+}
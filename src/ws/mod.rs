@@ -1,3 +1,9 @@
 //! Whitespace syntax.
 
+pub mod archive;
+pub mod canonical;
+pub mod encoding;
+pub mod lex;
+pub mod parse;
+pub mod rewrite;
 pub mod token_source;
@@ -0,0 +1,325 @@
+//! Parsing of Whitespace instructions.
+
+use std::{
+    error::Error,
+    fmt::{self, Display, Formatter},
+};
+
+use rug::Integer;
+
+use crate::{
+    codegen::{Inst, IntegerBits, LabelBits, Token},
+    tokens::integer::Sign,
+    ws::token_source::{self, TokenSource},
+};
+
+// TODO:
+// - Parse extension instructions. Burghard `debug_printstack` (LLSSS) and
+//   voliva `dbg` (LLS) conflict, so it needs to be selected by dialect.
+
+/// An incremental parser for standard Whitespace instructions.
+#[derive(Clone, Debug, Default)]
+pub struct InstParser {
+    /// The tokens of the opcode read so far.
+    prefix: Vec<Token>,
+    /// The opcode, when complete, and its argument so far.
+    op: Option<(Op, ArgState)>,
+}
+
+/// An error from parsing Whitespace instructions.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ParseError {
+    /// The byte offset of the token, at which the error was found.
+    pub offset: usize,
+    /// The kind of the error.
+    pub kind: ParseErrorKind,
+}
+
+/// The kind of an error from parsing Whitespace instructions.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ParseErrorKind {
+    /// The tokens do not form an opcode.
+    InvalidOpcode,
+    /// The program ends within an instruction.
+    Incomplete,
+    /// The encoding packs tokens into bits, so they cannot be rewritten in
+    /// place.
+    PackedEncoding,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Op {
+    Push,
+    Dup,
+    Copy,
+    Swap,
+    Drop,
+    Slide,
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Mod,
+    Store,
+    Retrieve,
+    Label,
+    Call,
+    Jmp,
+    Jz,
+    Jn,
+    Ret,
+    End,
+    Printc,
+    Printi,
+    Readc,
+    Readi,
+}
+
+#[derive(Clone, Debug, Default)]
+struct ArgState {
+    sign: Option<Sign>,
+    leading_zeros: usize,
+    value: Integer,
+}
+
+impl InstParser {
+    /// Constructs a parser at the start of an instruction.
+    pub fn new() -> Self {
+        InstParser::default()
+    }
+
+    /// Parses a token. Returns the instruction, when it is complete. After an
+    /// error, it resets to the start of an instruction.
+    pub fn push(&mut self, tok: Token) -> Result<Option<Inst<'static>>, ParseErrorKind> {
+        let Some((op, arg)) = &mut self.op else {
+            self.prefix.push(tok);
+            let op = Op::from_prefix(&self.prefix).inspect_err(|_| self.prefix.clear())?;
+            let Some(op) = op else {
+                return Ok(None);
+            };
+            self.prefix.clear();
+            if !op.has_arg() {
+                return Ok(Some(op.inst(ArgState::default())));
+            }
+            self.op = Some((op, ArgState::default()));
+            return Ok(None);
+        };
+        if arg.sign.is_none() && op.is_signed() {
+            arg.sign = Some(match tok {
+                Token::S => Sign::Pos,
+                Token::T => Sign::Neg,
+                Token::L => Sign::None,
+            });
+            if tok != Token::L {
+                return Ok(None);
+            }
+        }
+        match tok {
+            Token::S if arg.value.is_zero() => arg.leading_zeros += 1,
+            Token::S => arg.value <<= 1,
+            Token::T => {
+                arg.value <<= 1;
+                arg.value += 1;
+            }
+            Token::L => {
+                let (op, arg) = self.op.take().unwrap();
+                return Ok(Some(op.inst(arg)));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Returns whether the parser is at the start of an instruction.
+    pub fn is_empty(&self) -> bool {
+        self.prefix.is_empty() && self.op.is_none()
+    }
+
+    /// Checks that the program does not end within an instruction.
+    pub fn finish(&self) -> Result<(), ParseErrorKind> {
+        if self.is_empty() {
+            Ok(())
+        } else {
+            Err(ParseErrorKind::Incomplete)
+        }
+    }
+}
+
+/// Parses the standard instructions of a program. Tokens other than space,
+/// tab, and line feed are ignored.
+pub fn parse_program(source: &TokenSource) -> Result<Vec<Inst<'static>>, ParseError> {
+    let mut parser = InstParser::new();
+    let mut insts = Vec::new();
+    let mut offset = 0;
+    for (_, tok, lexeme) in source {
+        if let Some(tok) = standard(tok) {
+            let inst = (parser.push(tok)).map_err(|kind| ParseError { offset, kind })?;
+            insts.extend(inst);
+        }
+        offset += lexeme.len();
+    }
+    (parser.finish()).map_err(|kind| ParseError { offset, kind })?;
+    Ok(insts)
+}
+
+/// Converts a standard token to a code generation token.
+pub(super) fn standard(tok: token_source::Token) -> Option<Token> {
+    match tok {
+        token_source::Token::S => Some(Token::S),
+        token_source::Token::T => Some(Token::T),
+        token_source::Token::L => Some(Token::L),
+        _ => None,
+    }
+}
+
+impl Op {
+    const ALL: &[(&[Token], Op)] = {
+        use Token::*;
+        &[
+            (&[S, S], Op::Push),
+            (&[S, L, S], Op::Dup),
+            (&[S, T, S], Op::Copy),
+            (&[S, L, T], Op::Swap),
+            (&[S, L, L], Op::Drop),
+            (&[S, T, L], Op::Slide),
+            (&[T, S, S, S], Op::Add),
+            (&[T, S, S, T], Op::Sub),
+            (&[T, S, S, L], Op::Mul),
+            (&[T, S, T, S], Op::Div),
+            (&[T, S, T, T], Op::Mod),
+            (&[T, T, S], Op::Store),
+            (&[T, T, T], Op::Retrieve),
+            (&[L, S, S], Op::Label),
+            (&[L, S, T], Op::Call),
+            (&[L, S, L], Op::Jmp),
+            (&[L, T, S], Op::Jz),
+            (&[L, T, T], Op::Jn),
+            (&[L, T, L], Op::Ret),
+            (&[L, L, L], Op::End),
+            (&[T, L, S, S], Op::Printc),
+            (&[T, L, S, T], Op::Printi),
+            (&[T, L, T, S], Op::Readc),
+            (&[T, L, T, T], Op::Readi),
+        ]
+    };
+
+    /// Resolves the opcode with the tokens, or `None` when they are a proper
+    /// prefix of an opcode.
+    fn from_prefix(prefix: &[Token]) -> Result<Option<Op>, ParseErrorKind> {
+        let mut is_prefix = false;
+        for &(tokens, op) in Op::ALL {
+            if tokens == prefix {
+                return Ok(Some(op));
+            }
+            is_prefix |= tokens.starts_with(prefix);
+        }
+        if is_prefix {
+            Ok(None)
+        } else {
+            Err(ParseErrorKind::InvalidOpcode)
+        }
+    }
+
+    fn has_arg(self) -> bool {
+        matches!(
+            self,
+            Op::Push | Op::Copy | Op::Slide | Op::Label | Op::Call | Op::Jmp | Op::Jz | Op::Jn,
+        )
+    }
+
+    fn is_signed(self) -> bool {
+        matches!(self, Op::Push | Op::Copy | Op::Slide)
+    }
+
+    fn inst(self, arg: ArgState) -> Inst<'static> {
+        let int = || match arg.sign.unwrap() {
+            // An empty integer with no sign token.
+            Sign::None => IntegerBits::zero(Sign::None),
+            Sign::Pos => IntegerBits::with_sign(arg.value.clone(), Sign::Pos, arg.leading_zeros),
+            Sign::Neg => IntegerBits::with_sign(-arg.value.clone(), Sign::Neg, arg.leading_zeros),
+        };
+        let label = || LabelBits::owned(arg.value.clone(), arg.leading_zeros);
+        match self {
+            Op::Push => Inst::Push(int()),
+            Op::Dup => Inst::Dup,
+            Op::Copy => Inst::Copy(int()),
+            Op::Swap => Inst::Swap,
+            Op::Drop => Inst::Drop,
+            Op::Slide => Inst::Slide(int()),
+            Op::Add => Inst::Add,
+            Op::Sub => Inst::Sub,
+            Op::Mul => Inst::Mul,
+            Op::Div => Inst::Div,
+            Op::Mod => Inst::Mod,
+            Op::Store => Inst::Store,
+            Op::Retrieve => Inst::Retrieve,
+            Op::Label => Inst::Label(label()),
+            Op::Call => Inst::Call(label()),
+            Op::Jmp => Inst::Jmp(label()),
+            Op::Jz => Inst::Jz(label()),
+            Op::Jn => Inst::Jn(label()),
+            Op::Ret => Inst::Ret,
+            Op::End => Inst::End,
+            Op::Printc => Inst::Printc,
+            Op::Printi => Inst::Printi,
+            Op::Readc => Inst::Readc,
+            Op::Readi => Inst::Readi,
+        }
+    }
+}
+
+impl Display for ParseError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "at byte {}: ", self.offset)?;
+        match self.kind {
+            ParseErrorKind::InvalidOpcode => write!(f, "invalid opcode"),
+            ParseErrorKind::Incomplete => write!(f, "incomplete instruction"),
+            ParseErrorKind::PackedEncoding => write!(f, "packed Whitespace cannot be rewritten"),
+        }
+    }
+}
+
+impl Error for ParseError {}
+
+#[cfg(test)]
+mod tests {
+    use rug::Integer;
+
+    use crate::{
+        codegen::{Inst, IntegerBits, LabelBits, Token, TokenWrite},
+        tokens::integer::Sign,
+        ws::parse::{InstParser, ParseErrorKind},
+    };
+
+    #[test]
+    fn parse_round_trip() {
+        let n = Integer::from(-42);
+        let insts = [
+            Inst::Push(IntegerBits::new(&n, 3)),
+            Inst::Push(IntegerBits::zero(Sign::Neg)),
+            Inst::Slide(IntegerBits::with_sign(Integer::ZERO, Sign::Pos, 2)),
+            Inst::Slide(IntegerBits::zero(Sign::None)),
+            Inst::Label(LabelBits::owned(Integer::from(5), 1)),
+            Inst::Jz(LabelBits::owned(Integer::new(), 0)),
+            Inst::Printi,
+            Inst::End,
+        ];
+        let mut tokens = Vec::new();
+        for inst in &insts {
+            let Ok(()) = tokens.write_inst(inst.clone());
+        }
+        let mut parser = InstParser::new();
+        let mut parsed = Vec::new();
+        for tok in tokens {
+            parsed.extend(parser.push(tok).unwrap());
+        }
+        assert_eq!(parser.finish(), Ok(()));
+        assert_eq!(parsed, insts);
+
+        let mut parser = InstParser::new();
+        assert_eq!(parser.push(Token::T), Ok(None));
+        assert_eq!(parser.push(Token::L), Ok(None));
+        assert_eq!(parser.finish(), Err(ParseErrorKind::Incomplete));
+        assert_eq!(parser.push(Token::L), Err(ParseErrorKind::InvalidOpcode));
+        assert!(parser.is_empty());
+    }
+}
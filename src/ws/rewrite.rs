@@ -0,0 +1,248 @@
+//! Editing Whitespace programs while preserving their comments.
+
+use std::{
+    io::{self, Write},
+    ops::Range,
+};
+
+use crate::{
+    codegen::{Inst, TokenWrite},
+    ws::{
+        encoding::TokenEncoding,
+        parse::{InstParser, ParseError, ParseErrorKind, standard},
+        token_source::{TokenId, TokenSource},
+    },
+};
+
+/// A Whitespace program, which can be edited by instruction and written back
+/// with every comment and the lexemes of unchanged tokens preserved.
+#[derive(Clone, Debug)]
+pub struct Rewriter {
+    source: TokenSource,
+    ids: Vec<TokenId>,
+    encoding: TokenEncoding,
+    /// Instructions in the original and edited program, in order.
+    entries: Vec<Entry>,
+    /// The range in `ids` of the tokens after the last instruction.
+    trailing: Range<usize>,
+}
+
+/// An instruction in a rewritten program.
+#[derive(Clone, Debug)]
+struct Entry {
+    /// The instruction, or `None` if it was removed.
+    inst: Option<Inst<'static>>,
+    /// The range in `ids` of the original tokens of the instruction, including
+    /// the comments before it. It is empty for inserted instructions.
+    tokens: Range<usize>,
+    /// Whether the instruction was replaced, removed, or inserted.
+    edited: bool,
+}
+
+impl Rewriter {
+    /// Parses a Whitespace program in the encoding for editing. The packed
+    /// encoding is rejected, since its tokens are not whole bytes.
+    pub fn new(src: &[u8], encoding: TokenEncoding) -> Result<Self, ParseError> {
+        if encoding == TokenEncoding::Packed {
+            return Err(ParseError {
+                offset: 0,
+                kind: ParseErrorKind::PackedEncoding,
+            });
+        }
+        let source = encoding.decode(src);
+        let ids = source.iter().map(|(id, _, _)| id).collect::<Vec<_>>();
+        let mut parser = InstParser::new();
+        let mut entries = Vec::new();
+        let (mut start, mut offset) = (0, 0);
+        for (i, &id) in ids.iter().enumerate() {
+            let (tok, lexeme) = source.get(id);
+            if let Some(tok) = standard(tok) {
+                let inst = (parser.push(tok)).map_err(|kind| ParseError { offset, kind })?;
+                if let Some(inst) = inst {
+                    entries.push(Entry {
+                        inst: Some(inst),
+                        tokens: start..i + 1,
+                        edited: false,
+                    });
+                    start = i + 1;
+                }
+            }
+            offset += lexeme.len();
+        }
+        (parser.finish()).map_err(|kind| ParseError { offset, kind })?;
+        Ok(Rewriter {
+            trailing: start..ids.len(),
+            source,
+            ids,
+            encoding,
+            entries,
+        })
+    }
+
+    /// Returns the number of instructions.
+    pub fn len(&self) -> usize {
+        self.insts().count()
+    }
+
+    /// Returns whether there are no instructions.
+    pub fn is_empty(&self) -> bool {
+        self.insts().next().is_none()
+    }
+
+    /// Gets the instruction at the index.
+    pub fn get(&self, index: usize) -> Option<&Inst<'static>> {
+        self.insts().nth(index)
+    }
+
+    /// Iterates the instructions.
+    pub fn insts(&self) -> impl Iterator<Item = &Inst<'static>> {
+        self.entries.iter().filter_map(|entry| entry.inst.as_ref())
+    }
+
+    /// Inserts an instruction at the index, before the comments preceding the
+    /// instruction there. Panics if the index is out of bounds.
+    pub fn insert(&mut self, index: usize, inst: Inst<'_>) {
+        let i = if index == self.len() {
+            self.entries.len()
+        } else {
+            self.entry_index(index)
+        };
+        self.entries.insert(
+            i,
+            Entry {
+                inst: Some(inst.into_owned()),
+                tokens: 0..0,
+                edited: true,
+            },
+        );
+    }
+
+    /// Removes the instruction at the index. Its comments are kept. Panics if
+    /// the index is out of bounds.
+    pub fn remove(&mut self, index: usize) -> Inst<'static> {
+        let i = self.entry_index(index);
+        let entry = &mut self.entries[i];
+        entry.edited = true;
+        entry.inst.take().unwrap()
+    }
+
+    /// Replaces the instruction at the index. Its comments are kept and its
+    /// tokens are overwritten in place. Panics if the index is out of bounds.
+    pub fn replace(&mut self, index: usize, inst: Inst<'_>) -> Inst<'static> {
+        let i = self.entry_index(index);
+        let entry = &mut self.entries[i];
+        entry.edited = true;
+        entry.inst.replace(inst.into_owned()).unwrap()
+    }
+
+    /// Writes the edited program.
+    pub fn write_to<W: Write>(&self, mut w: W) -> io::Result<()> {
+        let lexemes = self.encoding.lexemes();
+        let mut tokens = Vec::new();
+        for entry in &self.entries {
+            if !entry.edited {
+                self.write_range(&mut w, entry.tokens.clone())?;
+                continue;
+            }
+            tokens.clear();
+            if let Some(inst) = &entry.inst {
+                let Ok(()) = tokens.write_inst(inst.clone());
+            }
+            let mut new = tokens.iter().copied();
+            for &id in &self.ids[entry.tokens.clone()] {
+                let (tok, lexeme) = self.source.get(id);
+                match standard(tok) {
+                    Some(tok) => match new.next() {
+                        Some(new) if new == tok => w.write_all(lexeme)?,
+                        Some(new) => w.write_all(lexemes[new as usize])?,
+                        None => {}
+                    },
+                    None => w.write_all(lexeme)?,
+                }
+            }
+            for tok in new {
+                w.write_all(lexemes[tok as usize])?;
+            }
+        }
+        self.write_range(&mut w, self.trailing.clone())
+    }
+
+    /// Returns the edited program.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut buf = Vec::new();
+        self.write_to(&mut buf)
+            .expect("writing to a Vec does not fail");
+        buf
+    }
+
+    fn write_range<W: Write>(&self, mut w: W, range: Range<usize>) -> io::Result<()> {
+        for &id in &self.ids[range] {
+            w.write_all(self.source.get(id).1)?;
+        }
+        Ok(())
+    }
+
+    /// Gets the index in `entries` of the instruction at the index.
+    fn entry_index(&self, index: usize) -> usize {
+        (self.entries.iter().enumerate())
+            .filter(|(_, entry)| entry.inst.is_some())
+            .nth(index)
+            .expect("instruction index out of bounds")
+            .0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rug::Integer;
+
+    use crate::{
+        codegen::{Inst, IntegerBits},
+        ws::{
+            encoding::TokenEncoding,
+            parse::{ParseError, ParseErrorKind},
+            rewrite::Rewriter,
+        },
+    };
+
+    #[test]
+    fn rewrite() {
+        let src = b"push  5 \t \t\n-print\t\n \t-end\n\n\n";
+        let mut rw = Rewriter::new(src, TokenEncoding::Standard).unwrap();
+        assert_eq!(rw.to_bytes(), src);
+        assert_eq!(rw.len(), 3);
+        assert_eq!(rw.get(1), Some(&Inst::Printi));
+
+        let six = Integer::from(6);
+        let push = rw.replace(0, Inst::Push(IntegerBits::from(&six)));
+        assert_eq!(push, Inst::Push(IntegerBits::from(Integer::from(5))));
+        rw.insert(1, Inst::Dup);
+        assert_eq!(rw.remove(3), Inst::End);
+        let out = rw.to_bytes();
+        assert_eq!(out, b"push  5 \t\t \n \n -print\t\n \t-end");
+
+        let rw = Rewriter::new(&out, TokenEncoding::Standard).unwrap();
+        assert_eq!(
+            rw.insts().cloned().collect::<Vec<_>>(),
+            [Inst::Push(IntegerBits::from(six)), Inst::Dup, Inst::Printi],
+        );
+
+        let stl = b"SS(five)STSTL TLST";
+        let mut rw = Rewriter::new(stl, TokenEncoding::Stl).unwrap();
+        rw.insert(2, Inst::End);
+        assert_eq!(rw.to_bytes(), b"SS(five)STSTL TLSTLLL");
+        assert!(Rewriter::new(b"\t\n\n", TokenEncoding::Standard).is_err());
+    }
+
+    #[test]
+    fn reject_packed() {
+        let err = Rewriter::new(b"", TokenEncoding::Packed).unwrap_err();
+        assert_eq!(
+            err,
+            ParseError {
+                offset: 0,
+                kind: ParseErrorKind::PackedEncoding,
+            },
+        );
+    }
+}
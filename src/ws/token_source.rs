@@ -9,21 +9,21 @@ use std::{
 use bstr::ByteSlice;
 use derive_more::Debug as DebugCustom;
 
+use crate::ws::archive::ArchiveErrorKind;
+
 // TODO:
 // - Store comment lexemes inline in `data`. They would quickly exhaust all of
 //   the 31 short lexemes.
 // - Make the lexeme table a proper index table with hashbrown.
-// - Create iterator and improve Debug impl using it.
 // - Consider unchecked indexing.
-// - Reconstruct spans with deltas like incremental compilers. Perhaps whenever
-//   a new lexeme is added, its line/col delta could be computed and stored.
+// - Count columns in characters or UTF-16 code units, in addition to bytes.
 
 /// A compact sequence of the Whitespace tokens in a single source file.
 ///
 /// It is optimized for few kinds of tokens and few unique lexemes per token.
 /// See the [design draft](../../docs/drafts/compact_ws_cst.md) for a
 /// description of its representation.
-#[derive(Clone, PartialEq, Eq)]
+#[derive(Clone)]
 pub struct TokenSource {
     /// Compact encoding of tokens.
     data: Vec<u8>,
@@ -34,6 +34,62 @@ pub struct TokenSource {
     /// Extension token kinds. A maximum of 256 extension tokens can be
     /// registered.
     extensions: Vec<ExtensionToken>,
+    /// The change in position over each lexeme, parallel to `lexemes`.
+    deltas: Vec<Delta>,
+    /// The position of every `CHECKPOINT_INTERVAL`th token, for seeking
+    /// without scanning from the start.
+    checkpoints: Vec<Checkpoint>,
+    /// The number of tokens.
+    len: usize,
+    /// The position after the last token.
+    end: Position,
+}
+
+/// A position in the source text of a [`TokenSource`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Position {
+    /// The byte offset.
+    pub offset: usize,
+    /// The line, starting at 1.
+    pub line: usize,
+    /// The column in bytes, starting at 1.
+    pub col: usize,
+}
+
+/// A double-ended iterator over the tokens in a [`TokenSource`] with their
+/// identifiers and lexemes.
+#[derive(Clone, Debug)]
+pub struct Iter<'a> {
+    source: &'a TokenSource,
+    /// The offset in `data` of the next token from the front.
+    front: usize,
+    /// The offset in `data` after the next token from the back.
+    back: usize,
+    /// Offsets in `data` of the tokens before `back` in its checkpoint block,
+    /// since tokens cannot be decoded in reverse.
+    back_block: Vec<usize>,
+    /// The number of tokens remaining.
+    remaining: usize,
+}
+
+/// The change in position over a lexeme.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct Delta {
+    /// The length in bytes.
+    len: usize,
+    /// The number of line feeds.
+    lines: usize,
+    /// The number of bytes after the last line feed.
+    tail: usize,
+}
+
+/// The position of a token.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct Checkpoint {
+    /// The offset of the token in `data`.
+    id: usize,
+    /// The position of the start of the token.
+    pos: Position,
 }
 
 /// A token in Whitespace.
@@ -106,6 +162,10 @@ impl TokenSource {
     const TAG_INVALID_UTF_8: u8 = 6;
     const TAG_RESERVED: u8 = 7;
 
+    /// The number of tokens between checkpoints. Seeking to a token or
+    /// position scans at most this many tokens.
+    const CHECKPOINT_INTERVAL: usize = 64;
+
     /// Constructs a new, empty `TokenSource`.
     pub fn new() -> Self {
         TokenSource {
@@ -113,6 +173,10 @@ impl TokenSource {
             lexemes: Vec::new(),
             lexeme_table: HashMap::new(),
             extensions: Vec::new(),
+            deltas: Vec::new(),
+            checkpoints: Vec::new(),
+            len: 0,
+            end: Position::START,
         }
     }
 
@@ -125,12 +189,17 @@ impl TokenSource {
             id
         } else {
             let id = u32::try_from(self.lexemes.len()).unwrap();
+            self.deltas.push(Delta::new(lexeme));
             self.lexemes.push(lexeme.to_owned());
             self.lexeme_table.insert(lexeme.to_owned(), id);
             id
         };
         let is_long_lexeme = lexeme_id >= Self::MAX_LEXEME as u32;
-        let short_lexeme = if is_long_lexeme { 0 } else { lexeme_id as u8 };
+        let short_lexeme = if is_long_lexeme {
+            Self::MAX_LEXEME
+        } else {
+            lexeme_id as u8
+        };
 
         let kind = tok.discriminant();
         self.data.push(short_lexeme << Self::KIND_BITS | kind);
@@ -140,21 +209,121 @@ impl TokenSource {
         if is_long_lexeme {
             self.data.extend_from_slice(&lexeme_id.to_le_bytes());
         }
+
+        if self.len.is_multiple_of(Self::CHECKPOINT_INTERVAL) {
+            self.checkpoints.push(Checkpoint {
+                id: id.0,
+                pos: self.end,
+            });
+        }
+        self.end.advance(self.deltas[lexeme_id as usize]);
+        self.len += 1;
         id
     }
 
     /// Gets the identified token and its lexeme.
     pub fn get(&self, id: TokenId) -> (Token, &[u8]) {
-        let data = &self.data[id.as_usize()..];
+        let (tok, lexeme_id, _) = self.decode(id.as_usize());
+        (tok, &self.lexemes[lexeme_id])
+    }
+
+    /// Returns the number of tokens.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns whether there are no tokens.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Iterates the tokens with their identifiers and lexemes, from either
+    /// end.
+    pub fn iter(&self) -> Iter<'_> {
+        Iter {
+            source: self,
+            front: 0,
+            back: self.data.len(),
+            back_block: Vec::new(),
+            remaining: self.len,
+        }
+    }
+
+    /// Gets the position of the start of the identified token.
+    pub fn position(&self, id: TokenId) -> Position {
+        let i = self.checkpoints.partition_point(|c| c.id <= id.0);
+        let Some(checkpoint) = i.checked_sub(1).map(|i| self.checkpoints[i]) else {
+            return Position::START;
+        };
+        let (mut offset, mut pos) = (checkpoint.id, checkpoint.pos);
+        while offset < id.0 {
+            let (_, lexeme_id, len) = self.decode(offset);
+            pos.advance(self.deltas[lexeme_id]);
+            offset += len;
+        }
+        debug_assert_eq!(offset, id.0, "not a token boundary");
+        pos
+    }
+
+    /// Gets the position after the last token.
+    pub fn end(&self) -> Position {
+        self.end
+    }
+
+    /// Gets the token containing the byte offset.
+    pub fn token_at_offset(&self, offset: usize) -> Option<TokenId> {
+        self.seek(|pos| pos.offset <= offset, |end| offset < end.offset)
+    }
+
+    /// Gets the token containing the line and column. A column past the end of
+    /// a line is contained by its line feed.
+    pub fn token_at_line_col(&self, line: usize, col: usize) -> Option<TokenId> {
+        self.seek(
+            |pos| (pos.line, pos.col) <= (line, col),
+            |end| (line, col) < (end.line, end.col),
+        )
+    }
+
+    /// Finds the first token, which ends after the target, starting from the
+    /// last checkpoint at or before it.
+    fn seek(
+        &self,
+        at_or_before: impl Fn(Position) -> bool,
+        ends_after: impl Fn(Position) -> bool,
+    ) -> Option<TokenId> {
+        let i = self.checkpoints.partition_point(|c| at_or_before(c.pos));
+        let checkpoint = self.checkpoints[i.checked_sub(1)?];
+        let (mut offset, mut pos) = (checkpoint.id, checkpoint.pos);
+        while offset < self.data.len() {
+            let (_, lexeme_id, len) = self.decode(offset);
+            pos.advance(self.deltas[lexeme_id]);
+            if ends_after(pos) {
+                return Some(TokenId(offset));
+            }
+            offset += len;
+        }
+        None
+    }
+
+    /// Decodes the token at the offset in `data`. Returns the token, the index
+    /// of its lexeme, and its encoded length.
+    fn decode(&self, offset: usize) -> (Token, usize, usize) {
+        Self::decode_data(&self.data, offset)
+    }
+
+    /// Decodes the token at the offset in encoded token data.
+    pub(super) fn decode_data(data: &[u8], offset: usize) -> (Token, usize, usize) {
+        let data = &data[offset..];
         let head = data[0];
         let kind = head & Self::KIND_MASK;
         let short_lexeme = (head & Self::LEXEME_MASK) >> Self::KIND_BITS;
-        let mut long_lexeme_start = 1;
+        let mut len = 1;
 
         let tok = if kind == Self::TAG_EXTENSION {
-            long_lexeme_start += 1;
+            len += 1;
             Token::Extension(ExtensionTokenId(data[1]))
         } else {
+            debug_assert_ne!(kind, Self::TAG_RESERVED);
             match kind {
                 Self::TAG_S => Token::S,
                 Self::TAG_T => Token::T,
@@ -167,16 +336,107 @@ impl TokenSource {
         };
 
         let lexeme_id = if short_lexeme == Self::MAX_LEXEME {
-            let bytes = data[long_lexeme_start..long_lexeme_start + 4]
-                .try_into()
-                .unwrap();
+            let bytes = data[len..len + 4].try_into().unwrap();
+            len += 4;
             u32::from_le_bytes(bytes)
         } else {
             short_lexeme as u32
         };
-        let lexeme = &*self.lexemes[usize::try_from(lexeme_id).unwrap()];
 
-        (tok, lexeme)
+        (tok, usize::try_from(lexeme_id).unwrap(), len)
+    }
+
+    /// Checks that encoded token data is well-formed with the numbers of
+    /// lexemes and extensions, so that it can be decoded. Lexeme indices must
+    /// use the shortest encoding, as from [`TokenSource::push`]. Calls the
+    /// function with the offset of each token. Returns the number of tokens,
+    /// or the offset and kind of the first error.
+    pub(super) fn validate_data(
+        data: &[u8],
+        lexemes: usize,
+        extensions: usize,
+        mut on_token: impl FnMut(usize),
+    ) -> Result<usize, (usize, ArchiveErrorKind)> {
+        let mut offset = 0;
+        let mut len = 0;
+        while offset < data.len() {
+            let head = data[offset];
+            let kind = head & Self::KIND_MASK;
+            let short_lexeme = (head & Self::LEXEME_MASK) >> Self::KIND_BITS;
+            let mut i = offset + 1;
+            if kind == Self::TAG_RESERVED {
+                return Err((offset, ArchiveErrorKind::InvalidTag));
+            }
+            if kind == Self::TAG_EXTENSION {
+                let Some(&ext) = data.get(i) else {
+                    return Err((i, ArchiveErrorKind::Truncated));
+                };
+                if ext as usize >= extensions {
+                    return Err((i, ArchiveErrorKind::InvalidExtension));
+                }
+                i += 1;
+            }
+            let lexeme_id = if short_lexeme == Self::MAX_LEXEME {
+                let Some(bytes) = data.get(i..i + 4) else {
+                    return Err((i, ArchiveErrorKind::Truncated));
+                };
+                let id = u32::from_le_bytes(bytes.try_into().unwrap());
+                if id < Self::MAX_LEXEME as u32 {
+                    return Err((i, ArchiveErrorKind::InvalidLexeme));
+                }
+                i += 4;
+                id as usize
+            } else {
+                short_lexeme as usize
+            };
+            if lexeme_id >= lexemes {
+                return Err((offset, ArchiveErrorKind::InvalidLexeme));
+            }
+            on_token(offset);
+            offset = i;
+            len += 1;
+        }
+        Ok(len)
+    }
+
+    /// Constructs a `TokenSource` from encoded token data, which has been
+    /// validated with [`TokenSource::validate_data`], and unique lexemes.
+    pub(super) fn from_parts(
+        data: Vec<u8>,
+        lexemes: Vec<Vec<u8>>,
+        extensions: Vec<ExtensionToken>,
+    ) -> Self {
+        let lexeme_table = (lexemes.iter().enumerate())
+            .map(|(i, lexeme)| (lexeme.clone(), i as u32))
+            .collect::<HashMap<_, _>>();
+        debug_assert_eq!(lexeme_table.len(), lexemes.len(), "duplicate lexemes");
+        let mut source = TokenSource {
+            deltas: lexemes.iter().map(|lexeme| Delta::new(lexeme)).collect(),
+            data,
+            lexemes,
+            lexeme_table,
+            extensions,
+            ..TokenSource::new()
+        };
+        let mut offset = 0;
+        while offset < source.data.len() {
+            let (_, lexeme_id, len) = source.decode(offset);
+            if source.len.is_multiple_of(Self::CHECKPOINT_INTERVAL) {
+                source.checkpoints.push(Checkpoint {
+                    id: offset,
+                    pos: source.end,
+                });
+            }
+            source.end.advance(source.deltas[lexeme_id]);
+            source.len += 1;
+            offset += len;
+        }
+        source
+    }
+
+    /// Gets the encoded token data, the lexemes, and the extensions.
+    pub(super) fn parts(&self) -> (&[u8], &[Vec<u8>], &[ExtensionToken]) {
+        (&self.data, &self.lexemes, &self.extensions)
     }
 
     /// Registers a kind of extension token.
@@ -192,53 +452,41 @@ impl TokenSource {
     }
 }
 
+impl PartialEq for TokenSource {
+    fn eq(&self, other: &Self) -> bool {
+        // The remaining fields are derived from these.
+        self.data == other.data
+            && self.lexemes == other.lexemes
+            && self.lexeme_table == other.lexeme_table
+            && self.extensions == other.extensions
+    }
+}
+
+impl Eq for TokenSource {}
+
 impl Debug for TokenSource {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
-        struct DebugByte(u8);
-        impl Debug for DebugByte {
-            fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
-                let kind = match self.0 & TokenSource::KIND_MASK {
-                    TokenSource::TAG_S => "S",
-                    TokenSource::TAG_T => "T",
-                    TokenSource::TAG_L => "L",
-                    TokenSource::TAG_EXTENSION => "Extension",
-                    TokenSource::TAG_COMMENT => "Comment",
-                    TokenSource::TAG_INVALID_TOKEN => "InvalidToken",
-                    TokenSource::TAG_INVALID_UTF_8 => "InvalidUtf8",
-                    TokenSource::TAG_RESERVED => "Reserved",
-                    _ => unreachable!(),
-                };
-                let lexeme = (self.0 & TokenSource::LEXEME_MASK) >> TokenSource::KIND_BITS;
-                write!(f, "{} ({kind}, {lexeme})", self.0)
-            }
-        }
-
-        struct DebugData<'a>(&'a Vec<u8>);
-        impl Debug for DebugData<'_> {
+        struct DebugTokens<'a>(&'a TokenSource);
+        impl Debug for DebugTokens<'_> {
             fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+                struct DebugToken<'a>(Token, &'a [u8]);
+                impl Debug for DebugToken<'_> {
+                    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+                        write!(f, "{:?} {:?}", self.0, self.1.as_bstr())
+                    }
+                }
                 f.debug_list()
-                    .entries(self.0.iter().map(|&b| DebugByte(b)))
-                    .finish()
-            }
-        }
-
-        struct DebugLexemes<'a>(&'a Vec<Vec<u8>>);
-        impl Debug for DebugLexemes<'_> {
-            fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
-                f.debug_map()
                     .entries(
                         self.0
                             .iter()
-                            .enumerate()
-                            .map(|(i, lexeme)| (i, lexeme.as_bstr())),
+                            .map(|(_, tok, lexeme)| DebugToken(tok, lexeme)),
                     )
                     .finish()
             }
         }
 
         f.debug_struct("TokenSource")
-            .field("data", &DebugData(&self.data))
-            .field("lexemes", &DebugLexemes(&self.lexemes))
+            .field("tokens", &DebugTokens(self))
             .field("extensions", &self.extensions)
             .finish()
     }
@@ -250,6 +498,90 @@ impl Default for TokenSource {
     }
 }
 
+impl<'a> Iterator for Iter<'a> {
+    type Item = (TokenId, Token, &'a [u8]);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.remaining == 0 {
+            return None;
+        }
+        let id = TokenId(self.front);
+        let (tok, lexeme_id, len) = self.source.decode(self.front);
+        self.front += len;
+        self.remaining -= 1;
+        Some((id, tok, &self.source.lexemes[lexeme_id]))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl DoubleEndedIterator for Iter<'_> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        if self.remaining == 0 {
+            return None;
+        }
+        if self.back_block.is_empty() {
+            let checkpoints = &self.source.checkpoints;
+            let i = checkpoints.partition_point(|c| c.id < self.back) - 1;
+            let mut offset = checkpoints[i].id.max(self.front);
+            while offset < self.back {
+                self.back_block.push(offset);
+                offset += self.source.decode(offset).2;
+            }
+        }
+        let offset = self.back_block.pop().unwrap();
+        self.back = offset;
+        self.remaining -= 1;
+        let (tok, lexeme_id, _) = self.source.decode(offset);
+        Some((TokenId(offset), tok, &self.source.lexemes[lexeme_id]))
+    }
+}
+
+impl ExactSizeIterator for Iter<'_> {}
+
+impl<'a> IntoIterator for &'a TokenSource {
+    type Item = (TokenId, Token, &'a [u8]);
+    type IntoIter = Iter<'a>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl Position {
+    /// The position at the start of a source.
+    pub const START: Position = Position {
+        offset: 0,
+        line: 1,
+        col: 1,
+    };
+
+    fn advance(&mut self, delta: Delta) {
+        self.offset += delta.len;
+        if delta.lines == 0 {
+            self.col += delta.len;
+        } else {
+            self.line += delta.lines;
+            self.col = delta.tail + 1;
+        }
+    }
+}
+
+impl Delta {
+    fn new(lexeme: &[u8]) -> Self {
+        Delta {
+            len: lexeme.len(),
+            lines: lexeme.iter().filter(|&&b| b == b'\n').count(),
+            tail: match lexeme.iter().rposition(|&b| b == b'\n') {
+                Some(i) => lexeme.len() - i - 1,
+                None => lexeme.len(),
+            },
+        }
+    }
+}
+
 impl Token {
     /// Gets its enum discriminant.
     #[inline]
@@ -278,9 +610,31 @@ impl From<ExtensionTokenId> for Token {
     }
 }
 
+impl ExtensionToken {
+    /// Constructs the definition of an extension token.
+    pub fn new(name: String, canon_lexeme: Vec<u8>) -> Self {
+        ExtensionToken { name, canon_lexeme }
+    }
+
+    /// Gets the human-readable name of this token.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Gets the canonical lexeme for encoding this token.
+    pub fn canon_lexeme(&self) -> &[u8] {
+        &self.canon_lexeme
+    }
+}
+
 impl TokenId {
     #[inline]
-    fn as_usize(self) -> usize {
+    pub(super) fn from_usize(offset: usize) -> Self {
+        TokenId(offset)
+    }
+
+    #[inline]
+    pub(super) fn as_usize(self) -> usize {
         self.0
     }
 }
@@ -360,6 +714,7 @@ mod tests {
                 .map(|(i, &lexeme)| (lexeme.to_owned(), i as u32))
                 .collect(),
             extensions: vec![],
+            ..TokenSource::new()
         };
 
         assert_eq!(source, expect);
@@ -369,4 +724,79 @@ mod tests {
             assert_eq!(source.get(id), expect);
         }
     }
+
+    #[test]
+    fn long_lexemes() {
+        let lexemes = (0..40).map(|i| format!("#{i}")).collect::<Vec<_>>();
+        let mut source = TokenSource::new();
+        let mut token_ids = Vec::new();
+        for lexeme in &lexemes {
+            token_ids.push(source.push(Token::Comment, lexeme.as_bytes()));
+            token_ids.push(source.push(Token::S, b" "));
+        }
+        assert_eq!(
+            source.iter().map(|(id, _, _)| id).collect::<Vec<_>>(),
+            token_ids,
+        );
+        for (i, lexeme) in lexemes.iter().enumerate() {
+            assert_eq!(
+                source.get(token_ids[2 * i]),
+                (Token::Comment, lexeme.as_bytes()),
+            );
+            assert_eq!(source.get(token_ids[2 * i + 1]), (Token::S, &b" "[..]));
+        }
+    }
+
+    #[test]
+    fn positions() {
+        let mut source = TokenSource::new();
+        let mut expect = Vec::new();
+        let mut pos = Position::START;
+        for i in 0..300 {
+            let comment = if i % 7 == 0 { "x\nyz" } else { "ab" };
+            for (tok, lexeme) in [(Token::Comment, comment), (Token::S, " "), (Token::L, "\n")] {
+                let id = source.push(tok, lexeme.as_bytes());
+                expect.push((id, tok, lexeme.as_bytes(), pos));
+                for b in lexeme.bytes() {
+                    pos.offset += 1;
+                    if b == b'\n' {
+                        pos.line += 1;
+                        pos.col = 1;
+                    } else {
+                        pos.col += 1;
+                    }
+                }
+            }
+        }
+        assert_eq!(source.len(), expect.len());
+        assert_eq!(source.end(), pos);
+
+        let forward = source.iter().collect::<Vec<_>>();
+        let backward = source.iter().rev().collect::<Vec<_>>();
+        let tokens = (expect.iter())
+            .map(|&(id, tok, lexeme, _)| (id, tok, lexeme))
+            .collect::<Vec<_>>();
+        assert_eq!(forward, tokens);
+        assert_eq!(backward.into_iter().rev().collect::<Vec<_>>(), tokens);
+        let mut iter = source.iter();
+        let mut meet = Vec::new();
+        while let (Some(front), Some(back)) = (iter.next(), iter.next_back()) {
+            meet.push(front);
+            meet.push(back);
+        }
+        assert_eq!(meet.len(), tokens.len());
+
+        for &(id, _, lexeme, pos) in &expect {
+            assert_eq!(source.position(id), pos);
+            assert_eq!(source.token_at_offset(pos.offset), Some(id));
+            assert_eq!(
+                source.token_at_offset(pos.offset + lexeme.len() - 1),
+                Some(id)
+            );
+            assert_eq!(source.token_at_line_col(pos.line, pos.col), Some(id));
+        }
+        assert_eq!(source.token_at_offset(pos.offset), None);
+        assert_eq!(source.token_at_line_col(2, 100), Some(expect[2].0));
+        assert_eq!(TokenSource::new().token_at_offset(0), None);
+    }
 }